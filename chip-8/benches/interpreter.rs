@@ -0,0 +1,77 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use chip_8::Chip8;
+use chip_8::Instruction::{self, *};
+
+fn assemble(program: &[Instruction]) -> Vec<u8> {
+    program
+        .iter()
+        .flat_map(|instruction| instruction.encode().to_be_bytes())
+        .collect()
+}
+
+fn machine(rom: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::new();
+    chip8.init();
+    chip8.seed_rng(0);
+    chip8.load_rom(rom).unwrap();
+    chip8
+}
+
+fn run(chip8: &mut Chip8, cycles: u32) {
+    for _ in 0..cycles {
+        chip8.cycle();
+    }
+}
+
+fn draw(c: &mut Criterion) {
+    // draws the 15-row sprite at 0x000 at random positions forever
+    let rom = assemble(&[
+        LoadI(0x000),
+        Random { x: 0, nn: 0xFF },
+        Random { x: 1, nn: 0xFF },
+        Draw { x: 0, y: 1, n: 15 },
+        Jump(0x202),
+    ]);
+    let mut chip8 = machine(&rom);
+    c.bench_function("draw 15-row sprites", |b| {
+        b.iter(|| run(black_box(&mut chip8), 1_000))
+    });
+}
+
+fn alu(c: &mut Criterion) {
+    // arithmetic and skips with no drawing
+    let rom = assemble(&[
+        AddImm { x: 0, nn: 3 },
+        AddReg { x: 1, y: 0 },
+        Xor { x: 2, y: 1 },
+        ShiftLeft { x: 2, y: 2 },
+        SkipEqImm { x: 0, nn: 0 },
+        Jump(0x200),
+        Jump(0x200),
+    ]);
+    let mut chip8 = machine(&rom);
+    c.bench_function("alu loop", |b| b.iter(|| run(black_box(&mut chip8), 1_000)));
+
+    let mut chip8 = machine(&rom);
+    chip8.set_decode_cache(true);
+    c.bench_function("alu loop, decode cache", |b| {
+        b.iter(|| run(black_box(&mut chip8), 1_000))
+    });
+}
+
+fn ibm_logo(c: &mut Criterion) {
+    let rom = include_bytes!("../../roms/IBM Logo.ch8");
+    c.bench_function("IBM logo, 20 frames", |b| {
+        b.iter(|| {
+            let mut chip8 = machine(rom);
+            for _ in 0..20 {
+                black_box(chip8.run_frame(12));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, draw, alu, ibm_logo);
+criterion_main!(benches);
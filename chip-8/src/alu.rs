@@ -0,0 +1,86 @@
+//! The arithmetic 8XY_ instructions as pure functions of the register
+//! values. Each returns the new Vx and the VF flag; `Chip8` writes the flag
+//! last, so it wins when Vx is VF.
+
+/// 8XY4: Vx + Vy, with VF set to the carry.
+pub fn add(vx: u8, vy: u8) -> (u8, u8) {
+    let (result, carry) = vx.overflowing_add(vy);
+    (result, carry as u8)
+}
+
+/// 8XY5: Vx - Vy, with VF set when there was no borrow.
+pub fn sub(vx: u8, vy: u8) -> (u8, u8) {
+    let (result, borrow) = vx.overflowing_sub(vy);
+    (result, !borrow as u8)
+}
+
+/// 8XY7: Vy - Vx, with VF set when there was no borrow.
+pub fn sub_reverse(vx: u8, vy: u8) -> (u8, u8) {
+    sub(vy, vx)
+}
+
+/// 8XY6: Vx shifted right, with VF set to the bit shifted out.
+pub fn shift_right(vx: u8) -> (u8, u8) {
+    (vx >> 1, vx & 0x1)
+}
+
+/// 8XYE: Vx shifted left, with VF set to the bit shifted out.
+pub fn shift_left(vx: u8) -> (u8, u8) {
+    (vx << 1, vx >> 7)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{Chip8, NUM_REGISTERS};
+
+    // the instructions worked out on wider integers
+    fn model(n: u8, vx: u8, vy: u8) -> (u8, u8) {
+        let (vx, vy) = (i32::from(vx), i32::from(vy));
+        let (result, flag) = match n {
+            0x4 => (vx + vy, vx + vy > 0xFF),
+            0x5 => (vx - vy, vx >= vy),
+            0x6 => (vx / 2, vx % 2 == 1),
+            0x7 => (vy - vx, vy >= vx),
+            0xE => (vx * 2, vx >= 0x80),
+            _ => unreachable!(),
+        };
+        (result.rem_euclid(0x100) as u8, flag as u8)
+    }
+
+    proptest! {
+        #[test]
+        fn test_functions_match_model(vx: u8, vy: u8) {
+            prop_assert_eq!(add(vx, vy), model(0x4, vx, vy));
+            prop_assert_eq!(sub(vx, vy), model(0x5, vx, vy));
+            prop_assert_eq!(shift_right(vx), model(0x6, vx, vy));
+            prop_assert_eq!(sub_reverse(vx, vy), model(0x7, vx, vy));
+            prop_assert_eq!(shift_left(vx), model(0xE, vx, vy));
+        }
+
+        #[test]
+        fn test_instructions_match_model(
+            n in prop::sample::select(vec![0x4u8, 0x5, 0x6, 0x7, 0xE]),
+            x in 0..NUM_REGISTERS as u8,
+            y in 0..NUM_REGISTERS as u8,
+            registers: [u8; NUM_REGISTERS],
+        ) {
+            let mut chip8 = Chip8::new();
+            chip8.init();
+            for (index, &value) in registers.iter().enumerate() {
+                chip8.set_v_register(index, value);
+            }
+            chip8.load_rom(&[0x80 | x, y << 4 | n]).unwrap();
+            chip8.cycle();
+
+            let (vx, vy) = (registers[x as usize], registers[y as usize]);
+            let (result, flag) = model(n, vx, vy);
+            let mut expected = registers;
+            expected[x as usize] = result;
+            expected[0xF] = flag;
+            prop_assert_eq!(chip8.v_registers(), &expected);
+        }
+    }
+}
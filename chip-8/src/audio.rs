@@ -0,0 +1,289 @@
+/// Default output level, as a fraction of full scale.
+pub const DEFAULT_VOLUME: f32 = 0.25;
+
+/// Bytes in an XO-CHIP audio pattern, played as 128 1-bit samples.
+pub const PATTERN_SIZE: usize = 16;
+/// The XO-CHIP pitch register's initial value, which plays patterns at
+/// 4000 bits a second.
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// Square wave oscillator for the buzzer, suitable for driving an audio
+/// callback directly. The wave is gated by a `playing` flag each time a
+/// buffer is filled; silence resets the phase so every beep starts the same way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SquareWave {
+    frequency: u32,
+    sample_rate: u32,
+    volume: f32,
+    // samples produced since the current beep started
+    position: u64,
+}
+
+impl SquareWave {
+    pub fn new(frequency: u32, sample_rate: u32) -> Self {
+        SquareWave {
+            frequency,
+            sample_rate,
+            volume: DEFAULT_VOLUME,
+            position: 0,
+        }
+    }
+
+    /// Sets the output level, clamped to `0.0..=1.0`.
+    pub fn with_volume(mut self, volume: f32) -> Self {
+        self.set_volume(volume);
+        self
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn set_frequency(&mut self, frequency: u32) {
+        self.frequency = frequency;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn fill_f32(&mut self, out: &mut [f32], playing: bool) {
+        let amplitude = self.volume;
+        self.fill(out, playing, amplitude, 0.0);
+    }
+
+    pub fn fill_i16(&mut self, out: &mut [i16], playing: bool) {
+        let amplitude = (self.volume * i16::MAX as f32) as i16;
+        self.fill(out, playing, amplitude, 0);
+    }
+
+    fn fill<T: Copy + core::ops::Neg<Output = T>>(
+        &mut self,
+        out: &mut [T],
+        playing: bool,
+        amplitude: T,
+        silence: T,
+    ) {
+        if !playing || self.sample_rate == 0 {
+            out.fill(silence);
+            self.position = 0;
+            return;
+        }
+        for sample in out.iter_mut() {
+            // which half-period of the wave this sample falls in
+            let half_period = self.position * 2 * self.frequency as u64 / self.sample_rate as u64;
+            *sample = if half_period.is_multiple_of(2) {
+                amplitude
+            } else {
+                -amplitude
+            };
+            self.position += 1;
+        }
+    }
+}
+
+/// Bits per second an XO-CHIP audio pattern plays at for a pitch register
+/// value: 4000 * 2^((pitch - 64) / 48).
+pub fn playback_rate(pitch: u8) -> f32 {
+    // 2^(1/48), one step of pitch
+    const PITCH_STEP: f64 = 1.014_545_334_937_523_7;
+    let steps = i32::from(pitch) - i32::from(DEFAULT_PITCH);
+    // whole octaves are exact, so only the steps within one are multiplied
+    let octaves = steps.div_euclid(48);
+    let mut rate = if octaves < 0 {
+        4000.0 / f64::from(1 << -octaves)
+    } else {
+        4000.0 * f64::from(1 << octaves)
+    };
+    for _ in 0..steps.rem_euclid(48) {
+        rate *= PITCH_STEP;
+    }
+    rate as f32
+}
+
+/// Player for XO-CHIP audio patterns, see `Chip8::audio_pattern`. The
+/// pattern's bits are played most significant first and loop while the sound
+/// timer runs; a set bit is a high sample and a clear one a low sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PatternWave {
+    pattern: [u8; PATTERN_SIZE],
+    rate: f32,
+    sample_rate: u32,
+    volume: f32,
+    // position in the pattern, in bits
+    phase: f64,
+}
+
+impl PatternWave {
+    pub fn new(sample_rate: u32) -> Self {
+        PatternWave {
+            pattern: [0; PATTERN_SIZE],
+            rate: playback_rate(DEFAULT_PITCH),
+            sample_rate,
+            volume: DEFAULT_VOLUME,
+            phase: 0.0,
+        }
+    }
+
+    /// Sets the output level, clamped to `0.0..=1.0`.
+    pub fn with_volume(mut self, volume: f32) -> Self {
+        self.set_volume(volume);
+        self
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    /// Replaces the pattern, carrying on from the same bit like XO-CHIP does.
+    pub fn set_pattern(&mut self, pattern: &[u8; PATTERN_SIZE]) {
+        self.pattern = *pattern;
+    }
+
+    /// Sets the bits played per second, see `playback_rate`.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Adds the pattern to the samples in `out`, so it can be mixed with the
+    /// buzzer or other sound. Adds nothing unless `playing`.
+    pub fn mix_f32(&mut self, out: &mut [f32], playing: bool) {
+        if !playing || self.sample_rate == 0 {
+            self.phase = 0.0;
+            return;
+        }
+        let bits = (PATTERN_SIZE * 8) as f64;
+        let step = f64::from(self.rate) / f64::from(self.sample_rate);
+        for sample in out.iter_mut() {
+            let bit = self.phase as usize;
+            let high = self.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            *sample += if high { self.volume } else { -self.volume };
+            self.phase = (self.phase + step) % bits;
+        }
+    }
+}
+
+#[cfg(feature = "cpal")]
+pub use device::Beep;
+
+#[cfg(feature = "cpal")]
+mod device {
+    use std::boxed::Box;
+    use std::error::Error;
+    use std::format;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::vec::Vec;
+
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    use super::SquareWave;
+    use crate::BEEP_FREQUENCY;
+
+    /// Buzzer output on the default audio device.
+    pub struct Beep {
+        // playback stops when the stream is dropped
+        _stream: cpal::Stream,
+        playing: Arc<AtomicBool>,
+    }
+
+    impl Beep {
+        /// Opens the default output device and starts a silent stream;
+        /// `set_playing` gates the square wave on and off.
+        pub fn new(volume: f32) -> Result<Self, Box<dyn Error>> {
+            let host = cpal::default_host();
+            let device = host
+                .default_output_device()
+                .ok_or("no audio output device")?;
+            let config = device.default_output_config()?;
+            if config.sample_format() != cpal::SampleFormat::F32 {
+                return Err(format!("unsupported sample format {}", config.sample_format()).into());
+            }
+
+            let channels = config.channels() as usize;
+            let mut wave =
+                SquareWave::new(BEEP_FREQUENCY, config.sample_rate().0).with_volume(volume);
+            let mut mono = Vec::new();
+            let playing = Arc::new(AtomicBool::new(false));
+            let flag = playing.clone();
+
+            let stream = device.build_output_stream(
+                &config.into(),
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    mono.resize(data.len() / channels, 0.0);
+                    wave.fill_f32(&mut mono, flag.load(Ordering::Relaxed));
+                    for (frame, &sample) in data.chunks_mut(channels).zip(&mono) {
+                        frame.fill(sample);
+                    }
+                },
+                // a glitch in the stream isn't worth stopping for, and a
+                // terminal frontend can't print it without corrupting the display
+                |_| {},
+                None,
+            )?;
+            stream.play()?;
+
+            Ok(Beep {
+                _stream: stream,
+                playing,
+            })
+        }
+
+        pub fn set_playing(&self, playing: bool) {
+            self.playing.store(playing, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playback_rate() {
+        assert_eq!(playback_rate(DEFAULT_PITCH), 4000.0);
+        assert_eq!(playback_rate(112), 8000.0);
+        assert_eq!(playback_rate(16), 2000.0);
+        // a semitone is 4 steps of pitch
+        assert!((playback_rate(68) - 4237.85).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_pattern_wave_mix_f32() {
+        // one bit per sample
+        let mut wave = PatternWave::new(4000).with_volume(0.5);
+        let mut pattern = [0; PATTERN_SIZE];
+        pattern[0] = 0b1010_0000;
+        wave.set_pattern(&pattern);
+        let mut out = [0.25; 4];
+        wave.mix_f32(&mut out, true);
+        assert_eq!(out, [0.75, -0.25, 0.75, -0.25]);
+
+        // twice as fast skips every other bit
+        wave.set_rate(8000.0);
+        let mut out = [0.0; 2];
+        wave.mix_f32(&mut out, false);
+        assert_eq!(out, [0.0; 2]);
+        wave.mix_f32(&mut out, true);
+        assert_eq!(out, [0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_square_wave_fill_f32() {
+        // 4 samples per period
+        let mut wave = SquareWave::new(100, 400).with_volume(0.5);
+        let mut out = [0.0; 6];
+        wave.fill_f32(&mut out, true);
+        assert_eq!(out, [0.5, 0.5, -0.5, -0.5, 0.5, 0.5]);
+
+        // silence restarts the phase
+        wave.fill_f32(&mut out, false);
+        assert_eq!(out, [0.0; 6]);
+        wave.fill_f32(&mut out[..2], true);
+        assert_eq!(out[..2], [0.5, 0.5]);
+    }
+}
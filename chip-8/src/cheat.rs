@@ -0,0 +1,221 @@
+//! Memory patches applied every frame, for infinite lives and the like.
+//!
+//! A cheat file has one cheat per line, a name and a colon followed by
+//! comma-separated patches. A patch writes a hex value to a hex address,
+//! optionally only while another address holds (or doesn't hold) a value:
+//!
+//! ```text
+//! # comments start with #
+//! Infinite lives: 2F0=03
+//! Full ammo, more lives: 2F1=09, 2F0=05 if 2F2!=00
+//! ```
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Chip8, MEMORY_SIZE};
+
+/// A named group of patches toggled together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub name: String,
+    pub patches: Vec<Patch>,
+    pub enabled: bool,
+}
+
+/// Writes `value` to `address` whenever `condition` holds, or always.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Patch {
+    pub address: u16,
+    pub value: u8,
+    pub condition: Option<Condition>,
+}
+
+/// Compares the byte at `address` with `value`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub address: u16,
+    pub value: u8,
+    /// Whether the byte must equal `value`, rather than differ from it.
+    pub equal: bool,
+}
+
+/// Why a cheat file couldn't be read, with the 1-based line it was on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+/// Reads a cheat file. Every cheat starts enabled.
+pub fn parse(text: &str) -> Result<Vec<Cheat>, ParseError> {
+    let mut cheats = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| ParseError {
+            line: index + 1,
+            message,
+        };
+        let (name, patches) = line
+            .split_once(':')
+            .ok_or_else(|| error("expected `name: patches`".to_string()))?;
+        let patches = patches
+            .split(',')
+            .map(parse_patch)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(error)?;
+        cheats.push(Cheat {
+            name: name.trim().to_string(),
+            patches,
+            enabled: true,
+        });
+    }
+    Ok(cheats)
+}
+
+fn parse_patch(text: &str) -> Result<Patch, String> {
+    let (write, condition) = match text.split_once(" if ") {
+        Some((write, condition)) => (write, Some(condition)),
+        None => (text, None),
+    };
+    let (address, value) = write
+        .split_once('=')
+        .ok_or_else(|| alloc::format!("expected `ADDR=VALUE`, found `{}`", write.trim()))?;
+    let condition = match condition {
+        Some(condition) => {
+            let (address, value, equal) = match condition.split_once("!=") {
+                Some((address, value)) => (address, value, false),
+                None => {
+                    let (address, value) = condition.split_once('=').ok_or_else(|| {
+                        alloc::format!("expected `if ADDR=VALUE`, found `{}`", condition.trim())
+                    })?;
+                    (address, value, true)
+                }
+            };
+            Some(Condition {
+                address: parse_address(address)?,
+                value: parse_hex(value)?,
+                equal,
+            })
+        }
+        None => None,
+    };
+    Ok(Patch {
+        address: parse_address(address)?,
+        value: parse_hex(value)?,
+        condition,
+    })
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    let address = parse_hex(text)?;
+    if usize::from(address) >= MEMORY_SIZE {
+        return Err(alloc::format!(
+            "address `{}` is past the end of memory at {:#05X}",
+            text.trim(),
+            MEMORY_SIZE - 1
+        ));
+    }
+    Ok(address)
+}
+
+fn parse_hex<T: TryFrom<u32>>(text: &str) -> Result<T, String> {
+    let text = text.trim();
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .and_then(|number| T::try_from(number).ok())
+        .ok_or_else(|| alloc::format!("`{}` is not a hex number in range", text))
+}
+
+impl Cheat {
+    /// Applies the patches, if the cheat is enabled. Bytes that already
+    /// hold their value aren't written again.
+    pub fn apply(&self, chip8: &mut Chip8) {
+        if !self.enabled {
+            return;
+        }
+        for patch in &self.patches {
+            if let Some(condition) = patch.condition {
+                let holds = chip8.read_byte(condition.address) == Some(condition.value);
+                if holds != condition.equal {
+                    continue;
+                }
+            }
+            if chip8.read_byte(patch.address) != Some(patch.value) {
+                chip8.write_byte(patch.address, patch.value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cheat_file() {
+        let cheats =
+            parse("# lives\nInfinite lives: 2F0=03\n\nBoth: 0x2F1=9, 2F0=05 if 2F2!=00\n").unwrap();
+        assert_eq!(cheats.len(), 2);
+        assert_eq!(cheats[0].name, "Infinite lives");
+        assert_eq!(
+            cheats[1].patches[1],
+            Patch {
+                address: 0x2F0,
+                value: 0x05,
+                condition: Some(Condition {
+                    address: 0x2F2,
+                    value: 0x00,
+                    equal: false,
+                }),
+            }
+        );
+
+        let error = parse("ok: 200=1\nbad: 200\n").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(parse("big: 200=100").is_err());
+        let error = parse("ok: 200=1\nfar: 1000=1\n").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(error.message.contains("1000"));
+        assert!(parse("far: 200=1 if FFFF=0").is_err());
+    }
+
+    #[test]
+    fn test_apply_respects_conditions_and_toggle() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        let mut cheats = parse("a: 300=07 if 301=01\nb: 302=08").unwrap();
+
+        for cheat in &cheats {
+            cheat.apply(&mut chip8);
+        }
+        assert_eq!(chip8.read_byte(0x300), Some(0));
+        assert_eq!(chip8.read_byte(0x302), Some(8));
+
+        chip8.write_byte(0x301, 1);
+        chip8.write_byte(0x302, 0);
+        cheats[1].enabled = false;
+        for cheat in &cheats {
+            cheat.apply(&mut chip8);
+        }
+        assert_eq!(chip8.read_byte(0x300), Some(7));
+        assert_eq!(chip8.read_byte(0x302), Some(0));
+    }
+}
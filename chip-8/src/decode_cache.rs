@@ -0,0 +1,42 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::MEMORY_SIZE;
+use crate::instruction::Instruction;
+
+/// Instructions decoded so far, by address, with the opcode they came from.
+/// Entries have to be invalidated whenever memory under them is written.
+#[derive(Clone)]
+pub(crate) struct DecodeCache {
+    entries: Vec<Option<(u16, Option<Instruction>)>>,
+}
+
+impl Default for DecodeCache {
+    fn default() -> Self {
+        DecodeCache {
+            entries: vec![None; MEMORY_SIZE],
+        }
+    }
+}
+
+impl DecodeCache {
+    pub fn get(&self, address: u16) -> Option<(u16, Option<Instruction>)> {
+        self.entries[address as usize % MEMORY_SIZE]
+    }
+
+    pub fn insert(&mut self, address: u16, opcode: u16, instruction: Option<Instruction>) {
+        self.entries[address as usize % MEMORY_SIZE] = Some((opcode, instruction));
+    }
+
+    /// Drops the instructions that include the byte at `address`: the one
+    /// starting there and the one starting just before it.
+    pub fn invalidate(&mut self, address: usize) {
+        let address = address % MEMORY_SIZE;
+        self.entries[address] = None;
+        self.entries[(address + MEMORY_SIZE - 1) % MEMORY_SIZE] = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+}
@@ -0,0 +1,77 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Chip8, NUM_REGISTERS, instruction, wrap_address};
+
+// instructions shown on each side of the one at PC
+const WINDOW_RADIUS: u16 = 3;
+
+/// Where the emulator was and what it was looking at, for explaining a fault
+/// or warning; see `Chip8::diagnose`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub pc: u16,
+    pub opcode: u16,
+    pub v_registers: [u8; NUM_REGISTERS],
+    pub i_register: u16,
+    pub sp: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// (address, opcode) for the instructions around `pc`, in order.
+    pub window: Vec<(u16, u16)>,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(chip8: &Chip8, pc: u16) -> Diagnostic {
+        let opcode_at = |address: u16| {
+            u16::from_be_bytes([
+                chip8.memory.data[wrap_address(address, 0)],
+                chip8.memory.data[wrap_address(address, 1)],
+            ])
+        };
+        let first = pc.saturating_sub(2 * WINDOW_RADIUS);
+        let window = (0..=2 * WINDOW_RADIUS)
+            .map(|n| first + 2 * n)
+            .filter(|&address| (address as usize) < crate::MEMORY_SIZE)
+            .map(|address| (address, opcode_at(address)))
+            .collect();
+        Diagnostic {
+            pc,
+            opcode: opcode_at(pc),
+            v_registers: chip8.v_registers,
+            i_register: chip8.i_register,
+            sp: chip8.stack.sp,
+            delay_timer: chip8.delay_timer,
+            sound_timer: chip8.sound_timer,
+            window,
+        }
+    }
+}
+
+/// Registers on two lines, then the disassembly window with PC marked.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, registers) in self.v_registers.chunks(NUM_REGISTERS / 2).enumerate() {
+            let first = index * NUM_REGISTERS / 2;
+            write!(f, "V{:X}-V{:X}:", first, first + registers.len() - 1)?;
+            for value in registers {
+                write!(f, " {:02X}", value)?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(
+            f,
+            "I: {:#05X}  SP: {}  DT: {}  ST: {}",
+            self.i_register, self.sp, self.delay_timer, self.sound_timer
+        )?;
+        for &(address, opcode) in &self.window {
+            let marker = if address == self.pc { '>' } else { ' ' };
+            write!(f, "{} {:03X}  {:04X}  ", marker, address, opcode)?;
+            match instruction::decode(opcode) {
+                Some(instruction) => writeln!(f, "{}", instruction)?,
+                None => writeln!(f, "???")?,
+            }
+        }
+        Ok(())
+    }
+}
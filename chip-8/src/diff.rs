@@ -0,0 +1,227 @@
+//! Structured differences between two machine states.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Chip8, Memory, NUM_REGISTERS, Screen, Snapshot, Stack};
+
+/// A value that differs between two states, as (first, second).
+pub type Change<T> = Option<(T, T)>;
+
+/// A run of adjacent memory bytes that differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryChange {
+    pub start: u16,
+    pub first: Vec<u8>,
+    pub second: Vec<u8>,
+}
+
+/// Everything that differs between two states; see `Chip8::diff`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub pc: Change<u16>,
+    pub i_register: Change<u16>,
+    /// (register, first, second) for each V register that differs.
+    pub v_registers: Vec<(usize, u8, u8)>,
+    pub sp: Change<u16>,
+    /// (level, first, second) for each stack entry that differs.
+    pub stack: Vec<(usize, u16, u16)>,
+    pub delay_timer: Change<u8>,
+    pub sound_timer: Change<u8>,
+    pub memory: Vec<MemoryChange>,
+    pub display_height: Change<usize>,
+    /// (row, first, second) for each display row that differs, packed as
+    /// by `Chip8::get_display_packed`.
+    pub display_rows: Vec<(usize, u64, u64)>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == StateDiff::default()
+    }
+}
+
+// borrowed view of the parts of a state that are compared
+pub(crate) struct State<'a> {
+    pub memory: &'a Memory,
+    pub screen: &'a Screen,
+    pub v_registers: &'a [u8; NUM_REGISTERS],
+    pub i_register: u16,
+    pub stack: &'a Stack,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl<'a> From<&'a Chip8> for State<'a> {
+    fn from(chip8: &'a Chip8) -> Self {
+        State {
+            memory: &chip8.memory,
+            screen: &chip8.screen,
+            v_registers: &chip8.v_registers,
+            i_register: chip8.i_register,
+            stack: &chip8.stack,
+            delay_timer: chip8.delay_timer,
+            sound_timer: chip8.sound_timer,
+        }
+    }
+}
+
+impl<'a> From<&'a Snapshot> for State<'a> {
+    fn from(snapshot: &'a Snapshot) -> Self {
+        State {
+            memory: &snapshot.memory,
+            screen: &snapshot.screen,
+            v_registers: &snapshot.v_registers,
+            i_register: snapshot.i_register,
+            stack: &snapshot.stack,
+            delay_timer: snapshot.delay_timer,
+            sound_timer: snapshot.sound_timer,
+        }
+    }
+}
+
+fn change<T: PartialEq>(first: T, second: T) -> Change<T> {
+    (first != second).then_some((first, second))
+}
+
+pub(crate) fn diff(first: State, second: State) -> StateDiff {
+    let v_registers = first
+        .v_registers
+        .iter()
+        .zip(second.v_registers)
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(x, (&a, &b))| (x, a, b))
+        .collect();
+
+    // entries above both stack pointers are stale, so aren't compared
+    let depth = first.stack.sp.max(second.stack.sp) as usize;
+    let stack = first.stack.stack[..depth]
+        .iter()
+        .zip(&second.stack.stack[..depth])
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(level, (&a, &b))| (level, a, b))
+        .collect();
+
+    let mut memory: Vec<MemoryChange> = Vec::new();
+    let pairs = first.memory.data.iter().zip(&second.memory.data);
+    for (address, (&a, &b)) in pairs.enumerate() {
+        if a == b {
+            continue;
+        }
+        match memory.last_mut() {
+            Some(run) if run.start as usize + run.first.len() == address => {
+                run.first.push(a);
+                run.second.push(b);
+            }
+            _ => memory.push(MemoryChange {
+                start: address as u16,
+                first: alloc::vec![a],
+                second: alloc::vec![b],
+            }),
+        }
+    }
+
+    let height = first.screen.height.max(second.screen.height);
+    let display_rows = (0..height)
+        .filter(|&y| first.screen.rows[y] != second.screen.rows[y])
+        .map(|y| (y, first.screen.rows[y], second.screen.rows[y]))
+        .collect();
+
+    StateDiff {
+        pc: change(first.memory.pc, second.memory.pc),
+        i_register: change(first.i_register, second.i_register),
+        v_registers,
+        sp: change(first.stack.sp, second.stack.sp),
+        stack,
+        delay_timer: change(first.delay_timer, second.delay_timer),
+        sound_timer: change(first.sound_timer, second.sound_timer),
+        memory,
+        display_height: change(first.screen.height, second.screen.height),
+        display_rows,
+    }
+}
+
+/// One line per difference, first value then second.
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((a, b)) = self.pc {
+            writeln!(f, "PC: {:#05X} -> {:#05X}", a, b)?;
+        }
+        if let Some((a, b)) = self.i_register {
+            writeln!(f, "I: {:#05X} -> {:#05X}", a, b)?;
+        }
+        for (x, a, b) in &self.v_registers {
+            writeln!(f, "V{:X}: {:#04X} -> {:#04X}", x, a, b)?;
+        }
+        if let Some((a, b)) = self.sp {
+            writeln!(f, "SP: {} -> {}", a, b)?;
+        }
+        for (level, a, b) in &self.stack {
+            writeln!(f, "stack[{}]: {:#05X} -> {:#05X}", level, a, b)?;
+        }
+        if let Some((a, b)) = self.delay_timer {
+            writeln!(f, "delay timer: {} -> {}", a, b)?;
+        }
+        if let Some((a, b)) = self.sound_timer {
+            writeln!(f, "sound timer: {} -> {}", a, b)?;
+        }
+        for run in &self.memory {
+            write!(f, "memory {:#05X}:", run.start)?;
+            for byte in &run.first {
+                write!(f, " {:02X}", byte)?;
+            }
+            write!(f, " ->")?;
+            for byte in &run.second {
+                write!(f, " {:02X}", byte)?;
+            }
+            writeln!(f)?;
+        }
+        if let Some((a, b)) = self.display_height {
+            writeln!(f, "display height: {} -> {}", a, b)?;
+        }
+        for (y, a, b) in &self.display_rows {
+            writeln!(f, "display row {}: {:016X} -> {:016X}", y, a, b)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Chip8;
+
+    #[test]
+    fn test_diff_reports_changed_state() {
+        let mut first = Chip8::new();
+        first.init();
+        first
+            .load_rom(&[0x60, 0x05, 0xA3, 0x00, 0xF0, 0x55, 0xD0, 0x01])
+            .unwrap();
+        let mut second = Chip8::new();
+        second.init();
+        second
+            .load_rom(&[0x60, 0x07, 0xA3, 0x00, 0xF0, 0x55, 0xD0, 0x01])
+            .unwrap();
+        assert!(first.snapshot().diff(&first.snapshot()).is_empty());
+
+        for _ in 0..4 {
+            first.cycle();
+            second.cycle();
+        }
+        let diff = first.diff(&second);
+        assert_eq!(diff.pc, None);
+        assert_eq!(diff.v_registers, [(0, 0x05, 0x07)]);
+        // the ROM's first byte, then the byte FX55 stored at 0x300
+        assert_eq!(diff.memory.len(), 2);
+        assert_eq!(diff.memory[0].start, 0x201);
+        assert_eq!(
+            (diff.memory[1].first[0], diff.memory[1].second[0]),
+            (0x05, 0x07)
+        );
+        // drawn at (5, 5) in one and (7, 7) in the other
+        assert_eq!(diff.display_rows.len(), 2);
+        assert!(diff.to_string().contains("V0: 0x05 -> 0x07"));
+    }
+}
@@ -0,0 +1,31 @@
+//! The HP-48's RPL user flags, which SUPER-CHIP programs save V registers to
+//! with FX75 and read back with FX85, mostly for high scores.
+
+/// Flags FX75 and FX85 can reach; SUPER-CHIP had 8, XO-CHIP has 16.
+pub const NUM_FLAGS: usize = 16;
+
+/// Where the RPL user flags live, see `Chip8::set_flag_storage`. A frontend
+/// can keep them in a file so they last from one session to the next.
+pub trait FlagStorage {
+    /// The flags as last saved, or all zero if they never were.
+    fn load(&mut self) -> [u8; NUM_FLAGS];
+
+    /// Replaces the saved flags.
+    fn save(&mut self, flags: &[u8; NUM_FLAGS]);
+}
+
+/// Keeps the flags for as long as the emulator lives. The default.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFlags {
+    flags: [u8; NUM_FLAGS],
+}
+
+impl FlagStorage for MemoryFlags {
+    fn load(&mut self) -> [u8; NUM_FLAGS] {
+        self.flags
+    }
+
+    fn save(&mut self, flags: &[u8; NUM_FLAGS]) {
+        self.flags = *flags;
+    }
+}
@@ -0,0 +1,133 @@
+//! Fontsets for the hex digit glyphs FX29 points at. Each is sixteen glyphs
+//! of five rows, one byte per row with the pixels in the top four bits.
+//! Interpreters shipped different glyph shapes, and some ROMs (and players)
+//! expect a particular one; load one with `Chip8::load_fontset`.
+
+/// Size of a fontset in bytes.
+pub const FONTSET_SIZE: usize = 80;
+/// Where `BIG`, the large font FX30 points at, is loaded, just after the
+/// small one.
+pub const BIG_FONT_ADDRESS: u16 = FONTSET_SIZE as u16;
+
+pub type Fontset = [u8; FONTSET_SIZE];
+
+/// The CHIP-48 font, the most common one and the default.
+pub const CHIP48: Fontset = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// The COSMAC VIP interpreter's font.
+pub const VIP: Fontset = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// The font of the Octo assembler and emulator.
+pub const OCTO: Fontset = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// The DREAM 6800's CHIPOS font, three pixels wide.
+pub const DREAM_6800: Fontset = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+/// The ETI-660's font, three pixels wide.
+pub const ETI_660: Fontset = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+/// The SUPER-CHIP's large font, 8x10 glyphs of ten bytes each, with Octo's
+/// glyphs for A-F (SUPER-CHIP itself only had 0-9).
+pub const BIG: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
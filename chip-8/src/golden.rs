@@ -0,0 +1,140 @@
+//! Golden-image tests: ROMs are run for a fixed number of cycles and the
+//! display is compared with a text snapshot checked in under `testdata/`.
+//! Run the tests with `BLESS=1` to write the snapshots from the current output.
+
+use std::fs;
+use std::path::PathBuf;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::instruction::Instruction::{self, *};
+use crate::{Chip8, SCREEN_WIDTH};
+
+/// The display as text, one line per row with `#` for lit pixels.
+pub fn snapshot(chip8: &Chip8) -> String {
+    let mut text = String::new();
+    for row in chip8.get_display().chunks(SCREEN_WIDTH) {
+        text.extend(row.iter().map(|&pixel| if pixel { '#' } else { '.' }));
+        text.push('\n');
+    }
+    text
+}
+
+/// Compares the display with `testdata/<name>.txt`, listing the rows that differ.
+pub fn assert_golden(chip8: &Chip8, name: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata")
+        .join(name)
+        .with_extension("txt");
+    let actual = snapshot(chip8);
+    if std::env::var_os("BLESS").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {} (run with BLESS=1 to create it)", path.display(), e));
+    if expected == actual {
+        return;
+    }
+    let differing: Vec<usize> = (0..expected.lines().count().max(actual.lines().count()))
+        .filter(|&row| expected.lines().nth(row) != actual.lines().nth(row))
+        .collect();
+    panic!(
+        "display differs from {} in rows {:?}\n{}",
+        path.display(),
+        differing,
+        actual
+    );
+}
+
+fn run(rom: &[u8], cycles: usize) -> Chip8 {
+    let mut chip8 = Chip8::new();
+    chip8.init();
+    chip8.seed_rng(0);
+    chip8.load_rom(rom).unwrap();
+    for _ in 0..cycles {
+        chip8.cycle();
+    }
+    chip8
+}
+
+fn assemble(program: &[Instruction]) -> Vec<u8> {
+    program
+        .iter()
+        .flat_map(|instruction| instruction.encode().to_be_bytes())
+        .collect()
+}
+
+#[test]
+fn test_ibm_logo() {
+    let chip8 = run(include_bytes!("../../roms/IBM Logo.ch8"), 200);
+    assert_golden(&chip8, "ibm_logo");
+}
+
+#[test]
+fn test_font_glyphs() {
+    // draws the sixteen hex digits in two rows of eight
+    let mut program = Vec::new();
+    for digit in 0..16u8 {
+        program.extend([
+            LoadImm {
+                x: 0,
+                nn: 2 + digit % 8 * 8,
+            },
+            LoadImm {
+                x: 1,
+                nn: 4 + digit / 8 * 8,
+            },
+            LoadImm { x: 2, nn: digit },
+            LoadFont { x: 2 },
+            Draw { x: 0, y: 1, n: 5 },
+        ]);
+    }
+    let chip8 = run(&assemble(&program), program.len());
+    assert_golden(&chip8, "font_glyphs");
+}
+
+#[test]
+fn test_big_font_glyphs() {
+    // draws the sixteen large hex digits in two rows of eight
+    let mut program = Vec::new();
+    for digit in 0..16u8 {
+        program.extend([
+            LoadImm {
+                x: 0,
+                nn: digit % 8 * 8,
+            },
+            LoadImm {
+                x: 1,
+                nn: 4 + digit / 8 * 14,
+            },
+            LoadImm { x: 2, nn: digit },
+            LoadBigFont { x: 2 },
+            Draw { x: 0, y: 1, n: 10 },
+        ]);
+    }
+    let chip8 = run(&assemble(&program), program.len());
+    assert_golden(&chip8, "big_font_glyphs");
+}
+
+#[test]
+fn test_sprites_clip_and_collide() {
+    let program = [
+        // the "8" glyph at the bottom right corner is clipped
+        LoadImm { x: 0, nn: 60 },
+        LoadImm { x: 1, nn: 29 },
+        LoadImm { x: 2, nn: 8 },
+        LoadFont { x: 2 },
+        Draw { x: 0, y: 1, n: 5 },
+        // drawing "0" over "8" erases their common pixels
+        LoadImm { x: 0, nn: 10 },
+        LoadImm { x: 1, nn: 10 },
+        Draw { x: 0, y: 1, n: 5 },
+        LoadImm { x: 2, nn: 0 },
+        LoadFont { x: 2 },
+        Draw { x: 0, y: 1, n: 5 },
+    ];
+    let chip8 = run(&assemble(&program), program.len());
+    assert_eq!(chip8.v_registers()[0xF], 1);
+    assert_golden(&chip8, "sprites_clip_and_collide");
+}
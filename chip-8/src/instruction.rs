@@ -0,0 +1,340 @@
+//! Decoded CHIP-8 instructions, shared by the interpreter and tools such as
+//! disassemblers and debuggers.
+
+use core::fmt;
+
+/// A decoded instruction. `x` and `y` are register numbers, `nn` an 8-bit
+/// immediate and addresses are 12 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// `00E0`: clear the display.
+    ClearScreen,
+    /// `00EE`: return from a subroutine.
+    Return,
+    /// `00FD`: exit the interpreter, halting the machine (from SUPER-CHIP).
+    Exit,
+    /// `1NNN`: jump to NNN.
+    Jump(u16),
+    /// `2NNN`: call the subroutine at NNN.
+    Call(u16),
+    /// `3XNN`: skip the next instruction if Vx == NN.
+    SkipEqImm { x: u8, nn: u8 },
+    /// `4XNN`: skip the next instruction if Vx != NN.
+    SkipNeImm { x: u8, nn: u8 },
+    /// `5XY0`: skip the next instruction if Vx == Vy.
+    SkipEqReg { x: u8, y: u8 },
+    /// `6XNN`: Vx = NN.
+    LoadImm { x: u8, nn: u8 },
+    /// `7XNN`: Vx += NN, without carry.
+    AddImm { x: u8, nn: u8 },
+    /// `8XY0`: Vx = Vy.
+    Move { x: u8, y: u8 },
+    /// `8XY1`: Vx |= Vy.
+    Or { x: u8, y: u8 },
+    /// `8XY2`: Vx &= Vy.
+    And { x: u8, y: u8 },
+    /// `8XY3`: Vx ^= Vy.
+    Xor { x: u8, y: u8 },
+    /// `8XY4`: Vx += Vy, VF = carry.
+    AddReg { x: u8, y: u8 },
+    /// `8XY5`: Vx -= Vy, VF = not borrow.
+    Sub { x: u8, y: u8 },
+    /// `8XY6`: Vx >>= 1, VF = the bit shifted out.
+    ShiftRight { x: u8, y: u8 },
+    /// `8XY7`: Vx = Vy - Vx, VF = not borrow.
+    SubReverse { x: u8, y: u8 },
+    /// `8XYE`: Vx <<= 1, VF = the bit shifted out.
+    ShiftLeft { x: u8, y: u8 },
+    /// `9XY0`: skip the next instruction if Vx != Vy.
+    SkipNeReg { x: u8, y: u8 },
+    /// `ANNN`: I = NNN.
+    LoadI(u16),
+    /// `BNNN`: jump to NNN + V0.
+    JumpV0(u16),
+    /// `CXNN`: Vx = random byte AND NN.
+    Random { x: u8, nn: u8 },
+    /// `DXYN`: draw the N-byte sprite at I at (Vx, Vy), VF = collision.
+    Draw { x: u8, y: u8, n: u8 },
+    /// `EX9E`: skip the next instruction if the key in Vx is held.
+    SkipKeyPressed { x: u8 },
+    /// `EXA1`: skip the next instruction if the key in Vx is not held.
+    SkipKeyNotPressed { x: u8 },
+    /// `EXF2`: skip the next instruction if the key in Vx is held on the
+    /// second keypad (from CHIP-8X).
+    SkipKey2Pressed { x: u8 },
+    /// `EXF5`: skip the next instruction if the key in Vx is not held on the
+    /// second keypad (from CHIP-8X).
+    SkipKey2NotPressed { x: u8 },
+    /// `F002`: load the 16-byte audio pattern at I (from XO-CHIP).
+    LoadAudio,
+    /// `FX07`: Vx = delay timer.
+    LoadDelay { x: u8 },
+    /// `FX0A`: wait for a key press and store it in Vx.
+    WaitKey { x: u8 },
+    /// `FX15`: delay timer = Vx.
+    SetDelay { x: u8 },
+    /// `FX18`: sound timer = Vx.
+    SetSound { x: u8 },
+    /// `FX1E`: I += Vx.
+    AddI { x: u8 },
+    /// `FX29`: I = address of the font glyph for the digit in Vx.
+    LoadFont { x: u8 },
+    /// `FX30`: I = address of the large font glyph for the digit in Vx.
+    LoadBigFont { x: u8 },
+    /// `FX33`: store the decimal digits of Vx at I, I+1 and I+2.
+    StoreBcd { x: u8 },
+    /// `FX3A`: audio pattern pitch = Vx (from XO-CHIP).
+    SetPitch { x: u8 },
+    /// `FX55`: store V0..=Vx at I.
+    StoreRegisters { x: u8 },
+    /// `FX65`: load V0..=Vx from I.
+    LoadRegisters { x: u8 },
+    /// `FX75`: save V0..=Vx to the RPL user flags.
+    SaveFlags { x: u8 },
+    /// `FX85`: load V0..=Vx from the RPL user flags.
+    LoadFlags { x: u8 },
+}
+
+/// Decodes an opcode, or returns `None` if it isn't a CHIP-8 instruction.
+pub fn decode(opcode: u16) -> Option<Instruction> {
+    use Instruction::*;
+
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let nn = (opcode & 0x00FF) as u8;
+    let nnn = opcode & 0x0FFF;
+
+    let instruction = match (opcode >> 12, x, y, n) {
+        (0, 0, 0xE, 0) => ClearScreen,
+        (0, 0, 0xE, 0xE) => Return,
+        (0, 0, 0xF, 0xD) => Exit,
+        (1, _, _, _) => Jump(nnn),
+        (2, _, _, _) => Call(nnn),
+        (3, _, _, _) => SkipEqImm { x, nn },
+        (4, _, _, _) => SkipNeImm { x, nn },
+        (5, _, _, 0) => SkipEqReg { x, y },
+        (6, _, _, _) => LoadImm { x, nn },
+        (7, _, _, _) => AddImm { x, nn },
+        (8, _, _, 0) => Move { x, y },
+        (8, _, _, 1) => Or { x, y },
+        (8, _, _, 2) => And { x, y },
+        (8, _, _, 3) => Xor { x, y },
+        (8, _, _, 4) => AddReg { x, y },
+        (8, _, _, 5) => Sub { x, y },
+        (8, _, _, 6) => ShiftRight { x, y },
+        (8, _, _, 7) => SubReverse { x, y },
+        (8, _, _, 0xE) => ShiftLeft { x, y },
+        (9, _, _, 0) => SkipNeReg { x, y },
+        (0xA, _, _, _) => LoadI(nnn),
+        (0xB, _, _, _) => JumpV0(nnn),
+        (0xC, _, _, _) => Random { x, nn },
+        (0xD, _, _, _) => Draw { x, y, n },
+        (0xE, _, 9, 0xE) => SkipKeyPressed { x },
+        (0xE, _, 0xA, 1) => SkipKeyNotPressed { x },
+        (0xE, _, 0xF, 2) => SkipKey2Pressed { x },
+        (0xE, _, 0xF, 5) => SkipKey2NotPressed { x },
+        (0xF, 0, 0, 2) => LoadAudio,
+        (0xF, _, 0, 7) => LoadDelay { x },
+        (0xF, _, 0, 0xA) => WaitKey { x },
+        (0xF, _, 1, 5) => SetDelay { x },
+        (0xF, _, 1, 8) => SetSound { x },
+        (0xF, _, 1, 0xE) => AddI { x },
+        (0xF, _, 2, 9) => LoadFont { x },
+        (0xF, _, 3, 0) => LoadBigFont { x },
+        (0xF, _, 3, 3) => StoreBcd { x },
+        (0xF, _, 3, 0xA) => SetPitch { x },
+        (0xF, _, 5, 5) => StoreRegisters { x },
+        (0xF, _, 6, 5) => LoadRegisters { x },
+        (0xF, _, 7, 5) => SaveFlags { x },
+        (0xF, _, 8, 5) => LoadFlags { x },
+        _ => return None,
+    };
+    Some(instruction)
+}
+
+impl Instruction {
+    /// The opcode pattern this instruction belongs to, e.g. `8XY4`.
+    pub fn pattern(self) -> &'static str {
+        use Instruction::*;
+
+        match self {
+            ClearScreen => "00E0",
+            Return => "00EE",
+            Exit => "00FD",
+            Jump(_) => "1NNN",
+            Call(_) => "2NNN",
+            SkipEqImm { .. } => "3XNN",
+            SkipNeImm { .. } => "4XNN",
+            SkipEqReg { .. } => "5XY0",
+            LoadImm { .. } => "6XNN",
+            AddImm { .. } => "7XNN",
+            Move { .. } => "8XY0",
+            Or { .. } => "8XY1",
+            And { .. } => "8XY2",
+            Xor { .. } => "8XY3",
+            AddReg { .. } => "8XY4",
+            Sub { .. } => "8XY5",
+            ShiftRight { .. } => "8XY6",
+            SubReverse { .. } => "8XY7",
+            ShiftLeft { .. } => "8XYE",
+            SkipNeReg { .. } => "9XY0",
+            LoadI(_) => "ANNN",
+            JumpV0(_) => "BNNN",
+            Random { .. } => "CXNN",
+            Draw { .. } => "DXYN",
+            SkipKeyPressed { .. } => "EX9E",
+            SkipKeyNotPressed { .. } => "EXA1",
+            SkipKey2Pressed { .. } => "EXF2",
+            SkipKey2NotPressed { .. } => "EXF5",
+            LoadAudio => "F002",
+            LoadDelay { .. } => "FX07",
+            WaitKey { .. } => "FX0A",
+            SetDelay { .. } => "FX15",
+            SetSound { .. } => "FX18",
+            AddI { .. } => "FX1E",
+            LoadFont { .. } => "FX29",
+            LoadBigFont { .. } => "FX30",
+            StoreBcd { .. } => "FX33",
+            SetPitch { .. } => "FX3A",
+            StoreRegisters { .. } => "FX55",
+            LoadRegisters { .. } => "FX65",
+            SaveFlags { .. } => "FX75",
+            LoadFlags { .. } => "FX85",
+        }
+    }
+
+    /// The opcode this instruction decodes from.
+    pub fn encode(self) -> u16 {
+        use Instruction::*;
+
+        let xy = |high: u16, x: u8, y: u8, low: u16| {
+            high << 12 | u16::from(x & 0xF) << 8 | u16::from(y & 0xF) << 4 | low
+        };
+        let xnn = |high: u16, x: u8, nn: u8| high << 12 | u16::from(x & 0xF) << 8 | u16::from(nn);
+        let fx = |x: u8, low: u16| 0xF000 | u16::from(x & 0xF) << 8 | low;
+
+        match self {
+            ClearScreen => 0x00E0,
+            Return => 0x00EE,
+            Exit => 0x00FD,
+            Jump(nnn) => 0x1000 | nnn & 0x0FFF,
+            Call(nnn) => 0x2000 | nnn & 0x0FFF,
+            SkipEqImm { x, nn } => xnn(3, x, nn),
+            SkipNeImm { x, nn } => xnn(4, x, nn),
+            SkipEqReg { x, y } => xy(5, x, y, 0),
+            LoadImm { x, nn } => xnn(6, x, nn),
+            AddImm { x, nn } => xnn(7, x, nn),
+            Move { x, y } => xy(8, x, y, 0),
+            Or { x, y } => xy(8, x, y, 1),
+            And { x, y } => xy(8, x, y, 2),
+            Xor { x, y } => xy(8, x, y, 3),
+            AddReg { x, y } => xy(8, x, y, 4),
+            Sub { x, y } => xy(8, x, y, 5),
+            ShiftRight { x, y } => xy(8, x, y, 6),
+            SubReverse { x, y } => xy(8, x, y, 7),
+            ShiftLeft { x, y } => xy(8, x, y, 0xE),
+            SkipNeReg { x, y } => xy(9, x, y, 0),
+            LoadI(nnn) => 0xA000 | nnn & 0x0FFF,
+            JumpV0(nnn) => 0xB000 | nnn & 0x0FFF,
+            Random { x, nn } => xnn(0xC, x, nn),
+            Draw { x, y, n } => xy(0xD, x, y, u16::from(n & 0xF)),
+            SkipKeyPressed { x } => 0xE09E | u16::from(x & 0xF) << 8,
+            SkipKeyNotPressed { x } => 0xE0A1 | u16::from(x & 0xF) << 8,
+            SkipKey2Pressed { x } => 0xE0F2 | u16::from(x & 0xF) << 8,
+            SkipKey2NotPressed { x } => 0xE0F5 | u16::from(x & 0xF) << 8,
+            LoadAudio => 0xF002,
+            LoadDelay { x } => fx(x, 0x07),
+            WaitKey { x } => fx(x, 0x0A),
+            SetDelay { x } => fx(x, 0x15),
+            SetSound { x } => fx(x, 0x18),
+            AddI { x } => fx(x, 0x1E),
+            LoadFont { x } => fx(x, 0x29),
+            LoadBigFont { x } => fx(x, 0x30),
+            StoreBcd { x } => fx(x, 0x33),
+            SetPitch { x } => fx(x, 0x3A),
+            StoreRegisters { x } => fx(x, 0x55),
+            LoadRegisters { x } => fx(x, 0x65),
+            SaveFlags { x } => fx(x, 0x75),
+            LoadFlags { x } => fx(x, 0x85),
+        }
+    }
+}
+
+/// Formats the instruction as an assembly mnemonic, e.g. `DRW V0, V1, 5`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            ClearScreen => write!(f, "CLS"),
+            Return => write!(f, "RET"),
+            Exit => write!(f, "EXIT"),
+            Jump(nnn) => write!(f, "JP {:#05X}", nnn),
+            Call(nnn) => write!(f, "CALL {:#05X}", nnn),
+            SkipEqImm { x, nn } => write!(f, "SE V{:X}, {:#04X}", x, nn),
+            SkipNeImm { x, nn } => write!(f, "SNE V{:X}, {:#04X}", x, nn),
+            SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            LoadImm { x, nn } => write!(f, "LD V{:X}, {:#04X}", x, nn),
+            AddImm { x, nn } => write!(f, "ADD V{:X}, {:#04X}", x, nn),
+            Move { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            ShiftRight { x, .. } => write!(f, "SHR V{:X}", x),
+            SubReverse { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            ShiftLeft { x, .. } => write!(f, "SHL V{:X}", x),
+            SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LoadI(nnn) => write!(f, "LD I, {:#05X}", nnn),
+            JumpV0(nnn) => write!(f, "JP V0, {:#05X}", nnn),
+            Random { x, nn } => write!(f, "RND V{:X}, {:#04X}", x, nn),
+            Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            SkipKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            SkipKey2Pressed { x } => write!(f, "SKP2 V{:X}", x),
+            SkipKey2NotPressed { x } => write!(f, "SKNP2 V{:X}", x),
+            LoadAudio => write!(f, "AUDIO"),
+            LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            AddI { x } => write!(f, "ADD I, V{:X}", x),
+            LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            LoadBigFont { x } => write!(f, "LD HF, V{:X}", x),
+            StoreBcd { x } => write!(f, "LD B, V{:X}", x),
+            SetPitch { x } => write!(f, "LD PITCH, V{:X}", x),
+            StoreRegisters { x } => write!(f, "LD [I], V{:X}", x),
+            LoadRegisters { x } => write!(f, "LD V{:X}, [I]", x),
+            SaveFlags { x } => write!(f, "LD R, V{:X}", x),
+            LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_decode_round_trips_every_opcode() {
+        for opcode in 0..=u16::MAX {
+            if let Some(instruction) = decode(opcode) {
+                assert_eq!(instruction.encode(), opcode, "{}", instruction);
+            }
+        }
+        assert_eq!(decode(0x0123), None);
+        assert_eq!(decode(0x5121), None);
+    }
+
+    #[test]
+    fn test_display_mnemonics() {
+        let draw = decode(0xD125).unwrap();
+        assert_eq!(draw, Instruction::Draw { x: 1, y: 2, n: 5 });
+        assert_eq!(draw.to_string(), "DRW V1, V2, 5");
+        assert_eq!(decode(0xA2F0).unwrap().to_string(), "LD I, 0x2F0");
+        assert_eq!(decode(0xFA65).unwrap().to_string(), "LD VA, [I]");
+    }
+}
@@ -0,0 +1,181 @@
+//! Undo records for stepping backwards one instruction at a time, see
+//! `Chip8::set_journal_capacity`. Each record keeps the registers, timers
+//! and generator state from before an instruction, plus the old value of
+//! whatever memory, stack slot, display rows or audio it changed, which is
+//! far smaller than a `Snapshot`.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::audio::PATTERN_SIZE;
+use crate::instruction::Instruction;
+use crate::rng::XorShift;
+use crate::{Chip8, Fault, NUM_REGISTERS, STACK_SIZE};
+
+/// Something an instruction overwrote, with the value it had before.
+#[derive(Debug, Clone, Copy)]
+enum Change {
+    Memory { address: usize, old: u8 },
+    Stack { slot: usize, old: u16 },
+    Row { y: usize, old: u64 },
+    Pattern { old: Option<Pattern> },
+    Pitch { old: u8 },
+}
+
+type Pattern = [u8; PATTERN_SIZE];
+
+#[derive(Debug, Clone)]
+struct Entry {
+    pc: u16,
+    i_register: u16,
+    sp: u16,
+    v_registers: [u8; NUM_REGISTERS],
+    delay_timer: u8,
+    sound_timer: u8,
+    rng: XorShift,
+    waiting_for_frame: bool,
+    halted: bool,
+    fault: Option<Fault>,
+    changes: Vec<Change>,
+}
+
+/// The undo records of the last `capacity` instructions, oldest first.
+#[derive(Debug, Clone)]
+pub(crate) struct Journal {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+    // memory writes belong to the newest entry while its instruction runs
+    open: bool,
+}
+
+impl Journal {
+    pub fn new(capacity: usize) -> Self {
+        Journal {
+            entries: VecDeque::new(),
+            capacity,
+            open: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Starts a record for the instruction at `pc`, about to run on
+    /// `chip8`. `None` for an unknown opcode, which only moves PC on.
+    pub fn begin(chip8: &mut Chip8, pc: u16, instruction: Option<Instruction>) {
+        let mut changes = Vec::new();
+        match instruction {
+            Some(Instruction::ClearScreen) => {
+                let rows = chip8.screen.rows.iter().enumerate();
+                changes.extend(
+                    rows.filter(|&(_, &old)| old != 0)
+                        .map(|(y, &old)| Change::Row { y, old }),
+                );
+            }
+            Some(Instruction::Draw { y, n, .. }) => {
+                let height = chip8.screen.height;
+                let top = chip8.v_registers[y as usize] as usize % height;
+                let bottom = if chip8.quirks.wrap_sprites {
+                    top + n as usize
+                } else {
+                    (top + n as usize).min(height)
+                };
+                changes.extend((top..bottom).map(|y| Change::Row {
+                    y: y % height,
+                    old: chip8.screen.rows[y % height],
+                }));
+            }
+            Some(Instruction::Call(_)) if (chip8.stack.sp as usize) < STACK_SIZE => {
+                let slot = chip8.stack.sp as usize;
+                changes.push(Change::Stack {
+                    slot,
+                    old: chip8.stack.stack[slot],
+                });
+            }
+            Some(Instruction::LoadAudio) => changes.push(Change::Pattern {
+                old: chip8.audio_pattern,
+            }),
+            Some(Instruction::SetPitch { .. }) => changes.push(Change::Pitch { old: chip8.pitch }),
+            _ => {}
+        }
+        let entry = Entry {
+            pc,
+            i_register: chip8.i_register,
+            sp: chip8.stack.sp,
+            v_registers: chip8.v_registers,
+            delay_timer: chip8.delay_timer,
+            sound_timer: chip8.sound_timer,
+            rng: chip8.rng,
+            waiting_for_frame: chip8.waiting_for_frame,
+            halted: chip8.halted,
+            fault: chip8.fault,
+            changes,
+        };
+        let Some(journal) = chip8.journal.as_mut() else {
+            return;
+        };
+        if journal.entries.len() == journal.capacity {
+            journal.entries.pop_front();
+        }
+        journal.entries.push_back(entry);
+        journal.open = true;
+    }
+
+    /// Ends the record started by `begin`.
+    pub fn end(&mut self) {
+        self.open = false;
+    }
+
+    /// Notes that memory at `address` held `old` before the running
+    /// instruction wrote to it.
+    pub fn memory_written(&mut self, address: usize, old: u8) {
+        if !self.open {
+            return;
+        }
+        if let Some(entry) = self.entries.back_mut() {
+            entry.changes.push(Change::Memory { address, old });
+        }
+    }
+
+    /// Undoes the newest recorded instruction on `chip8`. Returns false if
+    /// there's nothing left to undo.
+    pub fn undo(chip8: &mut Chip8) -> bool {
+        let Some(entry) = chip8
+            .journal
+            .as_mut()
+            .and_then(|journal| journal.entries.pop_back())
+        else {
+            return false;
+        };
+        // newest first, in case an instruction wrote the same place twice
+        for change in entry.changes.into_iter().rev() {
+            match change {
+                Change::Memory { address, old } => chip8.store(address, old),
+                Change::Stack { slot, old } => chip8.stack.stack[slot] = old,
+                Change::Row { y, old } => chip8.screen.set_row(y, old),
+                Change::Pattern { old } => chip8.audio_pattern = old,
+                Change::Pitch { old } => chip8.pitch = old,
+            }
+        }
+        chip8.memory.pc = entry.pc;
+        chip8.i_register = entry.i_register;
+        chip8.stack.sp = entry.sp;
+        chip8.v_registers = entry.v_registers;
+        chip8.delay_timer = entry.delay_timer;
+        chip8.sound_timer = entry.sound_timer;
+        chip8.rng = entry.rng;
+        chip8.waiting_for_frame = entry.waiting_for_frame;
+        chip8.halted = entry.halted;
+        chip8.fault = entry.fault;
+        true
+    }
+}
@@ -1,31 +1,78 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+mod alu;
+pub mod audio;
+pub mod cheat;
+mod decode_cache;
+mod diagnostic;
+pub mod diff;
+pub mod flags;
+pub mod font;
+#[cfg(all(test, feature = "std"))]
+mod golden;
+pub mod instruction;
+mod journal;
+pub mod mmio;
+mod platform;
+mod profile;
+mod quirks;
+pub mod render;
+pub mod replay;
+mod rng;
+#[cfg(feature = "romdb")]
+pub mod romdb;
+mod snapshot;
+mod timing;
+
+pub use diagnostic::Diagnostic;
+pub use diff::StateDiff;
+pub use instruction::{Instruction, decode};
+pub use platform::Platform;
+pub use profile::Profile;
+pub use quirks::Quirks;
+pub use snapshot::{Rewind, Snapshot, SnapshotError};
+pub use timing::Timing;
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use audio::{DEFAULT_PITCH, PATTERN_SIZE, SquareWave};
+use core::ops::RangeInclusive;
+use decode_cache::DecodeCache;
+use flags::{FlagStorage, MemoryFlags};
+use font::{BIG_FONT_ADDRESS, FONTSET_SIZE, Fontset};
+use journal::Journal;
+use mmio::Peripheral;
+use render::Palette;
+use replay::{InputEvent, InputLog, InputMode, Player, Recorder};
+use rng::XorShift;
+
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
+/// Display height of the 64x64 HIRES CHIP-8 variant, see `Chip8::is_hires`.
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
 pub const NUM_KEYS: usize = 16;
+/// Size of a buffer passed to `Chip8::render_rgba` for a 64x32 display.
+pub const RGBA_BUFFER_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT * 4;
 const MEMORY_SIZE: usize = 4096;
-const NUM_REGISTERS: usize = 16;
+pub const NUM_REGISTERS: usize = 16;
 const START_ADDRESS: u16 = 0x200;
-const STACK_SIZE: usize = 16;
-
-const FONTSET_SIZE: usize = 80;
-const FONTSET: [u8; FONTSET_SIZE] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
+// HIRES ROMs begin with a jump over the patched interpreter at 0x200..0x2C0;
+// the program proper starts at HIRES_ENTRY
+const HIRES_SIGNATURE: [u8; 2] = [0x12, 0x60];
+const HIRES_ENTRY: u16 = 0x2C0;
+// the HIRES interpreter's clear screen, a machine code call on plain CHIP-8
+const HIRES_CLEAR_SCREEN: u16 = 0x0230;
+/// Subroutine calls that can be nested before the stack overflows.
+pub const STACK_SIZE: usize = 16;
+// warnings kept until taken; later ones are dropped
+const MAX_WARNINGS: usize = 16;
+/// Largest ROM that fits in memory above the start address.
+pub const MAX_ROM_SIZE: usize = MEMORY_SIZE - START_ADDRESS as usize;
 
+#[derive(Clone)]
 struct Stack {
     stack: [u16; STACK_SIZE],
     sp: u16,
@@ -40,6 +87,7 @@ impl Default for Stack {
     }
 }
 
+#[derive(Clone)]
 struct Memory {
     data: [u8; MEMORY_SIZE],
     pc: u16,
@@ -55,102 +103,378 @@ impl Default for Memory {
 }
 
 impl Memory {
-    fn init(&mut self) {
+    fn init(&mut self, fontset: &Fontset) {
         // load fontset into memory
-        self.data[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        self.data[..FONTSET_SIZE].copy_from_slice(fontset);
+        let big_font = BIG_FONT_ADDRESS as usize;
+        self.data[big_font..big_font + font::BIG.len()].copy_from_slice(&font::BIG);
         // set program counter to start address
         self.pc = START_ADDRESS;
     }
 
-    fn load_rom(&mut self, data: &[u8]) {
-        let start = START_ADDRESS as usize;
-        let end = start + data.len();
-        if end > MEMORY_SIZE {
-            panic!("ROM too large to fit in memory");
+    fn load_rom(&mut self, address: u16, data: &[u8]) -> Result<(), RomTooLarge> {
+        let start = address as usize;
+        let room = MEMORY_SIZE.saturating_sub(start);
+        if data.len() > room {
+            return Err(RomTooLarge {
+                len: data.len(),
+                room,
+                address,
+            });
         }
-        self.data[start..end].copy_from_slice(data);
+        self.data[start..start + data.len()].copy_from_slice(data);
+        Ok(())
     }
 
     fn fetch_opcode(&mut self) -> u16 {
         // opcode is stored in two consecutive bytes
-        let first_byte = self.data[self.pc as usize] as u16;
-        let second_byte = self.data[(self.pc + 1) as usize] as u16;
+        let first_byte = self.data[self.pc as usize % MEMORY_SIZE] as u16;
+        let second_byte = self.data[(self.pc as usize + 1) % MEMORY_SIZE] as u16;
         let opcode = first_byte << 8 | second_byte;
         // increment program counter by 2
         self.next();
         opcode
     }
 
+    // both wrap within memory, like fetching does
     fn next(&mut self) {
-        self.pc += 2;
+        self.pc = ((self.pc as usize + 2) % MEMORY_SIZE) as u16;
     }
 
     fn prev(&mut self) {
-        self.pc -= 2;
-    }
-
-    fn get_bytes(&self, start: u16, length: usize) -> &[u8] {
-        &self.data[start as usize..start as usize + length]
+        self.pc = ((self.pc as usize + MEMORY_SIZE - 2) % MEMORY_SIZE) as u16;
     }
 }
 
 impl Stack {
-    pub fn push(&mut self, value: u16) {
-        self.stack[self.sp as usize] = value;
+    /// Returns false if the stack is full.
+    pub fn push(&mut self, value: u16) -> bool {
+        let Some(slot) = self.stack.get_mut(self.sp as usize) else {
+            return false;
+        };
+        *slot = value;
         self.sp += 1;
+        true
+    }
+
+    /// Returns `None` if the stack is empty.
+    pub fn pop(&mut self) -> Option<u16> {
+        self.sp = self.sp.checked_sub(1)?;
+        Some(self.stack[self.sp as usize])
+    }
+}
+
+/// Set of display rows that changed since the last `Chip8::take_dirty` call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DirtyRows(u64);
+
+impl DirtyRows {
+    /// Every row of a display `height` rows tall, for a full redraw.
+    pub const fn all(height: usize) -> DirtyRows {
+        DirtyRows(u64::MAX >> (64 - height))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(&self, row: usize) -> bool {
+        row < 64 && self.0 & (1 << row) != 0
+    }
+
+    /// Iterates over the indices of the changed rows, top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..64).filter(|&row| self.contains(row))
+    }
+
+    /// First and last changed row (inclusive), if any.
+    pub fn bounds(&self) -> Option<(usize, usize)> {
+        if self.is_empty() {
+            return None;
+        }
+        Some((
+            self.0.trailing_zeros() as usize,
+            63 - self.0.leading_zeros() as usize,
+        ))
     }
 
-    pub fn pop(&mut self) -> u16 {
-        self.sp -= 1;
-        self.stack[self.sp as usize]
+    fn mark(&mut self, row: usize) {
+        self.0 |= 1 << row;
     }
 }
 
+// Drawing works on `rows`, one u64 per row with the leftmost pixel in the
+// most significant bit; `pixels` mirrors it for `Chip8::get_display`.
+#[derive(Clone)]
 struct Screen {
-    pixels: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    // room for the HIRES display; only the first `height` rows are used
+    rows: [u64; HIRES_SCREEN_HEIGHT],
+    pixels: [bool; SCREEN_WIDTH * HIRES_SCREEN_HEIGHT],
+    height: usize,
+    dirty: DirtyRows,
 }
 
 impl Default for Screen {
     fn default() -> Self {
         Screen {
-            pixels: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            rows: [0; HIRES_SCREEN_HEIGHT],
+            pixels: [false; SCREEN_WIDTH * HIRES_SCREEN_HEIGHT],
+            height: SCREEN_HEIGHT,
+            // nothing has been presented yet, so everything needs drawing
+            dirty: DirtyRows::all(SCREEN_HEIGHT),
         }
     }
 }
 
 impl Screen {
     pub fn clear(&mut self) {
+        self.rows.fill(0);
         self.pixels.fill(false);
+        self.dirty = DirtyRows::all(self.height);
+    }
+
+    fn set_height(&mut self, height: usize) {
+        self.height = height;
+        self.clear();
+    }
+
+    fn visible(&self) -> &[bool] {
+        &self.pixels[..SCREEN_WIDTH * self.height]
+    }
+
+    pub fn take_dirty(&mut self) -> DirtyRows {
+        core::mem::take(&mut self.dirty)
+    }
+
+    fn set_row(&mut self, y: usize, bits: u64) {
+        self.rows[y] = bits;
+        for (x, pixel) in self.pixels[y * SCREEN_WIDTH..][..SCREEN_WIDTH]
+            .iter_mut()
+            .enumerate()
+        {
+            *pixel = bits & (1 << (63 - x)) != 0;
+        }
+        self.dirty.mark(y);
+    }
+
+    fn packed_row(&self, y: usize) -> u64 {
+        self.rows[y]
     }
 
-    pub fn draw_sprite(&mut self, x: usize, y: usize, height: usize, sprite: &[u8]) -> bool {
+    /// XORs `sprite` onto the display with its top left corner at (`x`, `y`).
+    /// Pixels past the right and bottom edges are clipped, or with `wrap`
+    /// come back in on the left and top. Returns whether any lit pixel was
+    /// turned off.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8], wrap: bool) -> bool {
         let mut collision = false;
-        for row in 0..height {
-            if let Some(sprite_row_byte) = sprite.get(row) {
-                for col in 0..8 {
-                    let pixel_value = (sprite_row_byte >> (7 - col)) & 0x1;
-                    let screen_x = x + col;
-                    let screen_y = y + row;
-                    if screen_x >= SCREEN_WIDTH || screen_y >= SCREEN_HEIGHT {
-                        continue; // Skip pixels that are out of bounds
-                    }
-                    let index = screen_y * SCREEN_WIDTH + screen_x;
-                    if pixel_value == 1 {
-                        // Check for collision
-                        if self.pixels[index] {
-                            collision = true;
-                        }
+        let rows = if wrap { sprite.len() } else { self.height - y };
+        for (screen_y, &byte) in (y..y + rows).zip(sprite) {
+            let screen_y = screen_y % self.height;
+            // the byte moves from the top of the row to column x; anything
+            // past the right edge is shifted out, or rotated round to the left
+            let byte = (byte as u64) << (64 - 8);
+            let bits = if wrap {
+                byte.rotate_right(x as u32)
+            } else {
+                byte >> x
+            };
+            if bits == 0 {
+                continue;
+            }
+            let row = &mut self.rows[screen_y];
+            collision |= *row & bits != 0;
+            *row ^= bits;
+            self.dirty.mark(screen_y);
 
-                        // Toggle pixel
-                        self.pixels[index] ^= true;
-                    }
-                }
+            let mut toggled = bits;
+            while toggled != 0 {
+                let screen_x = toggled.leading_zeros() as usize;
+                self.pixels[screen_y * SCREEN_WIDTH + screen_x] ^= true;
+                toggled &= !(1 << (63 - screen_x));
             }
         }
         collision
     }
 }
 
+/// Pitch of the buzzer produced by `Chip8::generate_audio`.
+pub const BEEP_FREQUENCY: u32 = 440;
+
+/// What happens when an instruction reaches past the end of memory through I
+/// (DXYN, FX33, FX55 and FX65).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemoryAccess {
+    /// Wrap around to address 0, like the 12-bit address bus of the original hardware.
+    #[default]
+    Wrap,
+    /// Stop the emulator with a `Fault` without executing the instruction.
+    Fault,
+}
+
+/// What happens on an opcode that isn't a known instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownOpcode {
+    /// Skip it, as the original interpreter would have run it as machine code.
+    #[default]
+    Ignore,
+    /// Skip it, and keep a `Diagnostic` for `Chip8::take_warnings`.
+    Warn,
+    /// Stop the emulator with a `Fault` without executing anything.
+    Fault,
+}
+
+/// What a call to `Chip8::cycle` found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CycleResult {
+    /// The program is still going, though nothing may have run if it was
+    /// waiting for the frame or faulted.
+    Continue,
+    /// A DXYN drew a sprite, so there's a new frame to show. Under the
+    /// display-wait quirk that's the frame's only draw, so a frontend can
+    /// present it straight away rather than at its next refresh.
+    DrawCompleted,
+    /// The program has finished, see `Chip8::is_halted`. Further cycles do
+    /// nothing.
+    ProgramFinished,
+}
+
+/// Why the emulator stopped, see `Chip8::fault`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The instruction at `pc` would have accessed `address`, which is past
+    /// the end of memory.
+    MemoryOutOfRange { pc: u16, address: usize },
+    /// The call at `pc` was nested deeper than the 16 level stack.
+    StackOverflow { pc: u16 },
+    /// The return at `pc` had no call to return to.
+    StackUnderflow { pc: u16 },
+    /// `opcode` at `pc` isn't an instruction, under `UnknownOpcode::Fault`.
+    UnknownOpcode { pc: u16, opcode: u16 },
+}
+
+impl core::fmt::Display for Fault {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Fault::MemoryOutOfRange { pc, address } => write!(
+                f,
+                "instruction at {:#05X} accessed {:#06X}, past the end of memory",
+                pc, address
+            ),
+            Fault::StackOverflow { pc } => write!(
+                f,
+                "call at {:#05X} overflowed the stack of {} return addresses",
+                pc, STACK_SIZE
+            ),
+            Fault::StackUnderflow { pc } => {
+                write!(f, "return at {:#05X} without a matching call", pc)
+            }
+            Fault::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:#05X}", opcode, pc)
+            }
+        }
+    }
+}
+
+/// A ROM that doesn't fit in memory, returned by `Chip8::load_rom`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomTooLarge {
+    /// Size of the ROM in bytes.
+    pub len: usize,
+    /// Bytes of memory from the load address to the end.
+    pub room: usize,
+    pub address: u16,
+}
+
+impl core::fmt::Display for RomTooLarge {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "the ROM is {} bytes, but only {} fit at {:#05X}",
+            self.len, self.room, self.address
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RomTooLarge {}
+
+// address `offset` bytes after `start`, wrapped into memory
+fn wrap_address(start: u16, offset: usize) -> usize {
+    (start as usize + offset) % MEMORY_SIZE
+}
+
+/// Buzzer state change reported by `Chip8::tick_timers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundEvent {
+    Started,
+    Stopped,
+}
+
+/// What happened during a `Chip8::run_frame` call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameSummary {
+    /// Instructions executed. Fewer than requested when a draw waited for
+    /// the end of the frame, the emulator faulted or the program exited.
+    pub cycles: u32,
+    /// Display rows changed during the frame, taken as by `Chip8::take_dirty`.
+    pub dirty: DirtyRows,
+    pub sound_playing: bool,
+    pub sound_event: Option<SoundEvent>,
+    pub fault: Option<Fault>,
+}
+
+/// What a call to `Chip8::step` did, so frontends and tools don't have to
+/// compare the machine before and after to find out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepSummary {
+    /// Where the instruction was, or would have been if nothing ran.
+    pub pc: u16,
+    /// The opcode run, or `None` if nothing ran because the program had
+    /// finished, had faulted or was waiting for the frame.
+    pub opcode: Option<u16>,
+    /// The opcode decoded, `None` if nothing ran or it isn't an instruction.
+    pub instruction: Option<Instruction>,
+    /// Display rows the instruction changed.
+    pub dirty: DirtyRows,
+    /// An FX0A found no key held, so the program waits on it.
+    pub waiting_for_key: bool,
+    /// Values FX15 and FX18 set the timers to.
+    pub delay_timer_set: Option<u8>,
+    pub sound_timer_set: Option<u8>,
+    /// The fault the instruction stopped the emulator with.
+    pub fault: Option<Fault>,
+    /// What `cycle` would have returned.
+    pub result: CycleResult,
+}
+
+/// Host-side replacement for a subroutine, see `Chip8::hook_subroutine`.
+pub type SubroutineHook = Box<dyn FnMut(&mut Chip8)>;
+
+/// Watches every instruction the program runs, see
+/// `Chip8::set_instruction_hook`. `instruction` is `None` for an opcode that
+/// isn't one. Both methods do nothing by default.
+pub trait InstructionHook {
+    /// Called before the instruction at `pc` runs.
+    fn before(&mut self, chip8: &Chip8, pc: u16, opcode: u16, instruction: Option<Instruction>) {
+        let _ = (chip8, pc, opcode, instruction);
+    }
+
+    /// Called after the instruction at `pc` ran.
+    fn after(&mut self, chip8: &Chip8, pc: u16, opcode: u16, instruction: Option<Instruction>) {
+        let _ = (chip8, pc, opcode, instruction);
+    }
+}
+
+impl<T: InstructionHook + ?Sized> InstructionHook for Box<T> {
+    fn before(&mut self, chip8: &Chip8, pc: u16, opcode: u16, instruction: Option<Instruction>) {
+        (**self).before(chip8, pc, opcode, instruction);
+    }
+
+    fn after(&mut self, chip8: &Chip8, pc: u16, opcode: u16, instruction: Option<Instruction>) {
+        (**self).after(chip8, pc, opcode, instruction);
+    }
+}
+
 pub struct Chip8 {
     memory: Memory,
     screen: Screen,
@@ -158,8 +482,43 @@ pub struct Chip8 {
     i_register: u16,
     stack: Stack,
     pressed_keys: [bool; NUM_KEYS],
+    // the CHIP-8X's second keypad, read by EXF2 and EXF5
+    second_keypad: [bool; NUM_KEYS],
+    // set by the instructions that read a keypad, see take_keypad_checked
+    keypad_checked: bool,
     delay_timer: u8,
     sound_timer: u8,
+    // buzzer state as last reported by tick_timers
+    sound_active: bool,
+    beep: SquareWave,
+    // loaded by F002; until then programs only have the buzzer
+    audio_pattern: Option<[u8; PATTERN_SIZE]>,
+    pitch: u8,
+    rng: XorShift,
+    quirks: Quirks,
+    // set by DXYN under the display-wait quirk, cleared at the end of the frame
+    waiting_for_frame: bool,
+    // set by 00FD or a detected self-jump; nothing runs after it
+    halted: bool,
+    detect_finish: bool,
+    hooks: BTreeMap<u16, SubroutineHook>,
+    instruction_hook: Option<Box<dyn InstructionHook>>,
+    input: InputMode,
+    memory_access: MemoryAccess,
+    unknown_opcode: UnknownOpcode,
+    fault: Option<Fault>,
+    warnings: Vec<Diagnostic>,
+    timing: Timing,
+    // time carried over between run_microseconds calls, in nanoseconds;
+    // negative when the last instruction overran its slice
+    time_budget: i64,
+    profile: Option<Profile>,
+    journal: Option<Journal>,
+    decode_cache: Option<DecodeCache>,
+    fontset: Fontset,
+    load_address: u16,
+    flag_storage: Box<dyn FlagStorage>,
+    io: Option<(RangeInclusive<u16>, Box<dyn Peripheral>)>,
 }
 
 impl Default for Chip8 {
@@ -171,299 +530,1233 @@ impl Default for Chip8 {
             i_register: 0,
             stack: Stack::default(),
             pressed_keys: [false; NUM_KEYS],
+            second_keypad: [false; NUM_KEYS],
+            keypad_checked: false,
             delay_timer: 0,
             sound_timer: 0,
+            sound_active: false,
+            beep: SquareWave::new(BEEP_FREQUENCY, 0),
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            rng: XorShift::default(),
+            quirks: Quirks::default(),
+            waiting_for_frame: false,
+            halted: false,
+            detect_finish: false,
+            hooks: BTreeMap::new(),
+            instruction_hook: None,
+            input: InputMode::Live,
+            memory_access: MemoryAccess::default(),
+            unknown_opcode: UnknownOpcode::default(),
+            fault: None,
+            warnings: Vec::new(),
+            timing: Timing::default(),
+            time_budget: 0,
+            profile: None,
+            journal: None,
+            decode_cache: None,
+            fontset: font::CHIP48,
+            load_address: START_ADDRESS,
+            flag_storage: Box::new(MemoryFlags::default()),
+            io: None,
         }
     }
 }
 
+/// Whether `rom` is for the 64x64 HIRES variant, which `Chip8::load_rom`
+/// switches to automatically.
+pub fn is_hires_rom(rom: &[u8]) -> bool {
+    rom.starts_with(&HIRES_SIGNATURE)
+}
+
 impl Chip8 {
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// A new emulator with `platform`'s quirks and timing, see
+    /// `set_platform`.
+    pub fn with_platform(platform: Platform) -> Self {
+        let mut chip8 = Self::new();
+        chip8.set_platform(platform);
+        chip8
+    }
+
+    /// Switches to `platform`'s quirks and timing, replacing any set before.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.set_quirks(platform.quirks());
+        self.set_timing(platform.timing());
+    }
+
     pub fn init(&mut self) {
-        self.memory.init();
+        self.memory.init(&self.fontset);
+        self.invalidate_decode_cache();
+        self.clear_journal();
+    }
+
+    /// Replaces the digit glyphs FX29 points at, both in memory now and on
+    /// every later `init`. See `font` for the built-in ones.
+    pub fn load_fontset(&mut self, fontset: &Fontset) {
+        self.fontset = *fontset;
+        self.memory.data[..FONTSET_SIZE].copy_from_slice(fontset);
+        self.invalidate_decode_cache();
+    }
+
+    /// The fontset `init` loads, `font::CHIP48` unless replaced with
+    /// `load_fontset`.
+    pub fn fontset(&self) -> &Fontset {
+        &self.fontset
+    }
+
+    /// Loads a ROM at 0x200. ROMs for the 64x64 HIRES variant, recognised by
+    /// the `1260` jump they start with, switch the display to HIRES and start
+    /// at 0x2C0 instead. Fails without changing anything if the ROM is larger
+    /// than `MAX_ROM_SIZE`.
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), RomTooLarge> {
+        self.load_rom_at(START_ADDRESS, data)
+    }
+
+    /// Loads a ROM at `address` and starts execution there, for programs
+    /// written for interpreters that load them elsewhere, like the ETI 660's
+    /// 0x600. Fails without changing anything if the ROM doesn't fit in memory.
+    pub fn load_rom_at(&mut self, address: u16, data: &[u8]) -> Result<(), RomTooLarge> {
+        self.memory.load_rom(address, data)?;
+        self.invalidate_decode_cache();
+        self.clear_journal();
+        self.load_address = address;
+        self.memory.pc = address;
+        let hires = address == START_ADDRESS && is_hires_rom(data);
+        let height = if hires {
+            HIRES_SCREEN_HEIGHT
+        } else {
+            SCREEN_HEIGHT
+        };
+        if height != self.screen.height {
+            self.screen.set_height(height);
+        }
+        if hires {
+            self.memory.pc = HIRES_ENTRY;
+        }
+        Ok(())
+    }
+
+    /// Where the ROM was loaded, 0x200 unless loaded with `load_rom_at`.
+    pub fn load_address(&self) -> u16 {
+        self.load_address
+    }
+
+    /// Whether the loaded ROM runs in the 64x64 HIRES mode.
+    pub fn is_hires(&self) -> bool {
+        self.screen.height == HIRES_SCREEN_HEIGHT
+    }
+
+    /// Height of the display in pixels, `SCREEN_HEIGHT` or `HIRES_SCREEN_HEIGHT`.
+    pub fn display_height(&self) -> usize {
+        self.screen.height
+    }
+
+    /// Reseeds the random number generator used by CXNN, making runs reproducible.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = XorShift::new(seed);
+    }
+
+    /// Replaces the subroutine at `address` with a host closure. A `2NNN` call
+    /// to that address runs the closure instead and continues after the call,
+    /// as if the subroutine had returned immediately.
+    pub fn hook_subroutine(&mut self, address: u16, hook: impl FnMut(&mut Chip8) + 'static) {
+        self.hooks.insert(address, Box::new(hook));
+    }
+
+    /// Keeps the RPL user flags FX75 and FX85 use in `storage` instead of
+    /// in memory, for example so saved high scores survive a restart.
+    pub fn set_flag_storage(&mut self, storage: impl FlagStorage + 'static) {
+        self.flag_storage = Box::new(storage);
+    }
+
+    /// Routes the program's reads and writes of `range` through `device`,
+    /// replacing any device mapped before. Sprite data (DXYN) and FX65 read
+    /// through it and FX33 and FX55 write through it; instructions are
+    /// always fetched from RAM, and `read_byte` and `memory` show RAM as is.
+    pub fn map_io(&mut self, range: RangeInclusive<u16>, device: impl Peripheral + 'static) {
+        self.io = Some((range, Box::new(device)));
+    }
+
+    /// Removes the device mapped with `map_io`, returning whether there was one.
+    pub fn unmap_io(&mut self) -> bool {
+        self.io.take().is_some()
+    }
+
+    /// Removes a hook installed with `hook_subroutine`, returning whether one existed.
+    pub fn unhook_subroutine(&mut self, address: u16) -> bool {
+        self.hooks.remove(&address).is_some()
+    }
+
+    /// Calls `hook` before and after each instruction, replacing any hook
+    /// set before. Tracers and coverage tools can see every step this way
+    /// without changing how the program runs.
+    pub fn set_instruction_hook(&mut self, hook: impl InstructionHook + 'static) {
+        self.instruction_hook = Some(Box::new(hook));
+    }
+
+    /// Removes the hook set with `set_instruction_hook` and returns it.
+    pub fn take_instruction_hook(&mut self) -> Option<Box<dyn InstructionHook>> {
+        self.instruction_hook.take()
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn memory_access(&self) -> MemoryAccess {
+        self.memory_access
+    }
+
+    pub fn set_memory_access(&mut self, memory_access: MemoryAccess) {
+        self.memory_access = memory_access;
+    }
+
+    pub fn unknown_opcode(&self) -> UnknownOpcode {
+        self.unknown_opcode
+    }
+
+    pub fn set_unknown_opcode(&mut self, policy: UnknownOpcode) {
+        self.unknown_opcode = policy;
     }
 
-    pub fn load_rom(&mut self, data: &[u8]) {
-        self.memory.load_rom(data);
+    /// Registers and the code around PC, which a fault leaves on the
+    /// faulting instruction.
+    pub fn diagnose(&self) -> Diagnostic {
+        Diagnostic::new(self, self.memory.pc)
     }
 
+    /// Unknown opcodes skipped under `UnknownOpcode::Warn` since the last
+    /// call, oldest first. Only the first few are kept.
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        core::mem::take(&mut self.warnings)
+    }
+
+    /// The fault that stopped the emulator, if any. While set, `cycle` does nothing.
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    /// Clears a fault so execution can continue from the faulting instruction.
+    pub fn clear_fault(&mut self) {
+        self.fault = None;
+    }
+
+    /// Reads a byte of memory, or `None` if the address is out of range.
+    pub fn read_byte(&self, address: u16) -> Option<u8> {
+        self.memory.data.get(address as usize).copied()
+    }
+
+    /// All of memory, for viewers and dumps.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory.data
+    }
+
+    /// Writes a byte of memory, returning false if the address is out of range.
+    pub fn write_byte(&mut self, address: u16, value: u8) -> bool {
+        if address as usize >= MEMORY_SIZE {
+            return false;
+        }
+        self.store(address as usize, value);
+        true
+    }
+
+    // every memory write goes through here so decoded instructions stay current
+    fn store(&mut self, address: usize, value: u8) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.memory_written(address, self.memory.data[address]);
+        }
+        self.memory.data[address] = value;
+        if let Some(cache) = self.decode_cache.as_mut() {
+            cache.invalidate(address);
+        }
+    }
+
+    // a read by the program, which a mapped device may answer
+    fn load(&mut self, address: usize) -> u8 {
+        let stored = self.memory.data[address];
+        match self.io.as_mut() {
+            Some((range, device)) if range.contains(&(address as u16)) => {
+                device.read(address as u16, stored)
+            }
+            _ => stored,
+        }
+    }
+
+    // a write by the program, which a mapped device sees after RAM does
+    fn store_io(&mut self, address: usize, value: u8) {
+        self.store(address, value);
+        if let Some((range, device)) = self.io.as_mut()
+            && range.contains(&(address as u16))
+        {
+            device.write(address as u16, value);
+        }
+    }
+
+    fn invalidate_decode_cache(&mut self) {
+        if let Some(cache) = self.decode_cache.as_mut() {
+            cache.clear();
+        }
+    }
+
+    /// Reads `len` bytes starting at `address`, or `None` if any of them are
+    /// out of range.
+    pub fn read_range(&self, address: u16, len: usize) -> Option<&[u8]> {
+        let start = address as usize;
+        self.memory.data.get(start..start.checked_add(len)?)
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.memory.pc
+    }
+
+    pub fn i_register(&self) -> u16 {
+        self.i_register
+    }
+
+    pub fn v_registers(&self) -> &[u8; NUM_REGISTERS] {
+        &self.v_registers
+    }
+
+    /// Number of return addresses on the call stack.
+    pub fn sp(&self) -> u16 {
+        self.stack.sp
+    }
+
+    /// The return addresses on the call stack, outermost call first.
+    pub fn call_stack(&self) -> &[u16] {
+        &self.stack.stack[..self.stack.sp as usize]
+    }
+
+    /// Whether a draw is holding execution until the end of the frame, as
+    /// the `display_wait` quirk does. `cycle` does nothing meanwhile.
+    pub fn is_waiting_for_frame(&self) -> bool {
+        self.waiting_for_frame
+    }
+
+    /// Whether the program exited with 00FD or, with finish detection on,
+    /// jumped to itself. PC stays on that instruction and `cycle` does
+    /// nothing from then on.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Turns on treating a `1NNN` that jumps to itself as the end of the
+    /// program. Many ROMs finish that way, and a headless run can stop there
+    /// instead of spinning until its cycle budget runs out.
+    pub fn set_finish_detection(&mut self, enabled: bool) {
+        self.detect_finish = enabled;
+    }
+
+    pub fn finish_detection(&self) -> bool {
+        self.detect_finish
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.memory.pc = pc;
+    }
+
+    pub fn set_i_register(&mut self, value: u16) {
+        self.i_register = value;
+    }
+
+    /// Sets register V`index`. Panics if `index` is not below `NUM_REGISTERS`.
+    pub fn set_v_register(&mut self, index: usize, value: u8) {
+        self.v_registers[index] = value;
+    }
+
+    /// Sets the stack depth, returning false if it exceeds the stack size.
+    pub fn set_sp(&mut self, sp: u16) -> bool {
+        if sp as usize > STACK_SIZE {
+            return false;
+        }
+        self.stack.sp = sp;
+        true
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    /// The display, `SCREEN_WIDTH` pixels per row and `display_height` rows.
     pub fn get_display(&self) -> &[bool] {
-        &self.screen.pixels
+        self.screen.visible()
     }
 
-    pub fn tick_timers(&mut self) {
+    /// Display packed one `u64` per row, with the leftmost pixel in the most
+    /// significant bit (the same order as sprite bytes). Rows below
+    /// `display_height` are always zero.
+    pub fn get_display_packed(&self) -> [u64; HIRES_SCREEN_HEIGHT] {
+        core::array::from_fn(|y| {
+            if y < self.screen.height {
+                self.screen.packed_row(y)
+            } else {
+                0
+            }
+        })
+    }
+
+    /// Iterates over the display rows in packed form, top to bottom.
+    pub fn display_rows(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.screen.height).map(|y| self.screen.packed_row(y))
+    }
+
+    /// Renders the display into an RGBA8 buffer of four bytes per pixel:
+    /// `RGBA_BUFFER_SIZE` bytes, or twice that in HIRES mode.
+    pub fn render_rgba(&self, out: &mut [u8], palette: &Palette) {
+        render::render_rgba(self.screen.visible(), out, palette);
+    }
+
+    /// Returns the rows that changed since the previous call and resets the set.
+    pub fn take_dirty(&mut self) -> DirtyRows {
+        self.screen.take_dirty()
+    }
+
+    /// Advances the delay and sound timers by one 60 Hz tick. Returns a sound
+    /// event when the buzzer should start or stop for the coming frame.
+    pub fn tick_timers(&mut self) -> Option<SoundEvent> {
+        match &mut self.input {
+            // the recorded ticks drive the timers instead
+            InputMode::Replaying(player) => return player.sound_event.take(),
+            InputMode::Recording(recorder) => recorder.timer_tick(),
+            InputMode::Live => {}
+        }
+        self.tick()
+    }
+
+    fn tick(&mut self) -> Option<SoundEvent> {
+        // a timer tick marks the end of a frame
+        self.waiting_for_frame = false;
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
+
+        // the buzzer sounds for every tick the sound timer is non-zero
+        let playing = self.sound_timer > 0;
+        let event = if playing != self.sound_active {
+            self.sound_active = playing;
+            Some(if playing {
+                SoundEvent::Started
+            } else {
+                SoundEvent::Stopped
+            })
+        } else {
+            None
+        };
         if self.sound_timer > 0 {
-            if self.sound_timer == 1 {
-                // BEEP!
-            }
             self.sound_timer -= 1;
         }
+        event
     }
 
-    pub fn cycle(&mut self) {
-        let opcode = self.memory.fetch_opcode();
-        self.execute(opcode);
+    /// Whether the buzzer is sounding for the current frame.
+    pub fn is_sound_playing(&self) -> bool {
+        self.sound_active
     }
 
-    pub fn set_pressed_keys(&mut self, keys: [bool; NUM_KEYS]) {
-        self.pressed_keys = keys;
+    /// Fills `out` with the buzzer's square wave for the current frame, or
+    /// silence when it isn't sounding. Call once per frame after `tick_timers`
+    /// with `sample_rate / 60` samples. Output depends only on emulator state,
+    /// so replays produce identical audio.
+    pub fn generate_audio(&mut self, out: &mut [i16], sample_rate: u32) {
+        self.beep.set_sample_rate(sample_rate);
+        self.beep.fill_i16(out, self.sound_active);
     }
 
-    pub fn execute(&mut self, opcode: u16) {
-        // opcode split into 4 digits. Each is 4 bits
-        let digit1 = (opcode & 0xF000) >> 12;
-        let digit2 = (opcode & 0x0F00) >> 8;
-        let digit3 = (opcode & 0x00F0) >> 4;
-        let digit4 = opcode & 0x000F;
-
-        match (digit1, digit2, digit3, digit4) {
-            (0, 0, 0xE, 0) => {
-                // clear the display
-                self.screen.clear();
-            }
-            (0, 0, 0xE, 0xE) => {
-                // return from subroutine
-                let return_address = self.stack.pop();
-                self.memory.pc = return_address;
-            }
-            (1, _, _, _) => {
-                // jump to address NNN
-                let address = opcode & 0x0FFF;
-                self.memory.pc = address;
-            }
-            (2, _, _, _) => {
-                // call subroutine at NNN
-                let address = opcode & 0x0FFF;
-                self.stack.push(self.memory.pc);
-                self.memory.pc = address;
-            }
-            (3, _, _, _) => {
-                // skip next instruction if Vx == NN
-                let x = digit2 as usize;
-                let nn = (opcode & 0x00FF) as u8;
-                if self.v_registers[x] == nn {
-                    self.memory.next();
-                }
+    /// The XO-CHIP audio pattern a program loaded with F002, or `None` if it
+    /// hasn't, in which case the buzzer plays instead. Frontends play it
+    /// with `audio::PatternWave` while `is_sound_playing`.
+    pub fn audio_pattern(&self) -> Option<&[u8; PATTERN_SIZE]> {
+        self.audio_pattern.as_ref()
+    }
+
+    /// The XO-CHIP pitch register, set with FX3A.
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Bits per second the audio pattern plays at, see `audio::playback_rate`.
+    pub fn playback_rate(&self) -> f32 {
+        audio::playback_rate(self.pitch)
+    }
+
+    pub fn cycle(&mut self) -> CycleResult {
+        self.step().result
+    }
+
+    /// Runs an instruction like `cycle`, describing what it did.
+    pub fn step(&mut self) -> StepSummary {
+        let pc = self.memory.pc;
+        let mut summary = StepSummary {
+            pc,
+            opcode: None,
+            instruction: None,
+            dirty: DirtyRows::default(),
+            waiting_for_key: false,
+            delay_timer_set: None,
+            sound_timer_set: None,
+            fault: None,
+            result: CycleResult::Continue,
+        };
+        if self.halted {
+            summary.result = CycleResult::ProgramFinished;
+            return summary;
+        }
+        if self.fault.is_some() {
+            return summary;
+        }
+        self.update_input();
+        if self.waiting_for_frame {
+            // a draw is blocked until the next frame
+            return summary;
+        }
+        let cached = self.decode_cache.as_ref().and_then(|cache| cache.get(pc));
+        let (opcode, instruction) = match cached {
+            Some(entry) => {
+                self.memory.next();
+                entry
             }
-            (4, _, _, _) => {
-                // skip next instruction if Vx != NN
-                let x = digit2 as usize;
-                let nn = (opcode & 0x00FF) as u8;
-                if self.v_registers[x] != nn {
-                    self.memory.next();
+            None => {
+                let opcode = self.memory.fetch_opcode();
+                let instruction = self.decode(opcode);
+                if let Some(cache) = self.decode_cache.as_mut() {
+                    cache.insert(pc, opcode, instruction);
                 }
+                (opcode, instruction)
             }
-            (5, _, _, 0) => {
-                // skip next instruction if Vx == Vy
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                if self.v_registers[x] == self.v_registers[y] {
-                    self.memory.next();
+        };
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record(pc, opcode);
+        }
+        if let Some(mut hook) = self.instruction_hook.take() {
+            hook.before(self, pc, opcode, instruction);
+            self.instruction_hook = Some(hook);
+        }
+        // rows changed earlier are set aside to tell this instruction's apart
+        let earlier = self.screen.take_dirty();
+        if self.journal.is_some() {
+            Journal::begin(self, pc, instruction);
+        }
+        match instruction {
+            Some(instruction) => self.execute_instruction(instruction),
+            None => self.skip_unknown(pc, opcode),
+        }
+        if let Some(journal) = self.journal.as_mut() {
+            journal.end();
+        }
+        if let Some(mut hook) = self.instruction_hook.take() {
+            hook.after(self, pc, opcode, instruction);
+            self.instruction_hook = Some(hook);
+        }
+        let dirty = self.screen.take_dirty();
+        self.screen.dirty = DirtyRows(earlier.0 | dirty.0);
+
+        summary.opcode = Some(opcode);
+        summary.instruction = instruction;
+        summary.dirty = dirty;
+        summary.fault = self.fault;
+        match instruction {
+            Some(Instruction::WaitKey { .. }) => summary.waiting_for_key = self.memory.pc == pc,
+            Some(Instruction::SetDelay { .. }) => summary.delay_timer_set = Some(self.delay_timer),
+            Some(Instruction::SetSound { .. }) => summary.sound_timer_set = Some(self.sound_timer),
+            _ => {}
+        }
+        let drew = matches!(instruction, Some(Instruction::Draw { .. }));
+        summary.result = match (self.halted, drew && self.fault.is_none()) {
+            (true, _) => CycleResult::ProgramFinished,
+            (false, true) => CycleResult::DrawCompleted,
+            (false, false) => CycleResult::Continue,
+        };
+        summary
+    }
+
+    fn skip_unknown(&mut self, pc: u16, opcode: u16) {
+        match self.unknown_opcode {
+            UnknownOpcode::Ignore => {}
+            UnknownOpcode::Warn => {
+                if self.warnings.len() < MAX_WARNINGS {
+                    self.warnings.push(Diagnostic::new(self, pc));
                 }
             }
-            (6, _, _, _) => {
-                // set Vx = NN
-                let x = digit2 as usize;
-                let nn = (opcode & 0x00FF) as u8;
-                self.v_registers[x] = nn;
-            }
-            (7, _, _, _) => {
-                // set Vx = Vx + NN
-                let x = digit2 as usize;
-                let nn = (opcode & 0x00FF) as u8;
-                self.v_registers[x] = self.v_registers[x].wrapping_add(nn);
-            }
-            (8, _, _, 0) => {
-                // set Vx = Vy
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                self.v_registers[x] = self.v_registers[y];
-            }
-            (8, _, _, 1) => {
-                // set Vx = Vx OR Vy
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                self.v_registers[x] |= self.v_registers[y];
+            UnknownOpcode::Fault => {
+                self.memory.prev();
+                self.fault = Some(Fault::UnknownOpcode { pc, opcode });
             }
-            (8, _, _, 2) => {
-                // set Vx = Vx AND Vy
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                self.v_registers[x] &= self.v_registers[y];
+        }
+    }
+
+    /// Turns caching of decoded instructions on or off. With the cache, each
+    /// address is decoded once until memory under it is written, which saves
+    /// time in tight loops at high clock speeds.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = enabled.then(DecodeCache::default);
+    }
+
+    pub fn decode_cache_enabled(&self) -> bool {
+        self.decode_cache.is_some()
+    }
+
+    /// Starts or stops counting executed instructions. Stopping discards the
+    /// counts; starting again begins from zero.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        match (enabled, &self.profile) {
+            (true, None) => self.profile = Some(Profile::default()),
+            (false, _) => self.profile = None,
+            (true, Some(_)) => {}
+        }
+    }
+
+    /// Keeps undo records for the last `capacity` instructions, so
+    /// `step_back` can reverse them. 0 turns journaling off and drops the
+    /// records.
+    pub fn set_journal_capacity(&mut self, capacity: usize) {
+        self.journal = (capacity > 0).then(|| Journal::new(capacity));
+    }
+
+    /// Instructions the journal keeps undo records for, or 0 if it's off.
+    pub fn journal_capacity(&self) -> usize {
+        self.journal.as_ref().map_or(0, Journal::capacity)
+    }
+
+    /// Instructions `step_back` can undo.
+    pub fn journal_len(&self) -> usize {
+        self.journal.as_ref().map_or(0, Journal::len)
+    }
+
+    /// Undoes the last instruction, putting back the registers, timers,
+    /// memory, stack and display it ran with. Returns false if journaling is
+    /// off or nothing is left to undo. Loading a ROM or restoring a snapshot
+    /// starts the journal over.
+    pub fn step_back(&mut self) -> bool {
+        Journal::undo(self)
+    }
+
+    fn clear_journal(&mut self) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.clear();
+        }
+    }
+
+    /// Counts collected since profiling was enabled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Captures the machine state, see `Snapshot`.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.clone(),
+            screen: self.screen.clone(),
+            v_registers: self.v_registers,
+            i_register: self.i_register,
+            stack: self.stack.clone(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            rng: self.rng,
+            waiting_for_frame: self.waiting_for_frame,
+            halted: self.halted,
+        }
+    }
+
+    /// Everything that differs between this machine's state and `other`'s,
+    /// for comparing two runs that should match.
+    pub fn diff(&self, other: &Chip8) -> StateDiff {
+        diff::diff(self.into(), other.into())
+    }
+
+    /// A 64-bit FNV-1a hash of the registers, memory, stack, timers and
+    /// display. Equal states hash the same on every platform, so it's a cheap
+    /// check that two runs match.
+    pub fn state_hash(&self) -> u64 {
+        const OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01B3;
+
+        let pointers = [self.memory.pc, self.i_register, self.stack.sp]
+            .into_iter()
+            .flat_map(u16::to_be_bytes);
+        let registers = self.v_registers.iter().copied();
+        // entries above the stack pointer are leftovers that can't be read
+        let stack = self.stack.stack[..self.stack.sp as usize]
+            .iter()
+            .flat_map(|address| address.to_be_bytes());
+        let timers = [self.delay_timer, self.sound_timer];
+        let display = self
+            .display_rows()
+            .flat_map(u64::to_be_bytes)
+            .chain([self.screen.height as u8]);
+        pointers
+            .chain(registers)
+            .chain(stack)
+            .chain(timers)
+            .chain(display)
+            .chain(self.memory.data.iter().copied())
+            .fold(OFFSET_BASIS, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+            })
+    }
+
+    /// Puts the machine back into a state captured with `snapshot`.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.memory = snapshot.memory.clone();
+        self.screen = snapshot.screen.clone();
+        // whatever is on screen now may not match the restored display
+        self.screen.dirty = DirtyRows::all(self.screen.height);
+        self.v_registers = snapshot.v_registers;
+        self.i_register = snapshot.i_register;
+        self.stack = snapshot.stack.clone();
+        self.delay_timer = snapshot.delay_timer;
+        self.sound_timer = snapshot.sound_timer;
+        self.audio_pattern = snapshot.audio_pattern;
+        self.pitch = snapshot.pitch;
+        self.rng = snapshot.rng;
+        self.waiting_for_frame = snapshot.waiting_for_frame;
+        self.halted = snapshot.halted;
+        self.fault = None;
+        self.invalidate_decode_cache();
+        self.clear_journal();
+    }
+
+    /// Runs one 60 Hz frame: up to `cycles_per_frame` instructions, stopping
+    /// early when a draw waits for the frame end, then one timer tick.
+    pub fn run_frame(&mut self, cycles_per_frame: u32) -> FrameSummary {
+        let mut cycles = 0;
+        while cycles < cycles_per_frame
+            && !self.waiting_for_frame
+            && self.fault.is_none()
+            && !self.halted
+        {
+            self.cycle();
+            cycles += 1;
+        }
+        let sound_event = self.tick_timers();
+        FrameSummary {
+            cycles,
+            dirty: self.take_dirty(),
+            sound_playing: self.is_sound_playing(),
+            sound_event,
+            fault: self.fault,
+        }
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.time_budget = 0;
+    }
+
+    /// Runs as many instructions as fit in `micros` microseconds of emulated
+    /// time under the current `Timing`, returning how many ran. Time left over
+    /// (or overrun by a slow instruction) carries into the next call. Waiting
+    /// for the end of a frame, a fault or a halt uses up the rest of the time.
+    pub fn run_microseconds(&mut self, micros: u64) -> u32 {
+        self.time_budget += micros as i64 * 1_000;
+        let mut count = 0;
+        while self.time_budget > 0 {
+            if self.waiting_for_frame || self.fault.is_some() || self.halted {
+                self.time_budget = 0;
+                break;
+            }
+            let pc = self.memory.pc;
+            let opcode = u16::from_be_bytes([
+                self.memory.data[wrap_address(pc, 0)],
+                self.memory.data[wrap_address(pc, 1)],
+            ]);
+            self.time_budget -= self.timing.instruction_nanos(opcode) as i64;
+            self.cycle();
+            count += 1;
+        }
+        count
+    }
+
+    /// Sets the keypad state. Ignored while a replay is running.
+    pub fn set_pressed_keys(&mut self, keys: [bool; NUM_KEYS]) {
+        if !self.is_replaying() {
+            self.pressed_keys = keys;
+        }
+    }
+
+    /// Sets the state of the second keypad, which two-player CHIP-8X games
+    /// read with EXF2 and EXF5. Ignored while a replay is running.
+    pub fn set_second_keypad(&mut self, keys: [bool; NUM_KEYS]) {
+        if !self.is_replaying() {
+            self.second_keypad = keys;
+        }
+    }
+
+    /// Whether the program has checked a keypad (EX9E, EXA1, EXF2, EXF5 or
+    /// FX0A) since the last call. Lets a frontend keep a tapped key down until the
+    /// program has seen it.
+    pub fn take_keypad_checked(&mut self) -> bool {
+        core::mem::take(&mut self.keypad_checked)
+    }
+
+    /// Starts logging keypad changes, timer ticks and random numbers so the
+    /// run from this point can be reproduced with `start_replay`.
+    pub fn start_recording(&mut self) {
+        self.input = InputMode::Recording(Recorder::new(self.rng.state()));
+    }
+
+    /// Stops recording and returns the log, or `None` if nothing was recording.
+    pub fn stop_recording(&mut self) -> Option<InputLog> {
+        match core::mem::take(&mut self.input) {
+            InputMode::Recording(recorder) => Some(recorder.finish()),
+            other => {
+                self.input = other;
+                None
             }
-            (8, _, _, 3) => {
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.input, InputMode::Recording(_))
+    }
+
+    /// Replays a recorded log. The emulator must be in the state the recording
+    /// started from (usually straight after `load_rom`). Until the log runs
+    /// out, keypad input and `tick_timers` calls from the frontend are ignored.
+    /// The random number generator is put back where the recording started,
+    /// so CXNN goes on drawing the same numbers after the log runs out.
+    pub fn start_replay(&mut self, log: InputLog) {
+        let player = Player::new(log);
+        if let Some(state) = player.rng() {
+            self.rng = XorShift::new(state);
+        }
+        self.input = if player.has_cycles() {
+            InputMode::Replaying(player)
+        } else {
+            InputMode::Live
+        };
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        matches!(self.input, InputMode::Replaying(_))
+    }
+
+    // records the inputs this cycle runs with, or feeds in the recorded ones
+    fn update_input(&mut self) {
+        match core::mem::take(&mut self.input) {
+            InputMode::Live => {}
+            InputMode::Recording(mut recorder) => {
+                recorder.cycle(&self.pressed_keys, &self.second_keypad);
+                self.input = InputMode::Recording(recorder);
+            }
+            InputMode::Replaying(mut player) => {
+                while let Some(event) = player.next_event() {
+                    match event {
+                        InputEvent::Keys(mask) => self.pressed_keys = replay::unpack_keys(mask),
+                        InputEvent::Keys2(mask) => self.second_keypad = replay::unpack_keys(mask),
+                        InputEvent::TimerTick => {
+                            if let Some(sound) = self.tick() {
+                                player.sound_event = Some(sound);
+                            }
+                        }
+                    }
+                }
+                // back to live input once the log runs out
+                if player.advance() {
+                    self.input = InputMode::Replaying(player);
+                }
+            }
+        }
+    }
+
+    // under the quirk FX55 and FX65 leave I past the last register
+    fn increment_i_after_load_store(&mut self, x: usize) {
+        if self.quirks.load_store_increment {
+            self.i_register = self.i_register.wrapping_add(x as u16 + 1);
+        }
+    }
+
+    // checks that `len` bytes from I fit in memory; under `MemoryAccess::Fault`
+    // a range that doesn't fit faults, leaving PC on the current instruction
+    fn check_i_range(&mut self, len: usize) -> bool {
+        let end = self.i_register as usize + len;
+        if end <= MEMORY_SIZE || self.memory_access == MemoryAccess::Wrap {
+            return true;
+        }
+        self.memory.prev();
+        self.fault = Some(Fault::MemoryOutOfRange {
+            pc: self.memory.pc,
+            address: self.i_register.max(MEMORY_SIZE as u16) as usize,
+        });
+        false
+    }
+
+    // after a logical 8XY_, under the `vf_reset` quirk
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.v_registers[0xF] = 0;
+        }
+    }
+
+    // sets Vx and then VF, so the flag is what's left when x is F
+    fn set_with_flag(&mut self, x: u8, (value, flag): (u8, u8)) {
+        self.v_registers[x as usize] = value;
+        self.v_registers[0xF] = flag;
+    }
+
+    // stops at the faulting call or return, like check_i_range
+    fn stack_fault(&mut self, fault: impl FnOnce(u16) -> Fault) {
+        self.memory.prev();
+        self.fault = Some(fault(self.memory.pc));
+    }
+
+    fn random_byte(&mut self) -> u8 {
+        match &mut self.input {
+            InputMode::Live => self.rng.next_u8(),
+            InputMode::Recording(recorder) => {
+                let value = self.rng.next_u8();
+                recorder.random(value);
+                value
+            }
+            // the generator keeps step with the recording, so it carries on
+            // as the recorded run's did once the log runs out
+            InputMode::Replaying(player) => {
+                let value = self.rng.next_u8();
+                player.random().unwrap_or(value)
+            }
+        }
+    }
+
+    pub fn execute(&mut self, opcode: u16) {
+        match self.decode(opcode) {
+            Some(instruction) => self.execute_instruction(instruction),
+            None => {
+                // unimplemented opcode
+            }
+        }
+    }
+
+    fn decode(&self, opcode: u16) -> Option<Instruction> {
+        if opcode == HIRES_CLEAR_SCREEN && self.is_hires() {
+            return Some(Instruction::ClearScreen);
+        }
+        instruction::decode(opcode)
+    }
+
+    fn execute_instruction(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::ClearScreen => {
+                // clear the display
+                self.screen.clear();
+            }
+            Instruction::Return => {
+                // return from subroutine
+                match self.stack.pop() {
+                    Some(return_address) => self.memory.pc = return_address,
+                    None => self.stack_fault(|pc| Fault::StackUnderflow { pc }),
+                }
+            }
+            Instruction::Exit => {
+                // stay on the 00FD so the debugger shows where it exited
+                self.memory.prev();
+                self.halted = true;
+            }
+            Instruction::Jump(address) => {
+                // jump to address NNN
+                if self.detect_finish && address == self.memory.pc.wrapping_sub(2) {
+                    self.halted = true;
+                }
+                self.memory.pc = address;
+            }
+            Instruction::Call(address) => {
+                // call subroutine at NNN
+                if let Some(mut hook) = self.hooks.remove(&address) {
+                    // run the host routine in place of the call; the return is implicit
+                    hook(self);
+                    // keep any replacement the hook installed for itself
+                    self.hooks.entry(address).or_insert(hook);
+                } else if self.stack.push(self.memory.pc) {
+                    self.memory.pc = address;
+                } else {
+                    self.stack_fault(|pc| Fault::StackOverflow { pc });
+                }
+            }
+            Instruction::SkipEqImm { x, nn } => {
+                // skip next instruction if Vx == NN
+                if self.v_registers[x as usize] == nn {
+                    self.memory.next();
+                }
+            }
+            Instruction::SkipNeImm { x, nn } => {
+                // skip next instruction if Vx != NN
+                if self.v_registers[x as usize] != nn {
+                    self.memory.next();
+                }
+            }
+            Instruction::SkipEqReg { x, y } => {
+                // skip next instruction if Vx == Vy
+                if self.v_registers[x as usize] == self.v_registers[y as usize] {
+                    self.memory.next();
+                }
+            }
+            Instruction::LoadImm { x, nn } => {
+                // set Vx = NN
+                self.v_registers[x as usize] = nn;
+            }
+            Instruction::AddImm { x, nn } => {
+                // set Vx = Vx + NN
+                let x = x as usize;
+                self.v_registers[x] = self.v_registers[x].wrapping_add(nn);
+            }
+            Instruction::Move { x, y } => {
+                // set Vx = Vy
+                self.v_registers[x as usize] = self.v_registers[y as usize];
+            }
+            Instruction::Or { x, y } => {
+                // set Vx = Vx OR Vy
+                self.v_registers[x as usize] |= self.v_registers[y as usize];
+                self.reset_vf();
+            }
+            Instruction::And { x, y } => {
+                // set Vx = Vx AND Vy
+                self.v_registers[x as usize] &= self.v_registers[y as usize];
+                self.reset_vf();
+            }
+            Instruction::Xor { x, y } => {
                 // set Vx = Vx XOR Vy
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                self.v_registers[x] ^= self.v_registers[y];
+                self.v_registers[x as usize] ^= self.v_registers[y as usize];
+                self.reset_vf();
             }
-            (8, _, _, 4) => {
+            Instruction::AddReg { x, y } => {
                 // set Vx = Vx + Vy, set VF = carry
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                let (result, carry) = self.v_registers[x].overflowing_add(self.v_registers[y]);
-                self.v_registers[x] = result;
-                self.v_registers[0xF] = carry as u8;
+                let (vx, vy) = (self.v_registers[x as usize], self.v_registers[y as usize]);
+                self.set_with_flag(x, alu::add(vx, vy));
             }
-            (8, _, _, 5) => {
+            Instruction::Sub { x, y } => {
                 // set Vx = Vx - Vy, set VF = NOT borrow
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                let (result, borrow) = self.v_registers[x].overflowing_sub(self.v_registers[y]);
-                self.v_registers[x] = result;
-                self.v_registers[0xF] = (!borrow) as u8;
-            }
-            (8, _, _, 6) => {
-                // Vx = Vx SHR 1, store dropped bit in VF
-                let x = digit2 as usize;
-                self.v_registers[0xF] = self.v_registers[x] & 0x1;
-                self.v_registers[x] >>= 1;
-            }
-            (8, _, _, 7) => {
+                let (vx, vy) = (self.v_registers[x as usize], self.v_registers[y as usize]);
+                self.set_with_flag(x, alu::sub(vx, vy));
+            }
+            Instruction::ShiftRight { x, y } => {
+                // Vx = Vx SHR 1 (Vy SHR 1 under the quirk), store dropped bit in VF
+                let source = if self.quirks.shift_vy { y } else { x };
+                self.set_with_flag(x, alu::shift_right(self.v_registers[source as usize]));
+            }
+            Instruction::SubReverse { x, y } => {
                 // set Vx = Vy - Vx, set VF = NOT borrow
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                let (result, borrow) = self.v_registers[y].overflowing_sub(self.v_registers[x]);
-                self.v_registers[x] = result;
-                self.v_registers[0xF] = (!borrow) as u8;
-            }
-            (8, _, _, 0xE) => {
-                // set Vx = Vx SHL 1, store dropped bit in VF
-                let x = digit2 as usize;
-                self.v_registers[0xF] = self.v_registers[x] >> 7;
-                self.v_registers[x] <<= 1;
-            }
-            (9, _, _, 0) => {
+                let (vx, vy) = (self.v_registers[x as usize], self.v_registers[y as usize]);
+                self.set_with_flag(x, alu::sub_reverse(vx, vy));
+            }
+            Instruction::ShiftLeft { x, y } => {
+                // set Vx = Vx SHL 1 (Vy SHL 1 under the quirk), store dropped bit in VF
+                let source = if self.quirks.shift_vy { y } else { x };
+                self.set_with_flag(x, alu::shift_left(self.v_registers[source as usize]));
+            }
+            Instruction::SkipNeReg { x, y } => {
                 // skip next instruction if Vx != Vy
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                if self.v_registers[x] != self.v_registers[y] {
+                if self.v_registers[x as usize] != self.v_registers[y as usize] {
                     self.memory.next();
                 }
             }
-            (0xA, _, _, _) => {
+            Instruction::LoadI(address) => {
                 // set I = NNN
-                let address = opcode & 0x0FFF;
                 self.i_register = address;
             }
-            (0xB, _, _, _) => {
-                // jump to address NNN + V0
-                let nnn = opcode & 0x0FFF;
-                self.memory.pc = nnn + self.v_registers[0] as u16;
+            Instruction::JumpV0(nnn) => {
+                // jump to address NNN + V0, or XNN + VX under the quirk
+                let x = if self.quirks.jump_vx { nnn >> 8 } else { 0 };
+                self.memory.pc = nnn + self.v_registers[x as usize] as u16;
             }
-            (0xC, _, _, _) => {
+            Instruction::Random { x, nn } => {
                 // set Vx = random number AND NN
-                let x = digit2 as usize;
-                let nn = (opcode & 0x00FF) as u8;
-                self.v_registers[x] = rand::random::<u8>() & nn;
+                self.v_registers[x as usize] = self.random_byte() & nn;
             }
-            (0xD, _, _, _) => {
+            Instruction::Draw { x, y, n } => {
                 // draw sprite at (Vx, Vy) with width 8 pixels and height N pixels
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                let height = digit4 as usize;
+                let height = n as usize;
                 // register Vx contains x coordinate
-                let vx = self.v_registers[x] as usize;
+                let vx = self.v_registers[x as usize] as usize;
                 let x_coor = vx % SCREEN_WIDTH;
                 // register Vy contains y coordinate
-                let vy = self.v_registers[y] as usize;
-                let y_coor = vy % SCREEN_HEIGHT;
+                let vy = self.v_registers[y as usize] as usize;
+                let y_coor = vy % self.screen.height;
 
                 // get sprite data from memory starting at I register
-                let sprite = self.memory.get_bytes(self.i_register, height);
+                if !self.check_i_range(height) {
+                    return;
+                }
+                let mut sprite = [0; 15];
+                let sprite = &mut sprite[..height];
+                for (offset, byte) in sprite.iter_mut().enumerate() {
+                    *byte = self.load(wrap_address(self.i_register, offset));
+                }
 
                 // init vf to 0
                 self.v_registers[0xF] = 0;
                 // draw sprite on screen
                 // record collision in vf
-                self.v_registers[0xF] = self.screen.draw_sprite(x_coor, y_coor, height, sprite) as u8;
+                let wrap = self.quirks.wrap_sprites;
+                let collision = self.screen.draw_sprite(x_coor, y_coor, sprite, wrap);
+                self.v_registers[0xF] = collision as u8;
+
+                if self.quirks.display_wait {
+                    self.waiting_for_frame = true;
+                }
             }
-            (0xE, _, 9, 0xE) => {
-                // skip next instruction if key with the value of Vx is pressed
-                let x = digit2 as usize;
-                if self.pressed_keys[self.v_registers[x] as usize] {
+            Instruction::SkipKeyPressed { x } => {
+                // skip next instruction if key with the value of Vx is pressed;
+                // only the low nibble names a key
+                self.keypad_checked = true;
+                if self.pressed_keys[(self.v_registers[x as usize] & 0xF) as usize] {
                     self.memory.next();
                 }
             }
-            (0xE, _, 0xA, 1) => {
+            Instruction::SkipKeyNotPressed { x } => {
                 // skip next instruction if key with the value of Vx is not pressed
-                let x = digit2 as usize;
-                if !self.pressed_keys[self.v_registers[x] as usize] {
+                self.keypad_checked = true;
+                if !self.pressed_keys[(self.v_registers[x as usize] & 0xF) as usize] {
+                    self.memory.next();
+                }
+            }
+            Instruction::SkipKey2Pressed { x } => {
+                self.keypad_checked = true;
+                if self.second_keypad[(self.v_registers[x as usize] & 0xF) as usize] {
                     self.memory.next();
                 }
             }
-            (0xF, _, 0, 7) => {
+            Instruction::SkipKey2NotPressed { x } => {
+                self.keypad_checked = true;
+                if !self.second_keypad[(self.v_registers[x as usize] & 0xF) as usize] {
+                    self.memory.next();
+                }
+            }
+            Instruction::LoadDelay { x } => {
                 // set Vx = delay timer value
-                let x = digit2 as usize;
-                self.v_registers[x] = self.delay_timer;
+                self.v_registers[x as usize] = self.delay_timer;
             }
-            (0xF, _, 0, 0xA) => {
+            Instruction::WaitKey { x } => {
                 // wait for a key press, then store the value of the key in Vx
-                let x = digit2 as usize;
                 // find first pressed key
+                self.keypad_checked = true;
                 let pressed_key_option: Option<usize> = self.pressed_keys.iter().position(|&k| k);
                 if let Some(pressed_key) = pressed_key_option {
                     // store key in Vx
-                    self.v_registers[x] = pressed_key as u8;
+                    self.v_registers[x as usize] = pressed_key as u8;
                 } else {
                     // no key pressed, decrement pc to repeat this instruction
                     self.memory.prev();
                 }
             }
-            (0xF, _, 1, 5) => {
+            Instruction::SetDelay { x } => {
                 // set delay timer = Vx
-                let x = digit2 as usize;
-                self.delay_timer = self.v_registers[x];
+                self.delay_timer = self.v_registers[x as usize];
             }
-            (0xF, _, 1, 8) => {
+            Instruction::SetSound { x } => {
                 // set sound timer = Vx
-                let x = digit2 as usize;
-                self.sound_timer = self.v_registers[x];
+                self.sound_timer = self.v_registers[x as usize];
             }
-            (0xF, _, 1, 0xE) => {
-                // set I = I + Vx
-                let x = digit2 as usize;
-                self.i_register = self.i_register.wrapping_add(self.v_registers[x] as u16);
+            Instruction::AddI { x } => {
+                // set I = I + Vx, and under the quirk VF = overflow past 0xFFF
+                let sum = self
+                    .i_register
+                    .wrapping_add(self.v_registers[x as usize] as u16);
+                if self.quirks.i_overflow {
+                    self.i_register = sum & 0xFFF;
+                    self.v_registers[0xF] = (sum > 0xFFF) as u8;
+                } else {
+                    self.i_register = sum;
+                }
             }
-            (0xF, _, 2, 9) => {
+            Instruction::LoadFont { x } => {
                 // set I = location of sprite for digit Vx
                 // get digit from Vx
-                let x = digit2 as usize;
-                let digit = self.v_registers[x] as u16;
+                let digit = self.v_registers[x as usize] as u16;
                 // set I to the location of the sprite
                 self.i_register = digit * 5; // each sprite is 5 bytes long
-                
             }
-            (0xF, _, 3, 3) => {
+            Instruction::LoadBigFont { x } => {
+                // large glyphs are 10 bytes long, only the low nibble picks one
+                let digit = (self.v_registers[x as usize] & 0xF) as u16;
+                self.i_register = BIG_FONT_ADDRESS + digit * 10;
+            }
+            Instruction::StoreBcd { x } => {
                 // store BCD representation of Vx in memory locations I, I+1, and I+2
-                let x = digit2 as usize;
-                let value = self.v_registers[x];
-                self.memory.data[self.i_register as usize] = value / 100;
-                self.memory.data[self.i_register as usize + 1] = (value / 10) % 10;
-                self.memory.data[self.i_register as usize + 2] = value % 10;
+                let value = self.v_registers[x as usize];
+                if !self.check_i_range(3) {
+                    return;
+                }
+                let digits = [value / 100, (value / 10) % 10, value % 10];
+                for (offset, digit) in digits.into_iter().enumerate() {
+                    self.store_io(wrap_address(self.i_register, offset), digit);
+                }
             }
-            (0xF, _, 5, 5) => {
+            Instruction::StoreRegisters { x } => {
                 // store registers V0 through Vx in memory starting at location I
-                let x = digit2 as usize;
+                let x = x as usize;
+                if !self.check_i_range(x + 1) {
+                    return;
+                }
                 for offset in 0..=x {
-                    self.memory.data[self.i_register as usize + offset] = self.v_registers[offset];
+                    self.store_io(
+                        wrap_address(self.i_register, offset),
+                        self.v_registers[offset],
+                    );
                 }
+                self.increment_i_after_load_store(x);
             }
-            (0xF, _, 6, 5) => {
+            Instruction::LoadRegisters { x } => {
                 // load registers V0 through Vx from memory starting at location I
-                let x = digit2 as usize;
+                let x = x as usize;
+                if !self.check_i_range(x + 1) {
+                    return;
+                }
                 for offset in 0..=x {
-                    self.v_registers[offset] = self.memory.data[self.i_register as usize + offset];
+                    self.v_registers[offset] = self.load(wrap_address(self.i_register, offset));
                 }
+                self.increment_i_after_load_store(x);
             }
-            (_, _, _, _) => {
-                // unimplemented opcode
+            Instruction::LoadAudio => {
+                // load the audio pattern from the 16 bytes at I
+                if !self.check_i_range(PATTERN_SIZE) {
+                    return;
+                }
+                let mut pattern = [0; PATTERN_SIZE];
+                for (offset, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.load(wrap_address(self.i_register, offset));
+                }
+                self.audio_pattern = Some(pattern);
+            }
+            Instruction::SetPitch { x } => {
+                // set the audio pattern's pitch = Vx
+                self.pitch = self.v_registers[x as usize];
+            }
+            Instruction::SaveFlags { x } => {
+                // save registers V0 through Vx to the user flags
+                let x = x as usize;
+                let mut flags = self.flag_storage.load();
+                flags[..=x].copy_from_slice(&self.v_registers[..=x]);
+                self.flag_storage.save(&flags);
+            }
+            Instruction::LoadFlags { x } => {
+                // load registers V0 through Vx from the user flags
+                let x = x as usize;
+                let flags = self.flag_storage.load();
+                self.v_registers[..=x].copy_from_slice(&flags[..=x]);
             }
         }
     }
@@ -486,21 +1779,1106 @@ mod tests {
         let mut chip8 = Chip8::new();
         chip8.init();
         let rom = [0x00, 0xE0]; // CLS
-        chip8.load_rom(&rom);
-        
+        chip8.load_rom(&rom).unwrap();
+
         assert_eq!(chip8.memory.data[START_ADDRESS as usize], 0x00);
         assert_eq!(chip8.memory.data[START_ADDRESS as usize + 1], 0xE0);
     }
-    
+
     #[test]
     fn test_cycle() {
         let mut chip8 = Chip8::new();
         chip8.init();
         // 6xNN: Set Vx = NN
-        let rom = [0x60, 0xAA]; 
-        chip8.load_rom(&rom);
-        
+        let rom = [0x60, 0xAA];
+        chip8.load_rom(&rom).unwrap();
+
+        chip8.cycle();
+        assert_eq!(chip8.v_registers[0], 0xAA);
+    }
+
+    #[test]
+    fn test_vf_reset_quirk() {
+        // VF = 1, V0 = 3, V1 = 5, then 8011, 8012 or 8013
+        let run = |vf_reset, n: u8| {
+            let mut chip8 = Chip8::new();
+            chip8.init();
+            chip8.set_quirks(Quirks {
+                vf_reset,
+                ..Quirks::default()
+            });
+            chip8
+                .load_rom(&[0x6F, 0x01, 0x60, 0x03, 0x61, 0x05, 0x80, 0x10 | n])
+                .unwrap();
+            for _ in 0..4 {
+                chip8.cycle();
+            }
+            (chip8.v_registers[0], chip8.v_registers[0xF])
+        };
+        assert_eq!(run(false, 0x1), (0x7, 1));
+        assert_eq!(run(true, 0x1), (0x7, 0));
+        assert_eq!(run(true, 0x2), (0x1, 0));
+        assert_eq!(run(true, 0x3), (0x6, 0));
+    }
+
+    #[test]
+    fn test_display_wait_blocks_until_frame_end() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.set_quirks(Quirks {
+            display_wait: true,
+            ..Quirks::default()
+        });
+        // DXY0 followed by 6xNN
+        let rom = [0xD0, 0x01, 0x60, 0xAA];
+        chip8.load_rom(&rom).unwrap();
+
+        chip8.cycle();
+        chip8.cycle();
+        assert_eq!(chip8.v_registers[0], 0);
+
+        chip8.tick_timers();
+        chip8.cycle();
+        assert_eq!(chip8.v_registers[0], 0xAA);
+    }
+
+    #[test]
+    fn test_take_dirty_reports_drawn_rows() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // everything is dirty before the first frame
+        assert_eq!(chip8.take_dirty().rows().count(), SCREEN_HEIGHT);
+        assert!(chip8.take_dirty().is_empty());
+
+        // 6104: V1 = 4, then draw the "0" glyph (5 rows) at (V0, V1)
+        let rom = [0x61, 0x04, 0xD0, 0x15];
+        chip8.load_rom(&rom).unwrap();
+        chip8.cycle();
+        chip8.cycle();
+
+        let dirty = chip8.take_dirty();
+        assert_eq!(dirty.bounds(), Some((4, 8)));
+        assert!(!dirty.contains(3));
+    }
+
+    #[test]
+    fn test_get_display_packed() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // 6008: V0 = 8, then draw the "0" glyph at (V0, V1)
+        let rom = [0x60, 0x08, 0xD0, 0x15];
+        chip8.load_rom(&rom).unwrap();
+        chip8.cycle();
+        chip8.cycle();
+
+        let packed = chip8.get_display_packed();
+        // 0xF0 shifted right by 8 pixels from the left edge
+        assert_eq!(packed[0], 0xF0 << (64 - 16));
+        assert_eq!(packed[1], 0x90 << (64 - 16));
+        assert_eq!(packed[5], 0);
+        assert!(
+            chip8
+                .display_rows()
+                .eq(packed[..SCREEN_HEIGHT].iter().copied())
+        );
+        assert!(packed[SCREEN_HEIGHT..].iter().all(|&row| row == 0));
+    }
+
+    #[test]
+    fn test_hires_rom_uses_tall_display() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        let mut rom = [0; 0xC6];
+        rom[..2].copy_from_slice(&[0x12, 0x60]);
+        // at 0x2C0: V1 = 60, then draw the "0" glyph at (V0, V1)
+        rom[0xC0..].copy_from_slice(&[0x61, 0x3C, 0xD0, 0x15, 0x02, 0x30]);
+        chip8.load_rom(&rom).unwrap();
+        assert!(chip8.is_hires());
+        assert_eq!(chip8.pc(), 0x2C0);
+        assert_eq!(
+            chip8.get_display().len(),
+            SCREEN_WIDTH * HIRES_SCREEN_HEIGHT
+        );
+
+        chip8.cycle();
+        chip8.cycle();
+        assert_eq!(chip8.get_display_packed()[60], 0xF0 << 56);
+        assert_eq!(chip8.get_display_packed()[63], 0x90 << 56);
+        // switching to HIRES redraws the whole taller display
+        assert_eq!(chip8.take_dirty().bounds(), Some((0, 63)));
+        assert_eq!(chip8.display_rows().count(), HIRES_SCREEN_HEIGHT);
+
+        // 0230 clears the screen in HIRES mode
+        chip8.cycle();
+        assert!(chip8.get_display().iter().all(|&pixel| !pixel));
+    }
+
+    #[test]
+    fn test_hook_subroutine() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // 2206: call 0x206, 6xNN: V0 = 0xAA
+        let rom = [0x22, 0x06, 0x60, 0xAA];
+        chip8.load_rom(&rom).unwrap();
+
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        chip8.hook_subroutine(0x206, move |_| counter.set(counter.get() + 1));
+
+        chip8.cycle();
+        assert_eq!(calls.get(), 1);
+        assert_eq!(chip8.memory.pc, START_ADDRESS + 2);
+        assert_eq!(chip8.stack.sp, 0);
+
         chip8.cycle();
         assert_eq!(chip8.v_registers[0], 0xAA);
+
+        assert!(chip8.unhook_subroutine(0x206));
+        assert!(!chip8.unhook_subroutine(0x206));
+    }
+
+    #[test]
+    fn test_instruction_hook() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        struct Recorder(Rc<RefCell<Vec<(u16, u8)>>>);
+
+        impl InstructionHook for Recorder {
+            fn after(&mut self, chip8: &Chip8, pc: u16, _: u16, _: Option<Instruction>) {
+                self.0.borrow_mut().push((pc, chip8.v_registers[0]));
+            }
+        }
+
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // 6001: V0 = 1, 7002: V0 += 2
+        chip8.load_rom(&[0x60, 0x01, 0x70, 0x02]).unwrap();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        chip8.set_instruction_hook(Recorder(seen.clone()));
+
+        chip8.cycle();
+        chip8.cycle();
+        assert_eq!(*seen.borrow(), [(0x200, 1), (0x202, 3)]);
+        assert!(chip8.take_instruction_hook().is_some());
+        chip8.cycle();
+        assert_eq!(seen.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_step_summary() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // 6005: V0 = 5, F015: delay timer = V0, A200: I = 0x200,
+        // D001: draw 1 row at (V0, V0), F10A: wait for a key in V1
+        chip8
+            .load_rom(&[0x60, 0x05, 0xF0, 0x15, 0xA2, 0x00, 0xD0, 0x01, 0xF1, 0x0A])
+            .unwrap();
+        chip8.take_dirty();
+
+        let step = chip8.step();
+        assert_eq!(step.pc, 0x200);
+        assert_eq!(step.opcode, Some(0x6005));
+        assert_eq!(step.instruction, Some(Instruction::LoadImm { x: 0, nn: 5 }));
+        assert!(step.dirty.is_empty());
+
+        assert_eq!(chip8.step().delay_timer_set, Some(5));
+        chip8.step();
+        let step = chip8.step();
+        assert_eq!(step.result, CycleResult::DrawCompleted);
+        assert_eq!(step.dirty.bounds(), Some((5, 5)));
+
+        let step = chip8.step();
+        assert!(step.waiting_for_key);
+        assert!(chip8.step().waiting_for_key);
+        // the draw's row stays dirty for the frontend
+        assert_eq!(chip8.take_dirty().bounds(), Some((5, 5)));
+
+        chip8.halted = true;
+        let step = chip8.step();
+        assert_eq!(step.opcode, None);
+        assert_eq!(step.result, CycleResult::ProgramFinished);
+    }
+
+    #[test]
+    fn test_tick_timers_sound_events() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // V0 = 2, sound timer = V0
+        let rom = [0x60, 0x02, 0xF0, 0x18];
+        chip8.load_rom(&rom).unwrap();
+        chip8.cycle();
+        chip8.cycle();
+
+        assert_eq!(chip8.tick_timers(), Some(SoundEvent::Started));
+        assert!(chip8.is_sound_playing());
+        assert_eq!(chip8.tick_timers(), None);
+        assert_eq!(chip8.tick_timers(), Some(SoundEvent::Stopped));
+        assert!(!chip8.is_sound_playing());
+        assert_eq!(chip8.tick_timers(), None);
+    }
+
+    #[test]
+    fn test_generate_audio() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        let mut out = [1; 8];
+        chip8.generate_audio(&mut out, 1760);
+        assert_eq!(out, [0; 8]);
+
+        chip8.sound_timer = 1;
+        chip8.tick_timers();
+        // 1760 Hz sample rate gives a 4 sample period at 440 Hz
+        chip8.generate_audio(&mut out, 1760);
+        let (hi, lo) = (i16::MAX / 4, -(i16::MAX / 4));
+        assert_eq!(out, [hi, hi, lo, lo, hi, hi, lo, lo]);
+    }
+
+    #[test]
+    fn test_seeded_rng_is_reproducible() {
+        // CXFF: V0 = random byte
+        let rom = [0xC0, 0xFF];
+        let run = |seed| {
+            let mut chip8 = Chip8::new();
+            chip8.init();
+            chip8.seed_rng(seed);
+            chip8.load_rom(&rom).unwrap();
+            chip8.cycle();
+            chip8.v_registers[0]
+        };
+        assert_eq!(run(42), run(42));
+    }
+
+    #[test]
+    fn test_load_rom_at() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // V0 = 7, at the ETI 660's load address
+        chip8.load_rom_at(0x600, &[0x60, 0x07]).unwrap();
+        assert_eq!((chip8.pc(), chip8.load_address()), (0x600, 0x600));
+        chip8.cycle();
+        assert_eq!(chip8.v_registers[0], 7);
+        assert_eq!(chip8.read_byte(START_ADDRESS), Some(0));
+    }
+
+    #[test]
+    fn test_load_rom_too_large() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        assert!(chip8.load_rom(&[0x12; MAX_ROM_SIZE]).is_ok());
+        assert_eq!(
+            chip8.load_rom(&[0x60; MAX_ROM_SIZE + 1]),
+            Err(RomTooLarge {
+                len: MAX_ROM_SIZE + 1,
+                room: MAX_ROM_SIZE,
+                address: START_ADDRESS
+            })
+        );
+        assert!(chip8.load_rom_at(0xFFF, &[0x60, 0x07]).is_err());
+        // nothing is loaded when the ROM doesn't fit
+        assert_eq!(chip8.read_byte(START_ADDRESS), Some(0x12));
+        assert_eq!(chip8.load_address(), START_ADDRESS);
+    }
+
+    #[test]
+    fn test_load_fontset() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.load_fontset(&font::DREAM_6800);
+        // V0 = 1, I = glyph for 1
+        chip8.load_rom(&[0x60, 0x01, 0xF0, 0x29]).unwrap();
+        chip8.cycle();
+        chip8.cycle();
+        let i = chip8.i_register();
+        assert_eq!(chip8.read_range(i, 5), Some(&font::DREAM_6800[5..10]));
+
+        // init keeps the loaded fontset
+        chip8.init();
+        assert_eq!(
+            chip8.read_range(0, FONTSET_SIZE),
+            Some(&font::DREAM_6800[..])
+        );
+    }
+
+    #[test]
+    fn test_peek_and_poke() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        assert!(chip8.write_byte(0x300, 0x42));
+        assert_eq!(chip8.read_byte(0x300), Some(0x42));
+        assert_eq!(chip8.read_range(0x2FF, 2), Some(&[0x00, 0x42][..]));
+        // fontset starts with the glyph for 0
+        assert_eq!(chip8.read_range(0, 5), Some(&font::CHIP48[..5]));
+
+        assert!(!chip8.write_byte(MEMORY_SIZE as u16, 1));
+        assert_eq!(chip8.read_byte(MEMORY_SIZE as u16), None);
+        assert_eq!(chip8.read_range(0xFFF, 2), None);
+        assert_eq!(chip8.read_range(0xFFF, 1), Some(&[0][..]));
+    }
+
+    #[test]
+    fn test_register_setters() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // 8014: V0 += V1
+        chip8.load_rom(&[0x80, 0x14, 0x80, 0x14]).unwrap();
+        chip8.set_v_register(0, 0x10);
+        chip8.set_v_register(1, 0x22);
+        chip8.cycle();
+        assert_eq!(chip8.v_registers()[0], 0x32);
+
+        chip8.set_pc(START_ADDRESS);
+        chip8.cycle();
+        assert_eq!(chip8.v_registers()[0], 0x54);
+        assert_eq!(chip8.pc(), START_ADDRESS + 2);
+
+        chip8.set_i_register(0x345);
+        chip8.set_delay_timer(3);
+        chip8.set_sound_timer(4);
+        assert_eq!(chip8.i_register(), 0x345);
+        assert_eq!(chip8.delay_timer(), 3);
+        assert_eq!(chip8.sound_timer(), 4);
+
+        assert!(chip8.set_sp(STACK_SIZE as u16));
+        assert!(!chip8.set_sp(STACK_SIZE as u16 + 1));
+        assert_eq!(chip8.sp(), STACK_SIZE as u16);
+    }
+
+    #[test]
+    fn test_i_relative_access_wraps_at_end_of_memory() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // F355: store V0..V3 at I
+        chip8.load_rom(&[0xF3, 0x55]).unwrap();
+        chip8.set_i_register(0xFFE);
+        chip8.v_registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+        chip8.cycle();
+        assert_eq!(chip8.read_range(0xFFE, 2), Some(&[1, 2][..]));
+        assert_eq!(chip8.read_range(0, 2), Some(&[3, 4][..]));
+        assert_eq!(chip8.fault(), None);
+    }
+
+    #[test]
+    fn test_i_relative_access_can_fault() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.set_memory_access(MemoryAccess::Fault);
+        // F355: store V0..V3 at I, then V0 = 0xAA
+        chip8.load_rom(&[0xF3, 0x55, 0x60, 0xAA]).unwrap();
+        chip8.set_i_register(0xFFE);
+        chip8.v_registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+        chip8.cycle();
+        assert_eq!(
+            chip8.fault(),
+            Some(Fault::MemoryOutOfRange {
+                pc: START_ADDRESS,
+                address: MEMORY_SIZE
+            })
+        );
+        assert_eq!(chip8.read_range(0xFFE, 2), Some(&[0, 0][..]));
+
+        // a faulted emulator stays put
+        chip8.cycle();
+        assert_eq!(chip8.pc(), START_ADDRESS);
+        assert_eq!(chip8.v_registers[0], 1);
+    }
+
+    #[test]
+    fn test_stack_misuse_faults() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // 2200: call itself until the stack runs out
+        chip8.load_rom(&[0x22, 0x00]).unwrap();
+        for _ in 0..=STACK_SIZE {
+            chip8.cycle();
+        }
+        assert_eq!(chip8.sp() as usize, STACK_SIZE);
+        assert_eq!(
+            chip8.fault(),
+            Some(Fault::StackOverflow { pc: START_ADDRESS })
+        );
+
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.load_rom(&[0x00, 0xEE]).unwrap();
+        chip8.cycle();
+        assert_eq!(
+            chip8.fault(),
+            Some(Fault::StackUnderflow { pc: START_ADDRESS })
+        );
+    }
+
+    #[test]
+    fn test_call_stack() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // 200: call 204, 204: call 208, 208: return
+        chip8
+            .load_rom(&[0x22, 0x04, 0x00, 0x00, 0x22, 0x08, 0x00, 0x00, 0x00, 0xEE])
+            .unwrap();
+        assert!(chip8.call_stack().is_empty());
+        chip8.cycle();
+        chip8.cycle();
+        assert_eq!(chip8.call_stack(), &[0x202, 0x206]);
+        chip8.cycle();
+        assert_eq!(chip8.call_stack(), &[0x202]);
+        assert_eq!(chip8.pc(), 0x206);
+    }
+
+    #[test]
+    fn test_take_keypad_checked() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // V0 = 1, then EX9E
+        chip8.load_rom(&[0x60, 0x01, 0xE0, 0x9E]).unwrap();
+        chip8.cycle();
+        assert!(!chip8.take_keypad_checked());
+        chip8.cycle();
+        assert!(chip8.take_keypad_checked());
+        assert!(!chip8.take_keypad_checked());
+    }
+
+    #[test]
+    fn test_flags_persist_in_storage() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+        use flags::NUM_FLAGS;
+
+        // a store outliving the emulator, like a file
+        #[derive(Clone, Default)]
+        struct Shared(Rc<RefCell<[u8; NUM_FLAGS]>>);
+        impl FlagStorage for Shared {
+            fn load(&mut self) -> [u8; NUM_FLAGS] {
+                *self.0.borrow()
+            }
+            fn save(&mut self, flags: &[u8; NUM_FLAGS]) {
+                *self.0.borrow_mut() = *flags;
+            }
+        }
+
+        let storage = Shared::default();
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.set_flag_storage(storage.clone());
+        // V0 = 7, V1 = 9, then LD R, V1
+        chip8
+            .load_rom(&[0x60, 0x07, 0x61, 0x09, 0xF1, 0x75])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.cycle();
+        }
+        assert_eq!(storage.0.borrow()[..3], [7, 9, 0]);
+
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.set_flag_storage(storage);
+        // LD V1, R
+        chip8.load_rom(&[0xF1, 0x85]).unwrap();
+        chip8.cycle();
+        assert_eq!(chip8.v_registers[..3], [7, 9, 0]);
+    }
+
+    #[test]
+    fn test_audio_pattern() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // LD I, 0x300; AUDIO; V0 = 112; LD PITCH, V0
+        chip8
+            .load_rom(&[0xA3, 0x00, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A])
+            .unwrap();
+        chip8.write_byte(0x300, 0xAA);
+        chip8.write_byte(0x30F, 0x01);
+        assert_eq!(chip8.audio_pattern(), None);
+        assert_eq!(chip8.playback_rate(), 4000.0);
+        for _ in 0..4 {
+            chip8.cycle();
+        }
+        let mut pattern = [0; PATTERN_SIZE];
+        pattern[0] = 0xAA;
+        pattern[15] = 0x01;
+        assert_eq!(chip8.audio_pattern(), Some(&pattern));
+        assert_eq!(chip8.pitch(), 112);
+        assert_eq!(chip8.playback_rate(), 8000.0);
+
+        // save states keep both
+        let bytes = chip8.snapshot().to_bytes();
+        let mut loaded = Chip8::new();
+        loaded.restore(&Snapshot::from_bytes(&bytes).unwrap());
+        assert_eq!(loaded.audio_pattern(), Some(&pattern));
+        assert_eq!(loaded.pitch(), 112);
+    }
+
+    #[test]
+    fn test_mapped_io() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        // a latch that always reads 0x42 and remembers what was written
+        #[derive(Clone, Default)]
+        struct Latch(Rc<RefCell<Vec<(u16, u8)>>>);
+        impl Peripheral for Latch {
+            fn read(&mut self, _address: u16, _stored: u8) -> u8 {
+                0x42
+            }
+            fn write(&mut self, address: u16, value: u8) {
+                self.0.borrow_mut().push((address, value));
+            }
+        }
+
+        let latch = Latch::default();
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.map_io(0xF00..=0xF00, latch.clone());
+        // LD I, 0xF00; V0 = 5, V1 = 6; LD [I], V1; V0 = 0; LD V1, [I]
+        chip8
+            .load_rom(&[
+                0xAF, 0x00, 0x60, 0x05, 0x61, 0x06, 0xF1, 0x55, 0x60, 0x00, 0xF1, 0x65,
+            ])
+            .unwrap();
+        for _ in 0..6 {
+            chip8.cycle();
+        }
+        // only 0xF00 is mapped, and RAM still holds what was written
+        assert_eq!(*latch.0.borrow(), [(0xF00, 5)]);
+        assert_eq!(chip8.v_registers[..2], [0x42, 6]);
+        assert_eq!(chip8.read_byte(0xF00), Some(5));
+
+        assert!(chip8.unmap_io());
+        chip8.memory.pc = START_ADDRESS + 10;
+        chip8.cycle();
+        assert_eq!(chip8.v_registers[0], 5);
+    }
+
+    #[test]
+    fn test_second_keypad() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // V0 = 3, then EXF2 and EXF5 on it
+        chip8
+            .load_rom(&[0x60, 0x03, 0xE0, 0xF2, 0x00, 0x00, 0xE0, 0xF5])
+            .unwrap();
+        let mut keys = [false; NUM_KEYS];
+        keys[3] = true;
+        // the first keypad doesn't count
+        chip8.set_pressed_keys(keys);
+        chip8.cycle();
+        chip8.cycle();
+        assert_eq!(chip8.pc(), START_ADDRESS + 4);
+
+        chip8.set_pc(START_ADDRESS + 2);
+        chip8.set_second_keypad(keys);
+        chip8.cycle();
+        assert_eq!(chip8.pc(), START_ADDRESS + 6);
+        chip8.cycle();
+        assert_eq!(chip8.pc(), START_ADDRESS + 8);
+    }
+
+    #[test]
+    fn test_state_hash() {
+        // CALL 0x204, then RET, then V0 = 1
+        let rom = [0x22, 0x04, 0x00, 0x00, 0x00, 0xEE];
+        let mut first = Chip8::new();
+        first.init();
+        first.load_rom(&rom).unwrap();
+        let mut second = Chip8::new();
+        second.init();
+        second.load_rom(&rom).unwrap();
+        assert_eq!(first.state_hash(), second.state_hash());
+        // pinned so the hash stays the same from one version to the next
+        assert_eq!(first.state_hash(), 0x5EC9_A6BB_D86E_5EB3);
+
+        first.cycle();
+        assert_ne!(first.state_hash(), second.state_hash());
+        second.cycle();
+        assert_eq!(first.state_hash(), second.state_hash());
+
+        // the returned-from address left on the stack doesn't count
+        first.cycle();
+        second.restore(&first.snapshot());
+        second.stack.stack[0] = 0;
+        assert_eq!(first.state_hash(), second.state_hash());
+
+        second.set_delay_timer(1);
+        assert_ne!(first.state_hash(), second.state_hash());
+    }
+
+    #[test]
+    fn test_with_platform() {
+        let chip8 = Chip8::with_platform(Platform::SuperChip);
+        assert!(chip8.quirks().jump_vx);
+        assert!(!chip8.quirks().display_wait);
+        assert_eq!(
+            chip8.timing().instruction_nanos(0x6000),
+            1_000_000_000 / 1_800
+        );
+
+        let chip8 = Chip8::with_platform(Platform::CosmacVip);
+        assert!(chip8.quirks().display_wait && chip8.quirks().vf_reset);
+        assert!(chip8.quirks().shift_vy && chip8.quirks().load_store_increment);
+        assert_eq!(chip8.timing(), Timing::CosmacVip);
+        for &name in Platform::NAMES {
+            assert!(Platform::from_name(name).is_some());
+        }
+    }
+
+    #[test]
+    fn test_i_overflow_quirk() {
+        // I = 0xFFE, V0 = 3, VF = 7, then I += V0
+        let run = |i_overflow| {
+            let mut chip8 = Chip8::new();
+            chip8.init();
+            chip8.set_quirks(Quirks {
+                i_overflow,
+                ..Quirks::default()
+            });
+            chip8
+                .load_rom(&[0xAF, 0xFE, 0x60, 0x03, 0x6F, 0x07, 0xF0, 0x1E])
+                .unwrap();
+            for _ in 0..4 {
+                chip8.cycle();
+            }
+            (chip8.i_register(), chip8.v_registers[0xF])
+        };
+        assert_eq!(run(false), (0x1001, 7));
+        assert_eq!(run(true), (0x001, 1));
+    }
+
+    #[test]
+    fn test_shift_vy_quirk() {
+        // V0 = 0x81, V1 = 0x06, then 8016 or 801E
+        let run = |shift_vy, n: u8| {
+            let mut chip8 = Chip8::new();
+            chip8.init();
+            chip8.set_quirks(Quirks {
+                shift_vy,
+                ..Quirks::default()
+            });
+            chip8
+                .load_rom(&[0x60, 0x81, 0x61, 0x06, 0x80, 0x10 | n])
+                .unwrap();
+            for _ in 0..3 {
+                chip8.cycle();
+            }
+            (chip8.v_registers[0], chip8.v_registers[0xF])
+        };
+        assert_eq!(run(false, 0x6), (0x40, 1));
+        assert_eq!(run(true, 0x6), (0x03, 0));
+        assert_eq!(run(false, 0xE), (0x02, 1));
+        assert_eq!(run(true, 0xE), (0x0C, 0));
+    }
+
+    #[test]
+    fn test_load_store_increment_quirk() {
+        // I = 0x300, then FX55 storing V0-V2, then FX65 loading V0-V1
+        let run = |load_store_increment| {
+            let mut chip8 = Chip8::new();
+            chip8.init();
+            chip8.set_quirks(Quirks {
+                load_store_increment,
+                ..Quirks::default()
+            });
+            chip8
+                .load_rom(&[0xA3, 0x00, 0xF2, 0x55, 0xF1, 0x65])
+                .unwrap();
+            chip8.cycle();
+            chip8.cycle();
+            let after_store = chip8.i_register();
+            chip8.cycle();
+            (after_store, chip8.i_register())
+        };
+        assert_eq!(run(false), (0x300, 0x300));
+        assert_eq!(run(true), (0x303, 0x305));
+    }
+
+    #[test]
+    fn test_jump_vx_quirk() {
+        // V0 = 2, V3 = 4, then B300
+        let run = |jump_vx| {
+            let mut chip8 = Chip8::new();
+            chip8.init();
+            chip8.set_quirks(Quirks {
+                jump_vx,
+                ..Quirks::default()
+            });
+            chip8
+                .load_rom(&[0x60, 0x02, 0x63, 0x04, 0xB3, 0x00])
+                .unwrap();
+            for _ in 0..3 {
+                chip8.cycle();
+            }
+            chip8.pc()
+        };
+        assert_eq!(run(false), 0x302);
+        assert_eq!(run(true), 0x304);
+    }
+
+    #[test]
+    fn test_wrap_sprites_quirk() {
+        // three rows of 0xFF drawn at (60, 30)
+        let rom = [
+            0x60, 0x3C, 0x61, 0x1E, 0xA2, 0x0A, 0xD0, 0x13, 0x12, 0x08, 0xFF, 0xFF, 0xFF,
+        ];
+        let run = |wrap_sprites| {
+            let mut chip8 = Chip8::new();
+            chip8.init();
+            chip8.set_quirks(Quirks {
+                wrap_sprites,
+                ..Quirks::default()
+            });
+            chip8.load_rom(&rom).unwrap();
+            for _ in 0..4 {
+                chip8.cycle();
+            }
+            chip8
+        };
+        let lit = |chip8: &Chip8| chip8.get_display().iter().filter(|&&pixel| pixel).count();
+
+        let clipped = run(false);
+        assert_eq!(lit(&clipped), 2 * 4);
+        assert!(!clipped.get_display()[0]);
+
+        let wrapped = run(true);
+        assert_eq!(lit(&wrapped), 3 * 8);
+        assert!(wrapped.get_display()[0]);
+        assert!(wrapped.get_display()[30 * SCREEN_WIDTH + 3]);
+    }
+
+    #[test]
+    fn test_draw_completes_frame() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.set_quirks(Quirks {
+            display_wait: true,
+            ..Quirks::default()
+        });
+        // CLS, then DRW V0, V0, 5 twice
+        chip8
+            .load_rom(&[0x00, 0xE0, 0xD0, 0x05, 0xD0, 0x05])
+            .unwrap();
+        assert_eq!(chip8.cycle(), CycleResult::Continue);
+        assert_eq!(chip8.cycle(), CycleResult::DrawCompleted);
+        // the second draw waits for the next frame
+        assert_eq!(chip8.cycle(), CycleResult::Continue);
+        chip8.tick_timers();
+        assert_eq!(chip8.cycle(), CycleResult::DrawCompleted);
+    }
+
+    #[test]
+    fn test_step_back() {
+        let rom = [
+            0xA2, 0x0A, // LD I, 0x20A
+            0x60, 0x7B, // LD V0, 123
+            0xF0, 0x33, // LD B, V0
+            0x22, 0x0C, // CALL 0x20C
+            0x12, 0x08, // JP 0x208
+            0x00, 0x00, // BCD digits land here
+            0xD0, 0x05, // DRW V0, V0, 5
+            0xC1, 0xFF, // RND V1, 0xFF
+            0x00, 0xE0, // CLS
+            0x00, 0xEE, // RET
+        ];
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.load_rom(&rom).unwrap();
+        assert!(!chip8.step_back());
+        chip8.set_journal_capacity(8);
+
+        let mut hashes = Vec::new();
+        for _ in 0..9 {
+            hashes.push(chip8.state_hash());
+            chip8.cycle();
+        }
+        // only the last 8 are kept
+        assert_eq!(chip8.journal_len(), 8);
+        while let Some(hash) = hashes.pop()
+            && chip8.step_back()
+        {
+            assert_eq!(chip8.state_hash(), hash);
+        }
+        assert_eq!(chip8.pc(), START_ADDRESS + 2);
+        assert_eq!(chip8.read_range(0x20A, 2), Some(&[0x00, 0x00][..]));
+
+        // the generator is put back too, so running forward repeats itself
+        chip8.set_pc(0x20E);
+        chip8.cycle();
+        let random = chip8.v_registers[1];
+        chip8.step_back();
+        chip8.cycle();
+        assert_eq!(chip8.v_registers[1], random);
+    }
+
+    #[test]
+    fn test_exit_halts() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // 00FD, then V0 = 1, which never runs
+        chip8.load_rom(&[0x00, 0xFD, 0x60, 0x01]).unwrap();
+        let summary = chip8.run_frame(10);
+        assert!(chip8.is_halted());
+        assert_eq!(summary.cycles, 1);
+        assert_eq!(chip8.pc(), START_ADDRESS);
+        assert_eq!(chip8.cycle(), CycleResult::ProgramFinished);
+        assert_eq!(chip8.v_registers[0], 0);
+    }
+
+    #[test]
+    fn test_self_jump_finishes_with_detection() {
+        // V0 += 1, then 1202 jumps to itself
+        let rom = [0x70, 0x01, 0x12, 0x02];
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.load_rom(&rom).unwrap();
+        for _ in 0..10 {
+            assert_eq!(chip8.cycle(), CycleResult::Continue);
+        }
+
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.set_finish_detection(true);
+        chip8.load_rom(&rom).unwrap();
+        assert_eq!(chip8.cycle(), CycleResult::Continue);
+        assert_eq!(chip8.cycle(), CycleResult::ProgramFinished);
+        assert_eq!(chip8.pc(), 0x202);
+        assert_eq!(chip8.cycle(), CycleResult::ProgramFinished);
+    }
+
+    #[test]
+    fn test_unknown_opcode_policies() {
+        // V0 = 1, then 5011, which isn't an instruction (5XY0 needs a 0)
+        let rom = [0x60, 0x01, 0x50, 0x11, 0x61, 0x02];
+        let run = |policy| {
+            let mut chip8 = Chip8::new();
+            chip8.init();
+            chip8.set_unknown_opcode(policy);
+            chip8.load_rom(&rom).unwrap();
+            for _ in 0..3 {
+                chip8.cycle();
+            }
+            chip8
+        };
+
+        let mut chip8 = run(UnknownOpcode::Ignore);
+        assert_eq!((chip8.fault(), chip8.v_registers[1]), (None, 2));
+        assert!(chip8.take_warnings().is_empty());
+
+        let mut chip8 = run(UnknownOpcode::Warn);
+        assert_eq!((chip8.fault(), chip8.v_registers[1]), (None, 2));
+        let warnings = chip8.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].pc, warnings[0].opcode), (0x202, 0x5011));
+
+        let chip8 = run(UnknownOpcode::Fault);
+        assert_eq!(
+            chip8.fault(),
+            Some(Fault::UnknownOpcode {
+                pc: 0x202,
+                opcode: 0x5011
+            })
+        );
+        assert_eq!(chip8.v_registers[1], 0);
+        let diagnostic = chip8.diagnose();
+        assert_eq!(diagnostic.v_registers[0], 1);
+        let text = diagnostic.to_string();
+        assert!(text.contains("> 202  5011  ???"), "{}", text);
+        assert!(text.contains("  200  6001"), "{}", text);
+    }
+
+    #[test]
+    fn test_out_of_range_operands_do_not_panic() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // V0 = 0x1F, then EX9E and EXA1 on it, which use the low nibble
+        chip8
+            .load_rom(&[0x60, 0x1F, 0xE0, 0x9E, 0xE0, 0xA1])
+            .unwrap();
+        let mut keys = [false; NUM_KEYS];
+        keys[0xF] = true;
+        chip8.set_pressed_keys(keys);
+        chip8.cycle();
+        chip8.cycle();
+        assert_eq!(chip8.pc(), START_ADDRESS + 6);
+
+        // the program counter wraps at the end of memory
+        chip8.set_pc(0xFFE);
+        chip8.cycle();
+        assert_eq!(chip8.pc(), 0);
+        chip8.set_pc(u16::MAX);
+        chip8.cycle();
+        assert!(chip8.pc() < MEMORY_SIZE as u16);
+    }
+
+    #[test]
+    fn test_decode_cache_sees_self_modifying_code() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.set_decode_cache(true);
+        chip8
+            .load_rom(&[
+                0xA2, 0x00, // I = 0x200
+                0x60, 0x61, // V0 = 0x61
+                0x61, 0x09, // V1 = 0x09
+                0xF1, 0x55, // store V0 and V1 at 0x200, making it 6109
+                0x61, 0x00, // V1 = 0
+                0x12, 0x00, // jump to 0x200
+            ])
+            .unwrap();
+        for _ in 0..7 {
+            chip8.cycle();
+        }
+        assert_eq!(chip8.v_registers[1], 0x09);
+
+        // host writes invalidate too
+        chip8.write_byte(0x201, 0x05);
+        chip8.set_pc(0x200);
+        chip8.cycle();
+        assert_eq!(chip8.v_registers[1], 0x05);
+    }
+
+    #[test]
+    fn test_profile_counts_addresses_and_classes() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        // V0 += 1; jump back
+        chip8.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        assert!(chip8.profile().is_none());
+        chip8.set_profiling(true);
+        for _ in 0..5 {
+            chip8.cycle();
+        }
+        let profile = chip8.profile().unwrap();
+        assert_eq!(profile.total(), 5);
+        assert_eq!(profile.count_at(START_ADDRESS), 3);
+        assert_eq!(profile.hot_addresses(1), [(START_ADDRESS, 3)]);
+        assert_eq!(profile.classes(), [("7XNN", 3), ("1NNN", 2)]);
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.set_quirks(Quirks {
+            display_wait: true,
+            ..Quirks::default()
+        });
+        // LD ST, V0 with V0 = 5; draw; then V1 = 1 forever
+        let rom = [0x60, 0x05, 0xF0, 0x18, 0xD0, 0x05, 0x71, 0x01, 0x12, 0x06];
+        chip8.load_rom(&rom).unwrap();
+        chip8.take_dirty();
+
+        let frame = chip8.run_frame(10);
+        // the draw ends the frame early
+        assert_eq!(frame.cycles, 3);
+        assert!(!frame.dirty.is_empty());
+        assert!(frame.sound_playing);
+        assert_eq!(frame.sound_event, Some(SoundEvent::Started));
+        assert_eq!(frame.fault, None);
+
+        let frame = chip8.run_frame(10);
+        assert_eq!(frame.cycles, 10);
+        assert!(frame.dirty.is_empty());
+        assert_eq!(frame.sound_event, None);
+    }
+
+    #[test]
+    fn test_run_microseconds_uses_timing_model() {
+        // 6xNN then a jump back
+        let rom = [0x60, 0x01, 0x12, 0x00];
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.load_rom(&rom).unwrap();
+        chip8.set_timing(Timing::Fixed {
+            instructions_per_second: 1000,
+        });
+        assert_eq!(chip8.run_microseconds(10_000), 10);
+        // half an instruction's worth of time carries over
+        assert_eq!(chip8.run_microseconds(500), 1);
+        assert_eq!(chip8.run_microseconds(500), 0);
+
+        chip8.set_timing(Timing::CosmacVip);
+        // 6xNN takes 27us and 1NNN 105us on the VIP
+        assert_eq!(chip8.run_microseconds(132), 2);
+    }
+
+    #[test]
+    fn test_replay_reproduces_recorded_run() {
+        // V0 = random; V3 += V0; skip unless key V5 held; V4 += 1;
+        // LD DT, V0 then loop
+        let rom = [
+            0xC0, 0xFF, 0x83, 0x04, 0xE5, 0x9E, 0x74, 0x01, 0xF0, 0x15, 0x12, 0x00,
+        ];
+        let boot = |seed| {
+            let mut chip8 = Chip8::new();
+            chip8.init();
+            chip8.seed_rng(seed);
+            chip8.load_rom(&rom).unwrap();
+            chip8
+        };
+
+        let mut recorded = boot(1);
+        recorded.start_recording();
+        for i in 0..200 {
+            let mut keys = [false; NUM_KEYS];
+            keys[0] = (50..120).contains(&i);
+            recorded.set_pressed_keys(keys);
+            if i % 16 == 0 {
+                recorded.tick_timers();
+            }
+            recorded.cycle();
+        }
+        let log = recorded.stop_recording().unwrap();
+        assert!(!recorded.is_recording());
+        assert_eq!(log.cycles(), 200);
+
+        // a different seed and no frontend input: everything comes from the log
+        let mut replayed = boot(2);
+        replayed.start_replay(log);
+        for _ in 0..200 {
+            replayed.set_pressed_keys([true; NUM_KEYS]);
+            replayed.tick_timers();
+            replayed.cycle();
+        }
+        assert!(!replayed.is_replaying());
+        assert_eq!(replayed.v_registers, recorded.v_registers);
+        assert_eq!(replayed.delay_timer, recorded.delay_timer);
+        assert_eq!(replayed.memory.pc, recorded.memory.pc);
+        // past the end of the log both draw the same numbers
+        assert_eq!(replayed.random_byte(), recorded.random_byte());
+    }
+
+    #[test]
+    fn test_replay_reproduces_second_keypad() {
+        // V0 = 3; V1 += 1 unless key V0 is held on the second keypad; loop
+        let rom = [0x60, 0x03, 0xE0, 0xF2, 0x71, 0x01, 0x12, 0x02];
+        let boot = || {
+            let mut chip8 = Chip8::new();
+            chip8.init();
+            chip8.load_rom(&rom).unwrap();
+            chip8
+        };
+
+        let mut recorded = boot();
+        recorded.start_recording();
+        for i in 0..300 {
+            let mut keys = [false; NUM_KEYS];
+            keys[3] = (40..100).contains(&i);
+            recorded.set_second_keypad(keys);
+            recorded.cycle();
+        }
+        let log = recorded.stop_recording().unwrap();
+        // the log survives being saved
+        let log = InputLog::from_bytes(&log.to_bytes()).unwrap();
+
+        let mut replayed = boot();
+        replayed.start_replay(log);
+        for _ in 0..300 {
+            replayed.set_second_keypad([false; NUM_KEYS]);
+            replayed.cycle();
+        }
+        assert_ne!(recorded.v_registers[1], 0);
+        assert_eq!(replayed.v_registers, recorded.v_registers);
+        assert_eq!(replayed.memory.pc, recorded.memory.pc);
     }
 }
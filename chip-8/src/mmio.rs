@@ -0,0 +1,21 @@
+//! Memory-mapped peripherals: hardware that answers reads and writes to a
+//! range of addresses instead of plain RAM, like the COSMAC VIP's hex keypad
+//! latch or a homebrew board's extra I/O.
+
+/// A device mapped over a range of memory with `Chip8::map_io`. Both methods
+/// default to behaving like RAM, so a device only implements the side it
+/// cares about.
+pub trait Peripheral {
+    /// The byte a program reads at `address`; `stored` is the byte in RAM
+    /// there.
+    fn read(&mut self, address: u16, stored: u8) -> u8 {
+        let _ = address;
+        stored
+    }
+
+    /// Called after a program writes `value` to `address`. The byte is
+    /// stored in RAM as well, so viewers and snapshots still see it.
+    fn write(&mut self, address: u16, value: u8) {
+        let _ = (address, value);
+    }
+}
@@ -0,0 +1,82 @@
+use crate::{Quirks, Timing};
+
+/// A machine CHIP-8 programs were written for, bundling the quirks and speed
+/// they expect. Set one with `Chip8::with_platform` or `set_platform`, then
+/// adjust single quirks with `set_quirks` if a program needs it.
+///
+/// Every platform runs on the same 4K of memory and the 64x32 (or HIRES
+/// 64x64) display; the SUPER-CHIP and XO-CHIP extensions to those aren't
+/// emulated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    /// The original interpreter on the COSMAC VIP.
+    CosmacVip,
+    /// CHIP-48 on the HP-48 calculators.
+    Chip48,
+    /// SUPER-CHIP 1.1 on the HP-48 calculators.
+    SuperChip,
+    /// XO-CHIP, as implemented by Octo.
+    XoChip,
+}
+
+impl Platform {
+    /// Names accepted by `from_name`, as written on the command line.
+    pub const NAMES: &'static [&'static str] = &["vip", "chip48", "schip", "xochip"];
+
+    /// The platform called `name`, one of `NAMES`.
+    pub fn from_name(name: &str) -> Option<Platform> {
+        Some(match name {
+            "vip" => Platform::CosmacVip,
+            "chip48" => Platform::Chip48,
+            "schip" => Platform::SuperChip,
+            "xochip" => Platform::XoChip,
+            _ => return None,
+        })
+    }
+
+    pub fn quirks(self) -> Quirks {
+        let defaults = Quirks::default();
+        match self {
+            Platform::CosmacVip => Quirks {
+                display_wait: true,
+                vf_reset: true,
+                shift_vy: true,
+                load_store_increment: true,
+                ..defaults
+            },
+            // CHIP-48 advanced I by one less than the VIP on FX55 and FX65,
+            // which no program relies on, so it's left unchanged as on SUPER-CHIP
+            Platform::Chip48 | Platform::SuperChip => Quirks {
+                jump_vx: true,
+                ..defaults
+            },
+            Platform::XoChip => Quirks {
+                wrap_sprites: true,
+                shift_vy: true,
+                load_store_increment: true,
+                ..defaults
+            },
+        }
+    }
+
+    /// Instructions per second programs for the platform were paced for.
+    pub fn clock_speed(self) -> u32 {
+        match self {
+            Platform::CosmacVip => 600,
+            Platform::Chip48 => 900,
+            Platform::SuperChip => 1_800,
+            // Octo's default of 1000 instructions a frame
+            Platform::XoChip => 60_000,
+        }
+    }
+
+    /// How long instructions take, which on the VIP varies per instruction.
+    pub fn timing(self) -> Timing {
+        match self {
+            Platform::CosmacVip => Timing::CosmacVip,
+            _ => Timing::Fixed {
+                instructions_per_second: self.clock_speed(),
+            },
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use crate::MEMORY_SIZE;
+use crate::instruction::decode;
+
+/// Execution counts collected while profiling, see `Chip8::set_profiling`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    by_address: Vec<u64>,
+    by_class: BTreeMap<&'static str, u64>,
+    total: u64,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            by_address: vec![0; MEMORY_SIZE],
+            by_class: BTreeMap::new(),
+            total: 0,
+        }
+    }
+}
+
+impl Profile {
+    pub(crate) fn record(&mut self, address: u16, opcode: u16) {
+        self.by_address[address as usize % MEMORY_SIZE] += 1;
+        let class = decode(opcode).map_or("unknown", |instruction| instruction.pattern());
+        *self.by_class.entry(class).or_default() += 1;
+        self.total += 1;
+    }
+
+    /// Number of instructions executed while profiling.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Times the instruction at `address` was executed.
+    pub fn count_at(&self, address: u16) -> u64 {
+        self.by_address.get(address as usize).copied().unwrap_or(0)
+    }
+
+    /// The `limit` most executed addresses with their counts, busiest first.
+    pub fn hot_addresses(&self, limit: usize) -> Vec<(u16, u64)> {
+        let mut hot: Vec<(u16, u64)> = self
+            .by_address
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(address, &count)| (address as u16, count))
+            .collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot.truncate(limit);
+        hot
+    }
+
+    /// Execution counts per instruction class such as `8XY4`, busiest first.
+    /// Undecodable opcodes are counted as `unknown`.
+    pub fn classes(&self) -> Vec<(&'static str, u64)> {
+        let mut classes: Vec<(&'static str, u64)> = self
+            .by_class
+            .iter()
+            .map(|(&class, &count)| (class, count))
+            .collect();
+        classes.sort_by_key(|&(_, count)| Reverse(count));
+        classes
+    }
+}
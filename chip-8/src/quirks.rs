@@ -0,0 +1,72 @@
+/// Behaviour toggles for instructions whose semantics differ between
+/// CHIP-8 interpreters. The default matches the interpreter's original behaviour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quirks {
+    /// DXYN blocks until the end of the current frame (the COSMAC VIP waits
+    /// for vertical blank before drawing). The frame ends on `tick_timers`.
+    pub display_wait: bool,
+    /// 8XY1, 8XY2 and 8XY3 set VF to 0 after the operation, as on the
+    /// COSMAC VIP, where they went through the same ALU path as 8XY4.
+    pub vf_reset: bool,
+    /// DXYN wraps pixels past the right and bottom edges round to the left
+    /// and top, instead of clipping them.
+    pub wrap_sprites: bool,
+    /// BNNN jumps to XNN + VX rather than NNN + V0, as on CHIP-48 and
+    /// SUPER-CHIP.
+    pub jump_vx: bool,
+    /// FX1E sets VF when I + VX goes past 0xFFF, and keeps only the low 12
+    /// bits of I, as on the Amiga interpreter. Spacefight 2091! needs it.
+    pub i_overflow: bool,
+    /// 8XY6 and 8XYE shift VY and put the result in VX, as on the COSMAC VIP
+    /// and XO-CHIP, instead of shifting VX in place as CHIP-48 and
+    /// SUPER-CHIP do.
+    pub shift_vy: bool,
+    /// FX55 and FX65 leave I pointing past the last register stored or
+    /// loaded, as on the COSMAC VIP and XO-CHIP, instead of leaving it
+    /// unchanged as SUPER-CHIP does.
+    pub load_store_increment: bool,
+}
+
+impl Quirks {
+    /// Names accepted by `set`, as written in config files and on the
+    /// command line.
+    pub const NAMES: &'static [&'static str] = &[
+        "display_wait",
+        "vf_reset",
+        "wrap_sprites",
+        "jump_vx",
+        "i_overflow",
+        "shift_vy",
+        "load_store_increment",
+    ];
+
+    /// Whether the quirk called `name` is on, or `None` for an unknown name.
+    pub fn get(&self, name: &str) -> Option<bool> {
+        Some(match name {
+            "display_wait" => self.display_wait,
+            "vf_reset" => self.vf_reset,
+            "wrap_sprites" => self.wrap_sprites,
+            "jump_vx" => self.jump_vx,
+            "i_overflow" => self.i_overflow,
+            "shift_vy" => self.shift_vy,
+            "load_store_increment" => self.load_store_increment,
+            _ => return None,
+        })
+    }
+
+    /// Turns the quirk called `name` on or off. Returns false for an unknown
+    /// name.
+    pub fn set(&mut self, name: &str, enabled: bool) -> bool {
+        match name {
+            "display_wait" => self.display_wait = enabled,
+            "vf_reset" => self.vf_reset = enabled,
+            "wrap_sprites" => self.wrap_sprites = enabled,
+            "jump_vx" => self.jump_vx = enabled,
+            "i_overflow" => self.i_overflow = enabled,
+            "shift_vy" => self.shift_vy = enabled,
+            "load_store_increment" => self.load_store_increment = enabled,
+            _ => return false,
+        }
+        true
+    }
+}
@@ -0,0 +1,350 @@
+use alloc::vec::Vec;
+
+/// An RGBA8 colour.
+pub type Rgba = [u8; 4];
+
+pub const BLACK: Rgba = [0x00, 0x00, 0x00, 0xFF];
+pub const WHITE: Rgba = [0xFF, 0xFF, 0xFF, 0xFF];
+
+/// Number of colours addressable by two XO-CHIP bitplanes.
+pub const PALETTE_SIZE: usize = 4;
+
+/// Colours for every combination of display bitplanes, in the same order as
+/// Octo's palette settings: background, plane 1, plane 2, both planes.
+///
+/// A plain CHIP-8 display only uses the first plane, so `Palette::mono`
+/// fills the plane 2 entries with the foreground colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    colors: [Rgba; PALETTE_SIZE],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::mono(WHITE, BLACK)
+    }
+}
+
+impl Palette {
+    pub const fn mono(on: Rgba, off: Rgba) -> Self {
+        Palette {
+            colors: [off, on, on, on],
+        }
+    }
+
+    pub const fn with_planes(colors: [Rgba; PALETTE_SIZE]) -> Self {
+        Palette { colors }
+    }
+
+    /// Parses an Octo-style comma separated list of `#RRGGBB` colours,
+    /// background first. Missing plane colours default to the last one given.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut colors = [BLACK; PALETTE_SIZE];
+        let mut count = 0;
+        for part in spec.split(',') {
+            if count == PALETTE_SIZE {
+                return None;
+            }
+            colors[count] = parse_color(part.trim())?;
+            count += 1;
+        }
+        if count < 2 {
+            return None;
+        }
+        let last = colors[count - 1];
+        colors[count..].fill(last);
+        Some(Palette { colors })
+    }
+
+    /// Colour for a pixel whose set bitplanes are given as a mask (bit 0 is plane 1).
+    pub fn color(&self, planes: u8) -> Rgba {
+        self.colors[planes as usize % PALETTE_SIZE]
+    }
+
+    pub fn colors(&self) -> &[Rgba; PALETTE_SIZE] {
+        &self.colors
+    }
+}
+
+/// Expands a framebuffer into RGBA8 pixels, four bytes per pixel. Each
+/// pixel is the mask of its set bitplanes, as taken by `Palette::color`; a
+/// `bool` display only has plane 1.
+///
+/// Panics if `out` does not hold exactly four bytes per display pixel.
+pub fn render_rgba<P: Copy + Into<u8>>(planes: &[P], out: &mut [u8], palette: &Palette) {
+    assert_eq!(
+        out.len(),
+        planes.len() * 4,
+        "RGBA buffer size does not match the display"
+    );
+    for (&pixel, rgba) in planes.iter().zip(out.chunks_exact_mut(4)) {
+        rgba.copy_from_slice(&palette.color(pixel.into()));
+    }
+}
+
+/// Anti-flicker filter imitating a slow phosphor. CHIP-8 programs move
+/// sprites by erasing and redrawing them, so they are often missing from
+/// the frame that gets presented; here a pixel that goes dark fades out over
+/// a few frames instead of vanishing at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phosphor {
+    frames: u8,
+    // frames since each pixel was last lit, up to `frames`
+    ages: Vec<u8>,
+    levels: Vec<u8>,
+}
+
+impl Phosphor {
+    /// A filter that fades pixels out over `frames` frames; 1 shows each
+    /// frame as it is.
+    pub fn new(frames: u8) -> Self {
+        Phosphor {
+            frames: frames.max(1),
+            ages: Vec::new(),
+            levels: Vec::new(),
+        }
+    }
+
+    /// Adds the next frame of `display` and returns each pixel's brightness,
+    /// from 0 for dark to 255 for lit this frame. A display of a different
+    /// size starts the fade over.
+    pub fn update(&mut self, display: &[bool]) -> &[u8] {
+        if self.ages.len() != display.len() {
+            self.ages = alloc::vec![self.frames; display.len()];
+            self.levels = alloc::vec![0; display.len()];
+        }
+        let frames = u32::from(self.frames);
+        for ((&pixel, age), level) in display.iter().zip(&mut self.ages).zip(&mut self.levels) {
+            *age = if pixel {
+                0
+            } else {
+                (*age + 1).min(self.frames)
+            };
+            *level = (255 * (frames - u32::from(*age)) / frames) as u8;
+        }
+        &self.levels
+    }
+
+    /// Brightness of each pixel as of the last `update`.
+    pub fn levels(&self) -> &[u8] {
+        &self.levels
+    }
+}
+
+/// Like `render_rgba`, but for pixel brightnesses from `Phosphor`, blending
+/// between the palette's background and foreground.
+///
+/// Panics if `out` does not hold exactly four bytes per display pixel.
+pub fn render_rgba_blended(levels: &[u8], out: &mut [u8], palette: &Palette) {
+    assert_eq!(
+        out.len(),
+        levels.len() * 4,
+        "RGBA buffer size does not match the display"
+    );
+    let (on, off) = (palette.color(1), palette.color(0));
+    for (&level, rgba) in levels.iter().zip(out.chunks_exact_mut(4)) {
+        for ((channel, &on), &off) in rgba.iter_mut().zip(&on).zip(&off) {
+            let (on, off, level) = (u32::from(on), u32::from(off), u32::from(level));
+            *channel = ((on * level + off * (255 - level)) / 255) as u8;
+        }
+    }
+}
+
+/// How far to turn the display clockwise, for programs made for a screen
+/// standing on its side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Quarter,
+    Half,
+    ThreeQuarters,
+}
+
+impl Rotation {
+    /// The rotation for 0, 90, 180 or 270 degrees.
+    pub fn from_degrees(degrees: u16) -> Option<Rotation> {
+        Some(match degrees {
+            0 => Rotation::None,
+            90 => Rotation::Quarter,
+            180 => Rotation::Half,
+            270 => Rotation::ThreeQuarters,
+            _ => return None,
+        })
+    }
+
+    /// Width and height of a `width` x `height` display once turned.
+    pub fn size(self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Rotation::None | Rotation::Half => (width, height),
+            Rotation::Quarter | Rotation::ThreeQuarters => (height, width),
+        }
+    }
+}
+
+/// Turns `display`, a framebuffer `width` pixels wide, by `rotation` into
+/// `out`, which is then `rotation.size(..)` pixels wide. Works on pixels or
+/// on `Phosphor` levels alike.
+///
+/// Panics if `out` isn't the same size as `display`.
+pub fn rotate<T: Copy>(display: &[T], width: usize, rotation: Rotation, out: &mut [T]) {
+    assert_eq!(
+        out.len(),
+        display.len(),
+        "rotated buffer size does not match the display"
+    );
+    let height = display.len() / width;
+    let (turned_width, _) = rotation.size(width, height);
+    for (index, &pixel) in display.iter().enumerate() {
+        let (x, y) = (index % width, index / width);
+        let (to_x, to_y) = match rotation {
+            Rotation::None => (x, y),
+            Rotation::Quarter => (height - 1 - y, x),
+            Rotation::Half => (width - 1 - x, height - 1 - y),
+            Rotation::ThreeQuarters => (y, width - 1 - x),
+        };
+        out[to_y * turned_width + to_x] = pixel;
+    }
+}
+
+/// Parses a `#RRGGBB` or `RRGGBB` hex colour.
+pub fn parse_color(s: &str) -> Option<Rgba> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?, 0xFF])
+}
+
+/// How the foreground entries of a palette are animated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CycleMode {
+    /// Rotate the plane colours (1 -> 2 -> 3 -> 1) each step.
+    Rotate,
+    /// Swap the plane 1 and plane 2 colours each step.
+    SwapPlanes,
+}
+
+/// Palette cycling effect, advanced once per frame by the frontend.
+/// The background colour is never cycled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaletteCycle {
+    base: Palette,
+    mode: CycleMode,
+    frames_per_step: u32,
+    frame: u32,
+    step: u32,
+}
+
+impl PaletteCycle {
+    pub fn new(base: Palette, mode: CycleMode, frames_per_step: u32) -> Self {
+        PaletteCycle {
+            base,
+            mode,
+            frames_per_step: frames_per_step.max(1),
+            frame: 0,
+            step: 0,
+        }
+    }
+
+    /// Advances the effect by one frame and returns the palette to render it with.
+    pub fn next_frame(&mut self) -> Palette {
+        let palette = self.current();
+        self.frame += 1;
+        if self.frame >= self.frames_per_step {
+            self.frame = 0;
+            self.step = self.step.wrapping_add(1);
+        }
+        palette
+    }
+
+    pub fn current(&self) -> Palette {
+        let mut colors = self.base.colors;
+        match self.mode {
+            CycleMode::Rotate => {
+                colors[1..].rotate_right(self.step as usize % (PALETTE_SIZE - 1));
+            }
+            CycleMode::SwapPlanes => {
+                if self.step % 2 == 1 {
+                    colors.swap(1, 2);
+                }
+            }
+        }
+        Palette { colors }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_palette() {
+        let palette = Palette::parse("#000000, #FF0000, 00FF00").unwrap();
+        assert_eq!(palette.color(0), BLACK);
+        assert_eq!(palette.color(1), [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(palette.color(3), [0x00, 0xFF, 0x00, 0xFF]);
+        assert!(Palette::parse("#000000").is_none());
+        assert!(Palette::parse("#00000G,#FFFFFF").is_none());
+    }
+
+    #[test]
+    fn test_render_rgba() {
+        let display = [false, true];
+        let mut out = [0; 8];
+        let palette = Palette::mono([1, 2, 3, 4], [5, 6, 7, 8]);
+        render_rgba(&display, &mut out, &palette);
+        assert_eq!(out, [5, 6, 7, 8, 1, 2, 3, 4]);
+
+        let planes: [u8; 4] = [0, 1, 2, 3];
+        let mut out = [0; 16];
+        let palette = Palette::parse("#000000,#FF0000,#00FF00,#0000FF").unwrap();
+        render_rgba(&planes, &mut out, &palette);
+        assert_eq!(out[8..12], [0x00, 0xFF, 0x00, 0xFF]);
+        assert_eq!(out[12..], [0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_phosphor_fades_out() {
+        let mut phosphor = Phosphor::new(3);
+        assert_eq!(phosphor.update(&[true, false]), [255, 0]);
+        assert_eq!(phosphor.update(&[false, false]), [170, 0]);
+        assert_eq!(phosphor.update(&[false, true]), [85, 255]);
+        assert_eq!(phosphor.update(&[false, false]), [0, 170]);
+
+        let mut out = [0; 8];
+        let palette = Palette::mono([255, 255, 255, 255], [0, 0, 0, 255]);
+        render_rgba_blended(phosphor.levels(), &mut out, &palette);
+        assert_eq!(out, [0, 0, 0, 255, 170, 170, 170, 255]);
+    }
+
+    #[test]
+    fn test_rotate() {
+        // 3x2, with the top left and bottom right corners lit
+        let display = [1, 0, 0, 0, 0, 2];
+        let mut out = [0; 6];
+        rotate(&display, 3, Rotation::Quarter, &mut out);
+        assert_eq!(out, [0, 1, 0, 0, 2, 0]);
+        rotate(&display, 3, Rotation::Half, &mut out);
+        assert_eq!(out, [2, 0, 0, 0, 0, 1]);
+        rotate(&display, 3, Rotation::ThreeQuarters, &mut out);
+        assert_eq!(out, [0, 2, 0, 0, 1, 0]);
+        assert_eq!(Rotation::Quarter.size(3, 2), (2, 3));
+        assert_eq!(Rotation::from_degrees(45), None);
+    }
+
+    #[test]
+    fn test_palette_cycle_rotates_foreground() {
+        let red = [0xFF, 0, 0, 0xFF];
+        let green = [0, 0xFF, 0, 0xFF];
+        let blue = [0, 0, 0xFF, 0xFF];
+        let base = Palette::with_planes([BLACK, red, green, blue]);
+        let mut cycle = PaletteCycle::new(base, CycleMode::Rotate, 2);
+
+        assert_eq!(cycle.next_frame(), base);
+        assert_eq!(cycle.next_frame(), base);
+        let rotated = cycle.next_frame();
+        assert_eq!(rotated.colors(), &[BLACK, blue, red, green]);
+    }
+}
@@ -0,0 +1,361 @@
+//! Input recording and deterministic replay.
+//!
+//! A recording captures everything that reaches the emulator from outside
+//! while it runs: keypad changes, timer ticks and the values CXNN drew from
+//! the random number generator, each tied to the cycle it happened on, and
+//! the generator's state when it began. Replaying the log from the same
+//! starting state reproduces the run exactly, and leaves the generator where
+//! the recorded run left it.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{NUM_KEYS, SoundEvent};
+
+const MAGIC: &[u8; 4] = b"C8IN";
+const VERSION: u8 = 3;
+// version 2 had no second keypad events, which are read the same way
+const VERSION_WITHOUT_KEYS2: u8 = 2;
+// version 1 had no generator state
+const VERSION_WITHOUT_RNG: u8 = 1;
+
+const TAG_KEYS: u8 = 0;
+const TAG_TIMER_TICK: u8 = 1;
+const TAG_KEYS2: u8 = 2;
+
+/// Something that happened to the emulator between two cycles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// The keypad changed; bit `n` is set while key `n` is held.
+    Keys(u16),
+    /// The second keypad changed, see `Chip8::set_second_keypad`.
+    Keys2(u16),
+    /// `Chip8::tick_timers` was called.
+    TimerTick,
+}
+
+/// A recorded session, see `Chip8::start_recording`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputLog {
+    // (cycle the event happened before, event), in order
+    events: Vec<(u64, InputEvent)>,
+    random: Vec<u8>,
+    cycles: u64,
+    // state of the random number generator when recording started
+    rng: Option<u64>,
+}
+
+impl InputLog {
+    /// Number of cycles the recording covers.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn events(&self) -> &[(u64, InputEvent)] {
+        &self.events
+    }
+
+    /// Encodes the log in a compact binary format for saving to disk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.rng.unwrap_or_default().to_le_bytes());
+        write_varint(&mut out, self.cycles);
+        write_varint(&mut out, self.events.len() as u64);
+        let mut last_cycle = 0;
+        for &(cycle, event) in &self.events {
+            write_varint(&mut out, cycle - last_cycle);
+            last_cycle = cycle;
+            match event {
+                InputEvent::Keys(mask) => {
+                    out.push(TAG_KEYS);
+                    out.extend_from_slice(&mask.to_le_bytes());
+                }
+                InputEvent::TimerTick => out.push(TAG_TIMER_TICK),
+                InputEvent::Keys2(mask) => {
+                    out.push(TAG_KEYS2);
+                    out.extend_from_slice(&mask.to_le_bytes());
+                }
+            }
+        }
+        write_varint(&mut out, self.random.len() as u64);
+        out.extend_from_slice(&self.random);
+        out
+    }
+
+    /// Decodes a log written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<InputLog, ReplayError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+        let version = reader.byte()?;
+        if !matches!(
+            version,
+            VERSION | VERSION_WITHOUT_KEYS2 | VERSION_WITHOUT_RNG
+        ) {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let rng = if version != VERSION_WITHOUT_RNG {
+            let state = reader.take(8)?;
+            Some(u64::from_le_bytes(state.try_into().unwrap()))
+        } else {
+            None
+        };
+        let cycles = reader.varint()?;
+
+        let count = reader.varint()?;
+        let mut events = Vec::new();
+        let mut cycle = 0u64;
+        for _ in 0..count {
+            cycle = cycle
+                .checked_add(reader.varint()?)
+                .ok_or(ReplayError::Truncated)?;
+            let event = match reader.byte()? {
+                TAG_KEYS => {
+                    let mask = reader.take(2)?;
+                    InputEvent::Keys(u16::from_le_bytes([mask[0], mask[1]]))
+                }
+                TAG_TIMER_TICK => InputEvent::TimerTick,
+                TAG_KEYS2 => {
+                    let mask = reader.take(2)?;
+                    InputEvent::Keys2(u16::from_le_bytes([mask[0], mask[1]]))
+                }
+                tag => return Err(ReplayError::BadEvent(tag)),
+            };
+            events.push((cycle, event));
+        }
+
+        let len = usize::try_from(reader.varint()?).map_err(|_| ReplayError::Truncated)?;
+        let random = reader.take(len)?.to_vec();
+        Ok(InputLog {
+            events,
+            random,
+            cycles,
+            rng,
+        })
+    }
+}
+
+/// Why an encoded `InputLog` couldn't be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    BadEvent(u8),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::BadMagic => write!(f, "not a CHIP-8 input recording"),
+            ReplayError::UnsupportedVersion(v) => write!(f, "unsupported recording version {}", v),
+            ReplayError::Truncated => write!(f, "recording is truncated"),
+            ReplayError::BadEvent(tag) => write!(f, "unknown event type {}", tag),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ReplayError {}
+
+/// What the emulator is doing with its inputs.
+#[derive(Default)]
+pub(crate) enum InputMode {
+    #[default]
+    Live,
+    Recording(Recorder),
+    Replaying(Player),
+}
+
+pub(crate) struct Recorder {
+    log: InputLog,
+    last_keys: Option<u16>,
+    last_second_keys: Option<u16>,
+}
+
+impl Recorder {
+    pub fn new(rng: u64) -> Self {
+        Recorder {
+            log: InputLog {
+                rng: Some(rng),
+                ..InputLog::default()
+            },
+            last_keys: None,
+            last_second_keys: None,
+        }
+    }
+
+    /// Notes the state of both keypads a cycle is about to run with.
+    pub fn cycle(&mut self, keys: &[bool; NUM_KEYS], second_keys: &[bool; NUM_KEYS]) {
+        let mask = pack_keys(keys);
+        if self.last_keys != Some(mask) {
+            self.last_keys = Some(mask);
+            self.log
+                .events
+                .push((self.log.cycles, InputEvent::Keys(mask)));
+        }
+        let mask = pack_keys(second_keys);
+        if self.last_second_keys != Some(mask) {
+            self.last_second_keys = Some(mask);
+            self.log
+                .events
+                .push((self.log.cycles, InputEvent::Keys2(mask)));
+        }
+        self.log.cycles += 1;
+    }
+
+    pub fn timer_tick(&mut self) {
+        self.log
+            .events
+            .push((self.log.cycles, InputEvent::TimerTick));
+    }
+
+    pub fn random(&mut self, value: u8) {
+        self.log.random.push(value);
+    }
+
+    pub fn finish(self) -> InputLog {
+        self.log
+    }
+}
+
+pub(crate) struct Player {
+    log: InputLog,
+    cycle: u64,
+    next_event: usize,
+    next_random: usize,
+    // from the last replayed timer tick, handed out by the next tick_timers call
+    pub sound_event: Option<SoundEvent>,
+}
+
+impl Player {
+    pub fn new(log: InputLog) -> Self {
+        Player {
+            log,
+            cycle: 0,
+            next_event: 0,
+            next_random: 0,
+            sound_event: None,
+        }
+    }
+
+    /// The next event due before the current cycle runs, if any.
+    pub fn next_event(&mut self) -> Option<InputEvent> {
+        let &(cycle, event) = self.log.events.get(self.next_event)?;
+        if cycle > self.cycle {
+            return None;
+        }
+        self.next_event += 1;
+        Some(event)
+    }
+
+    pub fn has_cycles(&self) -> bool {
+        self.log.cycles > 0
+    }
+
+    /// Moves on to the next cycle, returning false once the log is used up.
+    pub fn advance(&mut self) -> bool {
+        self.cycle += 1;
+        self.cycle < self.log.cycles
+    }
+
+    /// The generator state the recording started from, if it has one.
+    pub fn rng(&self) -> Option<u64> {
+        self.log.rng
+    }
+
+    pub fn random(&mut self) -> Option<u8> {
+        let value = *self.log.random.get(self.next_random)?;
+        self.next_random += 1;
+        Some(value)
+    }
+}
+
+pub(crate) fn pack_keys(keys: &[bool; NUM_KEYS]) -> u16 {
+    keys.iter()
+        .enumerate()
+        .fold(0, |mask, (i, &held)| mask | (held as u16) << i)
+}
+
+pub(crate) fn unpack_keys(mask: u16) -> [bool; NUM_KEYS] {
+    core::array::from_fn(|i| mask & (1 << i) != 0)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ReplayError> {
+        if self.bytes.len() < len {
+            return Err(ReplayError::Truncated);
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, ReplayError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ReplayError::Truncated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_round_trips_through_bytes() {
+        let log = InputLog {
+            events: alloc::vec![
+                (0, InputEvent::Keys(0)),
+                (300, InputEvent::TimerTick),
+                (300, InputEvent::Keys(0b1000_0000_0000_0001)),
+                (300, InputEvent::Keys2(0b0000_0000_0000_1000)),
+                (100_000, InputEvent::TimerTick),
+            ],
+            random: alloc::vec![1, 2, 255],
+            cycles: 100_001,
+            rng: Some(0x1234_5678_9ABC_DEF0),
+        };
+        let bytes = log.to_bytes();
+        assert_eq!(InputLog::from_bytes(&bytes), Ok(log));
+        assert_eq!(
+            InputLog::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ReplayError::Truncated)
+        );
+        assert_eq!(InputLog::from_bytes(b"nope"), Err(ReplayError::BadMagic));
+    }
+
+    #[test]
+    fn test_pack_keys() {
+        let mut keys = [false; NUM_KEYS];
+        keys[0] = true;
+        keys[0xF] = true;
+        assert_eq!(pack_keys(&keys), 0x8001);
+        assert_eq!(unpack_keys(0x8001), keys);
+    }
+}
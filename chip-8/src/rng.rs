@@ -0,0 +1,41 @@
+/// Small xorshift generator backing CXNN. Keeping the generator inside the
+/// emulator makes runs reproducible from a seed and works without `std`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct XorShift {
+    state: u64,
+}
+
+// used when no entropy source is available
+const DEFAULT_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+impl Default for XorShift {
+    fn default() -> Self {
+        #[cfg(feature = "rand")]
+        let seed = rand::random::<u64>();
+        #[cfg(not(feature = "rand"))]
+        let seed = DEFAULT_SEED;
+        XorShift::new(seed)
+    }
+}
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        // an all-zero state would only ever produce zeros
+        let state = if seed == 0 { DEFAULT_SEED } else { seed };
+        XorShift { state }
+    }
+
+    /// The generator's state, which `new` takes back to resume it.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        (x >> 56) as u8
+    }
+}
@@ -0,0 +1,116 @@
+//! Known ROMs and the settings they need, looked up by SHA-1 hash.
+//!
+//! Hashes are the same as in the community CHIP-8 database
+//! (<https://github.com/chip-8/chip-8-database>), so entries can be copied
+//! from its `sha1-hashes.json` and `programs.json`.
+
+use alloc::string::ToString;
+
+use crate::{Platform, Quirks};
+
+/// Settings a ROM is known to need.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomInfo {
+    pub title: &'static str,
+    pub quirks: Quirks,
+    /// Recommended instructions per second, if the default doesn't suit it.
+    pub clock_speed: Option<u32>,
+    /// The machine the ROM was written for, if known. Its clock speed is
+    /// used when `clock_speed` is `None`.
+    pub platform: Option<Platform>,
+}
+
+// the emulator's default quirks; the ROMs here don't depend on any of them
+const DEFAULT_QUIRKS: Quirks = Quirks {
+    display_wait: false,
+    vf_reset: false,
+    wrap_sprites: false,
+    jump_vx: false,
+    i_overflow: false,
+    shift_vy: false,
+    load_store_increment: false,
+};
+
+// (lowercase hex SHA-1 of the ROM file, info), sorted by hash
+const ROMS: &[(&str, RomInfo)] = &[
+    (
+        "1ba58656810b67fd131eb9af3e3987863bf26c90",
+        RomInfo {
+            title: "IBM Logo",
+            quirks: DEFAULT_QUIRKS,
+            clock_speed: None,
+            platform: None,
+        },
+    ),
+    (
+        "7b647841020b5eba8040df1e5e5f4a5c121ba5ab",
+        RomInfo {
+            title: "Keypad Test",
+            quirks: DEFAULT_QUIRKS,
+            clock_speed: None,
+            platform: None,
+        },
+    ),
+    // its frames are paced by the delay timer, but at the default clock
+    // speed the game logic takes longer than the two ticks it waits for
+    (
+        "924faafd159c79d982497ffc602d35bb323d9742",
+        RomInfo {
+            title: "Squash",
+            quirks: DEFAULT_QUIRKS,
+            clock_speed: Some(1_000),
+            platform: None,
+        },
+    ),
+    (
+        "b0a328e04455fc223c268862cccbd281dfd711d0",
+        RomInfo {
+            title: "Hex Digits",
+            quirks: DEFAULT_QUIRKS,
+            clock_speed: None,
+            platform: None,
+        },
+    ),
+];
+
+/// Looks up a ROM in the embedded database.
+pub fn lookup(rom: &[u8]) -> Option<&'static RomInfo> {
+    let hash = sha1_smol::Sha1::from(rom).digest().to_string();
+    ROMS.iter()
+        .find(|(sha1, _)| *sha1 == hash)
+        .map(|(_, info)| info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_by_hash() {
+        let ibm = include_bytes!("../../roms/IBM Logo.ch8");
+        assert_eq!(lookup(ibm).map(|info| info.title), Some("IBM Logo"));
+        assert_eq!(lookup(&[0x12, 0x00]), None);
+    }
+
+    #[test]
+    fn test_bundled_roms_are_known() {
+        let squash = lookup(include_bytes!("../../roms/Squash.ch8")).unwrap();
+        assert_eq!(squash.title, "Squash");
+        assert_eq!(squash.clock_speed, Some(1_000));
+        let digits = lookup(include_bytes!("../../roms/Hex Digits.ch8")).unwrap();
+        assert_eq!(digits.title, "Hex Digits");
+        let keypad = lookup(include_bytes!("../../roms/Keypad Test.ch8")).unwrap();
+        assert_eq!(keypad.title, "Keypad Test");
+    }
+
+    #[test]
+    fn test_hashes_are_sorted_lowercase_sha1() {
+        for pair in ROMS.windows(2) {
+            assert!(pair[0].0 < pair[1].0, "{} is out of order", pair[1].0);
+        }
+        for (sha1, _) in ROMS {
+            assert_eq!(sha1.len(), 40);
+            assert!(sha1.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')));
+        }
+    }
+}
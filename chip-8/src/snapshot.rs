@@ -0,0 +1,324 @@
+//! Copies of the machine state and a rewind buffer built on them.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::audio::{DEFAULT_PITCH, PATTERN_SIZE};
+use crate::diff::{self, StateDiff};
+use crate::rng::XorShift;
+use crate::{
+    Chip8, HIRES_SCREEN_HEIGHT, MEMORY_SIZE, Memory, NUM_REGISTERS, SCREEN_HEIGHT, STACK_SIZE,
+    Screen, Stack,
+};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 3;
+// version 1 had no XO-CHIP audio and version 2 no random number generator;
+// such states load with none and a freshly seeded one
+const VERSION_WITHOUT_AUDIO: u8 = 1;
+const VERSION_WITHOUT_RNG: u8 = 2;
+
+const FLAG_WAITING_FOR_FRAME: u8 = 1;
+const FLAG_HALTED: u8 = 2;
+const FLAG_AUDIO_PATTERN: u8 = 4;
+
+/// Everything needed to put an emulator back where it was: memory,
+/// registers, stack, timers, XO-CHIP audio, display and the random number
+/// generator.
+/// Configuration such as quirks and hooks is not included.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub(crate) memory: Memory,
+    pub(crate) screen: Screen,
+    pub(crate) v_registers: [u8; NUM_REGISTERS],
+    pub(crate) i_register: u16,
+    pub(crate) stack: Stack,
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+    pub(crate) audio_pattern: Option<[u8; PATTERN_SIZE]>,
+    pub(crate) pitch: u8,
+    pub(crate) rng: XorShift,
+    pub(crate) waiting_for_frame: bool,
+    pub(crate) halted: bool,
+}
+
+impl Snapshot {
+    pub fn pc(&self) -> u16 {
+        self.memory.pc
+    }
+
+    /// Everything that differs between this state and `other`.
+    pub fn diff(&self, other: &Snapshot) -> StateDiff {
+        diff::diff(self.into(), other.into())
+    }
+
+    /// Encodes the state in a binary format for saving to disk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.memory.data);
+        out.extend_from_slice(&self.memory.pc.to_le_bytes());
+        out.push(self.screen.height as u8);
+        for row in &self.screen.rows[..self.screen.height] {
+            out.extend_from_slice(&row.to_le_bytes());
+        }
+        out.extend_from_slice(&self.v_registers);
+        out.extend_from_slice(&self.i_register.to_le_bytes());
+        out.push(self.stack.sp as u8);
+        for address in &self.stack.stack {
+            out.extend_from_slice(&address.to_le_bytes());
+        }
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        let mut flags = 0;
+        if self.waiting_for_frame {
+            flags |= FLAG_WAITING_FOR_FRAME;
+        }
+        if self.halted {
+            flags |= FLAG_HALTED;
+        }
+        if self.audio_pattern.is_some() {
+            flags |= FLAG_AUDIO_PATTERN;
+        }
+        out.push(flags);
+        out.extend_from_slice(&self.audio_pattern.unwrap_or_default());
+        out.push(self.pitch);
+        out.extend_from_slice(&self.rng.state().to_le_bytes());
+        out
+    }
+
+    /// Decodes a state written by `to_bytes`.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+        let bytes = &mut bytes;
+        if take(bytes, MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = take(bytes, 1)?[0];
+        if !matches!(
+            version,
+            VERSION | VERSION_WITHOUT_AUDIO | VERSION_WITHOUT_RNG
+        ) {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let mut memory = Memory::default();
+        memory.data.copy_from_slice(take(bytes, MEMORY_SIZE)?);
+        memory.pc = read_u16(bytes)?;
+
+        let height = take(bytes, 1)?[0] as usize;
+        if height != SCREEN_HEIGHT && height != HIRES_SCREEN_HEIGHT {
+            return Err(SnapshotError::Invalid);
+        }
+        let mut screen = Screen::default();
+        screen.set_height(height);
+        for y in 0..height {
+            let row = take(bytes, 8)?;
+            screen.set_row(y, u64::from_le_bytes(row.try_into().unwrap()));
+        }
+
+        let mut v_registers = [0; NUM_REGISTERS];
+        v_registers.copy_from_slice(take(bytes, NUM_REGISTERS)?);
+        let i_register = read_u16(bytes)?;
+        let mut stack = Stack {
+            sp: u16::from(take(bytes, 1)?[0]),
+            ..Stack::default()
+        };
+        if stack.sp as usize > STACK_SIZE {
+            return Err(SnapshotError::Invalid);
+        }
+        for address in &mut stack.stack {
+            *address = read_u16(bytes)?;
+        }
+        let timers_and_flags = take(bytes, 3)?;
+        let flags = timers_and_flags[2];
+        let (audio_pattern, pitch) = if version == VERSION_WITHOUT_AUDIO {
+            (None, DEFAULT_PITCH)
+        } else {
+            let pattern: [u8; PATTERN_SIZE] = take(bytes, PATTERN_SIZE)?.try_into().unwrap();
+            let pitch = take(bytes, 1)?[0];
+            ((flags & FLAG_AUDIO_PATTERN != 0).then_some(pattern), pitch)
+        };
+        let rng = if version == VERSION {
+            XorShift::new(u64::from_le_bytes(take(bytes, 8)?.try_into().unwrap()))
+        } else {
+            XorShift::default()
+        };
+        Ok(Snapshot {
+            memory,
+            screen,
+            v_registers,
+            i_register,
+            stack,
+            delay_timer: timers_and_flags[0],
+            sound_timer: timers_and_flags[1],
+            audio_pattern,
+            pitch,
+            rng,
+            waiting_for_frame: flags & FLAG_WAITING_FOR_FRAME != 0,
+            halted: flags & FLAG_HALTED != 0,
+        })
+    }
+}
+
+/// Why an encoded `Snapshot` couldn't be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    /// A value is out of range, like a stack deeper than the stack.
+    Invalid,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::BadMagic => write!(f, "not a CHIP-8 save state"),
+            SnapshotError::UnsupportedVersion(v) => {
+                write!(f, "unsupported save state version {}", v)
+            }
+            SnapshotError::Truncated => write!(f, "save state is truncated"),
+            SnapshotError::Invalid => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SnapshotError {}
+
+// the next `len` bytes, moving past them
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], SnapshotError> {
+    let (head, rest) = bytes
+        .split_at_checked(len)
+        .ok_or(SnapshotError::Truncated)?;
+    *bytes = rest;
+    Ok(head)
+}
+
+fn read_u16(bytes: &mut &[u8]) -> Result<u16, SnapshotError> {
+    let pair = take(bytes, 2)?;
+    Ok(u16::from_le_bytes([pair[0], pair[1]]))
+}
+
+/// Bounded history of snapshots for stepping backwards through a run.
+///
+/// Call `record` once per frame; every `interval` frames it keeps a snapshot,
+/// dropping the oldest once `capacity` are stored.
+pub struct Rewind {
+    snapshots: VecDeque<Snapshot>,
+    capacity: usize,
+    interval: u32,
+    frames: u32,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize, interval: u32) -> Self {
+        Rewind {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+            interval: interval.max(1),
+            frames: 0,
+        }
+    }
+
+    pub fn record(&mut self, chip8: &Chip8) {
+        self.frames += 1;
+        if self.frames < self.interval || self.capacity == 0 {
+            return;
+        }
+        self.frames = 0;
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(chip8.snapshot());
+    }
+
+    /// Restores the most recent snapshot and forgets it, so repeated calls
+    /// walk further back. Returns false when there is nothing left.
+    pub fn step_back(&mut self, chip8: &mut Chip8) -> bool {
+        match self.snapshots.pop_back() {
+            Some(snapshot) => {
+                chip8.restore(&snapshot);
+                self.frames = 0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of snapshots available to step back through.
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.frames = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_round_trips_through_bytes() {
+        // V0 = 5, call 206, draw V0's glyph at (5, 5), then spin
+        let rom = [
+            0x60, 0x05, 0x22, 0x06, 0x00, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x0A,
+        ];
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.load_rom(&rom).unwrap();
+        for _ in 0..5 {
+            chip8.cycle();
+        }
+        chip8.set_delay_timer(9);
+        let bytes = chip8.snapshot().to_bytes();
+
+        let mut loaded = Chip8::new();
+        loaded.restore(&Snapshot::from_bytes(&bytes).unwrap());
+        assert!(chip8.diff(&loaded).is_empty());
+        assert_eq!(loaded.get_display(), chip8.get_display());
+        assert_eq!(loaded.call_stack(), &[0x204]);
+        // both go on to draw the same random numbers
+        assert_eq!(loaded.random_byte(), chip8.random_byte());
+
+        assert_eq!(
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1]).err(),
+            Some(SnapshotError::Truncated)
+        );
+        assert_eq!(
+            Snapshot::from_bytes(b"C8IN").err(),
+            Some(SnapshotError::BadMagic)
+        );
+    }
+
+    #[test]
+    fn test_rewind_steps_back_through_snapshots() {
+        // V0 += 1 forever
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.load_rom(&rom).unwrap();
+
+        let mut rewind = Rewind::new(2, 2);
+        for _ in 0..6 {
+            chip8.cycle();
+            chip8.cycle();
+            rewind.record(&chip8);
+        }
+        // snapshots were taken after frames 2, 4 and 6; only the last two remain
+        assert_eq!(rewind.len(), 2);
+        assert!(rewind.step_back(&mut chip8));
+        assert_eq!(chip8.v_registers()[0], 6);
+        assert!(rewind.step_back(&mut chip8));
+        assert_eq!(chip8.v_registers()[0], 4);
+        assert!(!rewind.step_back(&mut chip8));
+    }
+}
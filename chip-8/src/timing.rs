@@ -0,0 +1,67 @@
+/// How long instructions take, used by `Chip8::run_microseconds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// Every instruction takes the same time.
+    Fixed { instructions_per_second: u32 },
+    /// Per-instruction costs approximating the original COSMAC VIP
+    /// interpreter, whose instructions range from tens of microseconds for
+    /// register loads to milliseconds for sprite drawing.
+    CosmacVip,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing::Fixed {
+            instructions_per_second: 700,
+        }
+    }
+}
+
+impl Timing {
+    /// Time taken by `opcode`, in nanoseconds.
+    pub fn instruction_nanos(self, opcode: u16) -> u64 {
+        match self {
+            Timing::Fixed {
+                instructions_per_second,
+            } => 1_000_000_000 / u64::from(instructions_per_second.max(1)),
+            Timing::CosmacVip => vip_micros(opcode) * 1_000,
+        }
+    }
+}
+
+// average execution times on the VIP; drawing includes the wait for the
+// display interrupt, and skips and sprite positions vary a little in practice
+fn vip_micros(opcode: u16) -> u64 {
+    match (opcode >> 12, opcode & 0x00FF) {
+        (0x0, 0xE0) => 109,
+        (0x0, 0xEE) => 105,
+        (0x0, _) => 105,
+        (0x1, _) | (0x2, _) | (0xB, _) => 105,
+        (0x3, _) | (0x4, _) | (0xA, _) => 55,
+        (0x5, _) | (0x9, _) | (0xE, _) => 73,
+        (0x6, _) => 27,
+        (0x7, _) => 45,
+        (0x8, _) => 200,
+        (0xC, _) => 164,
+        (0xD, _) => 22_734,
+        (0xF, 0x1E) => 86,
+        (0xF, 0x29) => 91,
+        (0xF, 0x33) => 927,
+        (0xF, 0x55) | (0xF, 0x65) => 605,
+        // FX07, FX0A, FX15 and FX18
+        _ => 45,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vip_costs_differ_per_instruction() {
+        let vip = Timing::CosmacVip;
+        assert!(vip.instruction_nanos(0x6012) < vip.instruction_nanos(0x8014));
+        assert!(vip.instruction_nanos(0x8014) < vip.instruction_nanos(0xD015));
+        assert_eq!(Timing::default().instruction_nanos(0xD015), 1_428_571);
+    }
+}
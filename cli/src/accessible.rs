@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+
+use chip_8::{Chip8, DirtyRows, SoundEvent};
+
+// how long the display must stay unchanged before it is reported as idle
+const IDLE_AFTER: Duration = Duration::from_secs(5);
+
+/// Memory region holding the game's score. Multi-byte scores are read as
+/// one decimal digit per byte, the layout FX33 produces.
+pub struct ScoreRegion {
+    pub address: u16,
+    pub len: u16,
+}
+
+/// Produces short text lines describing what the game is doing, for use in
+/// place of the full-screen display.
+pub struct StatusReporter {
+    score: Option<ScoreRegion>,
+    last_score: Option<u32>,
+    last_change: Instant,
+    idle: bool,
+}
+
+impl StatusReporter {
+    pub fn new(score: Option<ScoreRegion>) -> Self {
+        StatusReporter {
+            score,
+            last_score: None,
+            last_change: Instant::now(),
+            idle: false,
+        }
+    }
+
+    /// Call once per frame; returns the lines to announce.
+    pub fn update(
+        &mut self,
+        chip8: &Chip8,
+        dirty: DirtyRows,
+        sound: Option<SoundEvent>,
+    ) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        if sound == Some(SoundEvent::Started) {
+            lines.push("Beep".to_string());
+        }
+
+        if let Some(score) = self.read_score(chip8)
+            && self.last_score != Some(score)
+        {
+            self.last_score = Some(score);
+            lines.push(format!("Score: {}", score));
+        }
+
+        // a display that stops changing usually means a title, pause or game over screen
+        if !dirty.is_empty() {
+            self.last_change = Instant::now();
+            if self.idle {
+                self.idle = false;
+                lines.push("Screen active".to_string());
+            }
+        } else if !self.idle && self.last_change.elapsed() >= IDLE_AFTER {
+            self.idle = true;
+            lines.push("Screen idle, the game may be over or waiting for input".to_string());
+        }
+
+        lines
+    }
+
+    fn read_score(&self, chip8: &Chip8) -> Option<u32> {
+        let region = self.score.as_ref()?;
+        (0..region.len).try_fold(0u32, |score, offset| {
+            let digit = *chip8
+                .memory()
+                .get(region.address.checked_add(offset)? as usize)?;
+            Some(score.wrapping_mul(10).wrapping_add(digit as u32))
+        })
+    }
+}
@@ -0,0 +1,70 @@
+//! ROMs inside zip archives, which is how ROM packs are usually shared. A
+//! ROM in an archive has a path like `pack.zip/games/pong.ch8`, so it can be
+//! given on the command line and browsed like a directory in the picker.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use chip_8::MAX_ROM_SIZE;
+use zip::ZipArchive;
+
+use crate::kiosk::has_rom_extension;
+
+/// Whether `path` is a zip archive.
+pub fn is_zip(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// Splits a path to a ROM inside an archive into the archive's path and the
+/// ROM's name within it. `None` for a path that isn't inside an archive.
+pub fn split(path: &Path) -> Option<(&Path, String)> {
+    let archive = path.ancestors().skip(1).find(|dir| is_zip(dir))?;
+    let entry = path.strip_prefix(archive).ok()?;
+    let names: Vec<_> = entry.iter().map(|part| part.to_string_lossy()).collect();
+    Some((archive, names.join("/")))
+}
+
+/// Paths of the ROMs in the archive at `path`, sorted by name.
+pub fn roms(path: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let archive = open(path)?;
+    let mut names = Vec::new();
+    for name in archive.file_names() {
+        let name = name.map_err(|e| format!("{}: {}", path.display(), e))?;
+        if !name.ends_with('/') && has_rom_extension(Path::new(name.as_ref())) {
+            names.push(name.into_owned());
+        }
+    }
+    names.sort_unstable();
+    Ok(names.into_iter().map(|name| path.join(name)).collect())
+}
+
+/// The ROM called `name` in the archive at `path`.
+pub fn read(path: &Path, name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut archive = open(path)?;
+    let entry = archive
+        .by_name(name)
+        .map_err(|e| format!("{}: {}: {}", path.display(), name, e))?;
+    let mut rom = Vec::new();
+    // nothing bigger fits in memory, so don't inflate more than one byte over
+    entry.take(MAX_ROM_SIZE as u64 + 1).read_to_end(&mut rom)?;
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(format!(
+            "{}: {} is too large, only {} bytes fit in memory",
+            path.display(),
+            name,
+            MAX_ROM_SIZE
+        )
+        .into());
+    }
+    Ok(rom)
+}
+
+fn open(path: &Path) -> Result<ZipArchive<File>, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    ZipArchive::new(file).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
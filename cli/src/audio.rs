@@ -0,0 +1,52 @@
+use std::error::Error;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "audio")]
+pub use chip_8::audio::Beep;
+
+/// Opens the buzzer on the default audio device.
+#[cfg(feature = "audio")]
+pub fn open(volume: f32) -> Result<Beep, Box<dyn Error>> {
+    Beep::new(volume)
+}
+
+/// Stand-in used when the CLI is built without the `audio` feature.
+#[cfg(not(feature = "audio"))]
+pub enum Beep {}
+
+#[cfg(not(feature = "audio"))]
+impl Beep {
+    pub fn set_playing(&self, _playing: bool) {
+        match *self {}
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+pub fn open(_volume: f32) -> Result<Beep, Box<dyn Error>> {
+    Err("built without the `audio` feature".into())
+}
+
+// beeps closer together than this ring the bell once
+const BELL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The terminal's bell, rung when a beep starts if there's no audio device
+/// or the CLI was built without the `audio` feature.
+#[derive(Default)]
+pub struct TerminalBell {
+    last_rung: Option<Instant>,
+}
+
+impl TerminalBell {
+    /// Writes BEL to `out`, unless the bell rang less than `BELL_INTERVAL` ago.
+    pub fn ring(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if self
+            .last_rung
+            .is_some_and(|last| last.elapsed() < BELL_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.last_rung = Some(Instant::now());
+        out.write_all(b"\x07")
+    }
+}
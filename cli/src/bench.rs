@@ -0,0 +1,80 @@
+//! `bench`: runs a ROM flat out, with no pacing or drawing, to measure how
+//! fast the interpreter goes on this machine.
+
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chip_8::Chip8;
+
+use crate::headless::{TIMER_HZ, TimerClock};
+use crate::loader;
+
+// reading the clock costs more than an instruction, so it's read this often
+const CLOCK_CHECK_INTERVAL: u64 = 10_000;
+
+/// Runs the ROM at `rom_path` for `duration` of wall time and reports the
+/// instructions per second and the time each frame's worth took. The timers
+/// tick as often as they would at `clock_speed`, so the program behaves as
+/// it would when played.
+pub fn run(
+    rom_path: &Path,
+    duration: Duration,
+    clock_speed: u64,
+    decode_cache: bool,
+) -> Result<(), Box<dyn Error>> {
+    let rom = loader::read(rom_path)?;
+    let mut chip8 = Chip8::new();
+    chip8.init();
+    chip8.seed_rng(0);
+    chip8.set_decode_cache(decode_cache);
+    chip8.load_rom(&rom)?;
+
+    let start = Instant::now();
+    let mut clock = TimerClock::new(clock_speed);
+    let mut instructions = 0;
+    'run: while start.elapsed() < duration {
+        for _ in 0..CLOCK_CHECK_INTERVAL {
+            if chip8.is_halted() || chip8.fault().is_some() {
+                break 'run;
+            }
+            // waiting for the frame (the display wait quirk) runs nothing
+            if !chip8.is_waiting_for_frame() {
+                instructions += 1;
+            }
+            chip8.cycle();
+            for _ in 0..clock.cycle() {
+                chip8.tick_timers();
+            }
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    println!("Ran {} instructions in {:.2}s", instructions, elapsed);
+    println!(
+        "{:.2} million instructions per second",
+        instructions as f64 / elapsed / 1e6
+    );
+    if clock.ticks() > 0 {
+        let frame = elapsed / clock.ticks() as f64;
+        println!(
+            "{:.2} µs per frame of {:.1} cycles at {} Hz, {:.0}x real time",
+            frame * 1e6,
+            clock_speed as f64 / TIMER_HZ as f64,
+            clock_speed,
+            1.0 / TIMER_HZ as f64 / frame
+        );
+    }
+    if let Some(fault) = chip8.fault() {
+        println!("Stopped early: {}", fault);
+    } else if chip8.is_halted() {
+        println!("Stopped early: the program exited");
+    }
+    Ok(())
+}
+
+/// Parses `--seconds`, which may be fractional.
+pub fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let seconds = s.parse::<f64>().map_err(|e| e.to_string())?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
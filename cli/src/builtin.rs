@@ -0,0 +1,93 @@
+//! ROMs compiled into the binary, so there's something to play before
+//! finding any ROM files. Apart from the IBM logo, they were written for this
+//! project and are in the public domain; their Octo sources and the IBM
+//! logo's provenance are in `roms/README.md`.
+
+/// A ROM run with `--builtin`.
+#[derive(Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub rom: &'static [u8],
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "ibm",
+        description: "The IBM logo, the usual first test of an emulator",
+        rom: include_bytes!("../../roms/IBM Logo.ch8"),
+    },
+    Builtin {
+        name: "digits",
+        description: "The sixteen hex digits of the font",
+        rom: include_bytes!("../../roms/Hex Digits.ch8"),
+    },
+    Builtin {
+        name: "keypad",
+        description: "Shows the last key pressed, with a beep",
+        rom: include_bytes!("../../roms/Keypad Test.ch8"),
+    },
+    Builtin {
+        name: "squash",
+        description: "Keep the ball in play; keys 4 and 6 (Q and E by default) move the paddle",
+        rom: include_bytes!("../../roms/Squash.ch8"),
+    },
+];
+
+/// The built-in ROM called `name`, for parsing `--builtin`.
+pub fn find(name: &str) -> Result<&'static Builtin, String> {
+    BUILTINS
+        .iter()
+        .find(|builtin| builtin.name == name)
+        .ok_or_else(|| {
+            let names: Vec<&str> = BUILTINS.iter().map(|builtin| builtin.name).collect();
+            format!("no built-in ROM `{}` (try {})", name, names.join(", "))
+        })
+}
+
+/// Prints the built-in ROMs with their descriptions.
+pub fn list() {
+    for builtin in BUILTINS {
+        println!("{:<8} {}", builtin.name, builtin.description);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::octo;
+
+    // Every `.8o` under roms/ is the source of the `.ch8` next to it. Run
+    // with `BLESS=1` to rebuild the ROMs after editing their sources.
+    #[test]
+    fn test_roms_match_their_sources() {
+        let roms = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../roms");
+        let mut checked = 0;
+        for dir in [roms.clone(), roms.join("tests")] {
+            for entry in fs::read_dir(&dir).unwrap() {
+                let source_path = entry.unwrap().path();
+                if !octo::is_source(&source_path) {
+                    continue;
+                }
+                let source = fs::read_to_string(&source_path).unwrap();
+                let compiled = octo::compile(&source)
+                    .unwrap_or_else(|e| panic!("{}: {}", source_path.display(), e));
+                let path = source_path.with_extension("ch8");
+                checked += 1;
+                if std::env::var_os("BLESS").is_some() {
+                    fs::write(&path, &compiled).unwrap();
+                    continue;
+                }
+                assert_eq!(
+                    fs::read(&path).unwrap(),
+                    compiled,
+                    "{} differs from its source",
+                    path.display()
+                );
+            }
+        }
+        assert!(checked > 0);
+    }
+}
@@ -0,0 +1,228 @@
+//! Lockstep comparison against a reference trace, for finding the first
+//! instruction where this emulator and another one part ways.
+//!
+//! A trace has one line per instruction with the state just before it runs,
+//! as `key=value` fields in hex:
+//!
+//! ```text
+//! # comments and blank lines are skipped
+//! pc=200 op=00E0 i=000 sp=0 v=00000000000000000000000000000000
+//! ```
+//!
+//! `v` holds all sixteen registers, or they can be given one at a time as
+//! `v0` to `vf`. Only the fields on a line are compared, so traces from
+//! emulators that log less still work. `compare --write` produces the
+//! format.
+
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use chip_8::{Chip8, NUM_REGISTERS};
+
+use crate::disasm::disassemble;
+use crate::headless::TimerClock;
+use crate::loader;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Pc,
+    Opcode,
+    I,
+    Sp,
+    V(usize),
+}
+
+impl Field {
+    fn value(self, chip8: &Chip8) -> u16 {
+        match self {
+            Field::Pc => chip8.pc(),
+            Field::Opcode => opcode(chip8),
+            Field::I => chip8.i_register(),
+            Field::Sp => chip8.sp(),
+            Field::V(x) => u16::from(chip8.v_registers()[x]),
+        }
+    }
+
+    fn name(self) -> String {
+        match self {
+            Field::Pc => "PC".to_string(),
+            Field::Opcode => "opcode".to_string(),
+            Field::I => "I".to_string(),
+            Field::Sp => "SP".to_string(),
+            Field::V(x) => format!("V{:X}", x),
+        }
+    }
+}
+
+/// Runs the ROM at `rom_path` one instruction per line of the trace at
+/// `trace_path`, stopping at the first line that doesn't match.
+pub fn run(rom_path: &Path, trace_path: &Path, clock_speed: u64) -> Result<(), Box<dyn Error>> {
+    let trace =
+        fs::read_to_string(trace_path).map_err(|e| format!("{}: {}", trace_path.display(), e))?;
+    let mut chip8 = start(rom_path)?;
+    let mut previous = None;
+    let mut steps = 0;
+    let mut clock = TimerClock::new(clock_speed);
+    for (index, line) in trace.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let expected = parse_line(line)
+            .map_err(|e| format!("{}:{}: {}", trace_path.display(), index + 1, e))?;
+        settle(&mut chip8, &mut clock);
+        if chip8.is_halted() {
+            println!(
+                "The program exited after {} steps, but the trace goes on at line {}",
+                steps,
+                index + 1
+            );
+            return Ok(());
+        }
+        let mismatches: Vec<_> = expected
+            .iter()
+            .filter(|&&(field, value)| field.value(&chip8) != value)
+            .collect();
+        if !mismatches.is_empty() {
+            println!("Mismatch at step {} (line {})", steps + 1, index + 1);
+            if let Some(previous) = previous {
+                println!("after:    {}", previous);
+            }
+            println!("expected: {}", line);
+            println!("actual:   {}", state_line(&chip8));
+            for &&(field, value) in &mismatches {
+                println!(
+                    "  {}: expected {:X}, found {:X}",
+                    field.name(),
+                    value,
+                    field.value(&chip8)
+                );
+            }
+            return Ok(());
+        }
+        previous = Some(describe(&chip8));
+        steps += 1;
+        step(&mut chip8, &mut clock);
+    }
+    println!("All {} steps match", steps);
+    Ok(())
+}
+
+/// Writes a trace of the first `cycles` instructions of the ROM at
+/// `rom_path` to `trace_path`.
+pub fn write(
+    rom_path: &Path,
+    trace_path: &Path,
+    cycles: u64,
+    clock_speed: u64,
+) -> Result<(), Box<dyn Error>> {
+    let mut chip8 = start(rom_path)?;
+    let mut trace = String::new();
+    let mut clock = TimerClock::new(clock_speed);
+    for _ in 0..cycles {
+        settle(&mut chip8, &mut clock);
+        if chip8.is_halted() {
+            break;
+        }
+        trace.push_str(&state_line(&chip8));
+        trace.push('\n');
+        step(&mut chip8, &mut clock);
+    }
+    fs::write(trace_path, trace).map_err(|e| format!("{}: {}", trace_path.display(), e).into())
+}
+
+// the same seed as `diff`, so random numbers match between runs
+fn start(rom_path: &Path) -> Result<Chip8, Box<dyn Error>> {
+    let rom = loader::read(rom_path)?;
+    let mut chip8 = Chip8::new();
+    chip8.init();
+    chip8.seed_rng(0);
+    chip8.load_rom(&rom)?;
+    Ok(chip8)
+}
+
+// runs one cycle, ticking the timers when they're due
+fn step(chip8: &mut Chip8, clock: &mut TimerClock) {
+    chip8.cycle();
+    for _ in 0..clock.cycle() {
+        chip8.tick_timers();
+    }
+}
+
+// cycles spent waiting for the frame (the display wait quirk) run no
+// instruction, so they aren't steps of the trace
+fn settle(chip8: &mut Chip8, clock: &mut TimerClock) {
+    while chip8.is_waiting_for_frame() && !chip8.is_halted() {
+        step(chip8, clock);
+    }
+}
+
+fn parse_line(line: &str) -> Result<Vec<(Field, u16)>, String> {
+    let mut fields = Vec::new();
+    for token in line.split_whitespace() {
+        let (key, value) = token
+            .split_once(['=', ':'])
+            .ok_or_else(|| format!("expected key=value, found `{}`", token))?;
+        let key = key.to_ascii_lowercase();
+        if key == "v" {
+            if value.len() != NUM_REGISTERS * 2 {
+                return Err(format!("`v` needs {} hex digits", NUM_REGISTERS * 2));
+            }
+            for x in 0..NUM_REGISTERS {
+                let byte = u8::from_str_radix(&value[x * 2..x * 2 + 2], 16)
+                    .map_err(|e| format!("invalid `v` value `{}`: {}", value, e))?;
+                fields.push((Field::V(x), u16::from(byte)));
+            }
+            continue;
+        }
+        let field = match key.as_str() {
+            "pc" => Field::Pc,
+            "op" | "opcode" => Field::Opcode,
+            "i" => Field::I,
+            "sp" => Field::Sp,
+            _ => match key.strip_prefix('v').map(|x| usize::from_str_radix(x, 16)) {
+                Some(Ok(x)) if x < NUM_REGISTERS => Field::V(x),
+                _ => return Err(format!("unknown field `{}`", key)),
+            },
+        };
+        let value = u16::from_str_radix(value.trim_start_matches("0x"), 16)
+            .map_err(|e| format!("invalid `{}` value `{}`: {}", key, value, e))?;
+        fields.push((field, value));
+    }
+    Ok(fields)
+}
+
+// the trace line for `chip8`'s current state
+fn state_line(chip8: &Chip8) -> String {
+    let mut v = String::new();
+    for value in chip8.v_registers() {
+        let _ = write!(v, "{:02X}", value);
+    }
+    format!(
+        "pc={:03X} op={:04X} i={:03X} sp={:X} v={}",
+        chip8.pc(),
+        opcode(chip8),
+        chip8.i_register(),
+        chip8.sp(),
+        v
+    )
+}
+
+fn describe(chip8: &Chip8) -> String {
+    let opcode = opcode(chip8);
+    format!(
+        "{:03X}  {:04X}  {}",
+        chip8.pc(),
+        opcode,
+        disassemble(opcode)
+    )
+}
+
+fn opcode(chip8: &Chip8) -> u16 {
+    let address = chip8.pc();
+    let high = chip8.read_byte(address).unwrap_or(0);
+    let low = chip8.read_byte(address.wrapping_add(1)).unwrap_or(0);
+    u16::from_be_bytes([high, low])
+}
@@ -0,0 +1,323 @@
+use crossterm::style::Color;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chip_8::romdb;
+use chip_8::{Platform, Quirks};
+
+use crate::DEFAULT_CLOCK_SPEED;
+use crate::input::{KeyStrategy, Retention};
+use crate::theme::{self, ThemeName};
+
+/// Settings read from `config.toml`. Every field is optional; command line
+/// flags take precedence over anything set here.
+///
+/// ```toml
+/// clock_speed = 1000
+/// turbo_factor = 8
+/// rom_dir = "/home/me/chip8/roms"
+/// keymap = "up=5,down=8,left=7,right=9"
+///
+/// [quirks]
+/// display_wait = true
+/// vf_reset = true
+/// wrap_sprites = true
+/// jump_vx = true
+/// i_overflow = true
+/// shift_vy = true
+/// load_store_increment = true
+///
+/// [input]
+/// key_strategy = "latch"
+///
+/// [audio]
+/// volume = 0.5
+///
+/// [display]
+/// theme = "green-phosphor"
+/// fg = "#33FF33"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(deserialize_with = "clock_speed")]
+    pub clock_speed: Option<u64>,
+    /// Same as `--turbo-factor`.
+    pub turbo_factor: Option<u32>,
+    /// Where the ROM picker starts when no ROM is given.
+    pub rom_dir: Option<PathBuf>,
+    /// Same syntax as `--keymap`.
+    pub keymap: Option<String>,
+    pub quirks: QuirksConfig,
+    pub input: InputConfig,
+    pub audio: AudioConfig,
+    pub display: DisplayConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuirksConfig {
+    pub display_wait: Option<bool>,
+    pub vf_reset: Option<bool>,
+    pub wrap_sprites: Option<bool>,
+    pub jump_vx: Option<bool>,
+    pub i_overflow: Option<bool>,
+    pub shift_vy: Option<bool>,
+    pub load_store_increment: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// Same as `--key-hold-ms`.
+    pub key_hold_ms: Option<u64>,
+    /// `"hold"` or `"latch"`, like `--key-strategy`.
+    pub key_strategy: Option<KeyStrategy>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    pub mute: Option<bool>,
+    /// From 0.0 to 1.0, like `--volume`.
+    pub volume: Option<f32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    /// Same names as `--theme`.
+    pub theme: Option<ThemeName>,
+    /// `#RRGGBB`, like `--fg` and `--bg`.
+    #[serde(deserialize_with = "color")]
+    pub fg: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub bg: Option<Color>,
+}
+
+impl Config {
+    /// Loads the config from `path`, or from the default location when no path
+    /// is given. A missing default config file is not an error.
+    pub fn load(path: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Config::default());
+            }
+            Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+        };
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+}
+
+/// Settings that can differ from one ROM to the next. Unset fields fall
+/// through to the next source, see `SettingsSources`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RomSettings {
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "clock_speed"
+    )]
+    pub clock_speed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_wait: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vf_reset: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wrap_sprites: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jump_vx: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub i_overflow: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shift_vy: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_store_increment: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_hold_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_strategy: Option<KeyStrategy>,
+}
+
+impl RomSettings {
+    /// These settings, with anything unset taken from `other`.
+    pub fn or(self, other: RomSettings) -> RomSettings {
+        RomSettings {
+            clock_speed: self.clock_speed.or(other.clock_speed),
+            display_wait: self.display_wait.or(other.display_wait),
+            vf_reset: self.vf_reset.or(other.vf_reset),
+            wrap_sprites: self.wrap_sprites.or(other.wrap_sprites),
+            jump_vx: self.jump_vx.or(other.jump_vx),
+            i_overflow: self.i_overflow.or(other.i_overflow),
+            shift_vy: self.shift_vy.or(other.shift_vy),
+            load_store_increment: self.load_store_increment.or(other.load_store_increment),
+            key_hold_ms: self.key_hold_ms.or(other.key_hold_ms),
+            key_strategy: self.key_strategy.or(other.key_strategy),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == RomSettings::default()
+    }
+
+    /// Sets the quirk called `name`, one of `Quirks::NAMES`. Returns false
+    /// for an unknown name.
+    pub fn set_quirk(&mut self, name: &str, enabled: bool) -> bool {
+        let setting = match name {
+            "display_wait" => &mut self.display_wait,
+            "vf_reset" => &mut self.vf_reset,
+            "wrap_sprites" => &mut self.wrap_sprites,
+            "jump_vx" => &mut self.jump_vx,
+            "i_overflow" => &mut self.i_overflow,
+            "shift_vy" => &mut self.shift_vy,
+            "load_store_increment" => &mut self.load_store_increment,
+            _ => return false,
+        };
+        *setting = Some(enabled);
+        true
+    }
+}
+
+/// Where a ROM's settings come from. Command line flags come first, then
+/// settings saved for the ROM in the library, then the config file, then the
+/// `--platform` preset, then the recommendations for known ROMs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SettingsSources {
+    pub flags: RomSettings,
+    pub config: RomSettings,
+    pub platform: Option<Platform>,
+}
+
+impl SettingsSources {
+    /// Clock speed and quirks for `rom`, given its own saved settings.
+    pub fn resolve(&self, rom: &[u8], saved: RomSettings) -> (u64, Quirks) {
+        let settings = self.flags.or(saved).or(self.config);
+        let preset = romdb::lookup(rom);
+        let clock_speed = settings
+            .clock_speed
+            .or(self.platform.map(|platform| platform.clock_speed().into()))
+            .or(preset.and_then(|preset| preset.clock_speed).map(u64::from))
+            .or(preset
+                .and_then(|preset| preset.platform)
+                .map(|platform| platform.clock_speed().into()))
+            .unwrap_or(DEFAULT_CLOCK_SPEED);
+        let preset_quirks = match self.platform {
+            Some(platform) => platform.quirks(),
+            None => preset.map(|preset| preset.quirks).unwrap_or_default(),
+        };
+        let quirks = Quirks {
+            display_wait: settings.display_wait.unwrap_or(preset_quirks.display_wait),
+            vf_reset: settings.vf_reset.unwrap_or(preset_quirks.vf_reset),
+            wrap_sprites: settings.wrap_sprites.unwrap_or(preset_quirks.wrap_sprites),
+            jump_vx: settings.jump_vx.unwrap_or(preset_quirks.jump_vx),
+            i_overflow: settings.i_overflow.unwrap_or(preset_quirks.i_overflow),
+            shift_vy: settings.shift_vy.unwrap_or(preset_quirks.shift_vy),
+            load_store_increment: settings
+                .load_store_increment
+                .unwrap_or(preset_quirks.load_store_increment),
+        };
+        (clock_speed, quirks)
+    }
+
+    /// How keys are held for a ROM, given its own saved settings.
+    pub fn retention(&self, saved: RomSettings) -> Retention {
+        let settings = self.flags.or(saved).or(self.config);
+        let default = Retention::default();
+        Retention {
+            strategy: settings.key_strategy.unwrap_or(default.strategy),
+            hold: settings
+                .key_hold_ms
+                .map_or(default.hold, Duration::from_millis),
+        }
+    }
+}
+
+// a clock speed of 0 would never run an instruction
+fn clock_speed<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match Option::<u64>::deserialize(deserializer)? {
+        Some(0) => Err(de::Error::invalid_value(
+            de::Unexpected::Unsigned(0),
+            &"a clock speed of at least 1 Hz",
+        )),
+        speed => Ok(speed),
+    }
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Color>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(text) => theme::parse(&text).map(Some).map_err(de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// `$XDG_CONFIG_HOME/chip8/config.toml`, falling back to `~/.config/chip8/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("chip8").join("config.toml"))
+}
+
+/// Where `--library` keeps per-game settings, next to the config file.
+pub fn library_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("chip8").join("library.toml"))
+}
+
+/// `$XDG_DATA_HOME/chip8`, falling back to `~/.local/share/chip8`, for
+/// what programs save, like their RPL user flags.
+pub fn data_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+        })
+        .map(|dir| dir.join("chip8"))
+}
+
+fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+/// Parses a `--platform` name.
+pub fn parse_platform(s: &str) -> Result<Platform, String> {
+    Platform::from_name(s).ok_or_else(|| {
+        format!(
+            "unknown platform `{}`, expected one of {}",
+            s,
+            Platform::NAMES.join(", ")
+        )
+    })
+}
+
+/// Every quirk as `name=on` or `name=off`, for showing what a ROM runs with.
+/// The shift and load/store quirks are shown the way `--quirk` also takes
+/// them, as `shift=vx|vy` and `memory=fixed|increment`.
+pub fn describe_quirks(quirks: Quirks) -> String {
+    let settings: Vec<_> = Quirks::NAMES
+        .iter()
+        .map(|&name| {
+            let enabled = quirks.get(name) == Some(true);
+            match name {
+                "shift_vy" => format!("shift={}", if enabled { "vy" } else { "vx" }),
+                "load_store_increment" => {
+                    format!("memory={}", if enabled { "increment" } else { "fixed" })
+                }
+                _ => format!("{}={}", name, if enabled { "on" } else { "off" }),
+            }
+        })
+        .collect();
+    settings.join(", ")
+}
@@ -0,0 +1,413 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::Stylize;
+use crossterm::{cursor, queue, style, terminal};
+use std::collections::BTreeSet;
+use std::io::{self, Stdout};
+
+use chip_8::Chip8;
+
+use crate::parse_address;
+use crate::symbols::Symbols;
+
+/// Columns taken by the panes drawn to the right of the display.
+pub const PANE_WIDTH: u16 = 44;
+/// Instructions `back` can undo, see `Chip8::set_journal_capacity`.
+pub const JOURNAL_CAPACITY: usize = 10_000;
+// instructions shown before and after PC in the disassembly window
+const DISASSEMBLY_CONTEXT: u16 = 3;
+// bytes per hexdump row, and the most the memory view shows
+const MEMORY_ROW: usize = 8;
+const DEFAULT_MEMORY_VIEW: usize = 64;
+const MAX_MEMORY_VIEW: usize = 128;
+// innermost calls listed in the backtrace
+const BACKTRACE_DEPTH: usize = 8;
+
+/// Interactive debugger drawn next to the display. While stopped, typed keys
+/// go to its command bar instead of the emulator.
+pub struct Debugger {
+    running: bool,
+    breakpoints: BTreeSet<u16>,
+    // PC a continue started from, so its breakpoint doesn't fire again immediately
+    resume_from: Option<u16>,
+    input: String,
+    message: String,
+    // start and length of the hexdump shown by `mem`
+    memory_view: Option<(u16, usize)>,
+    // lines drawn last time, so fewer lines now can blank the rest
+    drawn: usize,
+    symbols: Symbols,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger {
+            running: false,
+            breakpoints: BTreeSet::new(),
+            resume_from: None,
+            input: String::new(),
+            message: "Stopped. Type `help` for commands".to_string(),
+            memory_view: None,
+            drawn: 0,
+            symbols: Symbols::default(),
+        }
+    }
+}
+
+impl Debugger {
+    /// A debugger that shows and takes the names in `symbols`.
+    pub fn with_symbols(symbols: Symbols) -> Self {
+        Debugger {
+            symbols,
+            ..Debugger::default()
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Rows the panes take, which may be more than the display's.
+    pub fn lines(&self) -> u16 {
+        let memory_rows = self
+            .memory_view
+            .map_or(0, |(_, len)| len.div_ceil(MEMORY_ROW) + 1);
+        // header, registers, backtrace, disassembly, message and command bar
+        (2 + 4 + 1 + 1 + DISASSEMBLY_CONTEXT as usize * 2 + 1 + memory_rows + 2) as u16
+    }
+
+    /// Handles a key press, returning whether the debugger consumed it.
+    pub fn handle_key(&mut self, key: KeyEvent, chip8: &mut Chip8) -> bool {
+        if self.running {
+            if key.code == KeyCode::Char('b') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                self.stop(format!("Stopped at {:#05X}", chip8.pc()));
+                return true;
+            }
+            return false;
+        }
+
+        match key.code {
+            KeyCode::Enter => {
+                let command = std::mem::take(&mut self.input);
+                self.execute(command.trim(), chip8);
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.input.push(c);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Called before each instruction while running. Stops and returns true
+    /// when PC is on a breakpoint.
+    pub fn check_breakpoint(&mut self, pc: u16) -> bool {
+        if self.resume_from.take() == Some(pc) {
+            return false;
+        }
+        if self.breakpoints.contains(&pc) {
+            self.stop(format!("Breakpoint at {}", self.symbols.describe(pc)));
+            return true;
+        }
+        false
+    }
+
+    /// Stops execution, showing `message` in the command pane.
+    pub fn stop(&mut self, message: String) {
+        self.running = false;
+        self.message = message;
+    }
+
+    fn execute(&mut self, command: &str, chip8: &mut Chip8) {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or("step");
+        let arg = words.next();
+        self.message = match (name, arg) {
+            ("s" | "step", count) => {
+                let count = count.and_then(|c| c.parse().ok()).unwrap_or(1u32);
+                let mut last = None;
+                for _ in 0..count {
+                    last = Some(chip8.step());
+                }
+                match last {
+                    Some(step) if let Some(fault) = step.fault => format!("Stopped: {}", fault),
+                    Some(step) if step.waiting_for_key => {
+                        format!("Stepped {} to {:#05X}, waiting for a key", count, chip8.pc())
+                    }
+                    _ => format!("Stepped {} to {:#05X}", count, chip8.pc()),
+                }
+            }
+            ("bs" | "back", count) => {
+                let count = count.and_then(|c| c.parse().ok()).unwrap_or(1u32);
+                let stepped = (0..count).take_while(|_| chip8.step_back()).count();
+                if stepped == 0 {
+                    "Nothing to step back to".to_string()
+                } else {
+                    format!("Stepped back {} to {:#05X}", stepped, chip8.pc())
+                }
+            }
+            ("c" | "continue", _) => {
+                self.running = true;
+                self.resume_from = Some(chip8.pc());
+                "Running. Ctrl+B to stop".to_string()
+            }
+            ("b" | "break", Some(addr)) => match self.parse_location(addr) {
+                Ok(addr) if self.breakpoints.remove(&addr) => {
+                    format!("Removed breakpoint at {}", self.symbols.describe(addr))
+                }
+                Ok(addr) => {
+                    self.breakpoints.insert(addr);
+                    format!("Breakpoint set at {}", self.symbols.describe(addr))
+                }
+                Err(e) => e,
+            },
+            ("b" | "break", None) => {
+                let list: Vec<String> = self
+                    .breakpoints
+                    .iter()
+                    .map(|&addr| self.symbols.describe(addr))
+                    .collect();
+                format!("Breakpoints: {}", list.join(" "))
+            }
+            ("m" | "mem", Some(addr)) => {
+                let len = match words.next().map(parse_address) {
+                    Some(Ok(len)) => len as usize,
+                    Some(Err(e)) => {
+                        self.message = e;
+                        return;
+                    }
+                    None => DEFAULT_MEMORY_VIEW,
+                };
+                match self.parse_location(addr) {
+                    Ok(addr) if (addr as usize) < chip8.memory().len() => {
+                        let len = len.clamp(1, MAX_MEMORY_VIEW);
+                        self.memory_view = Some((addr, len));
+                        format!("Memory at {:#05X}: PC reversed, I underlined", addr)
+                    }
+                    Ok(addr) => format!("{:#05X} is past the end of memory", addr),
+                    Err(e) => e,
+                }
+            }
+            ("m" | "mem", None) => {
+                self.memory_view = None;
+                "Memory view closed".to_string()
+            }
+            ("set", Some(register)) => match words.next().map(parse_value) {
+                Some(Ok(value)) => set_register(chip8, register, value).unwrap_or_else(|e| e),
+                Some(Err(e)) => e,
+                None => "Usage: set <register> <value>".to_string(),
+            },
+            ("poke", Some(addr)) => match self
+                .parse_location(addr)
+                .and_then(|addr| poke(chip8, addr, words))
+            {
+                Ok(message) | Err(message) => message,
+            },
+            ("help" | "h", _) => {
+                "s [n] step | bs [n] back | c continue | b <addr|name> breakpoint | mem <addr> [len] hexdump | set <reg> <value> | poke <addr> <bytes>"
+                    .to_string()
+            }
+            _ => format!("Unknown command `{}`", command),
+        };
+    }
+
+    /// An address given as a number or a symbol name.
+    fn parse_location(&self, s: &str) -> Result<u16, String> {
+        match self.symbols.address(s) {
+            Some(address) => Ok(address),
+            None => parse_address(s),
+        }
+    }
+
+    /// Draws the register, disassembly and command panes with their top left
+    /// corner at (`left`, `top`).
+    pub fn draw(
+        &mut self,
+        chip8: &Chip8,
+        stdout: &mut Stdout,
+        left: u16,
+        top: u16,
+    ) -> io::Result<()> {
+        let mut lines = Vec::new();
+        lines.push(format!(
+            "DEBUGGER [{}]",
+            if self.running { "running" } else { "stopped" }
+        ));
+        lines.push(format!(
+            "PC {:#05X}  I {:#05X}  SP {}  DT {}  ST {}",
+            chip8.pc(),
+            chip8.i_register(),
+            chip8.sp(),
+            chip8.delay_timer(),
+            chip8.sound_timer()
+        ));
+        for (row, values) in chip8.v_registers().chunks(4).enumerate() {
+            let cells: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| format!("V{:X} {:02X}", row * 4 + i, value))
+                .collect();
+            lines.push(cells.join("  "));
+        }
+        lines.push(backtrace(chip8));
+        lines.push(String::new());
+
+        let pc = chip8.pc();
+        let start = pc.saturating_sub(DISASSEMBLY_CONTEXT * 2);
+        for addr in (start..=pc + DISASSEMBLY_CONTEXT * 2).step_by(2) {
+            let Some(&[hi, lo]) = chip8.read_range(addr, 2) else {
+                lines.push(String::new());
+                continue;
+            };
+            let opcode = u16::from_be_bytes([hi, lo]);
+            let marker = if addr == pc { '>' } else { ' ' };
+            let breakpoint = if self.breakpoints.contains(&addr) {
+                '*'
+            } else {
+                ' '
+            };
+            let mut line = format!(
+                "{}{}{:03X}  {:04X}  {}",
+                marker,
+                breakpoint,
+                addr,
+                opcode,
+                self.symbols.disassemble(opcode)
+            );
+            if let Some(name) = self.symbols.name(addr) {
+                line.push_str(&format!("  <{}>", name));
+            }
+            lines.push(line);
+        }
+        if let Some((start, len)) = self.memory_view {
+            lines.push(String::new());
+            lines.extend(hexdump(chip8, start, len));
+        }
+        lines.push(self.message.clone());
+        lines.push(if self.running {
+            String::new()
+        } else {
+            format!("> {}_", self.input)
+        });
+        let count = lines.len();
+        lines.resize(count.max(self.drawn), String::new());
+        self.drawn = count;
+
+        for (row, line) in lines.iter().enumerate() {
+            queue!(
+                stdout,
+                cursor::MoveTo(left, top + row as u16),
+                style::Print(line),
+                terminal::Clear(terminal::ClearType::UntilNewLine)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The addresses of the calls PC is nested in, innermost first. Each is the
+/// instruction before its return address.
+fn backtrace(chip8: &Chip8) -> String {
+    let calls = chip8.call_stack();
+    if calls.is_empty() {
+        return "Calls  (none)".to_string();
+    }
+    let mut line = "Calls ".to_string();
+    for address in calls.iter().rev().take(BACKTRACE_DEPTH) {
+        line.push_str(&format!(" {:03X}", address.wrapping_sub(2)));
+    }
+    if calls.len() > BACKTRACE_DEPTH {
+        line.push_str(&format!(" +{}", calls.len() - BACKTRACE_DEPTH));
+    }
+    line
+}
+
+/// A number in hex (`0x1F`) or decimal, as for addresses.
+fn parse_value(s: &str) -> Result<u16, String> {
+    parse_address(s).map_err(|_| format!("invalid value `{}`", s))
+}
+
+/// Sets `register` (`v0` to `vf`, `i`, `pc`, `sp`, `dt` or `st`) for the
+/// `set` command.
+fn set_register(chip8: &mut Chip8, register: &str, value: u16) -> Result<String, String> {
+    let name = register.to_ascii_uppercase();
+    let byte = || u8::try_from(value).map_err(|_| format!("{:#X} doesn't fit in {}", value, name));
+    match name.as_str() {
+        "I" => chip8.set_i_register(value),
+        "PC" if (value as usize) < chip8.memory().len() => chip8.set_pc(value),
+        "PC" => return Err(format!("{:#05X} is past the end of memory", value)),
+        "SP" if !chip8.set_sp(value) => {
+            return Err(format!("SP {} is deeper than the stack", value));
+        }
+        "SP" => {}
+        "DT" => chip8.set_delay_timer(byte()?),
+        "ST" => chip8.set_sound_timer(byte()?),
+        _ => {
+            let index = name
+                .strip_prefix('V')
+                .filter(|digit| digit.len() == 1)
+                .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+                .ok_or_else(|| format!("Unknown register `{}`", register))?;
+            chip8.set_v_register(index, byte()?);
+        }
+    }
+    Ok(format!("{} = {:#X}", name, value))
+}
+
+/// Writes the bytes `values` from `start` on for the `poke` command.
+fn poke<'a>(
+    chip8: &mut Chip8,
+    start: u16,
+    values: impl Iterator<Item = &'a str>,
+) -> Result<String, String> {
+    let mut bytes = Vec::new();
+    for value in values {
+        let value = parse_value(value)?;
+        bytes.push(u8::try_from(value).map_err(|_| format!("{:#X} is not a byte", value))?);
+    }
+    if bytes.is_empty() {
+        return Err("Usage: poke <addr> <bytes>".to_string());
+    }
+    if start as usize + bytes.len() > chip8.memory().len() {
+        return Err(format!(
+            "{:#05X} is past the end of memory",
+            start as usize + bytes.len() - 1
+        ));
+    }
+    for (address, &byte) in (start..).zip(&bytes) {
+        chip8.write_byte(address, byte);
+    }
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    Ok(format!("Wrote {} at {:#05X}", hex.join(" "), start))
+}
+
+/// Rows of `len` bytes of memory from `start`, with the instruction at PC in
+/// reverse video and the byte at I underlined.
+fn hexdump(chip8: &Chip8, start: u16, len: usize) -> Vec<String> {
+    let memory = chip8.memory();
+    let start = start as usize;
+    let end = (start + len).min(memory.len());
+    let pc = chip8.pc() as usize;
+    let i = chip8.i_register() as usize;
+    (start..end)
+        .step_by(MEMORY_ROW)
+        .map(|row| {
+            let mut line = format!("{:03X} ", row);
+            for (address, byte) in memory[row..end.min(row + MEMORY_ROW)].iter().enumerate() {
+                let address = row + address;
+                let text = format!("{:02X}", byte);
+                line.push(' ');
+                if address == pc || address == pc + 1 {
+                    line.push_str(&text.reverse().to_string());
+                } else if address == i {
+                    line.push_str(&text.underlined().to_string());
+                } else {
+                    line.push_str(&text);
+                }
+            }
+            line
+        })
+        .collect()
+}
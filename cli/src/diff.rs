@@ -0,0 +1,93 @@
+use std::error::Error;
+use std::path::Path;
+
+use chip_8::{Chip8, Quirks, RomTooLarge};
+
+use crate::disasm::disassemble;
+use crate::headless::TimerClock;
+use crate::loader;
+
+/// Runs `rom` under two quirk configurations in lockstep, with the same
+/// seed and no input, and prints the first point where their states differ.
+pub fn run(
+    rom_path: &Path,
+    first: Quirks,
+    second: Quirks,
+    cycles: u64,
+    clock_speed: u64,
+) -> Result<(), Box<dyn Error>> {
+    let rom = loader::read(rom_path)?;
+    let start = |quirks| -> Result<Chip8, RomTooLarge> {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.set_quirks(quirks);
+        chip8.seed_rng(0);
+        chip8.load_rom(&rom)?;
+        Ok(chip8)
+    };
+    let (mut a, mut b) = (start(first)?, start(second)?);
+
+    let mut clock = TimerClock::new(clock_speed);
+    for cycle in 1..=cycles {
+        // what each is about to do, for reporting
+        let before = (describe(&a), describe(&b));
+        a.cycle();
+        b.cycle();
+        for _ in 0..clock.cycle() {
+            a.tick_timers();
+            b.tick_timers();
+        }
+
+        let diff = a.diff(&b);
+        if diff.is_empty() {
+            continue;
+        }
+        println!("Diverged at cycle {} (frame {})", cycle, clock.ticks());
+        println!("first:  {}", before.0);
+        println!("second: {}", before.1);
+        print!("{}", diff);
+        return Ok(());
+    }
+    println!("No divergence in {} cycles", cycles);
+    Ok(())
+}
+
+// the instruction the next cycle executes
+fn describe(chip8: &Chip8) -> String {
+    let address = chip8.pc();
+    if chip8.is_waiting_for_frame() {
+        return format!("{:03X}  (waiting for the frame)", address);
+    }
+    let high = chip8.read_byte(address).unwrap_or(0);
+    let low = chip8.read_byte(address.wrapping_add(1)).unwrap_or(0);
+    let opcode = u16::from_be_bytes([high, low]);
+    format!("{:03X}  {:04X}  {}", address, opcode, disassemble(opcode))
+}
+
+/// Parses `name=on` or `name=off` for `--quirk` and `diff --first` and
+/// `--second`. The shift and load/store quirks can also be given by what
+/// they do, as `shift=vx|vy` and `memory=fixed|increment`.
+pub fn parse_quirk(s: &str) -> Result<(String, bool), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected `name=on` or `name=off`, found `{}`", s))?;
+    let (name, enabled) = match (name, value) {
+        ("shift", "vy") => ("shift_vy", true),
+        ("shift", "vx") => ("shift_vy", false),
+        ("shift", _) => return Err(format!("`{}` is not vx or vy", value)),
+        ("memory", "increment") => ("load_store_increment", true),
+        ("memory", "fixed") => ("load_store_increment", false),
+        ("memory", _) => return Err(format!("`{}` is not fixed or increment", value)),
+        (_, "on" | "true" | "1") => (name, true),
+        (_, "off" | "false" | "0") => (name, false),
+        _ => return Err(format!("`{}` is not on or off", value)),
+    };
+    if !Quirks::default().set(name, enabled) {
+        return Err(format!(
+            "unknown quirk `{}` (known: {})",
+            name,
+            Quirks::NAMES.join(", ")
+        ));
+    }
+    Ok((name.to_string(), enabled))
+}
@@ -0,0 +1,10 @@
+use chip_8::decode;
+
+/// Formats an opcode as an assembly mnemonic, e.g. `DRW V0, V1, 5`.
+/// Unknown opcodes are shown as a data word.
+pub fn disassemble(opcode: u16) -> String {
+    match decode(opcode) {
+        Some(instruction) => instruction.to_string(),
+        None => format!("DW {:#06X}", opcode),
+    }
+}
@@ -0,0 +1,31 @@
+use clap::ValueEnum;
+
+use chip_8::font::{self, Fontset};
+
+/// Built-in fontsets for the hex digit glyphs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum FontName {
+    /// The CHIP-48 font most interpreters use
+    #[default]
+    Chip48,
+    /// The COSMAC VIP's font
+    Vip,
+    /// Octo's font
+    Octo,
+    /// The DREAM 6800's narrow font
+    Dream6800,
+    /// The ETI-660's narrow font
+    Eti660,
+}
+
+impl FontName {
+    pub fn fontset(self) -> &'static Fontset {
+        match self {
+            FontName::Chip48 => &font::CHIP48,
+            FontName::Vip => &font::VIP,
+            FontName::Octo => &font::OCTO,
+            FontName::Dream6800 => &font::DREAM_6800,
+            FontName::Eti660 => &font::ETI_660,
+        }
+    }
+}
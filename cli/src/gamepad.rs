@@ -0,0 +1,262 @@
+//! Game controllers, with the `gamepad` feature. Buttons and the left stick
+//! press CHIP-8 keys alongside the keyboard, and pads can be plugged in or
+//! out while a ROM runs.
+
+use std::error::Error;
+
+use chip_8::NUM_KEYS;
+
+/// A button or direction on a gamepad that can press a CHIP-8 key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Control {
+    Up,
+    Down,
+    Left,
+    Right,
+    South,
+    East,
+    West,
+    North,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+}
+
+const CONTROL_NAMES: [(&str, Control); 12] = [
+    ("up", Control::Up),
+    ("down", Control::Down),
+    ("left", Control::Left),
+    ("right", Control::Right),
+    ("south", Control::South),
+    ("east", Control::East),
+    ("west", Control::West),
+    ("north", Control::North),
+    ("lt", Control::LeftTrigger),
+    ("rt", Control::RightTrigger),
+    ("select", Control::Select),
+    ("start", Control::Start),
+];
+
+/// Which CHIP-8 key each gamepad control presses.
+#[derive(Debug, Clone)]
+pub struct GamepadMap {
+    bindings: Vec<(Control, usize)>,
+}
+
+impl Default for GamepadMap {
+    /// The d-pad on 5/8/7/9 like the usual WASD-style controls, with the
+    /// south and east face buttons on 6 and 4.
+    fn default() -> Self {
+        GamepadMap {
+            bindings: vec![
+                (Control::Up, 0x5),
+                (Control::Down, 0x8),
+                (Control::Left, 0x7),
+                (Control::Right, 0x9),
+                (Control::South, 0x6),
+                (Control::East, 0x4),
+            ],
+        }
+    }
+}
+
+impl GamepadMap {
+    /// Parses comma separated `control=X` bindings added to the default,
+    /// e.g. `south=5,start=F`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut map = GamepadMap::default();
+        for binding in spec.split(',') {
+            let (control, chip8_key) = binding
+                .split_once('=')
+                .ok_or_else(|| format!("invalid binding `{}`, expected control=X", binding))?;
+            let control = parse_control(control.trim())?;
+            let chip8_key = usize::from_str_radix(chip8_key.trim(), 16)
+                .ok()
+                .filter(|&k| k < NUM_KEYS)
+                .ok_or_else(|| format!("invalid CHIP-8 key `{}`, expected 0-F", chip8_key))?;
+            map.bindings.retain(|&(c, _)| c != control);
+            map.bindings.push((control, chip8_key));
+        }
+        Ok(map)
+    }
+
+    /// The CHIP-8 keys pressed while `held` controls are down.
+    #[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+    pub fn keys(&self, held: impl IntoIterator<Item = Control>) -> [bool; NUM_KEYS] {
+        let mut keys = [false; NUM_KEYS];
+        for control in held {
+            for &(_, key) in self.bindings.iter().filter(|&&(c, _)| c == control) {
+                keys[key] = true;
+            }
+        }
+        keys
+    }
+}
+
+fn parse_control(name: &str) -> Result<Control, String> {
+    CONTROL_NAMES
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, control)| control)
+        .ok_or_else(|| {
+            let names: Vec<_> = CONTROL_NAMES.iter().map(|(n, _)| *n).collect();
+            format!(
+                "unknown control `{}`, expected one of {}",
+                name,
+                names.join(", ")
+            )
+        })
+}
+
+#[cfg(feature = "gamepad")]
+pub use backend::Gamepads;
+
+/// Starts watching for gamepads, including ones plugged in later.
+#[cfg(feature = "gamepad")]
+pub fn open(map: GamepadMap) -> Result<Gamepads, Box<dyn Error>> {
+    Gamepads::new(map)
+}
+
+/// Stand-in used when the CLI is built without the `gamepad` feature.
+#[cfg(not(feature = "gamepad"))]
+pub enum Gamepads {}
+
+#[cfg(not(feature = "gamepad"))]
+impl Gamepads {
+    pub fn poll(&mut self) -> Vec<String> {
+        match *self {}
+    }
+
+    pub fn pressed(&self) -> [bool; NUM_KEYS] {
+        match *self {}
+    }
+}
+
+#[cfg(not(feature = "gamepad"))]
+pub fn open(_map: GamepadMap) -> Result<Gamepads, Box<dyn Error>> {
+    Err("built without the `gamepad` feature".into())
+}
+
+#[cfg(feature = "gamepad")]
+mod backend {
+    use std::collections::{HashMap, HashSet};
+    use std::error::Error;
+
+    use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+
+    use chip_8::NUM_KEYS;
+
+    use super::{Control, GamepadMap};
+
+    // how far the stick has to move to count as a direction
+    const STICK_THRESHOLD: f32 = 0.5;
+
+    /// Every connected gamepad, each pressing keys through the same map.
+    pub struct Gamepads {
+        gilrs: Gilrs,
+        map: GamepadMap,
+        pads: HashMap<GamepadId, Pad>,
+    }
+
+    // controls held on one pad, with the stick's kept apart from the d-pad's
+    // so letting go of one doesn't release the other
+    #[derive(Default)]
+    struct Pad {
+        buttons: HashSet<Control>,
+        stick: HashSet<Control>,
+    }
+
+    impl Gamepads {
+        pub fn new(map: GamepadMap) -> Result<Self, Box<dyn Error>> {
+            let gilrs = Gilrs::new().map_err(|e| e.to_string())?;
+            Ok(Gamepads {
+                gilrs,
+                map,
+                pads: HashMap::new(),
+            })
+        }
+
+        /// Handles pending controller events, returning messages about pads
+        /// that were connected or disconnected.
+        pub fn poll(&mut self) -> Vec<String> {
+            let mut messages = Vec::new();
+            while let Some(event) = self.gilrs.next_event() {
+                let pad = self.pads.entry(event.id).or_default();
+                match event.event {
+                    EventType::ButtonPressed(button, _) => {
+                        pad.buttons.extend(control(button));
+                    }
+                    EventType::ButtonReleased(button, _) => {
+                        if let Some(control) = control(button) {
+                            pad.buttons.remove(&control);
+                        }
+                    }
+                    EventType::AxisChanged(Axis::LeftStickX, value, _) => {
+                        set_direction(&mut pad.stick, Control::Left, Control::Right, value);
+                    }
+                    // up is positive
+                    EventType::AxisChanged(Axis::LeftStickY, value, _) => {
+                        set_direction(&mut pad.stick, Control::Down, Control::Up, value);
+                    }
+                    EventType::Connected => {
+                        let name = self.gilrs.gamepad(event.id).name().to_string();
+                        messages.push(format!("Gamepad connected: {}", name));
+                    }
+                    EventType::Disconnected => {
+                        // or its keys would stay held
+                        self.pads.remove(&event.id);
+                        messages.push("Gamepad disconnected".to_string());
+                    }
+                    _ => {}
+                }
+            }
+            messages
+        }
+
+        /// The CHIP-8 keys held on any connected pad.
+        pub fn pressed(&self) -> [bool; NUM_KEYS] {
+            let held = self
+                .pads
+                .values()
+                .flat_map(|pad| pad.buttons.union(&pad.stick));
+            self.map.keys(held.copied())
+        }
+    }
+
+    fn control(button: Button) -> Option<Control> {
+        Some(match button {
+            Button::DPadUp => Control::Up,
+            Button::DPadDown => Control::Down,
+            Button::DPadLeft => Control::Left,
+            Button::DPadRight => Control::Right,
+            Button::South => Control::South,
+            Button::East => Control::East,
+            Button::West => Control::West,
+            Button::North => Control::North,
+            Button::LeftTrigger | Button::LeftTrigger2 => Control::LeftTrigger,
+            Button::RightTrigger | Button::RightTrigger2 => Control::RightTrigger,
+            Button::Select => Control::Select,
+            Button::Start => Control::Start,
+            _ => return None,
+        })
+    }
+
+    fn set_direction(
+        held: &mut HashSet<Control>,
+        negative: Control,
+        positive: Control,
+        value: f32,
+    ) {
+        for (control, on) in [
+            (negative, value < -STICK_THRESHOLD),
+            (positive, value > STICK_THRESHOLD),
+        ] {
+            if on {
+                held.insert(control);
+            } else {
+                held.remove(&control);
+            }
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! `--renderer kitty` and `--renderer sixel`: the display drawn as a bitmap
+//! through the kitty graphics protocol or as sixels, for terminals that can
+//! show images, instead of with block characters.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use crossterm::terminal;
+use std::error::Error;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::renderer::Renderer;
+use crate::theme::Theme;
+
+// asks a kitty-compatible terminal to check a 1x1 image without showing it;
+// terminals that don't know the protocol ignore it
+const KITTY_QUERY: &str = "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\";
+const KITTY_OK: &[u8] = b"\x1b_Gi=31;OK";
+// primary device attributes, which every terminal answers; attribute 4 means
+// sixel graphics. Sent last, so its reply also ends the replies to wait for.
+const DEVICE_ATTRIBUTES_QUERY: &str = "\x1b[c";
+const DEVICE_ATTRIBUTES_REPLY: &[u8] = b"\x1b[?";
+const SIXEL_ATTRIBUTE: &str = "4";
+const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+// pixel size of a terminal cell when the terminal doesn't say
+const DEFAULT_CELL_PIXELS: (usize, usize) = (10, 20);
+// bytes of base64 in each kitty escape sequence, the most the protocol allows
+const KITTY_CHUNK: usize = 4096;
+
+/// The image protocols a terminal understands.
+#[derive(Debug, Clone, Copy, Default)]
+struct Support {
+    kitty: bool,
+    sixel: bool,
+}
+
+impl Support {
+    fn has(self, renderer: Renderer) -> bool {
+        match renderer {
+            Renderer::Kitty => self.kitty,
+            Renderer::Sixel => self.sixel,
+            _ => true,
+        }
+    }
+}
+
+/// `renderer` if the terminal can show it, or else the other image protocol
+/// if it can show that, or else half blocks, with a warning.
+pub fn resolve(renderer: Renderer) -> Renderer {
+    if !renderer.is_image() {
+        return renderer;
+    }
+    let support = match detect() {
+        Ok(support) => support,
+        Err(e) => {
+            eprintln!("Warning: couldn't ask the terminal about images: {}", e);
+            Support::default()
+        }
+    };
+    let other = match renderer {
+        Renderer::Kitty => Renderer::Sixel,
+        _ => Renderer::Kitty,
+    };
+    let fallback = [renderer, other, Renderer::HalfBlock]
+        .into_iter()
+        .find(|&candidate| support.has(candidate))
+        .unwrap_or_default();
+    if fallback != renderer {
+        eprintln!(
+            "Warning: the terminal doesn't support {} graphics, using {}",
+            name(renderer),
+            name(fallback)
+        );
+    }
+    fallback
+}
+
+fn name(renderer: Renderer) -> &'static str {
+    match renderer {
+        Renderer::Kitty => "kitty",
+        Renderer::Sixel => "sixel",
+        _ => "half blocks",
+    }
+}
+
+/// Queries the terminal for the protocols it supports.
+fn detect() -> Result<Support, Box<dyn Error>> {
+    terminal::enable_raw_mode()?;
+    let reply = query();
+    terminal::disable_raw_mode()?;
+    let Some(reply) = reply? else {
+        return Ok(Support::default());
+    };
+    let kitty = reply.windows(KITTY_OK.len()).any(|w| w == KITTY_OK);
+    let sixel = attributes(&reply).is_some_and(|attributes| {
+        attributes
+            .split(';')
+            .skip(1)
+            .any(|attribute| attribute == SIXEL_ATTRIBUTE)
+    });
+    Ok(Support { kitty, sixel })
+}
+
+// everything the terminal sent back up to the end of its device attributes,
+// or `None` if it didn't answer in time
+fn query() -> io::Result<Option<Vec<u8>>> {
+    let mut stdout = io::stdout();
+    write!(stdout, "{}{}", KITTY_QUERY, DEVICE_ATTRIBUTES_QUERY)?;
+    stdout.flush()?;
+    read_reply()
+}
+
+// reads a byte at a time, so nothing typed after the reply is taken from
+// the keyboard input
+#[cfg(unix)]
+fn read_reply() -> io::Result<Option<Vec<u8>>> {
+    use rustix::event::{PollFd, PollFlags, Timespec, poll};
+    use std::time::Instant;
+
+    let stdin = rustix::stdio::stdin();
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut reply = Vec::new();
+    while attributes(&reply).is_none() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let timeout = Timespec::try_from(left).map_err(io::Error::other)?;
+        if poll(&mut [PollFd::new(&stdin, PollFlags::IN)], Some(&timeout))? == 0 {
+            return Ok(None);
+        }
+        let mut byte = [0];
+        if rustix::io::read(stdin, &mut byte)? == 0 {
+            return Ok(None);
+        }
+        reply.push(byte[0]);
+    }
+    Ok(Some(reply))
+}
+
+// there's no waiting on the console with a timeout elsewhere, so the
+// terminal is taken to show no images
+#[cfg(not(unix))]
+fn read_reply() -> io::Result<Option<Vec<u8>>> {
+    Ok(None)
+}
+
+// the parameters of a complete device attributes reply in `reply`, such as
+// `62;4;22` for `ESC [ ? 62;4;22 c`
+fn attributes(reply: &[u8]) -> Option<&str> {
+    let start = reply
+        .windows(DEVICE_ATTRIBUTES_REPLY.len())
+        .position(|w| w == DEVICE_ATTRIBUTES_REPLY)?
+        + DEVICE_ATTRIBUTES_REPLY.len();
+    let len = reply[start..].iter().position(|&byte| byte == b'c')?;
+    std::str::from_utf8(&reply[start..start + len]).ok()
+}
+
+/// A two-colour image of the display, scaled to fill its cells.
+struct Bitmap {
+    pixels: Vec<bool>,
+    width: usize,
+    height: usize,
+}
+
+impl Bitmap {
+    // each display pixel becomes a block as close to the size of its share
+    // of the `columns` x `lines` cells as whole pixels allow
+    fn scaled((pixels, width): (&[bool], usize), (columns, lines): (u16, u16)) -> Self {
+        let height = pixels.len() / width;
+        let (cell_width, cell_height) = cell_pixels();
+        let scale_x = (cell_width * usize::from(columns) / width).max(1);
+        let scale_y = (cell_height * usize::from(lines) / height).max(1);
+        let mut scaled = Vec::with_capacity(pixels.len() * scale_x * scale_y);
+        for row in pixels.chunks_exact(width) {
+            let start = scaled.len();
+            for &pixel in row {
+                scaled.extend(std::iter::repeat_n(pixel, scale_x));
+            }
+            for _ in 1..scale_y {
+                scaled.extend_from_within(start..start + width * scale_x);
+            }
+        }
+        Bitmap {
+            pixels: scaled,
+            width: width * scale_x,
+            height: height * scale_y,
+        }
+    }
+}
+
+// pixel size of a terminal cell
+fn cell_pixels() -> (usize, usize) {
+    match terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+            usize::from(size.width / size.columns),
+            usize::from(size.height / size.rows),
+        ),
+        _ => DEFAULT_CELL_PIXELS,
+    }
+}
+
+/// Escape sequences that draw `pixels`, a display `width` pixels wide, over
+/// the `cells` (columns, lines) from the cursor, in the colours of `theme`.
+/// `renderer` must be one of the image renderers.
+pub fn image(
+    renderer: Renderer,
+    display: (&[bool], usize),
+    cells: (u16, u16),
+    theme: Theme,
+) -> io::Result<String> {
+    let bitmap = Bitmap::scaled(display, cells);
+    let colors = colors(theme);
+    match renderer {
+        Renderer::Kitty => kitty(&bitmap, cells, colors),
+        _ => Ok(sixel(&bitmap, colors)),
+    }
+}
+
+// (lit, unlit) colours
+fn colors(theme: Theme) -> ([u8; 3], [u8; 3]) {
+    let palette = theme.palette();
+    let [r, g, b, _] = palette.color(1);
+    let [off_r, off_g, off_b, _] = palette.color(0);
+    ([r, g, b], [off_r, off_g, off_b])
+}
+
+// the bitmap as a 1 bit PNG, sent in chunks and placed over the cells with
+// the same image and placement every time, so each frame replaces the last
+fn kitty(
+    bitmap: &Bitmap,
+    (columns, lines): (u16, u16),
+    (fg, bg): ([u8; 3], [u8; 3]),
+) -> io::Result<String> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, bitmap.width as u32, bitmap.height as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::One);
+    encoder.set_palette([bg, fg].concat());
+    let packed: Vec<u8> = bitmap
+        .pixels
+        .chunks_exact(bitmap.width)
+        .flat_map(|row| row.chunks(8))
+        .map(|byte| {
+            byte.iter()
+                .enumerate()
+                .fold(0, |packed, (i, &lit)| packed | (lit as u8) << (7 - i))
+        })
+        .collect();
+    encoder.write_header()?.write_image_data(&packed)?;
+
+    let data = STANDARD.encode(&png);
+    let mut out = String::new();
+    for start in (0..data.len()).step_by(KITTY_CHUNK) {
+        let end = data.len().min(start + KITTY_CHUNK);
+        let more = u8::from(end < data.len());
+        // q=2 keeps the terminal's replies out of the keyboard input, and
+        // C=1 leaves the cursor where it was
+        if start == 0 {
+            out.push_str(&format!(
+                "\x1b_Ga=T,f=100,i=1,p=1,q=2,C=1,c={},r={},m={};",
+                columns, lines, more
+            ));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};", more));
+        }
+        out.push_str(&data[start..end]);
+        out.push_str("\x1b\\");
+    }
+    Ok(out)
+}
+
+// the bitmap as sixels: bands six pixels tall, each drawn once for the unlit
+// and once for the lit pixels, with repeated columns run length encoded
+fn sixel(bitmap: &Bitmap, (fg, bg): ([u8; 3], [u8; 3])) -> String {
+    // colours are given as percentages
+    let percent = |[r, g, b]: [u8; 3]| {
+        let scale = |c: u8| u32::from(c) * 100 / 255;
+        format!("{};{};{}", scale(r), scale(g), scale(b))
+    };
+    // P2=1 leaves pixels that aren't set alone, so the band below the image
+    // isn't painted over
+    let mut out = format!(
+        "\x1bP0;1;0q\"1;1;{};{}#0;2;{}#1;2;{}",
+        bitmap.width,
+        bitmap.height,
+        percent(bg),
+        percent(fg)
+    );
+    for top in (0..bitmap.height).step_by(6) {
+        if top > 0 {
+            out.push('-');
+        }
+        for (color, lit) in [(0, false), (1, true)] {
+            out.push_str(&format!("#{}", color));
+            let mut columns = (0..bitmap.width).map(|x| {
+                let bits = (0..6)
+                    .filter(|row| top + row < bitmap.height)
+                    .filter(|row| bitmap.pixels[(top + row) * bitmap.width + x] == lit)
+                    .fold(0, |bits, row| bits | 1 << row);
+                char::from(b'?' + bits)
+            });
+            let Some(mut current) = columns.next() else {
+                continue;
+            };
+            let mut count = 1;
+            for sixel in columns.chain([char::MAX]) {
+                if sixel == current {
+                    count += 1;
+                    continue;
+                }
+                if count > 3 {
+                    out.push_str(&format!("!{}{}", count, current));
+                } else {
+                    out.extend(std::iter::repeat_n(current, count));
+                }
+                current = sixel;
+                count = 1;
+            }
+            out.push('$');
+        }
+    }
+    out.push_str("\x1b\\");
+    out
+}
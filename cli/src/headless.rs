@@ -0,0 +1,81 @@
+use chip_8::{Chip8, SCREEN_WIDTH};
+
+use crate::script::Script;
+
+/// How often the timers tick, per second of emulated time.
+pub const TIMER_HZ: u64 = 60;
+
+/// Emulated time for runs that aren't paced in real time: counts cycles and
+/// says when the timers are due, so they tick as often as they would at the
+/// clock speed however fast the host runs.
+pub struct TimerClock {
+    clock_speed: u64,
+    cycles: u64,
+    ticks: u64,
+}
+
+impl TimerClock {
+    pub fn new(clock_speed: u64) -> Self {
+        TimerClock {
+            // the command line and config reject 0, but it must never divide
+            clock_speed: clock_speed.max(1),
+            cycles: 0,
+            ticks: 0,
+        }
+    }
+
+    /// Counts one cycle and returns how many timer ticks fall due with it.
+    pub fn cycle(&mut self) -> u64 {
+        self.cycles += 1;
+        let due = self.cycles * TIMER_HZ / self.clock_speed;
+        let ticks = due - self.ticks;
+        self.ticks = due;
+        ticks
+    }
+
+    /// Timer ticks so far, which is frames of emulated time.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+}
+
+/// Runs `cycles` instructions as fast as possible, ticking the timers as often
+/// as they would tick at `clock_speed`, so results don't depend on the host.
+/// A script runs at each tick, and its failure ends the run. The run ends
+/// early once the program finishes, see `Chip8::is_halted`, or stops on a
+/// fault.
+pub fn run(
+    chip8: &mut Chip8,
+    cycles: u64,
+    clock_speed: u64,
+    mut script: Option<&mut Script>,
+) -> Result<(), String> {
+    let mut clock = TimerClock::new(clock_speed);
+    for _ in 0..cycles {
+        if chip8.is_halted() || chip8.fault().is_some() {
+            break;
+        }
+        chip8.cycle();
+        for _ in 0..clock.cycle() {
+            chip8.tick_timers();
+            if let Some(script) = script.as_deref_mut() {
+                for message in script.frame(chip8)? {
+                    eprintln!("{}", message);
+                }
+                // there's no keyboard, so the script's keys are the only ones
+                chip8.set_pressed_keys(script.keys());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The display as text, `#` for lit pixels and `.` for unlit ones.
+pub fn display_text(chip8: &Chip8) -> String {
+    let mut text = String::new();
+    for row in chip8.get_display().chunks(SCREEN_WIDTH) {
+        text.extend(row.iter().map(|&lit| if lit { '#' } else { '.' }));
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,105 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use chip_8::NUM_KEYS;
+
+/// Default for `--key-hold-ms`.
+pub const DEFAULT_KEY_HOLD: Duration = Duration::from_millis(100);
+
+/// How long a press counts as held where the terminal doesn't report key
+/// releases.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyStrategy {
+    /// Held for --key-hold-ms after each press or auto-repeat
+    #[default]
+    Hold,
+    /// Held until the program next checks the keypad, so a tap is never missed
+    Latch,
+}
+
+/// Which `KeyStrategy` to use, and how long `Hold` holds for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    pub strategy: KeyStrategy,
+    pub hold: Duration,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            strategy: KeyStrategy::default(),
+            hold: DEFAULT_KEY_HOLD,
+        }
+    }
+}
+
+/// Tracks which CHIP-8 keys are held.
+///
+/// Terminals that support the kitty keyboard protocol report real press and
+/// release events. Elsewhere only presses (and auto-repeats) arrive, so a key
+/// counts as held for a while after each press, see `Retention`.
+pub struct KeyState {
+    release_events: bool,
+    retention: Retention,
+    held: [bool; NUM_KEYS],
+    last_seen: [Option<Instant>; NUM_KEYS],
+    // pressed since the program last checked the keypad
+    latched: [bool; NUM_KEYS],
+}
+
+impl KeyState {
+    pub fn new(release_events: bool, retention: Retention) -> Self {
+        KeyState {
+            release_events,
+            retention,
+            held: [false; NUM_KEYS],
+            last_seen: [None; NUM_KEYS],
+            latched: [false; NUM_KEYS],
+        }
+    }
+
+    pub fn set_retention(&mut self, retention: Retention) {
+        self.retention = retention;
+    }
+
+    pub fn press(&mut self, key: usize) {
+        self.held[key] = true;
+        self.last_seen[key] = Some(Instant::now());
+        self.latched[key] = true;
+    }
+
+    pub fn release(&mut self, key: usize) {
+        self.held[key] = false;
+    }
+
+    /// Lets go of every key, for when releases may have been missed.
+    pub fn clear(&mut self) {
+        self.held = [false; NUM_KEYS];
+        self.last_seen = [None; NUM_KEYS];
+        self.latched = [false; NUM_KEYS];
+    }
+
+    /// Lets go of latched keys, once the program has checked the keypad
+    /// with them down.
+    pub fn keypad_checked(&mut self) {
+        self.latched = [false; NUM_KEYS];
+    }
+
+    /// Keys currently considered pressed.
+    pub fn pressed(&self) -> [bool; NUM_KEYS] {
+        if self.release_events {
+            return self.held;
+        }
+        if self.retention.strategy == KeyStrategy::Latch {
+            return self.latched;
+        }
+        let now = Instant::now();
+        std::array::from_fn(|i| {
+            self.last_seen[i]
+                .map(|last_time| now.duration_since(last_time) < self.retention.hold)
+                .unwrap_or(false)
+        })
+    }
+}
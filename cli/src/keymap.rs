@@ -0,0 +1,203 @@
+use clap::ValueEnum;
+use crossterm::event::KeyCode;
+use std::collections::HashMap;
+
+use chip_8::NUM_KEYS;
+
+/// CHIP-8 keys in the order they appear on the 4x4 COSMAC VIP keypad.
+const KEYPAD_ORDER: [usize; NUM_KEYS] = [
+    0x1, 0x2, 0x3, 0xC, //
+    0x4, 0x5, 0x6, 0xD, //
+    0x7, 0x8, 0x9, 0xE, //
+    0xA, 0x0, 0xB, 0xF,
+];
+
+const DEFAULT_LAYOUT: &str = "1234qwerasdfzxcv";
+
+/// Named keymaps for other keyboard layouts and ways of playing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// 1234/QWER/ASDF/ZXCV, the default
+    Qwerty,
+    /// The same keys on an AZERTY keyboard
+    Azerty,
+    /// Each hex digit on its own key: 0-9, then / * - + Enter . for A-F
+    Numpad,
+    /// The default, plus the arrows on 5 7 8 9 like WASD
+    WasdArrows,
+    /// The default's shape on the right of the keyboard, 7890/UIOP/JKL;/M,./,
+    /// for a second player
+    RightHand,
+}
+
+impl Preset {
+    pub fn keymap(self) -> Keymap {
+        // a layout in keypad order, then bindings added to it
+        let (layout, bindings) = match self {
+            Preset::Qwerty => (Some(DEFAULT_LAYOUT), ""),
+            // the number row types symbols unless shifted
+            Preset::Azerty => (Some("&é\"'azerqsdfwxcv"), "1=1,2=2,3=3,4=c"),
+            Preset::Numpad => (
+                None,
+                "0=0,1=1,2=2,3=3,4=4,5=5,6=6,7=7,8=8,9=9,/=a,*=b,-=c,+=d,enter=e,.=f",
+            ),
+            Preset::WasdArrows => (Some(DEFAULT_LAYOUT), "up=5,left=7,down=8,right=9"),
+            Preset::RightHand => (Some("7890uiopjkl;m,./"), ""),
+        };
+        let mut keymap = match layout {
+            Some(layout) => Keymap::from_layout(layout).expect("preset layouts are valid"),
+            None => Keymap {
+                bindings: HashMap::new(),
+            },
+        };
+        if !bindings.is_empty() {
+            keymap
+                .add_bindings(bindings)
+                .expect("preset bindings are valid");
+        }
+        keymap
+    }
+}
+
+/// Maps physical keys to CHIP-8 keys. Several physical keys may share a CHIP-8 key.
+#[derive(Clone)]
+pub struct Keymap {
+    bindings: HashMap<KeyCode, usize>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::from_layout(DEFAULT_LAYOUT).expect("default layout is valid")
+    }
+}
+
+impl Keymap {
+    /// Builds a keymap from 16 single-character keys listed in keypad order
+    /// (`1 2 3 C / 4 5 6 D / 7 8 9 E / A 0 B F`), e.g. `1234qwerasdfzxcv`.
+    pub fn from_layout(layout: &str) -> Result<Self, String> {
+        let chars: Vec<char> = layout.chars().collect();
+        if chars.len() != NUM_KEYS {
+            return Err(format!(
+                "layout `{}` must list exactly {} keys",
+                layout, NUM_KEYS
+            ));
+        }
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+        };
+        for (c, chip8_key) in chars.into_iter().zip(KEYPAD_ORDER) {
+            keymap.bind(KeyCode::Char(c.to_ascii_lowercase()), chip8_key);
+        }
+        Ok(keymap)
+    }
+
+    /// Parses a `--keymap` value: either a 16 key layout (see `from_layout`) or
+    /// comma separated `key=X` bindings added on top of the default layout,
+    /// e.g. `up=5,down=8,left=7,right=9`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        if !spec.contains('=') {
+            return Keymap::from_layout(spec);
+        }
+        let mut keymap = Keymap::default();
+        keymap.add_bindings(spec)?;
+        Ok(keymap)
+    }
+
+    /// Parses a `--player2-keymap` value. Like `parse`, but bindings start
+    /// from no keys at all, since the default layout is player 1's.
+    pub fn parse_player2(spec: &str) -> Result<Self, String> {
+        if !spec.contains('=') {
+            return Keymap::from_layout(spec);
+        }
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+        };
+        keymap.add_bindings(spec)?;
+        Ok(keymap)
+    }
+
+    // adds comma separated `key=X` bindings
+    fn add_bindings(&mut self, spec: &str) -> Result<(), String> {
+        for binding in spec.split(',') {
+            let (key, chip8_key) = binding
+                .split_once('=')
+                .ok_or_else(|| format!("invalid binding `{}`, expected key=X", binding))?;
+            let code = parse_key(key.trim())?;
+            let chip8_key = usize::from_str_radix(chip8_key.trim(), 16)
+                .ok()
+                .filter(|&k| k < NUM_KEYS)
+                .ok_or_else(|| format!("invalid CHIP-8 key `{}`, expected 0-F", chip8_key))?;
+            self.bind(code, chip8_key);
+        }
+        Ok(())
+    }
+
+    pub fn bind(&mut self, code: KeyCode, chip8_key: usize) {
+        self.bindings.insert(code, chip8_key);
+    }
+
+    /// A physical key bound in both this keymap and `other`, if any.
+    pub fn shared_key(&self, other: &Keymap) -> Option<KeyCode> {
+        self.bindings
+            .keys()
+            .find(|code| other.bindings.contains_key(code))
+            .copied()
+    }
+
+    pub fn chip8_key(&self, code: KeyCode) -> Option<usize> {
+        // shifted letters arrive in upper case
+        let code = match code {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            code => code,
+        };
+        self.bindings.get(&code).copied()
+    }
+
+    /// Short description of the character keys in keypad order, e.g. `1234 QWER ASDF ZXCV`.
+    pub fn layout_hint(&self) -> String {
+        let mut hint = String::new();
+        for (i, chip8_key) in KEYPAD_ORDER.into_iter().enumerate() {
+            if i > 0 && i % 4 == 0 {
+                hint.push(' ');
+            }
+            let key = self
+                .bindings
+                .iter()
+                .filter(|&(_, &k)| k == chip8_key)
+                .filter_map(|(code, _)| match code {
+                    KeyCode::Char(c) => Some(c.to_ascii_uppercase()),
+                    _ => None,
+                })
+                .min()
+                .unwrap_or('?');
+            hint.push(key);
+        }
+        hint
+    }
+}
+
+/// Parses a physical key name: a single character or one of the named keys.
+pub fn parse_key(name: &str) -> Result<KeyCode, String> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c.to_ascii_lowercase()));
+    }
+    let code = match name.to_ascii_lowercase().as_str() {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "space" => KeyCode::Char(' '),
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "insert" => KeyCode::Insert,
+        "delete" => KeyCode::Delete,
+        _ => return Err(format!("unknown key `{}`", name)),
+    };
+    Ok(code)
+}
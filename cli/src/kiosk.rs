@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chip_8::MAX_ROM_SIZE;
+
+const ROM_EXTENSIONS: [&str; 4] = ["ch8", "c8", "sc8", "xo8"];
+// how often the drop directory is rescanned
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Something the kiosk noticed while scanning its directory.
+pub enum KioskEvent {
+    Added(String),
+    Rejected(String, &'static str),
+    /// A ROM in the rotation that could no longer be loaded, e.g. because it
+    /// was deleted.
+    Dropped(String, &'static str),
+}
+
+/// Unattended mode that rotates through the ROMs in a directory and picks up
+/// ROMs copied into it while running.
+pub struct Kiosk {
+    dir: PathBuf,
+    roms: Vec<PathBuf>,
+    current: usize,
+    interval: Duration,
+    started_at: Instant,
+    last_scan: Instant,
+    // files seen but not yet accepted, with their size at the last scan;
+    // a file is only ingested once its size stops changing
+    pending: HashMap<PathBuf, u64>,
+    rejected: Vec<PathBuf>,
+    // ROMs dropped from the rotation since the last scan
+    dropped: Vec<KioskEvent>,
+}
+
+impl Kiosk {
+    pub fn open(dir: &Path, interval: Duration) -> io::Result<Self> {
+        let mut kiosk = Kiosk {
+            dir: dir.to_path_buf(),
+            roms: Vec::new(),
+            current: 0,
+            interval,
+            started_at: Instant::now(),
+            last_scan: Instant::now(),
+            pending: HashMap::new(),
+            rejected: Vec::new(),
+            dropped: Vec::new(),
+        };
+        for path in kiosk.list()? {
+            if validate(&path).is_ok() {
+                kiosk.roms.push(path);
+            }
+        }
+        if kiosk.roms.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no ROMs found in {}", dir.display()),
+            ));
+        }
+        Ok(kiosk)
+    }
+
+    pub fn current_path(&self) -> &Path {
+        &self.roms[self.current]
+    }
+
+    pub fn current_name(&self) -> String {
+        display_name(self.current_path())
+    }
+
+    /// Reads the current ROM. ROMs that can't be read any more are dropped
+    /// from the rotation, reported by the next `scan`, and the next one is
+    /// tried; it's only an error once none are left.
+    pub fn current_rom(&mut self) -> io::Result<Vec<u8>> {
+        while !self.roms.is_empty() {
+            let path = self.current_path();
+            match read(path) {
+                Ok(data) => return Ok(data),
+                Err(reason) => {
+                    self.dropped
+                        .push(KioskEvent::Dropped(display_name(path), reason));
+                    self.roms.remove(self.current);
+                    if self.current == self.roms.len() {
+                        self.current = 0;
+                    }
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no ROMs left in {}", self.dir.display()),
+        ))
+    }
+
+    /// Whether the current ROM has been shown for the full rotation interval.
+    pub fn rotation_due(&self) -> bool {
+        self.started_at.elapsed() >= self.interval
+    }
+
+    pub fn advance(&mut self) {
+        self.current = (self.current + 1) % self.roms.len();
+        self.started_at = Instant::now();
+    }
+
+    /// Rescans the directory (at most once per second) and ingests new ROMs.
+    pub fn scan(&mut self) -> Vec<KioskEvent> {
+        let mut events = std::mem::take(&mut self.dropped);
+        if self.last_scan.elapsed() < SCAN_INTERVAL {
+            return events;
+        }
+        self.last_scan = Instant::now();
+
+        let Ok(paths) = self.list() else {
+            return events;
+        };
+        for path in paths {
+            if self.roms.contains(&path) || self.rejected.contains(&path) {
+                continue;
+            }
+            let Ok(size) = fs::metadata(&path).map(|m| m.len()) else {
+                continue;
+            };
+            // wait for the copy to finish before reading the file
+            if self.pending.insert(path.clone(), size) != Some(size) {
+                continue;
+            }
+            self.pending.remove(&path);
+            match validate(&path) {
+                Ok(()) => {
+                    events.push(KioskEvent::Added(display_name(&path)));
+                    self.roms.push(path);
+                }
+                Err(reason) => {
+                    events.push(KioskEvent::Rejected(display_name(&path), reason));
+                    self.rejected.push(path);
+                }
+            }
+        }
+        events
+    }
+
+    fn list(&self) -> io::Result<Vec<PathBuf>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| has_rom_extension(path))
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+pub fn has_rom_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ROM_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+fn validate(path: &Path) -> Result<(), &'static str> {
+    read(path).map(|_| ())
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, &'static str> {
+    let data = fs::read(path).map_err(|_| "unreadable")?;
+    if data.is_empty() {
+        return Err("empty file");
+    }
+    if data.len() > MAX_ROM_SIZE {
+        return Err("too large");
+    }
+    Ok(data)
+}
+
+pub fn display_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
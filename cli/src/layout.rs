@@ -0,0 +1,23 @@
+/// Where the display goes in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Top left corner of the centred display, and the terminal width.
+    Centered { left: u16, top: u16, columns: u16 },
+    /// The terminal is smaller than the `columns` x `rows` needed.
+    TooSmall { columns: u16, rows: u16 },
+}
+
+impl Layout {
+    /// Centres an area of `needed` (columns, rows) in a terminal of `size`.
+    pub fn new(size: (u16, u16), needed: (u16, u16)) -> Self {
+        let (columns, rows) = needed;
+        if size.0 < columns || size.1 < rows {
+            return Layout::TooSmall { columns, rows };
+        }
+        Layout::Centered {
+            left: (size.0 - columns) / 2,
+            top: (size.1 - rows) / 2,
+            columns: size.0,
+        }
+    }
+}
@@ -0,0 +1,434 @@
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::{cursor, execute, queue, style, terminal};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chip_8::{MAX_ROM_SIZE, Quirks, is_hires_rom, romdb};
+
+use crate::config::{RomSettings, SettingsSources};
+use crate::input::{KeyStrategy, Retention};
+use crate::kiosk::{display_name, has_rom_extension};
+use crate::truncate;
+
+// lines used by the title, column headings and help text around the list
+const CHROME_LINES: u16 = 5;
+// clock speed change per Left/Right press, and its limits
+const SPEED_STEP: u64 = 100;
+const MIN_SPEED: u64 = 100;
+const MAX_SPEED: u64 = 10_000;
+
+/// The variant a ROM was written for, as far as can be told from the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    Hires,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    fn detect(path: &Path, rom: &[u8]) -> Platform {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("sc8") => Platform::SuperChip,
+            Some("xo8") => Platform::XoChip,
+            _ if is_hires_rom(rom) => Platform::Hires,
+            _ => Platform::Chip8,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::Hires => "HIRES",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP",
+        }
+    }
+}
+
+/// A ROM found in the library directory.
+pub struct Game {
+    pub path: PathBuf,
+    pub name: String,
+    pub rom: Vec<u8>,
+    pub platform: Platform,
+    // the path relative to the library directory, which settings are saved under
+    key: String,
+}
+
+/// The settings file: `[games."<path in library>"]` tables of `RomSettings`.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct SettingsFile {
+    games: BTreeMap<String, RomSettings>,
+}
+
+/// Every ROM under a directory, with settings remembered per game, for
+/// switching games from a menu without restarting.
+pub struct Library {
+    games: Vec<Game>,
+    current: usize,
+    sources: SettingsSources,
+    saved: SettingsFile,
+    settings_path: Option<PathBuf>,
+}
+
+impl Library {
+    /// Indexes the ROMs under `dir` and reads saved settings from
+    /// `settings_path`, where a missing file counts as empty.
+    pub fn open(
+        dir: &Path,
+        sources: SettingsSources,
+        settings_path: Option<PathBuf>,
+    ) -> Result<Library, Box<dyn Error>> {
+        let mut games = Vec::new();
+        index(dir, dir, &mut games)?;
+        if games.is_empty() {
+            return Err(format!("no ROMs found in {}", dir.display()).into());
+        }
+        games.sort_by_key(|game| game.name.to_lowercase());
+
+        let saved = match settings_path.as_deref().map(fs::read_to_string) {
+            Some(Ok(text)) => {
+                let path = settings_path.as_deref().unwrap_or(dir);
+                toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+            }
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => SettingsFile::default(),
+        };
+        Ok(Library {
+            games,
+            current: 0,
+            sources,
+            saved,
+            settings_path,
+        })
+    }
+
+    pub fn current(&self) -> &Game {
+        &self.games[self.current]
+    }
+
+    /// Clock speed and quirks for the current game.
+    pub fn current_settings(&self) -> (u64, Quirks) {
+        self.settings(self.current)
+    }
+
+    /// How keys are held for the current game.
+    pub fn current_retention(&self) -> Retention {
+        self.sources.retention(self.saved_settings(self.current))
+    }
+
+    fn settings(&self, index: usize) -> (u64, Quirks) {
+        let game = &self.games[index];
+        self.sources.resolve(&game.rom, self.saved_settings(index))
+    }
+
+    fn saved_settings(&self, index: usize) -> RomSettings {
+        let key = &self.games[index].key;
+        self.saved.games.get(key).copied().unwrap_or_default()
+    }
+
+    fn save_settings(&mut self, index: usize, settings: RomSettings) -> io::Result<()> {
+        let key = self.games[index].key.clone();
+        if settings.is_empty() {
+            self.saved.games.remove(&key);
+        } else {
+            self.saved.games.insert(key, settings);
+        }
+        let Some(path) = self.settings_path.as_deref() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string(&self.saved).map_err(io::Error::other)?;
+        fs::write(path, text)
+    }
+
+    /// Shows the menu before anything else is running, taking over the
+    /// terminal while it's open. Returns false if the user quits.
+    pub fn pick(&mut self) -> io::Result<bool> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+        let result = self.browse(&mut stdout, "Esc quit");
+        execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+        terminal::disable_raw_mode()?;
+        result
+    }
+
+    /// Shows the menu over a running game, on a terminal already in raw
+    /// mode. Returns whether a different game was chosen; either way the
+    /// current game's settings may have changed.
+    pub fn menu(&mut self, stdout: &mut impl Write) -> io::Result<bool> {
+        let previous = self.current;
+        self.browse(stdout, "Esc back")?;
+        Ok(self.current != previous)
+    }
+
+    // true once a game is chosen with Enter, false on Esc
+    fn browse(&mut self, stdout: &mut impl Write, escape_hint: &str) -> io::Result<bool> {
+        let mut selected = self.current;
+        let mut scroll = 0;
+        let mut message = None;
+
+        loop {
+            let (_, rows) = terminal::size()?;
+            let visible = rows.saturating_sub(CHROME_LINES).max(1) as usize;
+            // keep the selection on screen
+            if selected < scroll {
+                scroll = selected;
+            } else if selected >= scroll + visible {
+                scroll = selected + 1 - visible;
+            }
+            self.draw(
+                stdout,
+                selected,
+                scroll,
+                visible,
+                escape_hint,
+                message.take(),
+            )?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+            let last = self.games.len() - 1;
+            let (clock_speed, quirks) = self.settings(selected);
+            let saved = self.saved_settings(selected);
+            let retention = self.sources.retention(saved);
+            let changed = match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(false),
+                KeyCode::Enter => {
+                    self.current = selected;
+                    return Ok(true);
+                }
+                KeyCode::Up => {
+                    selected = selected.saturating_sub(1);
+                    None
+                }
+                KeyCode::Down => {
+                    selected = (selected + 1).min(last);
+                    None
+                }
+                KeyCode::PageUp => {
+                    selected = selected.saturating_sub(visible);
+                    None
+                }
+                KeyCode::PageDown => {
+                    selected = (selected + visible).min(last);
+                    None
+                }
+                KeyCode::Home => {
+                    selected = 0;
+                    None
+                }
+                KeyCode::End => {
+                    selected = last;
+                    None
+                }
+                KeyCode::Left | KeyCode::Right => {
+                    let speed = if key.code == KeyCode::Left {
+                        clock_speed.saturating_sub(SPEED_STEP)
+                    } else {
+                        clock_speed + SPEED_STEP
+                    };
+                    Some(RomSettings {
+                        clock_speed: Some(speed.clamp(MIN_SPEED, MAX_SPEED)),
+                        ..saved
+                    })
+                }
+                KeyCode::Char('d') => Some(RomSettings {
+                    display_wait: Some(!quirks.display_wait),
+                    ..saved
+                }),
+                KeyCode::Char('v') => Some(RomSettings {
+                    vf_reset: Some(!quirks.vf_reset),
+                    ..saved
+                }),
+                KeyCode::Char('w') => Some(RomSettings {
+                    wrap_sprites: Some(!quirks.wrap_sprites),
+                    ..saved
+                }),
+                KeyCode::Char('j') => Some(RomSettings {
+                    jump_vx: Some(!quirks.jump_vx),
+                    ..saved
+                }),
+                KeyCode::Char('i') => Some(RomSettings {
+                    i_overflow: Some(!quirks.i_overflow),
+                    ..saved
+                }),
+                KeyCode::Char('s') => Some(RomSettings {
+                    shift_vy: Some(!quirks.shift_vy),
+                    ..saved
+                }),
+                KeyCode::Char('m') => Some(RomSettings {
+                    load_store_increment: Some(!quirks.load_store_increment),
+                    ..saved
+                }),
+                KeyCode::Char('l') => Some(RomSettings {
+                    key_strategy: Some(match retention.strategy {
+                        KeyStrategy::Hold => KeyStrategy::Latch,
+                        KeyStrategy::Latch => KeyStrategy::Hold,
+                    }),
+                    ..saved
+                }),
+                KeyCode::Delete | KeyCode::Backspace => Some(RomSettings::default()),
+                _ => None,
+            };
+            if let Some(settings) = changed
+                && let Err(e) = self.save_settings(selected, settings)
+            {
+                message = Some(format!("Saving settings failed: {}", e));
+            }
+        }
+    }
+
+    fn draw(
+        &self,
+        stdout: &mut impl Write,
+        selected: usize,
+        scroll: usize,
+        visible: usize,
+        escape_hint: &str,
+        message: Option<String>,
+    ) -> io::Result<()> {
+        let (columns, rows) = terminal::size()?;
+        let heading = format!(
+            "  {:<32} {:>6}  {:<10}  {:>8}  {}",
+            "Game", "Bytes", "Platform", "Speed", "Quirks"
+        );
+        queue!(
+            stdout,
+            terminal::Clear(terminal::ClearType::All),
+            cursor::MoveTo(0, 0),
+            style::Print(format!("Library: {} games\r\n\r\n", self.games.len())),
+            style::Print(truncate(&heading, columns)),
+            style::Print("\r\n")
+        )?;
+        for (index, game) in self.games.iter().enumerate().skip(scroll).take(visible) {
+            let (clock_speed, quirks) = self.settings(index);
+            let retention = self.sources.retention(self.saved_settings(index));
+            let line = format!(
+                "{} {:<32} {:>6}  {:<10}  {:>5} Hz  {}{}{}{}{}{}{}{}{}",
+                if index == selected { '>' } else { ' ' },
+                truncate(&game.name, 32),
+                game.rom.len(),
+                game.platform.label(),
+                clock_speed,
+                if quirks.display_wait {
+                    "display wait "
+                } else {
+                    ""
+                },
+                if quirks.vf_reset { "vf reset " } else { "" },
+                if quirks.wrap_sprites {
+                    "wrap sprites "
+                } else {
+                    ""
+                },
+                if quirks.jump_vx { "jump vx " } else { "" },
+                if quirks.i_overflow { "i overflow " } else { "" },
+                if quirks.shift_vy { "shift vy " } else { "" },
+                if quirks.load_store_increment {
+                    "memory increment "
+                } else {
+                    ""
+                },
+                if retention.strategy == KeyStrategy::Latch {
+                    "latch keys "
+                } else {
+                    ""
+                },
+                if self.saved_settings(index).is_empty() {
+                    ""
+                } else {
+                    "*"
+                },
+            );
+            if index == selected {
+                queue!(
+                    stdout,
+                    style::SetAttribute(style::Attribute::Reverse),
+                    style::Print(truncate(&line, columns)),
+                    style::SetAttribute(style::Attribute::Reset)
+                )?;
+            } else {
+                queue!(stdout, style::Print(truncate(&line, columns)))?;
+            }
+            queue!(stdout, style::Print("\r\n"))?;
+        }
+
+        let footer = match message {
+            Some(message) => message,
+            None if !self.sources.flags.is_empty() => {
+                String::from("Command line flags override the settings saved here")
+            }
+            None => String::from("* settings saved for this game"),
+        };
+        let help = format!(
+            "Enter play | Left/Right speed | D display wait | V VF reset | W wrap sprites | J jump VX | I I overflow | S shift VY | M memory increment | L latch keys | Del forget settings | {}",
+            escape_hint
+        );
+        queue!(
+            stdout,
+            cursor::MoveTo(0, rows.saturating_sub(2)),
+            style::Print(truncate(&footer, columns)),
+            cursor::MoveTo(0, rows.saturating_sub(1)),
+            style::Print(truncate(&help, columns))
+        )?;
+        stdout.flush()
+    }
+}
+
+/// Adds the ROMs under `dir` to `games`, recursing into subdirectories.
+/// Hidden files, and files too large to be ROMs, are left out.
+fn index(root: &Path, dir: &Path, games: &mut Vec<Game>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)?.filter_map(Result::ok) {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            index(root, &path, games)?;
+            continue;
+        }
+        if !has_rom_extension(&path) {
+            continue;
+        }
+        let Ok(rom) = fs::read(&path) else {
+            continue;
+        };
+        if rom.is_empty() || rom.len() > MAX_ROM_SIZE {
+            continue;
+        }
+        let name = match romdb::lookup(&rom) {
+            Some(info) => info.title.to_string(),
+            None => display_name(&path),
+        };
+        let key = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        games.push(Game {
+            platform: Platform::detect(&path, &rom),
+            path,
+            name,
+            rom,
+            key,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,248 @@
+//! `lint`: follows every path through a ROM from its entry point without
+//! running it, reporting invalid opcodes, jumps out of the program, calls
+//! nested deeper than the stack and writes over the font, and which parts
+//! of the ROM are code and which data.
+
+use std::collections::{BTreeSet, HashSet};
+use std::error::Error;
+use std::path::Path;
+
+use chip_8::font::{BIG, BIG_FONT_ADDRESS};
+use chip_8::{Chip8, Instruction, STACK_SIZE, decode};
+
+use crate::loader;
+
+// the small and the large font sit back to back from address 0
+const FONT_END: u16 = BIG_FONT_ADDRESS + BIG.len() as u16;
+
+/// A place the program can get to: the address, how many calls deep, and
+/// the value of I if it's known there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    address: u16,
+    depth: usize,
+    i: Option<u16>,
+}
+
+struct Lint<'a> {
+    memory: &'a [u8],
+    // the bytes the ROM was loaded into
+    start: u16,
+    end: u16,
+    // whether each byte of the ROM is part of a reachable instruction
+    code: Vec<bool>,
+    problems: BTreeSet<(u16, String)>,
+    computed_jumps: BTreeSet<u16>,
+    pending: Vec<State>,
+    visited: HashSet<State>,
+}
+
+/// Lints the ROM at `rom_path`, printing each problem with its address and
+/// then the code and data regions. Fails if there were any problems.
+pub fn run(rom_path: &Path) -> Result<(), Box<dyn Error>> {
+    let rom = loader::read(rom_path)?;
+    let mut chip8 = Chip8::new();
+    chip8.init();
+    chip8.load_rom(&rom)?;
+    let start = chip8.load_address();
+    let end = (usize::from(start) + rom.len()).min(chip8.memory().len()) as u16;
+
+    let mut lint = Lint {
+        memory: chip8.memory(),
+        start,
+        end,
+        code: vec![false; usize::from(end - start)],
+        problems: BTreeSet::new(),
+        computed_jumps: BTreeSet::new(),
+        pending: Vec::new(),
+        visited: HashSet::new(),
+    };
+    lint.follow(chip8.pc());
+    lint.report();
+
+    match lint.problems.len() {
+        0 => Ok(()),
+        1 => Err("1 problem found".into()),
+        n => Err(format!("{} problems found", n).into()),
+    }
+}
+
+impl Lint<'_> {
+    fn follow(&mut self, entry: u16) {
+        self.pending.push(State {
+            address: entry,
+            depth: 0,
+            i: None,
+        });
+        while let Some(state) = self.pending.pop() {
+            if self.visited.insert(state) {
+                self.step(state);
+            }
+        }
+    }
+
+    fn step(&mut self, state: State) {
+        let address = state.address;
+        if address.saturating_add(2) > self.end {
+            self.problem(address, "runs past the end of the ROM".to_string());
+            return;
+        }
+        let offset = usize::from(address - self.start);
+        self.code[offset] = true;
+        self.code[offset + 1] = true;
+        let opcode = u16::from_be_bytes([
+            self.memory[usize::from(address)],
+            self.memory[usize::from(address) + 1],
+        ]);
+        let Some(instruction) = decode(opcode) else {
+            self.problem(address, format!("invalid opcode {:04X}", opcode));
+            return;
+        };
+
+        let next = State {
+            address: address + 2,
+            ..state
+        };
+        match instruction {
+            Instruction::Exit => {}
+            Instruction::Return => {
+                if state.depth == 0 {
+                    self.problem(address, "returns with nothing on the stack".to_string());
+                }
+            }
+            // jumping to itself is how programs stop
+            Instruction::Jump(target) if target == address => {}
+            Instruction::Jump(target) => {
+                if self.check_target(address, target) {
+                    self.pending.push(State {
+                        address: target,
+                        ..state
+                    });
+                }
+            }
+            Instruction::Call(target) => {
+                if state.depth == STACK_SIZE {
+                    self.problem(
+                        address,
+                        format!("calls {} deep, more than the stack holds", STACK_SIZE + 1),
+                    );
+                    return;
+                }
+                if self.check_target(address, target) {
+                    self.pending.push(State {
+                        address: target,
+                        depth: state.depth + 1,
+                        ..state
+                    });
+                }
+                // the subroutine may have changed I by the time it returns
+                self.pending.push(State { i: None, ..next });
+            }
+            Instruction::JumpV0(_) => {
+                self.computed_jumps.insert(address);
+            }
+            Instruction::SkipEqImm { .. }
+            | Instruction::SkipNeImm { .. }
+            | Instruction::SkipEqReg { .. }
+            | Instruction::SkipNeReg { .. }
+            | Instruction::SkipKeyPressed { .. }
+            | Instruction::SkipKeyNotPressed { .. }
+            | Instruction::SkipKey2Pressed { .. }
+            | Instruction::SkipKey2NotPressed { .. } => {
+                self.pending.push(next);
+                self.pending.push(State {
+                    address: address + 4,
+                    ..state
+                });
+            }
+            Instruction::LoadI(i) => self.pending.push(State { i: Some(i), ..next }),
+            Instruction::StoreBcd { .. } => {
+                self.check_write(address, state.i, 3);
+                self.pending.push(next);
+            }
+            Instruction::StoreRegisters { x } => {
+                self.check_write(address, state.i, u16::from(x) + 1);
+                // I may move past the registers, depending on the quirks
+                self.pending.push(State { i: None, ..next });
+            }
+            Instruction::LoadRegisters { .. }
+            | Instruction::AddI { .. }
+            | Instruction::LoadFont { .. }
+            | Instruction::LoadBigFont { .. } => self.pending.push(State { i: None, ..next }),
+            _ => self.pending.push(next),
+        }
+    }
+
+    // whether a jump or call from `address` lands in the ROM
+    fn check_target(&mut self, address: u16, target: u16) -> bool {
+        if target < self.start {
+            self.problem(
+                address,
+                format!("jumps to {:#05X}, below the program", target),
+            );
+            false
+        } else if target >= self.end {
+            self.problem(
+                address,
+                format!("jumps to {:#05X}, past the end of the ROM", target),
+            );
+            false
+        } else {
+            true
+        }
+    }
+
+    fn check_write(&mut self, address: u16, i: Option<u16>, len: u16) {
+        let Some(i) = i else { return };
+        if i < FONT_END {
+            self.problem(address, format!("writes to {:#05X}, over the font", i));
+        } else if usize::from(i + len) > self.memory.len() {
+            self.problem(
+                address,
+                format!("writes to {:#05X}, past the end of memory", i),
+            );
+        }
+    }
+
+    fn problem(&mut self, address: u16, message: String) {
+        self.problems.insert((address, message));
+    }
+
+    fn report(&self) {
+        // computed jumps aren't problems, but are listed in the same order
+        let notes = self.computed_jumps.iter().map(|&address| {
+            (
+                address,
+                "jumps by V0, so where it goes isn't followed".to_string(),
+            )
+        });
+        let lines: BTreeSet<_> = self.problems.iter().cloned().chain(notes).collect();
+        for (address, message) in lines {
+            println!("{:#05X}  {}", address, message);
+        }
+
+        println!("Regions:");
+        let mut region_start = 0;
+        for offset in 1..=self.code.len() {
+            if offset < self.code.len() && self.code[offset] == self.code[region_start] {
+                continue;
+            }
+            let kind = if self.code[region_start] {
+                "code"
+            } else {
+                "data"
+            };
+            println!(
+                "  {:#05X}-{:#05X}  {} ({} bytes)",
+                usize::from(self.start) + region_start,
+                usize::from(self.start) + offset - 1,
+                kind,
+                offset - region_start
+            );
+            region_start = offset;
+        }
+        if self.problems.is_empty() {
+            println!("No problems found");
+        }
+    }
+}
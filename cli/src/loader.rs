@@ -0,0 +1,96 @@
+//! Reads a ROM from wherever the command line points: a file, a file inside
+//! a zip archive, stdin for `-`, or with the `http` feature an `http://` or
+//! `https://` URL. Octo source in a `.8o` file is compiled on the way.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use chip_8::MAX_ROM_SIZE;
+
+use crate::{archive, octo, screenshot};
+
+/// The ROM at `path`, which may also be `-` or a URL. Fails for a ROM
+/// larger than `MAX_ROM_SIZE`.
+pub fn read(path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    let rom = read_any(path)?;
+    if rom.len() > MAX_ROM_SIZE {
+        let source = if is_stdin(path) {
+            "stdin".to_string()
+        } else {
+            path.display().to_string()
+        };
+        return Err(format!(
+            "{}: the ROM is too large, only {} bytes fit in memory",
+            source, MAX_ROM_SIZE
+        )
+        .into());
+    }
+    Ok(rom)
+}
+
+fn read_any(path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    if is_stdin(path) {
+        let mut rom = Vec::new();
+        // one byte over is enough to tell it's too large
+        io::stdin()
+            .take(MAX_ROM_SIZE as u64 + 1)
+            .read_to_end(&mut rom)?;
+        return Ok(rom);
+    }
+    if let Some(url) = url(path) {
+        return download(url);
+    }
+    if let Some((archive, name)) = archive::split(path) {
+        return archive::read(archive, &name);
+    }
+    if octo::is_source(path) {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        return octo::compile(&source).map_err(|e| format!("{}: {}", path.display(), e).into());
+    }
+    fs::read(path).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+/// A name for the ROM at `path`, for screenshots and logs.
+pub fn name(path: &Path) -> String {
+    if is_stdin(path) {
+        return "stdin".to_string();
+    }
+    match url(path) {
+        // the last path segment, without a query or extension
+        Some(url) => {
+            let segment = url.split(['?', '#']).next().unwrap_or(url);
+            screenshot::name_for(Path::new(segment.rsplit('/').next().unwrap_or(segment)))
+        }
+        None => screenshot::name_for(path),
+    }
+}
+
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+fn url(path: &Path) -> Option<&str> {
+    let text = path.to_str()?;
+    (text.starts_with("http://") || text.starts_with("https://")).then_some(text)
+}
+
+#[cfg(feature = "http")]
+fn download(url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut response = ureq::get(url)
+        .call()
+        .map_err(|e| format!("{}: {}", url, e))?;
+    let rom = response
+        .body_mut()
+        .with_config()
+        .limit(MAX_ROM_SIZE as u64)
+        .read_to_vec()
+        .map_err(|e| format!("{}: {}", url, e))?;
+    Ok(rom)
+}
+
+#[cfg(not(feature = "http"))]
+fn download(url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    Err(format!("{}: loading ROMs from URLs needs the `http` feature", url).into())
+}
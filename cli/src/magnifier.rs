@@ -0,0 +1,117 @@
+use crossterm::{cursor, queue, style};
+use std::io::{self, Stdout};
+
+use chip_8::{DirtyRows, SCREEN_WIDTH};
+
+const ZOOM_WIDTH: usize = 16;
+const ZOOM_HEIGHT: usize = 8;
+// the title and column coordinates, then a line per zoomed row
+const LINES: usize = 2 + ZOOM_HEIGHT;
+// a row coordinate, then three cells per pixel
+const COLUMNS: usize = 3 + 3 * ZOOM_WIDTH;
+
+/// Enlarged view of a region of the display, one pixel per cell with coordinates.
+/// Drawn below the main display when visible.
+#[derive(Default)]
+pub struct Magnifier {
+    x: usize,
+    y: usize,
+    visible: bool,
+}
+
+impl Magnifier {
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Terminal lines the magnifier takes up, or 0 when hidden.
+    pub fn lines(&self) -> u16 {
+        if self.visible { LINES as u16 } else { 0 }
+    }
+
+    /// Terminal columns the magnifier takes up, or 0 when hidden.
+    pub fn columns(&self) -> u16 {
+        if self.visible { COLUMNS as u16 } else { 0 }
+    }
+
+    /// Moves the zoomed region, keeping it inside a display `height` rows tall.
+    pub fn move_by(&mut self, dx: isize, dy: isize, height: usize) {
+        self.x = self
+            .x
+            .saturating_add_signed(dx)
+            .min(SCREEN_WIDTH - ZOOM_WIDTH);
+        self.y = self.y.saturating_add_signed(dy).min(height - ZOOM_HEIGHT);
+    }
+
+    /// Whether any of the `dirty` rows of a display `height` rows tall are
+    /// zoomed in on.
+    pub fn shows_any(&self, dirty: DirtyRows, height: usize) -> bool {
+        let top_row = self.top_row(height);
+        self.visible && (top_row..top_row + ZOOM_HEIGHT).any(|row| dirty.contains(row))
+    }
+
+    // the display may have shrunk since the region was moved
+    fn top_row(&self, height: usize) -> usize {
+        self.y.min(height - ZOOM_HEIGHT)
+    }
+
+    /// Draws the magnifier with its top left corner at terminal cell
+    /// (`left`, `top`), or blanks its area when hidden. Nothing outside the
+    /// area is touched.
+    pub fn draw(
+        &self,
+        pixels: &[bool],
+        stdout: &mut Stdout,
+        (left, top): (u16, u16),
+    ) -> io::Result<()> {
+        let lines = if self.visible {
+            self.lines_for(pixels)
+        } else {
+            vec![String::new(); LINES]
+        };
+        for (offset, line) in lines.iter().enumerate() {
+            queue!(
+                stdout,
+                cursor::MoveTo(left, top + offset as u16),
+                style::Print(format!("{:<1$}", line, COLUMNS))
+            )?;
+        }
+        Ok(())
+    }
+
+    fn lines_for(&self, pixels: &[bool]) -> Vec<String> {
+        let top_row = self.top_row(pixels.len() / SCREEN_WIDTH);
+        let mut lines = Vec::with_capacity(LINES);
+        lines.push(format!(
+            "Zoom ({},{})-({},{}) | Arrows move, F2 closes",
+            self.x,
+            top_row,
+            self.x + ZOOM_WIDTH - 1,
+            top_row + ZOOM_HEIGHT - 1
+        ));
+
+        // column coordinates
+        let mut header = String::from("   ");
+        for x in self.x..self.x + ZOOM_WIDTH {
+            header.push_str(&format!("{:>3}", x));
+        }
+        lines.push(header);
+
+        for y in top_row..top_row + ZOOM_HEIGHT {
+            let mut line = format!("{:>3}", y);
+            for x in self.x..self.x + ZOOM_WIDTH {
+                line.push_str(if pixels[y * SCREEN_WIDTH + x] {
+                    " ██"
+                } else {
+                    "  ·"
+                });
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
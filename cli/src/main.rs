@@ -1,177 +1,1747 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use crossterm::{
     cursor,
-    event::{self, Event, KeyCode, KeyModifiers},
-    execute,
+    event::{
+        self, DisableFocusChange, EnableFocusChange, Event, KeyCode, KeyEventKind, KeyModifiers,
+        KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, queue,
     style::{self},
     terminal::{self},
-    queue,
 };
 use std::error::Error;
-use std::fs::File;
-use std::io::{self, Read, Stdout, Write};
+use std::io::{self, Stdout, Write};
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
-use chip_8::{Chip8, NUM_KEYS, SCREEN_HEIGHT, SCREEN_WIDTH};
+mod accessible;
+mod archive;
+mod audio;
+mod bench;
+mod builtin;
+mod compare;
+mod config;
+mod debugger;
+mod diff;
+mod disasm;
+mod font;
+mod gamepad;
+mod graphics;
+mod headless;
+mod input;
+mod keymap;
+mod kiosk;
+mod layout;
+mod library;
+mod lint;
+mod loader;
+mod magnifier;
+mod octo;
+mod osd;
+mod pacing;
+mod picker;
+mod profile;
+mod recording;
+mod remote;
+mod renderer;
+mod save_state;
+mod screenshot;
+mod script;
+mod server;
+mod stats;
+mod suite;
+mod symbols;
+mod theme;
+mod trace;
+mod unknown_opcode;
+mod user_flags;
+mod watch;
+
+use accessible::{ScoreRegion, StatusReporter};
+use audio::{Beep, TerminalBell};
+use builtin::Builtin;
+use config::{Config, RomSettings, SettingsSources};
+use debugger::Debugger;
+use font::FontName;
+use gamepad::{GamepadMap, Gamepads};
+use input::{KeyState, KeyStrategy, Retention};
+use keymap::{Keymap, Preset};
+use kiosk::{Kiosk, KioskEvent};
+use layout::Layout;
+use library::Library;
+use magnifier::Magnifier;
+use osd::Osd;
+use pacing::CycleBudget;
+use recording::Recording;
+use renderer::{Grid, Renderer};
+use save_state::SaveStates;
+use screenshot::{ImageFormat, Screenshots};
+use script::Script;
+use stats::Stats;
+use symbols::Symbols;
+use theme::{Theme, ThemeName};
+use trace::{TraceFilter, Tracer};
+use unknown_opcode::{OnUnknownOpcode, OpcodeLog};
+use watch::RomWatcher;
+
+use chip_8::audio::DEFAULT_VOLUME;
+use chip_8::cheat::{self, Cheat};
+use chip_8::render::{self, Phosphor, Rotation};
+use chip_8::{Chip8, CycleResult, Platform, Quirks, Rewind, RomTooLarge, SCREEN_WIDTH, SoundEvent};
+
+const DEFAULT_CLOCK_SPEED: u64 = 700;
+const DEFAULT_HEADLESS_CYCLES: u64 = 10_000;
+// a snapshot every 10 frames, kept for the last 50 seconds
+const REWIND_INTERVAL: u32 = 10;
+const REWIND_SNAPSHOTS: usize = 300;
+// columns between the display and the debugger panes
+const DEBUGGER_GAP: u16 = 2;
+const DEFAULT_TURBO_FACTOR: u32 = 4;
+const PAUSED_STATUS: &str = "Paused | P/Space to resume";
+const UNFOCUSED_STATUS: &str = "Paused while the terminal is in the background | P/Space to resume";
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// Path to the ROM file to load
-    #[arg(default_value = "../roms/test_opcode.ch8")]
-    rom_path: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Clock speed in Hz (instructions per second)
-    #[arg(short, long, default_value_t = 700)]
-    clock_speed: u64,
+    /// Path to the ROM file to load, `-` to read it from stdin, or an
+    /// http(s) URL (with the `http` feature). Octo source in a `.8o` file
+    /// is compiled first. Without one, or given a
+    /// directory or a zip archive of several ROMs, choose a ROM from a list
+    rom_path: Option<PathBuf>,
+
+    /// Run one of the ROMs built into the emulator instead of a file
+    #[arg(long, value_name = "NAME", value_parser = builtin::find, conflicts_with_all = ["rom_path", "kiosk", "library"])]
+    builtin: Option<&'static Builtin>,
+
+    /// List the built-in ROMs and exit
+    #[arg(long)]
+    list_builtin: bool,
+
+    /// Clock speed in Hz (instructions per second) [default: 700]
+    #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+    clock_speed: Option<u64>,
+
+    /// Machine to emulate, setting the quirks and clock speed its programs
+    /// expect: vip, chip48, schip or xochip. Single quirk flags and
+    /// --clock-speed still apply on top
+    #[arg(long, value_parser = config::parse_platform)]
+    platform: Option<Platform>,
+
+    /// How many times faster the emulator runs while Tab is held [default: 4]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=64))]
+    turbo_factor: Option<u32>,
+
+    /// Config file to read instead of ~/.config/chip8/config.toml
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Block on each draw until the next frame, like the COSMAC VIP
+    #[arg(long)]
+    display_wait: bool,
+
+    /// Reset VF to 0 after OR, AND and XOR, like the COSMAC VIP
+    #[arg(long)]
+    vf_reset: bool,
+
+    /// Wrap sprites past the right and bottom edges round to the other
+    /// side instead of clipping them
+    #[arg(long)]
+    wrap_sprites: bool,
+
+    /// Make BNNN jump to XNN + VX, like CHIP-48 and SUPER-CHIP
+    #[arg(long)]
+    jump_vx: bool,
+
+    /// Set VF when FX1E takes I past 0xFFF, and keep I to 12 bits, like
+    /// the Amiga interpreter
+    #[arg(long)]
+    i_overflow: bool,
+
+    /// Make 8XY6 and 8XYE shift VY into VX, like the COSMAC VIP and XO-CHIP
+    #[arg(long)]
+    shift_vy: bool,
+
+    /// Leave I past the last register after FX55 and FX65, like the COSMAC
+    /// VIP and XO-CHIP
+    #[arg(long)]
+    load_store_increment: bool,
+
+    /// Quirk setting as name=on or name=off, or shift=vx|vy and
+    /// memory=fixed|increment, above every other source (repeatable). The
+    /// resulting quirks are printed at startup
+    #[arg(long, value_name = "QUIRK", value_parser = diff::parse_quirk)]
+    quirk: Vec<(String, bool)>,
+
+    /// How long a key press counts as held, where the terminal doesn't
+    /// report key releases [default: 100]
+    #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(1..=5000))]
+    key_hold_ms: Option<u64>,
+
+    /// How key presses are held where the terminal doesn't report key
+    /// releases [default: hold]
+    #[arg(long, value_enum, value_name = "STRATEGY")]
+    key_strategy: Option<KeyStrategy>,
+
+    /// Rotate through the ROMs in a directory, picking up newly copied ones
+    #[arg(long, value_name = "DIR")]
+    kiosk: Option<PathBuf>,
+
+    /// Choose games from the ROMs under a directory, switching with F4 and
+    /// remembering clock speed and quirks per game (flags still take precedence)
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["kiosk", "rom_path", "accessible"])]
+    library: Option<PathBuf>,
+
+    /// Reload and restart the ROM whenever its file changes
+    #[arg(long, requires = "rom_path", conflicts_with_all = ["headless", "serve"])]
+    watch: bool,
+
+    /// Seconds each ROM runs for in kiosk mode
+    #[arg(long, default_value_t = 120)]
+    kiosk_interval: u64,
+
+    /// Print short status lines instead of drawing the display (screen-reader friendly)
+    #[arg(long)]
+    accessible: bool,
+
+    /// Memory address of the score to announce in accessible mode (e.g. 0x2F0)
+    #[arg(long, value_name = "ADDR", value_parser = parse_address)]
+    score_addr: Option<u16>,
+
+    /// Number of one-digit-per-byte score bytes at --score-addr
+    #[arg(long, default_value_t = 1, requires = "score_addr")]
+    score_len: u16,
+
+    /// Key mapping: 16 keys in keypad order (e.g. 1234qwerasdfzxcv),
+    /// or key=X bindings added to the default (e.g. up=5,down=8). Bound keys
+    /// go to the game instead of any emulator control on the same key
+    #[arg(long, value_parser = Keymap::parse)]
+    keymap: Option<Keymap>,
+
+    /// A named key mapping for another keyboard layout or way of playing
+    #[arg(long, value_enum, value_name = "NAME", conflicts_with = "keymap")]
+    keymap_preset: Option<Preset>,
+
+    /// Keys for a second player, on the second keypad two-player CHIP-8X
+    /// games read: a 16 key layout, or key=X bindings
+    #[arg(long, value_name = "KEYMAP", value_parser = Keymap::parse_player2)]
+    player2_keymap: Option<Keymap>,
+
+    /// A named key mapping for a second player, e.g. right-hand
+    #[arg(
+        long,
+        value_enum,
+        value_name = "NAME",
+        conflicts_with = "player2_keymap"
+    )]
+    player2_preset: Option<Preset>,
+
+    /// Play with game controllers, which can be plugged in while running
+    /// (needs the `gamepad` feature)
+    #[arg(long, conflicts_with_all = ["headless", "serve"])]
+    gamepad: bool,
+
+    /// Gamepad bindings added to the default, as control=X (e.g. south=5,start=F).
+    /// Controls: up down left right south east west north lt rt select start
+    #[arg(long, value_parser = GamepadMap::parse, requires = "gamepad")]
+    gamepad_map: Option<GamepadMap>,
+
+    /// Start in the interactive debugger (needs a terminal at least 110 columns wide)
+    #[arg(long, conflicts_with = "accessible")]
+    debug: bool,
+
+    /// Symbol file naming addresses for the debugger, one `ADDRESS NAME`
+    /// per line [default: the ROM's path with a .sym extension, if it exists]
+    #[arg(long, value_name = "PATH")]
+    symbols: Option<PathBuf>,
+
+    /// Write a line for each instruction run, with the registers and memory
+    /// it changed, to PATH (`-` for stderr, with --headless or --serve)
+    #[arg(long, value_name = "PATH")]
+    trace: Option<PathBuf>,
+
+    /// Trace only these kinds of instructions, comma separated
+    #[arg(long, value_enum, value_delimiter = ',', requires = "trace")]
+    trace_filter: Vec<TraceFilter>,
+
+    /// Stop tracing after this many lines
+    #[arg(long, value_name = "LINES", requires = "trace")]
+    trace_limit: Option<u64>,
+
+    /// Pause while the terminal is in the background, resuming when it's
+    /// back (where the terminal reports focus changes)
+    #[arg(long, conflicts_with_all = ["headless", "serve"])]
+    pause_on_unfocus: bool,
+
+    /// Disable the buzzer
+    #[arg(long)]
+    mute: bool,
+
+    /// Buzzer volume from 0.0 to 1.0 [default: 0.25]
+    #[arg(long, value_parser = parse_volume)]
+    volume: Option<f32>,
+
+    /// Show the display in reverse video while the buzzer sounds
+    #[arg(long)]
+    visual_bell: bool,
+
+    /// Seed for the random number generator, for reproducible runs
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Run without a terminal UI and print the final display (for CI)
+    #[arg(long, conflicts_with_all = ["kiosk", "library", "accessible", "debug"])]
+    headless: bool,
+
+    /// Run without a terminal UI, serving the display and taking keys over
+    /// TCP or WebSocket on an address like 0.0.0.0:8080
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["kiosk", "library", "accessible", "debug", "headless"])]
+    serve: Option<String>,
+
+    /// Number of instructions to execute in headless mode [default: 10000,
+    /// or no limit with --until-finished]
+    #[arg(long, requires = "headless")]
+    cycles: Option<u64>,
+
+    /// In headless mode, stop when the program exits with 00FD or jumps to
+    /// itself, failing if --cycles runs out first or the program faults
+    #[arg(long, requires = "headless")]
+    until_finished: bool,
+
+    /// In headless mode, print a hash of the machine state instead of the display
+    #[arg(long, requires = "headless")]
+    hash: bool,
+
+    /// Count executed instructions and print the busiest addresses and
+    /// instruction classes on exit
+    #[arg(long)]
+    profile: bool,
+
+    /// Rhai script with on_start/on_frame hooks that can read and change
+    /// registers and memory and press keys (needs the `scripting` feature)
+    #[arg(long, value_name = "FILE", conflicts_with = "serve")]
+    script: Option<PathBuf>,
+
+    /// File of memory patches applied every frame, one `name: ADDR=VALUE`
+    /// cheat per line; Alt+1 to Alt+9 toggle the first nine
+    #[arg(long, value_name = "FILE", conflicts_with = "serve")]
+    cheats: Option<PathBuf>,
+
+    /// Address to load the ROM at and start from, e.g. 0x600 for ETI 660
+    /// programs [default: 0x200]
+    #[arg(long, value_name = "ADDR", value_parser = parse_address, conflicts_with_all = ["kiosk", "library"])]
+    load_address: Option<u16>,
+
+    /// Glyphs for the hex digits ROMs draw with FX29
+    #[arg(long, value_enum, default_value_t = FontName::Chip48)]
+    font: FontName,
+
+    /// What to do on an opcode that isn't an instruction
+    #[arg(long, value_enum, default_value_t = OnUnknownOpcode::Ignore)]
+    on_unknown_opcode: OnUnknownOpcode,
+
+    /// File --on-unknown-opcode log appends to
+    #[arg(long, value_name = "FILE", default_value = "unknown-opcodes.log")]
+    opcode_log: PathBuf,
+
+    /// Cache decoded instructions by address, which speeds up tight loops at
+    /// high clock speeds
+    #[arg(long)]
+    decode_cache: bool,
+
+    /// How the display is drawn in the terminal
+    #[arg(long, value_enum, default_value_t = Renderer::HalfBlock)]
+    renderer: Renderer,
+
+    /// Colour scheme for the display [default: default]
+    #[arg(long, value_enum)]
+    theme: Option<ThemeName>,
+
+    /// Keep pixels lit for FRAMES frames after they go dark, so sprites
+    /// erased and redrawn every frame don't flicker [default: 3]
+    #[arg(long, value_name = "FRAMES", num_args = 0..=1, default_missing_value = "3")]
+    anti_flicker: Option<u8>,
+
+    /// Turn the display clockwise by 90, 180 or 270 degrees, for programs
+    /// made for a screen on its side
+    #[arg(long, value_name = "DEGREES", value_parser = parse_rotation, default_value = "0")]
+    rotate: Rotation,
+
+    /// Colour of lit pixels as #RRGGBB, overriding the theme
+    #[arg(long, value_parser = theme::parse)]
+    fg: Option<style::Color>,
+
+    /// Colour of unlit pixels as #RRGGBB, overriding the theme
+    #[arg(long, value_parser = theme::parse)]
+    bg: Option<style::Color>,
+
+    /// Directory F12 screenshots and F9 recordings are saved to
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    screenshot_dir: PathBuf,
+
+    /// Directory F5 save states are kept in [default:
+    /// ~/.local/share/chip8/states]
+    #[arg(long, value_name = "DIR")]
+    state_dir: Option<PathBuf>,
+
+    /// Screenshot file format
+    #[arg(long, value_enum, default_value_t = ImageFormat::Png)]
+    screenshot_format: ImageFormat,
+
+    /// Size of each CHIP-8 pixel in PNG screenshots and GIF recordings
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..=64))]
+    screenshot_scale: u32,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run each ROM in a directory, or the built-in opcode tests, headlessly
+    /// and compare its final display with the expected bitmap in `<rom>.txt`,
+    /// reporting each opcode group listed in `<rom>.groups`
+    Test {
+        /// Directory of ROMs with expected bitmaps; without one, the test
+        /// ROMs built into the emulator are run
+        dir: Option<PathBuf>,
+
+        /// Number of instructions to execute per ROM
+        #[arg(long, default_value_t = 100_000)]
+        cycles: u64,
+
+        /// Write the expected bitmaps from this run instead of comparing
+        #[arg(long, requires = "dir")]
+        bless: bool,
+    },
+
+    /// Run a ROM under two quirk configurations in lockstep and report the
+    /// first difference between their states
+    Diff {
+        rom: PathBuf,
+
+        /// Quirk setting for the first run, as name=on or name=off (repeatable)
+        #[arg(long, value_name = "QUIRK", value_parser = diff::parse_quirk)]
+        first: Vec<(String, bool)>,
+
+        /// Quirk setting for the second run (repeatable)
+        #[arg(long, value_name = "QUIRK", value_parser = diff::parse_quirk)]
+        second: Vec<(String, bool)>,
+
+        /// Number of instructions to compare
+        #[arg(long, default_value_t = 100_000)]
+        cycles: u64,
+
+        /// Clock speed in Hz, which sets how often the timers tick
+        #[arg(
+            short,
+            long,
+            default_value_t = DEFAULT_CLOCK_SPEED,
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        clock_speed: u64,
+    },
+
+    /// Run a ROM against a reference trace from another emulator, one
+    /// instruction per line, and report the first step that doesn't match
+    Compare {
+        rom: PathBuf,
+
+        /// Trace file with the PC, opcode and registers before each
+        /// instruction
+        trace: PathBuf,
+
+        /// Write this emulator's trace to the file instead of comparing
+        #[arg(long)]
+        write: bool,
+
+        /// Number of instructions to write with --write
+        #[arg(long, default_value_t = 100_000)]
+        cycles: u64,
+
+        /// Clock speed in Hz, which sets how often the timers tick
+        #[arg(
+            short,
+            long,
+            default_value_t = DEFAULT_CLOCK_SPEED,
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        clock_speed: u64,
+    },
+
+    /// Run a ROM as fast as possible, without drawing, and report how many
+    /// instructions per second this machine manages
+    Bench {
+        rom: PathBuf,
+
+        /// How long to run for
+        #[arg(long, default_value = "3", value_parser = bench::parse_seconds)]
+        seconds: Duration,
+
+        /// Clock speed in Hz, which sets how often the timers tick
+        #[arg(
+            short,
+            long,
+            default_value_t = DEFAULT_CLOCK_SPEED,
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        clock_speed: u64,
+
+        /// Cache decoded instructions, as for the emulator itself
+        #[arg(long)]
+        decode_cache: bool,
+    },
+
+    /// Check a ROM without running it, following each path from the entry
+    /// point, and report invalid opcodes, jumps out of the program, stack
+    /// overflows, writes over the font, and which parts are code and data
+    Lint { rom: PathBuf },
+
+    /// Show the display of an emulator started with --serve and play it
+    /// with this terminal's keys
+    Remote {
+        /// Address the emulator is serving on, like raspberrypi:8080
+        address: String,
+
+        /// How the display is drawn in the terminal
+        #[arg(long, value_enum, default_value_t = Renderer::HalfBlock)]
+        renderer: Renderer,
+
+        /// Key mapping, as for the emulator itself
+        #[arg(long, value_parser = Keymap::parse)]
+        keymap: Option<Keymap>,
+
+        /// A named key mapping, as for the emulator itself
+        #[arg(long, value_enum, value_name = "NAME", conflicts_with = "keymap")]
+        keymap_preset: Option<Preset>,
+    },
+}
+
+/// Parses a memory address given in hex (`0x200`) or decimal.
+fn parse_address(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|e| format!("invalid address `{}`: {}", s, e))
+}
+
+fn parse_volume(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(volume) if (0.0..=1.0).contains(&volume) => Ok(volume),
+        Ok(_) => Err(format!("volume `{}` is not between 0.0 and 1.0", s)),
+        Err(e) => Err(format!("invalid volume `{}`: {}", s, e)),
+    }
+}
+
+fn parse_rotation(s: &str) -> Result<Rotation, String> {
+    s.parse()
+        .ok()
+        .and_then(Rotation::from_degrees)
+        .ok_or_else(|| format!("invalid rotation `{}`, expected 0, 90, 180 or 270", s))
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let cli = Cli::parse();
+    match &cli.command {
+        Some(Command::Test { dir, cycles, bless }) => {
+            return suite::run(dir.as_deref(), *cycles, *bless);
+        }
+        Some(Command::Diff {
+            rom,
+            first,
+            second,
+            cycles,
+            clock_speed,
+        }) => {
+            let quirks = |settings: &[(String, bool)]| {
+                let mut quirks = Quirks::default();
+                for (name, enabled) in settings {
+                    quirks.set(name, *enabled);
+                }
+                quirks
+            };
+            return diff::run(rom, quirks(first), quirks(second), *cycles, *clock_speed);
+        }
+        Some(Command::Compare {
+            rom,
+            trace,
+            write,
+            cycles,
+            clock_speed,
+        }) => {
+            if *write {
+                return compare::write(rom, trace, *cycles, *clock_speed);
+            }
+            return compare::run(rom, trace, *clock_speed);
+        }
+        Some(Command::Bench {
+            rom,
+            seconds,
+            clock_speed,
+            decode_cache,
+        }) => return bench::run(rom, *seconds, *clock_speed, *decode_cache),
+        Some(Command::Lint { rom }) => return lint::run(rom),
+        Some(Command::Remote {
+            address,
+            renderer,
+            keymap,
+            keymap_preset,
+        }) => {
+            let keymap = match keymap_preset {
+                Some(preset) => preset.keymap(),
+                None => keymap.clone().unwrap_or_default(),
+            };
+            return remote::run(address, graphics::resolve(*renderer), keymap);
+        }
+        None => {}
+    }
+    if cli.list_builtin {
+        builtin::list();
+        return Ok(());
+    }
+    let config = Config::load(cli.config.as_deref())?;
+
+    // command line flags take precedence over the config file
+    let keymap = match (cli.keymap_preset, cli.keymap, &config.keymap) {
+        (Some(preset), _, _) => preset.keymap(),
+        (None, Some(keymap), _) => keymap,
+        (None, None, Some(spec)) => Keymap::parse(spec)?,
+        (None, None, None) => Keymap::default(),
+    };
+    let player2 = match (cli.player2_preset, cli.player2_keymap) {
+        (Some(preset), _) => Some(preset.keymap()),
+        (None, keymap) => keymap,
+    };
+    if let Some(player2) = &player2
+        && let Some(code) = keymap.shared_key(player2)
+    {
+        return Err(format!("key {} is bound for both players", code).into());
+    }
+    let turbo_factor = cli
+        .turbo_factor
+        .or(config.turbo_factor)
+        .unwrap_or(DEFAULT_TURBO_FACTOR)
+        .max(1);
+    let theme = Theme::named(cli.theme.or(config.display.theme).unwrap_or_default())
+        .with_overrides(cli.fg.or(config.display.fg), cli.bg.or(config.display.bg));
+    let mute = cli.mute || config.audio.mute.unwrap_or(false);
+    let volume = cli.volume.or(config.audio.volume).unwrap_or(DEFAULT_VOLUME);
+    if !(0.0..=1.0).contains(&volume) {
+        return Err(format!("volume {} is not between 0.0 and 1.0", volume).into());
+    }
+
+    // known ROMs come with recommended settings, below flags, the config file
+    // and --platform
+    let mut sources = SettingsSources {
+        flags: RomSettings {
+            clock_speed: cli.clock_speed,
+            display_wait: cli.display_wait.then_some(true),
+            vf_reset: cli.vf_reset.then_some(true),
+            wrap_sprites: cli.wrap_sprites.then_some(true),
+            jump_vx: cli.jump_vx.then_some(true),
+            i_overflow: cli.i_overflow.then_some(true),
+            shift_vy: cli.shift_vy.then_some(true),
+            load_store_increment: cli.load_store_increment.then_some(true),
+            key_hold_ms: cli.key_hold_ms,
+            key_strategy: cli.key_strategy,
+        },
+        config: RomSettings {
+            clock_speed: config.clock_speed,
+            display_wait: config.quirks.display_wait,
+            vf_reset: config.quirks.vf_reset,
+            wrap_sprites: config.quirks.wrap_sprites,
+            jump_vx: config.quirks.jump_vx,
+            i_overflow: config.quirks.i_overflow,
+            shift_vy: config.quirks.shift_vy,
+            load_store_increment: config.quirks.load_store_increment,
+            key_hold_ms: config.input.key_hold_ms,
+            key_strategy: config.input.key_strategy,
+        },
+        platform: cli.platform,
+    };
+    for (name, enabled) in &cli.quirk {
+        sources.flags.set_quirk(name, *enabled);
+    }
+
+    let mut kiosk = match &cli.kiosk {
+        Some(dir) => Some(Kiosk::open(dir, Duration::from_secs(cli.kiosk_interval))?),
+        None => None,
+    };
+    // read before taking the kiosk's current path, which changes if the ROM
+    // turns out to be unreadable and is dropped
+    let kiosk_rom = kiosk.as_mut().map(Kiosk::current_rom).transpose()?;
+    let mut library = match &cli.library {
+        Some(dir) => Some(Library::open(dir, sources, config::library_path())?),
+        None => None,
+    };
+    if let Some(library) = library.as_mut()
+        && !library.pick()?
+    {
+        return Ok(());
+    }
 
     // Load ROM
-    let mut rom_file = File::open(&cli.rom_path)?;
-    let mut rom_data = Vec::new();
-    rom_file.read_to_end(&mut rom_data)?;
+    let rom_path = match (cli.builtin, &kiosk, &library, cli.rom_path) {
+        // a built-in ROM's path is only its name
+        (Some(builtin), ..) => PathBuf::from(builtin.name),
+        (None, Some(kiosk), _, _) => kiosk.current_path().to_path_buf(),
+        (None, None, Some(library), _) => library.current().path.clone(),
+        (None, None, None, Some(path)) if archive::is_zip(&path) => {
+            let mut roms = archive::roms(&path)?;
+            match roms.len() {
+                0 => return Err(format!("{}: no ROMs in the archive", path.display()).into()),
+                1 => roms.remove(0),
+                count if cli.headless || cli.serve.is_some() => {
+                    return Err(format!(
+                        "{}: the archive has {} ROMs, choose one like {}",
+                        path.display(),
+                        count,
+                        roms[0].display()
+                    )
+                    .into());
+                }
+                _ => match picker::pick(&path)? {
+                    Some(path) => path,
+                    None => return Ok(()),
+                },
+            }
+        }
+        (None, None, None, Some(path)) if !path.is_dir() => path,
+        (None, None, None, path) => {
+            if cli.headless {
+                return Err("--headless needs a ROM file".into());
+            }
+            if cli.serve.is_some() {
+                return Err("--serve needs a ROM file".into());
+            }
+            let dir = path
+                .or(config.rom_dir.clone())
+                .unwrap_or_else(|| PathBuf::from("."));
+            match picker::pick(&dir)? {
+                Some(path) => path,
+                None => return Ok(()),
+            }
+        }
+    };
+    let rom_data = match (cli.builtin, kiosk_rom, &library) {
+        (Some(builtin), ..) => builtin.rom.to_vec(),
+        (None, Some(rom), _) => rom,
+        (None, None, Some(library)) => library.current().rom.clone(),
+        (None, None, None) => loader::read(&rom_path)?,
+    };
+
+    let (clock_speed, quirks) = match &library {
+        Some(library) => library.current_settings(),
+        None => sources.resolve(&rom_data, RomSettings::default()),
+    };
+    let key_retention = match &library {
+        Some(library) => library.current_retention(),
+        None => sources.retention(RomSettings::default()),
+    };
+
+    let quirks_status = format!("Quirks: {}", config::describe_quirks(quirks));
+    let show_quirks = !cli.quirk.is_empty() || cli.platform.is_some();
+    if show_quirks {
+        eprintln!("{}", quirks_status);
+    }
 
     // Init Chip8
     let mut chip8 = Chip8::new();
+    chip8.load_fontset(cli.font.fontset());
     chip8.init();
-    chip8.load_rom(&rom_data);
+    chip8.set_quirks(quirks);
+    match cli.load_address {
+        Some(address) => chip8.load_rom_at(address, &rom_data),
+        None => chip8.load_rom(&rom_data),
+    }
+    .map_err(|e| format!("{}: {}", rom_path.display(), e))?;
+    chip8.set_profiling(cli.profile);
+    chip8.set_decode_cache(cli.decode_cache);
+    chip8.set_unknown_opcode(cli.on_unknown_opcode.policy());
+    let symbols = match &cli.symbols {
+        Some(path) => Symbols::load(path)?,
+        None => Symbols::sidecar(&rom_path)?,
+    };
+    if let Some(path) = &cli.trace {
+        if path.as_os_str() == "-" && !cli.headless && cli.serve.is_none() {
+            return Err("--trace - would draw over the display, trace to a file instead".into());
+        }
+        let filters = cli.trace_filter.clone();
+        let tracer = Tracer::create(path, filters, cli.trace_limit, symbols.clone())?;
+        chip8.set_instruction_hook(tracer);
+    }
+    let mut opcode_log = OpcodeLog::new(cli.opcode_log, loader::name(&rom_path));
+    if let Some(seed) = cli.seed {
+        chip8.seed_rng(seed);
+    }
+
+    let cheats = match &cli.cheats {
+        Some(path) => {
+            let text =
+                std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            cheat::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        None => Vec::new(),
+    };
+    let mut script = match &cli.script {
+        Some(path) => Some(script::load(path)?),
+        None => None,
+    };
+
+    if cli.headless {
+        // runs must be reproducible, so never use a random seed here
+        if cli.seed.is_none() {
+            chip8.seed_rng(0);
+        }
+        chip8.set_finish_detection(cli.until_finished);
+        let cycles = match (cli.cycles, cli.until_finished) {
+            (Some(cycles), _) => cycles,
+            (None, true) => u64::MAX,
+            (None, false) => DEFAULT_HEADLESS_CYCLES,
+        };
+        headless::run(&mut chip8, cycles, clock_speed, script.as_mut())?;
+        if cli.hash {
+            println!("{:016x}", chip8.state_hash());
+        } else {
+            print!("{}", headless::display_text(&chip8));
+        }
+        opcode_log.write(&chip8.take_warnings())?;
+        report_fault(&chip8);
+        // the report goes to stderr so it can't be mistaken for the display
+        profile::report(&chip8, &mut io::stderr())?;
+        // the fault itself was reported above
+        if chip8.fault().is_some() {
+            return Err("the program stopped on a fault".into());
+        }
+        if cli.until_finished && !chip8.is_halted() {
+            return Err(format!("the program didn't finish within {} cycles", cycles).into());
+        }
+        return Ok(());
+    }
+    // headless runs stay reproducible without flags saved by earlier runs
+    user_flags::attach(&mut chip8, &loader::name(&rom_path));
+    if let Some(address) = &cli.serve {
+        return Ok(server::run(&mut chip8, address, clock_speed)?);
+    }
+
+    // Audio is opened before raw mode so a warning prints cleanly
+    let beep = if mute || volume == 0.0 {
+        None
+    } else {
+        match audio::open(volume) {
+            Ok(beep) => Some(beep),
+            Err(e) => {
+                if cfg!(feature = "audio") {
+                    eprintln!("Warning: no audio: {}, using the terminal bell", e);
+                }
+                None
+            }
+        }
+    };
+    let bell = (beep.is_none() && !mute && volume > 0.0).then(TerminalBell::default);
+
+    let gamepads = if cli.gamepad {
+        let map = cli.gamepad_map.clone().unwrap_or_default();
+        Some(gamepad::open(map).map_err(|e| format!("--gamepad: {}", e))?)
+    } else {
+        None
+    };
+
+    // asked before the terminal is set up, so a warning can be seen
+    let renderer = if cli.accessible {
+        cli.renderer
+    } else {
+        graphics::resolve(cli.renderer)
+    };
 
     // Setup Terminal
     terminal::enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let reporter = cli.accessible.then(|| {
+        StatusReporter::new(cli.score_addr.map(|address| ScoreRegion {
+            address,
+            len: cli.score_len,
+        }))
+    });
+    if reporter.is_none() {
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+    } else {
+        execute!(stdout, style::Print("Running. Press Esc to quit.\r\n"))?;
+    }
+    // the kitty keyboard protocol reports key releases, where supported
+    let release_events = terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if release_events {
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(
+                KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                    | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+            )
+        )?;
+    }
+    if cli.pause_on_unfocus {
+        execute!(stdout, EnableFocusChange)?;
+    }
 
     // Run loop
-    let result = run_loop(&mut chip8, &mut stdout, cli.clock_speed);
+    let options = RunOptions {
+        clock_speed,
+        seed: cli.seed,
+        turbo_factor,
+        release_events,
+        key_retention,
+        keymap,
+        player2,
+        kiosk,
+        library,
+        script,
+        cheats,
+        reporter,
+        pause_on_unfocus: cli.pause_on_unfocus,
+        debugger: cli.debug.then(|| Debugger::with_symbols(symbols.clone())),
+        symbols,
+        beep,
+        bell,
+        visual_bell: cli.visual_bell,
+        gamepads,
+        screenshots: Screenshots::new(
+            cli.screenshot_dir,
+            cli.screenshot_format,
+            cli.screenshot_scale,
+            theme.palette(),
+        ),
+        save_states: SaveStates::new(cli.state_dir),
+        rom_name: loader::name(&rom_path),
+        watcher: cli.watch.then(|| RomWatcher::new(rom_path.clone())),
+        opcode_log,
+        renderer,
+        theme,
+        phosphor: cli.anti_flicker.map(Phosphor::new),
+        rotation: cli.rotate,
+        startup_message: show_quirks.then_some(quirks_status),
+    };
+    // cheap enough to keep on, so a fault can be stepped back from
+    chip8.set_journal_capacity(debugger::JOURNAL_CAPACITY);
+    let result = run_loop(&mut chip8, rom_data, &mut stdout, options);
 
     // Cleanup
-    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    if cli.pause_on_unfocus {
+        execute!(stdout, DisableFocusChange)?;
+    }
+    if release_events {
+        execute!(stdout, PopKeyboardEnhancementFlags)?;
+    }
+    if !cli.accessible {
+        execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    }
     terminal::disable_raw_mode()?;
 
     if let Err(e) = result {
         eprintln!("Error: {}", e);
     }
+    report_fault(&chip8);
+    profile::report(&chip8, &mut io::stderr())?;
 
     Ok(())
 }
 
-fn run_loop(chip8: &mut Chip8, stdout: &mut Stdout, clock_speed: u64) -> Result<(), Box<dyn Error>> {
+/// Prints why the emulator stopped, if it faulted, with where it was.
+fn report_fault(chip8: &Chip8) {
+    if let Some(fault) = chip8.fault() {
+        eprintln!("Stopped: {}\n{}", fault, chip8.diagnose());
+    }
+}
+
+/// Settings and optional frontend features for `run_loop`.
+struct RunOptions {
+    clock_speed: u64,
+    /// The `--seed` the RNG starts from again on every reboot.
+    seed: Option<u64>,
+    /// Speed multiplier while turbo is on.
+    turbo_factor: u32,
+    /// The terminal reports key releases, so no retention heuristic is needed.
+    release_events: bool,
+    key_retention: Retention,
+    keymap: Keymap,
+    /// Keys for the second keypad, when a second player has any.
+    player2: Option<Keymap>,
+    kiosk: Option<Kiosk>,
+    library: Option<Library>,
+    script: Option<Script>,
+    cheats: Vec<Cheat>,
+    reporter: Option<StatusReporter>,
+    pause_on_unfocus: bool,
+    debugger: Option<Debugger>,
+    /// Names the debugger shows, including one opened on a fault.
+    symbols: Symbols,
+    beep: Option<Beep>,
+    /// Rung instead of `beep` when there's no audio device.
+    bell: Option<TerminalBell>,
+    visual_bell: bool,
+    gamepads: Option<Gamepads>,
+    screenshots: Screenshots,
+    save_states: SaveStates,
+    /// File name prefix for screenshots outside kiosk mode.
+    rom_name: String,
+    watcher: Option<RomWatcher>,
+    opcode_log: OpcodeLog,
+    renderer: Renderer,
+    theme: Theme,
+    /// Anti-flicker filter the display is drawn through.
+    phosphor: Option<Phosphor>,
+    rotation: Rotation,
+    /// Shown on the OSD at the start, unless kiosk mode has its own message.
+    startup_message: Option<String>,
+}
+
+fn run_loop(
+    chip8: &mut Chip8,
+    mut rom: Vec<u8>,
+    stdout: &mut Stdout,
+    options: RunOptions,
+) -> Result<(), Box<dyn Error>> {
+    let RunOptions {
+        mut clock_speed,
+        seed,
+        turbo_factor,
+        release_events,
+        key_retention,
+        keymap,
+        player2,
+        mut kiosk,
+        mut library,
+        mut script,
+        mut cheats,
+        mut reporter,
+        pause_on_unfocus,
+        mut debugger,
+        symbols,
+        beep,
+        mut bell,
+        visual_bell,
+        mut gamepads,
+        screenshots,
+        mut save_states,
+        mut rom_name,
+        mut watcher,
+        mut opcode_log,
+        renderer,
+        mut theme,
+        mut phosphor,
+        rotation,
+        startup_message,
+    } = options;
     let mut last_frame_time = Instant::now();
     let mut last_instruction_time = Instant::now();
-    // 1_000_000 microseconds in a second
-    // find microseconds per instruction
-    let instruction_duration = Duration::from_micros(1_000_000 / clock_speed);
+    let mut budget = CycleBudget::new(clock_speed);
 
     // 60fps
     // 1000 milliseconds in a second
     // 1000 / 16 = 62.5
     let frame_duration = Duration::from_millis(16);
-    
-    // Key state tracking: index -> last_pressed_time
-    let mut key_last_seen = [None; NUM_KEYS]; 
-    // hold key for 100ms after press event
-    let key_retention = Duration::from_millis(100); 
+
+    let mut keys = KeyState::new(release_events, key_retention);
+    let mut player2_keys = KeyState::new(release_events, key_retention);
+
+    let mut magnifier = Magnifier::default();
+    let mut magnifier_changed = false;
+    let mut osd = Osd::default();
+    let mut paused = false;
+    // paused by --pause-on-unfocus, so regaining focus resumes
+    let mut paused_unfocused = false;
+    let mut recording: Option<Recording> = None;
+    // the fault the debugger was last opened for
+    let mut fault_shown = None;
+    let mut rewind = Rewind::new(REWIND_SNAPSHOTS, REWIND_INTERVAL);
+    // shown in front of the status line while toggled on with F3
+    let mut stats: Option<Stats> = None;
+    // held with Tab where the terminal reports releases, otherwise toggled
+    let mut turbo = false;
+    let turbo_key = keymap.chip8_key(KeyCode::Tab).is_none()
+        && player2
+            .as_ref()
+            .is_none_or(|map| map.chip8_key(KeyCode::Tab).is_none());
+    let controls_status = format!(
+        "Controls: {}{} | P Pause | Ctrl+R Reset | Bksp Rewind{} | F2 Zoom | F3 Stats{}{} | F5/F6/F7 Save/Slot/Load | F9 Record | F12 Screenshot | Esc/Ctrl+C to Quit",
+        keymap.layout_hint(),
+        match &player2 {
+            Some(player2) => format!(" | Player 2: {}", player2.layout_hint()),
+            None => String::new(),
+        },
+        if turbo_key { " | Tab Turbo" } else { "" },
+        if library.is_some() {
+            " | F4 Library"
+        } else {
+            ""
+        },
+        if cheats.is_empty() {
+            ""
+        } else {
+            " | Alt+1-9 Cheats"
+        }
+    );
+    if let Some(kiosk) = kiosk.as_ref() {
+        osd.show(format!("Now playing: {}", kiosk.current_name()));
+    } else if let Some(message) = startup_message {
+        osd.show(message);
+    }
+    let mut terminal_size = terminal::size()?;
+    // where the display was last drawn; None until the first frame
+    let mut layout: Option<Layout> = None;
+    // a draw finished a frame that can be shown before the next tick
+    let mut frame_ready = false;
+    // the characters last drawn for the display
+    let mut grid = Grid::default();
 
     loop {
         // Handle Input
         while event::poll(Duration::from_secs(0))? {
-            if let Event::Key(key) = event::read()? {
-                if key.code == KeyCode::Esc || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)) {
+            let event = event::read()?;
+            if let Event::Resize(columns, rows) = event {
+                terminal_size = (columns, rows);
+            }
+            if pause_on_unfocus {
+                match event {
+                    Event::FocusLost if !paused => {
+                        paused = true;
+                        paused_unfocused = true;
+                        // releases can't be seen from the background
+                        keys.clear();
+                        player2_keys.clear();
+                    }
+                    Event::FocusGained if paused_unfocused => {
+                        paused = false;
+                        paused_unfocused = false;
+                    }
+                    _ => {}
+                }
+            }
+            if let Event::Key(key) = event {
+                // releases and auto-repeats only affect the keypad
+                match key.kind {
+                    KeyEventKind::Release => {
+                        if let Some(k) = keymap.chip8_key(key.code) {
+                            keys.release(k);
+                        }
+                        if let Some(k) = player2.as_ref().and_then(|map| map.chip8_key(key.code)) {
+                            player2_keys.release(k);
+                        }
+                        if turbo_key && key.code == KeyCode::Tab {
+                            turbo = false;
+                        }
+                        continue;
+                    }
+                    KeyEventKind::Repeat => {
+                        if let Some(k) = keymap.chip8_key(key.code) {
+                            keys.press(k);
+                        }
+                        if let Some(k) = player2.as_ref().and_then(|map| map.chip8_key(key.code)) {
+                            player2_keys.press(k);
+                        }
+                        continue;
+                    }
+                    KeyEventKind::Press => {}
+                }
+
+                if key.code == KeyCode::Esc
+                    || (key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL))
+                {
                     return Ok(());
                 }
-                
-                // Map keys
-                let chip8_key = match key.code {
-                    KeyCode::Char('1') => Some(0x1),
-                    KeyCode::Char('2') => Some(0x2),
-                    KeyCode::Char('3') => Some(0x3),
-                    KeyCode::Char('4') => Some(0xC),
-                    KeyCode::Char('q') => Some(0x4),
-                    KeyCode::Char('w') => Some(0x5),
-                    KeyCode::Char('e') => Some(0x6),
-                    KeyCode::Char('r') => Some(0xD),
-                    KeyCode::Char('a') => Some(0x7),
-                    KeyCode::Char('s') => Some(0x8),
-                    KeyCode::Char('d') => Some(0x9),
-                    KeyCode::Char('f') => Some(0xE),
-                    KeyCode::Char('z') => Some(0xA),
-                    KeyCode::Char('x') => Some(0x0),
-                    KeyCode::Char('c') => Some(0xB),
-                    KeyCode::Char('v') => Some(0xF),
+
+                if let Some(debugger) = debugger.as_mut()
+                    && debugger.handle_key(key, chip8)
+                {
+                    continue;
+                }
+
+                // Keys bound in a keymap go to the game, even those that are
+                // also emulator controls, like `p` in the right-hand preset.
+                // Ctrl and Alt combinations are always controls.
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+                {
+                    let chip8_key = keymap.chip8_key(key.code);
+                    let player2_key = player2.as_ref().and_then(|map| map.chip8_key(key.code));
+                    if let Some(k) = chip8_key {
+                        keys.press(k);
+                    }
+                    if let Some(k) = player2_key {
+                        player2_keys.press(k);
+                    }
+                    if chip8_key.is_some() || player2_key.is_some() {
+                        continue;
+                    }
+                }
+
+                // Emulator controls
+                if matches!(key.code, KeyCode::Char('p') | KeyCode::Char(' ')) {
+                    paused = !paused;
+                    paused_unfocused = false;
+                    continue;
+                }
+                if key.code == KeyCode::Char('r') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    reboot(chip8, &rom, &rom_name, seed)?;
+                    rewind.clear();
+                    osd.show("Reset");
+                    continue;
+                }
+                if turbo_key && key.code == KeyCode::Tab {
+                    turbo = release_events || !turbo;
+                    if turbo {
+                        osd.show(format!("Turbo x{}", turbo_factor));
+                    }
+                    continue;
+                }
+                if let KeyCode::Char(digit @ '1'..='9') = key.code
+                    && key.modifiers.contains(KeyModifiers::ALT)
+                {
+                    let index = digit as usize - '1' as usize;
+                    if let Some(cheat) = cheats.get_mut(index) {
+                        cheat.enabled = !cheat.enabled;
+                        let state = if cheat.enabled { "on" } else { "off" };
+                        osd.show(format!("Cheat {}: {}", state, cheat.name));
+                    }
+                    continue;
+                }
+                if key.code == KeyCode::F(3) {
+                    stats = match stats {
+                        Some(_) => None,
+                        None => Some(Stats::default()),
+                    };
+                    continue;
+                }
+                if key.code == KeyCode::Backspace {
+                    if rewind.step_back(chip8) {
+                        osd.show(format!("Rewound ({} steps left)", rewind.len()));
+                    } else {
+                        osd.show("Nothing to rewind");
+                    }
+                    continue;
+                }
+
+                match key.code {
+                    KeyCode::F(5) => {
+                        match save_states.save(chip8, &rom_name) {
+                            Ok(()) => osd.show(format!("Saved slot {}", save_states.slot())),
+                            Err(e) => osd.show(format!("Saving failed: {}", e)),
+                        }
+                        continue;
+                    }
+                    KeyCode::F(6) => {
+                        osd.show(format!("Slot {}", save_states.next_slot()));
+                        continue;
+                    }
+                    KeyCode::F(7) => {
+                        match save_states.load(chip8, &rom_name) {
+                            Ok(()) => osd.show(format!("Loaded slot {}", save_states.slot())),
+                            Err(e) => osd.show(format!("Loading failed: {}", e)),
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                if matches!(key.code, KeyCode::F(9) | KeyCode::F(12)) {
+                    let name = match kiosk.as_ref() {
+                        Some(kiosk) => screenshot::name_for(kiosk.current_path()),
+                        None => rom_name.clone(),
+                    };
+                    let saved = if key.code == KeyCode::F(12) {
+                        Some(screenshots.save(chip8, &name))
+                    } else if let Some(finished) = recording.take() {
+                        Some(screenshots.save_recording(&finished, &name))
+                    } else {
+                        recording = Some(Recording::default());
+                        osd.show("Recording. F9 to stop");
+                        None
+                    };
+                    match saved {
+                        Some(Ok(path)) => osd.show(format!("Saved {}", path.display())),
+                        Some(Err(e)) => osd.show(format!("Saving failed: {}", e)),
+                        None => {}
+                    }
+                    continue;
+                }
+
+                // Magnifier controls
+                let zoom_move = match key.code {
+                    KeyCode::F(2) => {
+                        magnifier.toggle();
+                        Some((0, 0))
+                    }
+                    KeyCode::Left if magnifier.is_visible() => Some((-1, 0)),
+                    KeyCode::Right if magnifier.is_visible() => Some((1, 0)),
+                    KeyCode::Up if magnifier.is_visible() => Some((0, -1)),
+                    KeyCode::Down if magnifier.is_visible() => Some((0, 1)),
                     _ => None,
                 };
-                
-                if let Some(k) = chip8_key {
-                    key_last_seen[k] = Some(Instant::now());
+                if let Some((dx, dy)) = zoom_move {
+                    magnifier.move_by(dx, dy, chip8.display_height());
+                    magnifier_changed = true;
+                    continue;
+                }
+
+                if let Some(library) = library.as_mut()
+                    && key.code == KeyCode::F(4)
+                {
+                    if library.menu(stdout)? {
+                        rom = library.current().rom.clone();
+                        rom_name = screenshot::name_for(&library.current().path);
+                        opcode_log.set_rom_name(rom_name.clone());
+                        reboot(chip8, &rom, &rom_name, seed)?;
+                        rewind.clear();
+                        osd.show(format!("Now playing: {}", library.current().name));
+                    }
+                    // settings may have changed even if the game didn't
+                    let quirks;
+                    (clock_speed, quirks) = library.current_settings();
+                    chip8.set_quirks(quirks);
+                    keys.set_retention(library.current_retention());
+                    player2_keys.set_retention(library.current_retention());
+                    budget.set_clock_speed(clock_speed);
+                    // the menu covered the display, and no time passed for the game
+                    layout = None;
+                    last_instruction_time = Instant::now();
+                    last_frame_time = Instant::now();
+                    continue;
+                }
+
+                if let Some(kiosk) = kiosk.as_mut()
+                    && key.code == KeyCode::Char('n')
+                {
+                    rom = next_kiosk_rom(chip8, kiosk, &mut osd, seed)?;
+                    rom_name = screenshot::name_for(kiosk.current_path());
+                    opcode_log.set_rom_name(rom_name.clone());
+                    rewind.clear();
+                    continue;
                 }
             }
         }
 
-        // If key was pressed within retention period, mark it as pressed
-        let now = Instant::now();
-        let keys: [bool; NUM_KEYS] = std::array::from_fn(|i| {
-            key_last_seen[i]
-                .map(|last_time| now.duration_since(last_time) < key_retention)
-                .unwrap_or(false)
-        });
-        chip8.set_pressed_keys(keys);
+        if let Some(result) = watcher.as_mut().and_then(RomWatcher::poll) {
+            let room = chip8.memory().len() - chip8.load_address() as usize;
+            match result {
+                Ok(new_rom) if new_rom.len() > room => osd.show(format!(
+                    "Not reloaded: the ROM is {} bytes, but only {} fit",
+                    new_rom.len(),
+                    room
+                )),
+                Ok(new_rom) => {
+                    rom = new_rom;
+                    reboot(chip8, &rom, &rom_name, seed)?;
+                    rewind.clear();
+                    osd.show("Reloaded");
+                }
+                Err(e) => osd.show(format!("Not reloaded: {}", e)),
+            }
+        }
+
+        if let Some(kiosk) = kiosk.as_mut() {
+            for event in kiosk.scan() {
+                match event {
+                    KioskEvent::Added(name) => osd.show(format!("New ROM added: {}", name)),
+                    KioskEvent::Rejected(name, reason) => {
+                        osd.show(format!("Skipped {}: {}", name, reason))
+                    }
+                    KioskEvent::Dropped(name, reason) => osd.show(format!(
+                        "Removed {}: {}. Now playing: {}",
+                        name,
+                        reason,
+                        kiosk.current_name()
+                    )),
+                }
+            }
+            if kiosk.rotation_due() {
+                rom = next_kiosk_rom(chip8, kiosk, &mut osd, seed)?;
+                rom_name = screenshot::name_for(kiosk.current_path());
+                opcode_log.set_rom_name(rom_name.clone());
+                rewind.clear();
+            }
+        }
+
+        let mut pressed = keys.pressed();
+        if let Some(gamepads) = gamepads.as_mut() {
+            for message in gamepads.poll() {
+                osd.show(message);
+            }
+            for (key, held) in pressed.iter_mut().zip(gamepads.pressed()) {
+                *key |= held;
+            }
+        }
+        if let Some(script) = script.as_ref() {
+            for (key, held) in pressed.iter_mut().zip(script.keys()) {
+                *key |= held;
+            }
+        }
+        chip8.set_pressed_keys(pressed);
+        if player2.is_some() {
+            chip8.set_second_keypad(player2_keys.pressed());
+        }
 
         // Execute Instructions
-        // Catch up on cycles
-        while last_instruction_time.elapsed() >= instruction_duration {
-             chip8.cycle();
-             last_instruction_time += instruction_duration;
+        let running = !paused && debugger.as_ref().is_none_or(Debugger::is_running);
+        let speed = if turbo { turbo_factor } else { 1 };
+        if running {
+            // Catch up on cycles
+            let now = Instant::now();
+            let cycles = budget.take(now - last_instruction_time, speed);
+            last_instruction_time = now;
+            for _ in 0..cycles {
+                if let Some(debugger) = debugger.as_mut()
+                    && debugger.check_breakpoint(chip8.pc())
+                {
+                    break;
+                }
+                // under display wait a draw completes the frame, so show it now
+                if chip8.cycle() == CycleResult::DrawCompleted && chip8.quirks().display_wait {
+                    frame_ready = true;
+                }
+                if let Some(stats) = stats.as_mut() {
+                    stats.instructions(1);
+                }
+            }
+        } else {
+            // don't try to catch up on the time spent stopped
+            last_instruction_time = Instant::now();
+        }
+        if chip8.take_keypad_checked() {
+            keys.keypad_checked();
+            player2_keys.keypad_checked();
         }
-        
+
         // Timer tick and draw
-        if last_frame_time.elapsed() >= frame_duration {
-            chip8.tick_timers();
-            draw_screen(chip8, stdout)?;
-            last_frame_time = Instant::now();
+        let frame_due = last_frame_time.elapsed() >= frame_duration;
+        if frame_due || frame_ready {
+            let mut sound = None;
+            if frame_due {
+                // turbo speeds up the timers too, so waits on the delay timer are skipped
+                if running {
+                    for _ in 0..speed {
+                        sound = chip8.tick_timers().or(sound);
+                    }
+                }
+                if let Some(beep) = beep.as_ref() {
+                    beep.set_playing(running && chip8.is_sound_playing());
+                }
+                if sound == Some(SoundEvent::Started)
+                    && let Some(bell) = bell.as_mut()
+                {
+                    bell.ring(stdout)?;
+                }
+                if running {
+                    for cheat in &cheats {
+                        cheat.apply(chip8);
+                    }
+                }
+                if running && let Some(running_script) = script.as_mut() {
+                    match running_script.frame(chip8) {
+                        Ok(messages) => {
+                            if let Some(message) = messages.last() {
+                                osd.show(message.clone());
+                            }
+                        }
+                        Err(e) => {
+                            osd.show(format!("Script stopped: {}", e));
+                            script = None;
+                        }
+                    }
+                }
+                let warnings = chip8.take_warnings();
+                if let Some(warning) = warnings.last() {
+                    osd.show(format!(
+                        "Skipped unknown opcode {:04X} at {:03X}",
+                        warning.opcode, warning.pc
+                    ));
+                }
+                if let Err(e) = opcode_log.write(&warnings) {
+                    osd.show(format!("Writing the opcode log failed: {}", e));
+                }
+                // a fault stops in the debugger, which shows the code around it
+                if let Some(fault) = chip8.fault()
+                    && fault_shown != Some(fault)
+                {
+                    debugger
+                        .get_or_insert_with(|| Debugger::with_symbols(symbols.clone()))
+                        .stop(format!("Stopped: {}", fault));
+                }
+                fault_shown = chip8.fault();
+                if running {
+                    rewind.record(chip8);
+                    if let Some(recording) = recording.as_mut() {
+                        recording.capture(chip8);
+                    }
+                }
+            }
+            let dirty = chip8.take_dirty();
+            if let Some(reporter) = reporter.as_mut() {
+                for line in reporter.update(chip8, dirty, sound) {
+                    queue!(stdout, style::Print(line), style::Print("\r\n"))?;
+                }
+                stdout.flush()?;
+            } else {
+                let (width, height) = rotation.size(SCREEN_WIDTH, chip8.display_height());
+                let lines = renderer.lines(height);
+                // the magnifier goes under the display and the debugger right of both
+                let area = renderer.columns(width).max(magnifier.columns());
+                let mut needed = (area, lines + 1 + magnifier.lines());
+                if let Some(debugger) = debugger.as_ref() {
+                    needed.0 += DEBUGGER_GAP + debugger::PANE_WIDTH;
+                    needed.1 = needed.1.max(debugger.lines());
+                }
+                // a resize or a switch to a different display size needs a full redraw
+                let new_layout = Layout::new(terminal_size, needed);
+                let redraw = layout != Some(new_layout);
+                if redraw {
+                    layout = Some(new_layout);
+                    queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+                    magnifier_changed = true;
+                }
+                match new_layout {
+                    Layout::TooSmall { columns, rows } => {
+                        if redraw {
+                            let message =
+                                format!("Terminal too small (needs {}x{})", columns, rows);
+                            queue!(
+                                stdout,
+                                cursor::MoveTo(0, 0),
+                                style::Print(truncate(&message, terminal_size.0))
+                            )?;
+                            stdout.flush()?;
+                        }
+                    }
+                    Layout::Centered { left, top, columns } => {
+                        if magnifier_changed || magnifier.shows_any(dirty, chip8.display_height()) {
+                            let origin = (left, top + lines + 1);
+                            magnifier.draw(chip8.get_display(), stdout, origin)?;
+                            magnifier_changed = false;
+                        }
+                        let fault_status = match chip8.fault() {
+                            Some(fault) => Some(format!("Stopped: {} | Ctrl+R Reset", fault)),
+                            None if chip8.is_halted() => {
+                                Some("Program exited | Ctrl+R Reset".to_string())
+                            }
+                            None => None,
+                        };
+                        let status = osd.message().or(fault_status.as_deref()).unwrap_or(
+                            match (paused, paused_unfocused) {
+                                (true, true) => UNFOCUSED_STATUS,
+                                (true, false) => PAUSED_STATUS,
+                                (false, _) => &controls_status,
+                            },
+                        );
+                        if let Some(debugger) = debugger.as_mut() {
+                            let pane = left + area + DEBUGGER_GAP;
+                            debugger.draw(chip8, stdout, pane, top)?;
+                        }
+                        let display = chip8.get_display();
+                        // the display through the anti-flicker filter or rotation
+                        let mut shown: Vec<bool>;
+                        let pixels = if phosphor.is_none() && rotation == Rotation::None {
+                            display
+                        } else {
+                            shown = match phosphor.as_mut() {
+                                Some(phosphor) => phosphor
+                                    .update(display)
+                                    .iter()
+                                    .map(|&level| level > 0)
+                                    .collect(),
+                                None => display.to_vec(),
+                            };
+                            if rotation != Rotation::None {
+                                let mut turned = vec![false; shown.len()];
+                                render::rotate(&shown, SCREEN_WIDTH, rotation, &mut turned);
+                                shown = turned;
+                            }
+                            &shown[..]
+                        };
+                        let reverse = visual_bell && chip8.is_sound_playing();
+                        if redraw || reverse != theme.reverse {
+                            grid.invalidate();
+                        }
+                        theme.reverse = reverse;
+                        let status = match stats.as_mut() {
+                            Some(stats) => {
+                                stats.frame();
+                                let target_fps = 1.0 / frame_duration.as_secs_f64();
+                                format!("{} | {}", stats.summary(target_fps, clock_speed), status)
+                            }
+                            None => status.to_string(),
+                        };
+                        let status = truncate(&status, columns - left);
+                        let display = (pixels, width);
+                        let origin = (left, top);
+                        draw_screen(display, stdout, renderer, theme, origin, &mut grid, status)?;
+                    }
+                }
+            }
+            if frame_due {
+                last_frame_time = Instant::now();
+            }
+            frame_ready = false;
         }
-        
+
         // Sleep a tiny bit to yield
         std::thread::sleep(Duration::from_millis(1));
     }
 }
 
-fn draw_screen(chip8: &Chip8, stdout: &mut Stdout) -> io::Result<()> {
-    let pixels = chip8.get_display();
-    
-    // Reset cursor
-    queue!(stdout, cursor::MoveTo(0, 0))?;
-    
-    for y in (0..SCREEN_HEIGHT).step_by(2) {
-        for x in 0..SCREEN_WIDTH {
-            let p1 = pixels[y * SCREEN_WIDTH + x];
-            let p2 = if y + 1 < SCREEN_HEIGHT {
-                pixels[(y + 1) * SCREEN_WIDTH + x]
-            } else {
-                false
-            };
+/// Restarts the emulator with a new ROM, keeping the configured quirks,
+/// load address and journal. `rom_name` picks the file its user flags are
+/// kept in, and `seed` is the `--seed` to start the RNG from again.
+fn reboot(
+    chip8: &mut Chip8,
+    rom: &[u8],
+    rom_name: &str,
+    seed: Option<u64>,
+) -> Result<(), RomTooLarge> {
+    let quirks = chip8.quirks();
+    let load_address = chip8.load_address();
+    let memory_access = chip8.memory_access();
+    let unknown_opcode = chip8.unknown_opcode();
+    let profiling = chip8.profile().is_some();
+    let decode_cache = chip8.decode_cache_enabled();
+    let journal_capacity = chip8.journal_capacity();
+    let fontset = *chip8.fontset();
+    let instruction_hook = chip8.take_instruction_hook();
+    *chip8 = Chip8::new();
+    chip8.load_fontset(&fontset);
+    chip8.init();
+    chip8.set_quirks(quirks);
+    chip8.set_memory_access(memory_access);
+    chip8.set_unknown_opcode(unknown_opcode);
+    chip8.set_profiling(profiling);
+    chip8.set_decode_cache(decode_cache);
+    chip8.set_journal_capacity(journal_capacity);
+    if let Some(seed) = seed {
+        chip8.seed_rng(seed);
+    }
+    if let Some(hook) = instruction_hook {
+        chip8.set_instruction_hook(hook);
+    }
+    chip8.load_rom_at(load_address, rom)?;
+    user_flags::attach(chip8, rom_name);
+    Ok(())
+}
 
-            let c = match (p1, p2) {
-                (true, true) => '█',
-                (true, false) => '▀',
-                (false, true) => '▄',
-                (false, false) => ' ',
-            };
-            queue!(stdout, style::Print(c))?;
+/// Switches to the next ROM in the kiosk rotation and returns its bytes.
+fn next_kiosk_rom(
+    chip8: &mut Chip8,
+    kiosk: &mut Kiosk,
+    osd: &mut Osd,
+    seed: Option<u64>,
+) -> io::Result<Vec<u8>> {
+    kiosk.advance();
+    let rom = kiosk.current_rom()?;
+    let rom_name = screenshot::name_for(kiosk.current_path());
+    reboot(chip8, &rom, &rom_name, seed).map_err(io::Error::other)?;
+    osd.show(format!("Now playing: {}", kiosk.current_name()));
+    Ok(rom)
+}
+
+/// The first `columns` characters of `text`, so a long line can't wrap.
+fn truncate(text: &str, columns: u16) -> &str {
+    match text.char_indices().nth(columns as usize) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// Draws `pixels`, a display `width` pixels wide, at (`left`, `top`) with
+/// `status` below it.
+fn draw_screen(
+    (pixels, width): (&[bool], usize),
+    stdout: &mut Stdout,
+    renderer: Renderer,
+    theme: Theme,
+    (left, top): (u16, u16),
+    grid: &mut Grid,
+    status: &str,
+) -> io::Result<()> {
+    let lines = renderer.lines(pixels.len() / width);
+    let columns = renderer.columns(width) as usize;
+    let (cell_width, cell_height) = renderer.cell_size();
+
+    // Only the cells that differ from the last frame are sent, or for an
+    // image the whole image if anything changed
+    let runs = if renderer.is_image() {
+        if grid.update_image(pixels) {
+            let cells = (columns as u16, lines);
+            let image = graphics::image(renderer, (pixels, width), cells, theme)?;
+            queue!(stdout, cursor::MoveTo(left, top), style::Print(image))?;
         }
-        queue!(stdout, style::Print("\r\n"))?;
+        Vec::new()
+    } else {
+        let cells = (0..lines as usize)
+            .flat_map(|line| (0..columns).map(move |column| (column, line)))
+            .map(|(column, line)| {
+                renderer.cell(pixels, width, column * cell_width, line * cell_height)
+            })
+            .collect();
+        grid.update(cells, columns)
+    };
+    if !runs.is_empty() {
+        if let Some(fg) = theme.fg {
+            queue!(stdout, style::SetForegroundColor(fg))?;
+        }
+        if let Some(bg) = theme.bg {
+            queue!(stdout, style::SetBackgroundColor(bg))?;
+        }
+        if theme.reverse {
+            queue!(stdout, style::SetAttribute(style::Attribute::Reverse))?;
+        }
+        for run in runs {
+            queue!(
+                stdout,
+                cursor::MoveTo(left + run.column, top + run.line),
+                style::Print(run.text)
+            )?;
+        }
+        queue!(
+            stdout,
+            style::ResetColor,
+            style::SetAttribute(style::Attribute::Reset)
+        )?;
     }
-    
+
     // Draw status/info line
-    queue!(stdout, style::Print("Controls: 1234 QWER ASDF ZXCV | Esc/Ctrl+C to Quit\r\n"))?;
-    
-        stdout.flush()?;
-    
-        Ok(())
-    
-    }
-    
-    
+    if grid.update_status(status) {
+        queue!(
+            stdout,
+            cursor::MoveTo(left, top + lines),
+            style::Print(status),
+            terminal::Clear(terminal::ClearType::UntilNewLine),
+            style::Print("\r\n")
+        )?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reboot_keeps_journal_and_seed() {
+        let rom = [0xC0, 0xFF, 0x12, 0x00]; // RND V0, 0xFF; JP 0x200
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.seed_rng(7);
+        chip8.load_rom(&rom).unwrap();
+        chip8.set_journal_capacity(debugger::JOURNAL_CAPACITY);
+        chip8.cycle();
+        let first = chip8.v_registers()[0];
+
+        chip8.cycle();
+        reboot(&mut chip8, &rom, "test", Some(7)).unwrap();
+        chip8.cycle();
+        assert_eq!(chip8.v_registers()[0], first);
+        assert!(chip8.step_back());
+        assert_eq!(chip8.pc(), 0x200);
+    }
+}
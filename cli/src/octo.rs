@@ -0,0 +1,812 @@
+//! A compiler for Octo, the CHIP-8 assembly language, so `.8o` source runs
+//! like a ROM. It covers the CHIP-8 instructions, labels, `:const`,
+//! `:alias`, `:unpack`, `:org` and the structured `if`, `loop` and `while`
+//! statements. Macros, `:calc` and the SUPER-CHIP and XO-CHIP display
+//! instructions aren't supported.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use chip_8::MAX_ROM_SIZE;
+
+// programs are loaded here, and addresses are counted from it
+const START_ADDRESS: u16 = 0x200;
+// what Octo calls vF when it's used for comparisons
+const VF: u8 = 0xF;
+
+/// Why a program didn't compile, with the line it's on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for CompileError {}
+
+/// Whether `path` is Octo source, going by its `.8o` extension.
+pub fn is_source(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("8o"))
+}
+
+/// Compiles Octo source to a ROM loaded at 0x200, starting at `: main`.
+pub fn compile(source: &str) -> Result<Vec<u8>, CompileError> {
+    let mut compiler = Compiler::new(source);
+    // the program starts with a jump to main, wherever it ends up
+    compiler.emit_address(0x1000, "main", 1);
+    while let Some(token) = compiler.next() {
+        compiler.statement(token)?;
+    }
+    compiler.finish()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Byte(u8),
+}
+
+/// A condition of `if` or `while`.
+#[derive(Debug, Clone, Copy)]
+enum Condition {
+    Equal(u8, Operand),
+    NotEqual(u8, Operand),
+    Less(u8, Operand),
+    Greater(u8, Operand),
+    LessOrEqual(u8, Operand),
+    GreaterOrEqual(u8, Operand),
+    Key(u8),
+    NotKey(u8),
+}
+
+impl Condition {
+    fn negate(self) -> Condition {
+        use Condition::*;
+        match self {
+            Equal(x, y) => NotEqual(x, y),
+            NotEqual(x, y) => Equal(x, y),
+            Less(x, y) => GreaterOrEqual(x, y),
+            GreaterOrEqual(x, y) => Less(x, y),
+            Greater(x, y) => LessOrEqual(x, y),
+            LessOrEqual(x, y) => Greater(x, y),
+            Key(x) => NotKey(x),
+            NotKey(x) => Key(x),
+        }
+    }
+}
+
+/// An open `if ... begin` or `loop`, waiting for its `end` or `again`.
+enum Block {
+    // offset of the jump past the block, patched at `else` or `end`
+    If {
+        jump: usize,
+        line: usize,
+    },
+    Else {
+        jump: usize,
+        line: usize,
+    },
+    // offsets of the jumps out of the loop that its `while`s made
+    Loop {
+        start: u16,
+        exits: Vec<usize>,
+        line: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FixupKind {
+    // the low 12 bits of the instruction
+    Address,
+    // the second byte of the `v0 :=` and `v1 :=` of an `:unpack`
+    UnpackHigh(u8),
+    UnpackLow,
+}
+
+/// A reference to a label that may not be defined yet.
+struct Fixup<'a> {
+    offset: usize,
+    label: &'a str,
+    line: usize,
+    kind: FixupKind,
+}
+
+struct Compiler<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+    rom: Vec<u8>,
+    labels: HashMap<&'a str, u16>,
+    constants: HashMap<&'a str, u16>,
+    aliases: HashMap<&'a str, u8>,
+    fixups: Vec<Fixup<'a>>,
+    blocks: Vec<Block>,
+    // line of the last token taken, for errors at the end of the source
+    line: usize,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(source: &'a str) -> Self {
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(index, line)| {
+                let code = line.split('#').next().unwrap_or("");
+                code.split_whitespace().map(move |text| Token {
+                    text,
+                    line: index + 1,
+                })
+            })
+            .collect();
+        Compiler {
+            tokens,
+            position: 0,
+            rom: Vec::new(),
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            line: 1,
+        }
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = *self.tokens.get(self.position)?;
+        self.position += 1;
+        self.line = token.line;
+        Some(token)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, CompileError> {
+        Err(CompileError {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    fn expect(&mut self, what: &str) -> Result<Token<'a>, CompileError> {
+        match self.next() {
+            Some(token) => Ok(token),
+            None => self.error(format!("expected {} at the end of the program", what)),
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), CompileError> {
+        let token = self.expect(&format!("`{}`", word))?;
+        if token.text != word {
+            return self.error(format!("expected `{}`, found `{}`", word, token.text));
+        }
+        Ok(())
+    }
+
+    fn here(&self) -> u16 {
+        START_ADDRESS + self.rom.len() as u16
+    }
+
+    fn emit(&mut self, opcode: u16) {
+        self.rom.extend_from_slice(&opcode.to_be_bytes());
+    }
+
+    fn emit_address(&mut self, opcode: u16, label: &'a str, line: usize) {
+        self.fixups.push(Fixup {
+            offset: self.rom.len(),
+            label,
+            line,
+            kind: FixupKind::Address,
+        });
+        self.emit(opcode);
+    }
+
+    // sets the low 12 bits of the instruction at `offset`
+    fn patch(&mut self, offset: usize, address: u16) {
+        self.rom[offset] = self.rom[offset] & 0xF0 | (address >> 8) as u8;
+        self.rom[offset + 1] = address as u8;
+    }
+
+    fn statement(&mut self, token: Token<'a>) -> Result<(), CompileError> {
+        match token.text {
+            ":" => {
+                let name = self.expect("a label name")?;
+                self.define(name.text)?;
+                self.labels.insert(name.text, self.here());
+            }
+            ":const" => {
+                let name = self.expect("a constant name")?;
+                self.define(name.text)?;
+                let value = self.expect("a value")?;
+                let value = self.number(value)?;
+                self.constants.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = self.expect("an alias name")?;
+                self.define(name.text)?;
+                let register = self.register()?;
+                self.aliases.insert(name.text, register);
+            }
+            ":unpack" => {
+                let nibble = self.expect("a nibble")?;
+                let nibble = self.number(nibble)?;
+                if nibble > 0xF {
+                    return self.error(format!("{:#X} doesn't fit in a nibble", nibble));
+                }
+                let high = (nibble as u8) << 4;
+                let target = self.expect("a label")?;
+                match self.known_address(target)? {
+                    Some(address) => {
+                        self.emit(0x6000 | u16::from(high) | address >> 8);
+                        self.emit(0x6100 | address & 0xFF);
+                    }
+                    None => {
+                        self.fixups.push(Fixup {
+                            offset: self.rom.len(),
+                            label: target.text,
+                            line: target.line,
+                            kind: FixupKind::UnpackHigh(high),
+                        });
+                        self.emit(0x6000);
+                        self.fixups.push(Fixup {
+                            offset: self.rom.len(),
+                            label: target.text,
+                            line: target.line,
+                            kind: FixupKind::UnpackLow,
+                        });
+                        self.emit(0x6100);
+                    }
+                }
+            }
+            ":org" => {
+                let address = self.expect("an address")?;
+                let address = self.number(address)?;
+                if address < self.here() {
+                    return self.error(format!(
+                        "can't go back to {:#05X}, the program is already at {:#05X}",
+                        address,
+                        self.here()
+                    ));
+                }
+                self.rom.resize(usize::from(address - START_ADDRESS), 0);
+            }
+            ":byte" => {
+                let value = self.expect("a byte")?;
+                let byte = self.byte(value)?;
+                self.rom.push(byte);
+            }
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "exit" => self.emit(0x00FD),
+            "jump" => self.address_instruction(0x1000)?,
+            "jump0" => self.address_instruction(0xB000)?,
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let height = self.expect("a sprite height")?;
+                let height = self.number(height)?;
+                if height > 0xF {
+                    return self.error(format!("sprites are at most 15 rows, not {}", height));
+                }
+                self.emit(0xD000 | u16::from(x) << 8 | u16::from(y) << 4 | height);
+            }
+            "bcd" => self.register_instruction(0xF033)?,
+            "save" => self.register_instruction(0xF055)?,
+            "load" => self.register_instruction(0xF065)?,
+            "saveflags" => self.register_instruction(0xF075)?,
+            "loadflags" => self.register_instruction(0xF085)?,
+            "audio" => self.emit(0xF002),
+            "delay" => self.timer(0xF015)?,
+            "buzzer" => self.timer(0xF018)?,
+            "pitch" => self.timer(0xF03A)?,
+            "i" => self.i_statement()?,
+            "if" => {
+                let condition = self.condition()?;
+                let form = self.expect("`then` or `begin`")?;
+                match form.text {
+                    "then" => self.skip_unless(condition),
+                    "begin" => {
+                        self.skip_unless(condition.negate());
+                        self.blocks.push(Block::If {
+                            jump: self.rom.len(),
+                            line: token.line,
+                        });
+                        self.emit(0x1000);
+                    }
+                    other => {
+                        return self
+                            .error(format!("expected `then` or `begin`, found `{}`", other));
+                    }
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump, line }) => {
+                    let end = self.rom.len();
+                    self.emit(0x1000);
+                    self.patch(jump, self.here());
+                    self.blocks.push(Block::Else { jump: end, line });
+                }
+                _ => return self.error("`else` without `if ... begin`"),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. } | Block::Else { jump, .. }) => {
+                    self.patch(jump, self.here());
+                }
+                _ => return self.error("`end` without `if ... begin`"),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.here(),
+                exits: Vec::new(),
+                line: token.line,
+            }),
+            "while" => {
+                let condition = self.condition()?;
+                self.skip_unless(condition.negate());
+                let exit = self.rom.len();
+                self.emit(0x1000);
+                let innermost_loop = self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                });
+                match innermost_loop {
+                    Some(exits) => exits.push(exit),
+                    None => return self.error("`while` outside a loop"),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits, .. }) => {
+                    self.emit(0x1000 | start);
+                    for exit in exits {
+                        self.patch(exit, self.here());
+                    }
+                }
+                _ => return self.error("`again` without `loop`"),
+            },
+            "hires" | "lores" | "scroll-down" | "scroll-up" | "scroll-left" | "scroll-right"
+            | "plane" | "long" | "native" | ":macro" | ":calc" | ":next" | ":proto"
+            | ":breakpoint" | ":monitor" | ":stringmode" | ":assert" | ":pointer" => {
+                return self.error(format!("`{}` isn't supported", token.text));
+            }
+            _ if self.register_name(token.text).is_some() => self.register_statement(token)?,
+            _ if parse_number(token.text).is_some() || self.constants.contains_key(token.text) => {
+                let byte = self.byte(token)?;
+                self.rom.push(byte);
+            }
+            _ if is_identifier(token.text) => {
+                // a bare name calls the subroutine with that label
+                self.emit_address(0x2000, token.text, token.line);
+            }
+            other => return self.error(format!("unexpected `{}`", other)),
+        }
+        Ok(())
+    }
+
+    // checks that `name` can be given to a label, constant or alias
+    fn define(&self, name: &str) -> Result<(), CompileError> {
+        if !is_identifier(name) || self.register_name(name).is_some() {
+            return self.error(format!("`{}` can't be used as a name", name));
+        }
+        if self.labels.contains_key(name)
+            || self.constants.contains_key(name)
+            || self.aliases.contains_key(name)
+        {
+            return self.error(format!("`{}` is already defined", name));
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, CompileError> {
+        if let Some(block) = self.blocks.last() {
+            let (line, message) = match block {
+                Block::If { line, .. } | Block::Else { line, .. } => {
+                    (*line, "`begin` without `end`")
+                }
+                Block::Loop { line, .. } => (*line, "`loop` without `again`"),
+            };
+            return Err(CompileError {
+                line,
+                message: message.to_string(),
+            });
+        }
+        if !self.labels.contains_key("main") {
+            return Err(CompileError {
+                line: 1,
+                message: "the program has no `: main` to start at".to_string(),
+            });
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(&address) = self.labels.get(fixup.label) else {
+                return Err(CompileError {
+                    line: fixup.line,
+                    message: format!("undefined label `{}`", fixup.label),
+                });
+            };
+            match fixup.kind {
+                FixupKind::Address => self.patch(fixup.offset, address),
+                FixupKind::UnpackHigh(high) => {
+                    self.rom[fixup.offset + 1] = high | (address >> 8) as u8;
+                }
+                FixupKind::UnpackLow => self.rom[fixup.offset + 1] = address as u8,
+            }
+        }
+        if self.rom.len() > MAX_ROM_SIZE {
+            return Err(CompileError {
+                line: self.line,
+                message: format!(
+                    "the program is {} bytes, but only {} fit in memory",
+                    self.rom.len(),
+                    MAX_ROM_SIZE
+                ),
+            });
+        }
+        Ok(self.rom)
+    }
+
+    fn register_name(&self, text: &str) -> Option<u8> {
+        if let Some(&register) = self.aliases.get(text) {
+            return Some(register);
+        }
+        let digit = text.strip_prefix(['v', 'V'])?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn register(&mut self) -> Result<u8, CompileError> {
+        let token = self.expect("a register")?;
+        match self.register_name(token.text) {
+            Some(register) => Ok(register),
+            None => self.error(format!("expected a register, found `{}`", token.text)),
+        }
+    }
+
+    // a number or constant
+    fn number(&self, token: Token) -> Result<u16, CompileError> {
+        let value = match self.constants.get(token.text) {
+            Some(&value) => i64::from(value),
+            None => match parse_number(token.text) {
+                Some(value) => value,
+                None => return self.error(format!("expected a number, found `{}`", token.text)),
+            },
+        };
+        match u16::try_from(value) {
+            Ok(value) if value <= 0xFFF => Ok(value),
+            _ => self.error(format!("{} is out of range", token.text)),
+        }
+    }
+
+    // a number or constant that fits in a byte, where -1 means 0xFF
+    fn byte(&self, token: Token) -> Result<u8, CompileError> {
+        let value = match self.constants.get(token.text) {
+            Some(&value) => i64::from(value),
+            None => match parse_number(token.text) {
+                Some(value) => value,
+                None => return self.error(format!("expected a byte, found `{}`", token.text)),
+            },
+        };
+        if !(-128..=255).contains(&value) {
+            return self.error(format!("{} doesn't fit in a byte", token.text));
+        }
+        Ok(value as u8)
+    }
+
+    // an address given as a number or constant, or `None` for a label
+    fn known_address(&self, token: Token) -> Result<Option<u16>, CompileError> {
+        if parse_number(token.text).is_some() || self.constants.contains_key(token.text) {
+            return self.number(token).map(Some);
+        }
+        if !is_identifier(token.text) || self.register_name(token.text).is_some() {
+            return self.error(format!("expected an address, found `{}`", token.text));
+        }
+        Ok(None)
+    }
+
+    fn address_instruction(&mut self, opcode: u16) -> Result<(), CompileError> {
+        let target = self.expect("an address")?;
+        match self.known_address(target)? {
+            Some(address) => self.emit(opcode | address),
+            None => self.emit_address(opcode, target.text, target.line),
+        }
+        Ok(())
+    }
+
+    // an FX__ instruction, such as `bcd vX`
+    fn register_instruction(&mut self, opcode: u16) -> Result<(), CompileError> {
+        let x = self.register()?;
+        self.emit(opcode | u16::from(x) << 8);
+        Ok(())
+    }
+
+    // `delay := vX` and the like
+    fn timer(&mut self, opcode: u16) -> Result<(), CompileError> {
+        self.expect_word(":=")?;
+        self.register_instruction(opcode)
+    }
+
+    fn i_statement(&mut self) -> Result<(), CompileError> {
+        let operator = self.expect("`:=` or `+=`")?;
+        match operator.text {
+            ":=" => {
+                let value = self.expect("an address")?;
+                match value.text {
+                    "hex" => self.register_instruction(0xF029),
+                    "bighex" => self.register_instruction(0xF030),
+                    _ => {
+                        self.position -= 1;
+                        self.address_instruction(0xA000)
+                    }
+                }
+            }
+            "+=" => self.register_instruction(0xF01E),
+            other => self.error(format!(
+                "expected `:=` or `+=` after `i`, found `{}`",
+                other
+            )),
+        }
+    }
+
+    fn register_statement(&mut self, token: Token<'a>) -> Result<(), CompileError> {
+        let x = self.register_name(token.text).unwrap_or_default();
+        let xy = |opcode: u16, y: u8| opcode | u16::from(x) << 8 | u16::from(y) << 4;
+        let xnn = |opcode: u16, nn: u8| opcode | u16::from(x) << 8 | u16::from(nn);
+        let operator = self.expect("an operator")?;
+        let value = self.expect("a value")?;
+        let y = self.register_name(value.text);
+        let opcode = match (operator.text, y) {
+            (":=", Some(y)) => xy(0x8000, y),
+            (":=", None) => match value.text {
+                "random" => {
+                    let mask = self.expect("a mask")?;
+                    xnn(0xC000, self.byte(mask)?)
+                }
+                "key" => xnn(0xF00A, 0),
+                "delay" => xnn(0xF007, 0),
+                _ => xnn(0x6000, self.byte(value)?),
+            },
+            ("+=", Some(y)) => xy(0x8004, y),
+            ("+=", None) => xnn(0x7000, self.byte(value)?),
+            ("-=", Some(y)) => xy(0x8005, y),
+            ("-=", None) => xnn(0x7000, self.byte(value)?.wrapping_neg()),
+            ("=-", Some(y)) => xy(0x8007, y),
+            ("|=", Some(y)) => xy(0x8001, y),
+            ("&=", Some(y)) => xy(0x8002, y),
+            ("^=", Some(y)) => xy(0x8003, y),
+            (">>=", Some(y)) => xy(0x8006, y),
+            ("<<=", Some(y)) => xy(0x800E, y),
+            ("=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", None) => {
+                return self.error(format!(
+                    "`{}` needs a register, not `{}`",
+                    operator.text, value.text
+                ));
+            }
+            (other, _) => return self.error(format!("unknown operator `{}`", other)),
+        };
+        self.emit(opcode);
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, CompileError> {
+        let x = self.register()?;
+        let operator = self.expect("a comparison")?;
+        match operator.text {
+            "key" => return Ok(Condition::Key(x)),
+            "-key" => return Ok(Condition::NotKey(x)),
+            _ => {}
+        }
+        let value = self.expect("a value")?;
+        let y = match self.register_name(value.text) {
+            Some(y) => Operand::Register(y),
+            None => Operand::Byte(self.byte(value)?),
+        };
+        Ok(match operator.text {
+            "==" => Condition::Equal(x, y),
+            "!=" => Condition::NotEqual(x, y),
+            "<" => Condition::Less(x, y),
+            ">" => Condition::Greater(x, y),
+            "<=" => Condition::LessOrEqual(x, y),
+            ">=" => Condition::GreaterOrEqual(x, y),
+            other => return self.error(format!("unknown comparison `{}`", other)),
+        })
+    }
+
+    /// Emits the instructions that skip the next one unless `condition`
+    /// holds. The ordering comparisons leave vF changed, as in Octo.
+    fn skip_unless(&mut self, condition: Condition) {
+        use Condition::*;
+        let x8 = |x: u8| u16::from(x) << 8;
+        match condition {
+            Equal(x, Operand::Byte(nn)) => self.emit(0x4000 | x8(x) | u16::from(nn)),
+            Equal(x, Operand::Register(y)) => self.emit(0x9000 | x8(x) | u16::from(y) << 4),
+            NotEqual(x, Operand::Byte(nn)) => self.emit(0x3000 | x8(x) | u16::from(nn)),
+            NotEqual(x, Operand::Register(y)) => self.emit(0x5000 | x8(x) | u16::from(y) << 4),
+            Key(x) => self.emit(0xE0A1 | x8(x)),
+            NotKey(x) => self.emit(0xE09E | x8(x)),
+            // vF ends up 1 when the subtraction doesn't borrow
+            Less(x, y) | GreaterOrEqual(x, y) => {
+                self.subtract_into_vf(Operand::Register(x), y);
+                let holds_when_borrowing = matches!(condition, Less(..));
+                self.emit(if holds_when_borrowing { 0x4F00 } else { 0x3F00 });
+            }
+            Greater(x, y) | LessOrEqual(x, y) => {
+                self.subtract_into_vf(y, Operand::Register(x));
+                let holds_when_borrowing = matches!(condition, Greater(..));
+                self.emit(if holds_when_borrowing { 0x4F00 } else { 0x3F00 });
+            }
+        }
+    }
+
+    // vF := a - b, keeping only the flag
+    fn subtract_into_vf(&mut self, a: Operand, b: Operand) {
+        let vf = u16::from(VF) << 8;
+        match (a, b) {
+            (Operand::Register(a), Operand::Register(b)) => {
+                self.emit(0x8000 | vf | u16::from(a) << 4);
+                self.emit(0x8005 | vf | u16::from(b) << 4);
+            }
+            (Operand::Register(a), Operand::Byte(b)) => {
+                self.emit(0x6000 | vf | u16::from(b));
+                self.emit(0x8007 | vf | u16::from(a) << 4);
+            }
+            (Operand::Byte(a), Operand::Register(b)) => {
+                self.emit(0x6000 | vf | u16::from(a));
+                self.emit(0x8005 | vf | u16::from(b) << 4);
+            }
+            // a condition always has a register on one side
+            (Operand::Byte(_), Operand::Byte(_)) => unreachable!(),
+        }
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// decimal, 0x hex or 0b binary, optionally negative
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits
+        .strip_prefix("0b")
+        .or_else(|| digits.strip_prefix("0B"))
+    {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the program after the initial jump to main
+    fn body(source: &str) -> Vec<u8> {
+        let rom = compile(source).unwrap();
+        assert_eq!(rom[..2], [0x12, 0x02], "main should follow the jump");
+        rom[2..].to_vec()
+    }
+
+    fn error(source: &str) -> CompileError {
+        compile(source).unwrap_err()
+    }
+
+    #[test]
+    fn test_labels_and_forward_references() {
+        let rom = compile(": sub v0 := 1 ;\n: main sub jump end\n: end jump end").unwrap();
+        // main comes after the subroutine at 0x202
+        assert_eq!(
+            rom,
+            [
+                0x12, 0x06, 0x60, 0x01, 0x00, 0xEE, 0x22, 0x02, 0x12, 0x0A, 0x12, 0x0A
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constants_and_aliases() {
+        let source = ":const SPEED 3\n:alias speed v4\n: main speed := SPEED speed -= SPEED";
+        assert_eq!(body(source), [0x64, 0x03, 0x74, 0xFD]);
+    }
+
+    #[test]
+    fn test_if_then_and_begin_else_end() {
+        assert_eq!(
+            body(": main if v1 == 2 then v3 := 4"),
+            [0x41, 0x02, 0x63, 0x04]
+        );
+        assert_eq!(
+            body(": main if v1 key begin v0 := 1 else v0 := 2 end"),
+            [
+                0xE1, 0x9E, 0x12, 0x0A, // skip into the block if the key is down
+                0x60, 0x01, 0x12, 0x0C, // then jump past the else
+                0x60, 0x02,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loop_while_again() {
+        assert_eq!(
+            body(": main loop v0 += 1 while v0 != 10 again"),
+            [0x70, 0x01, 0x40, 0x0A, 0x12, 0x0A, 0x12, 0x02]
+        );
+    }
+
+    #[test]
+    fn test_ordering_comparison_uses_vf() {
+        assert_eq!(
+            body(": main if v1 < 5 then v2 := 0"),
+            [0x6F, 0x05, 0x8F, 0x17, 0x4F, 0x00, 0x62, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_sprite_data_and_unpack() {
+        let rom =
+            compile(": main :unpack 0xA glyph sprite v0 v1 2\n: glyph 0b11000011 0xFF").unwrap();
+        assert_eq!(
+            rom,
+            [0x12, 0x02, 0x60, 0xA2, 0x61, 0x08, 0xD0, 0x12, 0xC3, 0xFF]
+        );
+    }
+
+    #[test]
+    fn test_errors_report_their_line() {
+        let e = error(": main\n  jump nowhere\n");
+        assert_eq!(
+            (e.line, e.message.as_str()),
+            (2, "undefined label `nowhere`")
+        );
+
+        let e = error(": main\nv0 := 1\nv0 %= 2\n");
+        assert_eq!(e.line, 3);
+        assert!(e.message.contains("unknown operator `%=`"), "{}", e.message);
+
+        let e = error(": main\nv0 := 256\n");
+        assert_eq!(e.line, 2);
+        assert!(e.message.contains("doesn't fit in a byte"), "{}", e.message);
+
+        let e = error(": main\nif v0 == 1 begin\nv1 := 2\n");
+        assert_eq!((e.line, e.message.as_str()), (2, "`begin` without `end`"));
+
+        let e = error(": main\n\nelse\n");
+        assert_eq!(e.line, 3);
+
+        let e = error(": start clear");
+        assert_eq!(e.message, "the program has no `: main` to start at");
+
+        let e = error(": main\n: main\n");
+        assert_eq!(
+            (e.line, e.message.as_str()),
+            (2, "`main` is already defined")
+        );
+
+        let e = error(": main\nhires\n");
+        assert_eq!((e.line, e.message.as_str()), (2, "`hires` isn't supported"));
+    }
+}
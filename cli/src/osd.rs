@@ -0,0 +1,24 @@
+use std::time::{Duration, Instant};
+
+/// How long an on-screen message stays visible.
+const MESSAGE_DURATION: Duration = Duration::from_secs(3);
+
+/// Short-lived on-screen messages shown in place of the status line.
+#[derive(Default)]
+pub struct Osd {
+    message: Option<(String, Instant)>,
+}
+
+impl Osd {
+    pub fn show(&mut self, message: impl Into<String>) {
+        self.message = Some((message.into(), Instant::now()));
+    }
+
+    /// Current message, if one is still visible.
+    pub fn message(&self) -> Option<&str> {
+        self.message
+            .as_ref()
+            .filter(|(_, shown_at)| shown_at.elapsed() < MESSAGE_DURATION)
+            .map(|(message, _)| message.as_str())
+    }
+}
@@ -0,0 +1,40 @@
+//! Turns the time that passed into how many instructions to run, so the
+//! emulator keeps to its clock speed however often the run loop comes round.
+
+use std::time::Duration;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+// the most time made up for at once; after the process was suspended
+// (Ctrl+Z) or the terminal stalled, the rest is dropped rather than run in
+// one burst
+const MAX_CATCH_UP: Duration = Duration::from_millis(50);
+
+/// Instructions owed at a clock speed, carrying fractions of an instruction
+/// over so none are lost to rounding.
+pub struct CycleBudget {
+    clock_speed: u64,
+    // instructions owed, in billionths
+    remainder: u128,
+}
+
+impl CycleBudget {
+    pub fn new(clock_speed: u64) -> Self {
+        CycleBudget {
+            clock_speed,
+            remainder: 0,
+        }
+    }
+
+    pub fn set_clock_speed(&mut self, clock_speed: u64) {
+        self.clock_speed = clock_speed;
+    }
+
+    /// Instructions to run for `elapsed` time at `speed` times the clock
+    /// speed, for at most `MAX_CATCH_UP` of it.
+    pub fn take(&mut self, elapsed: Duration, speed: u32) -> u64 {
+        let elapsed = elapsed.min(MAX_CATCH_UP).as_nanos();
+        let owed = u128::from(self.clock_speed) * u128::from(speed) * elapsed + self.remainder;
+        self.remainder = owed % NANOS_PER_SECOND;
+        (owed / NANOS_PER_SECOND) as u64
+    }
+}
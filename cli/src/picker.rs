@@ -0,0 +1,184 @@
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::{cursor, execute, queue, style, terminal};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::kiosk::has_rom_extension;
+use crate::{archive, octo};
+
+// lines used by the title and help text around the list
+const CHROME_LINES: u16 = 3;
+
+struct Entry {
+    path: PathBuf,
+    label: String,
+    is_dir: bool,
+}
+
+/// Full-screen file browser for choosing a ROM, starting in `dir`. Lists
+/// subdirectories, zip archives (opened like directories) and ROM files;
+/// returns `None` if the user quits.
+pub fn pick(dir: &Path) -> io::Result<Option<PathBuf>> {
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let result = browse(dir, &mut stdout);
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn browse(dir: &Path, stdout: &mut impl Write) -> io::Result<Option<PathBuf>> {
+    let mut dir = fs::canonicalize(dir)?;
+    let mut entries = list(&dir)?;
+    let mut selected = 0;
+    let mut scroll = 0;
+
+    loop {
+        let (_, rows) = terminal::size()?;
+        let visible = rows.saturating_sub(CHROME_LINES).max(1) as usize;
+        // keep the selection on screen
+        if selected < scroll {
+            scroll = selected;
+        } else if selected >= scroll + visible {
+            scroll = selected + 1 - visible;
+        }
+        draw(stdout, &dir, &entries, selected, scroll, visible)?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        let last = entries.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => return Ok(None),
+            KeyCode::Up => selected = selected.saturating_sub(1),
+            KeyCode::Down => selected = (selected + 1).min(last),
+            KeyCode::PageUp => selected = selected.saturating_sub(visible),
+            KeyCode::PageDown => selected = (selected + visible).min(last),
+            KeyCode::Home => selected = 0,
+            KeyCode::End => selected = last,
+            KeyCode::Backspace | KeyCode::Left => {
+                if let Some(parent) = dir.parent() {
+                    dir = parent.to_path_buf();
+                    entries = list(&dir)?;
+                    selected = 0;
+                }
+            }
+            KeyCode::Enter | KeyCode::Right => {
+                let Some(entry) = entries.get(selected) else {
+                    continue;
+                };
+                if !entry.is_dir {
+                    return Ok(Some(entry.path.clone()));
+                }
+                // unreadable directories are simply not entered
+                if let Ok(list) = list(&entry.path) {
+                    dir = entry.path.clone();
+                    entries = list;
+                    selected = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The parent directory, then subdirectories and archives, then ROM files,
+/// each sorted by name. Hidden files are left out.
+fn list(dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut dirs = Vec::new();
+    let mut roms = Vec::new();
+    if archive::is_zip(dir) {
+        let paths = archive::roms(dir).map_err(|e| io::Error::other(e.to_string()))?;
+        for path in paths {
+            let label = path
+                .strip_prefix(dir)
+                .unwrap_or(&path)
+                .display()
+                .to_string();
+            roms.push(Entry {
+                path,
+                label,
+                is_dir: false,
+            });
+        }
+    }
+    // an archive has no directory entries of its own
+    let files = if dir.is_dir() {
+        fs::read_dir(dir)?.filter_map(Result::ok).collect()
+    } else {
+        Vec::new()
+    };
+    for entry in files {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        if path.is_dir() || archive::is_zip(&path) {
+            dirs.push(Entry {
+                path,
+                label: format!("{}/", name),
+                is_dir: true,
+            });
+        } else if has_rom_extension(&path) || octo::is_source(&path) {
+            roms.push(Entry {
+                path,
+                label: name,
+                is_dir: false,
+            });
+        }
+    }
+    dirs.sort_by(|a, b| a.label.cmp(&b.label));
+    roms.sort_by(|a, b| a.label.cmp(&b.label));
+
+    let parent = dir.parent().map(|parent| Entry {
+        path: parent.to_path_buf(),
+        label: String::from("../"),
+        is_dir: true,
+    });
+    Ok(parent.into_iter().chain(dirs).chain(roms).collect())
+}
+
+fn draw(
+    stdout: &mut impl Write,
+    dir: &Path,
+    entries: &[Entry],
+    selected: usize,
+    scroll: usize,
+    visible: usize,
+) -> io::Result<()> {
+    queue!(
+        stdout,
+        terminal::Clear(terminal::ClearType::All),
+        cursor::MoveTo(0, 0),
+        style::Print(format!("Choose a ROM: {}\r\n\r\n", dir.display()))
+    )?;
+    for (index, entry) in entries.iter().enumerate().skip(scroll).take(visible) {
+        if index == selected {
+            queue!(
+                stdout,
+                style::SetAttribute(style::Attribute::Reverse),
+                style::Print(format!("> {}", entry.label)),
+                style::SetAttribute(style::Attribute::Reset)
+            )?;
+        } else {
+            queue!(stdout, style::Print(format!("  {}", entry.label)))?;
+        }
+        queue!(stdout, style::Print("\r\n"))?;
+    }
+    if entries.iter().all(|entry| entry.is_dir) {
+        queue!(stdout, style::Print("  (no ROMs here)\r\n"))?;
+    }
+    let (_, rows) = terminal::size()?;
+    queue!(
+        stdout,
+        cursor::MoveTo(0, rows.saturating_sub(1)),
+        style::Print("Arrows select | Enter open | Backspace up | Esc quit")
+    )?;
+    stdout.flush()
+}
@@ -0,0 +1,50 @@
+use std::io::{self, Write};
+
+use chip_8::Chip8;
+
+use crate::disasm;
+
+// number of addresses listed in the report
+const HOT_ADDRESSES: usize = 20;
+
+/// Writes the execution profile collected by `--profile`: the busiest
+/// addresses with their disassembly, then counts per instruction class.
+pub fn report(chip8: &Chip8, out: &mut impl Write) -> io::Result<()> {
+    let Some(profile) = chip8.profile() else {
+        return Ok(());
+    };
+    let total = profile.total();
+    writeln!(out, "Executed {} instructions", total)?;
+    if total == 0 {
+        return Ok(());
+    }
+    let percent = |count: u64| count as f64 * 100.0 / total as f64;
+
+    writeln!(out, "\nHot addresses:")?;
+    for (address, count) in profile.hot_addresses(HOT_ADDRESSES) {
+        let instruction = match chip8.read_range(address, 2) {
+            Some(&[high, low]) => disasm::disassemble(u16::from_be_bytes([high, low])),
+            _ => String::from("??"),
+        };
+        writeln!(
+            out,
+            "  {:#05X}  {:>12}  {:5.1}%  {}",
+            address,
+            count,
+            percent(count),
+            instruction
+        )?;
+    }
+
+    writeln!(out, "\nInstruction classes:")?;
+    for (class, count) in profile.classes() {
+        writeln!(
+            out,
+            "  {:<7}  {:>12}  {:5.1}%",
+            class,
+            count,
+            percent(count)
+        )?;
+    }
+    Ok(())
+}
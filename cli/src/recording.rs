@@ -0,0 +1,72 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::io::Write;
+
+use chip_8::render::Palette;
+use chip_8::{Chip8, HIRES_SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use crate::screenshot::scale_up;
+
+// each captured frame is shown for 1/60 s
+const FRAMES_PER_SECOND: u32 = 60;
+
+/// Display frames captured while recording. Runs of identical frames are
+/// stored once with a count, so a mostly static screen costs almost nothing.
+#[derive(Default)]
+pub struct Recording {
+    frames: Vec<([u64; HIRES_SCREEN_HEIGHT], u32)>,
+    // tallest display captured, so HIRES frames aren't cut off
+    height: usize,
+}
+
+impl Recording {
+    /// Adds the current display as the next frame.
+    pub fn capture(&mut self, chip8: &Chip8) {
+        let display = chip8.get_display_packed();
+        self.height = self.height.max(chip8.display_height());
+        match self.frames.last_mut() {
+            Some((last, count)) if *last == display => *count += 1,
+            _ => self.frames.push((display, 1)),
+        }
+    }
+
+    /// Encodes the recording as a looping animated GIF in `palette`.
+    pub fn write_gif(
+        &self,
+        out: impl Write,
+        scale: u32,
+        palette: &Palette,
+    ) -> Result<(), Box<dyn Error>> {
+        let [off, on, ..] = palette.colors();
+        let global_palette = [off[0], off[1], off[2], on[0], on[1], on[2]];
+
+        let width = (SCREEN_WIDTH as u32 * scale) as u16;
+        let height = (self.height as u32 * scale) as u16;
+        let mut encoder = gif::Encoder::new(out, width, height, &global_palette)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        // GIF delays are in hundredths of a second; carry the rounding over
+        // so long recordings keep the right overall speed
+        let mut elapsed_frames = 0;
+        let mut elapsed_centis = 0;
+        for (display, count) in &self.frames {
+            elapsed_frames += count;
+            let end_centis = (elapsed_frames * 100 + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND;
+
+            let indices: Vec<u8> = display[..self.height]
+                .iter()
+                .flat_map(|row| (0..SCREEN_WIDTH).map(move |x| (row >> (63 - x) & 1) as u8))
+                .collect();
+            let frame = gif::Frame {
+                width,
+                height,
+                delay: u16::try_from(end_centis - elapsed_centis).unwrap_or(u16::MAX),
+                buffer: Cow::Owned(scale_up(&indices, scale)),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame)?;
+            elapsed_centis = end_centis;
+        }
+        Ok(())
+    }
+}
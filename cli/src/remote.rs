@@ -0,0 +1,198 @@
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::{cursor, execute, queue, style, terminal};
+use std::error::Error;
+use std::io::{self, Read, Stdout, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use chip_8::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use crate::input::{KeyState, Retention};
+use crate::keymap::Keymap;
+use crate::layout::Layout;
+use crate::renderer::{Grid, Renderer};
+use crate::server::{FRAME, KEYS_LEN, SOUND, keys_message};
+use crate::theme::Theme;
+use crate::{draw_screen, truncate};
+
+const READ_TIMEOUT: Duration = Duration::from_millis(5);
+
+/// Shows the display of an emulator started with `--serve` and sends it
+/// this terminal's keys.
+pub fn run(address: &str, renderer: Renderer, keymap: Keymap) -> Result<(), Box<dyn Error>> {
+    let mut stream = TcpStream::connect(address)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let release_events = terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if release_events {
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(
+                KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                    | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+            )
+        )?;
+    }
+    let result = view(
+        &mut stream,
+        &mut stdout,
+        address,
+        renderer,
+        keymap,
+        release_events,
+    );
+    if release_events {
+        execute!(stdout, PopKeyboardEnhancementFlags)?;
+    }
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn view(
+    stream: &mut TcpStream,
+    stdout: &mut Stdout,
+    address: &str,
+    renderer: Renderer,
+    keymap: Keymap,
+    release_events: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut keys = KeyState::new(release_events, Retention::default());
+    let mut sent_keys = keys_message(keys.pressed());
+    // the server tells TCP clients from WebSocket ones by this first message
+    stream.write_all(&sent_keys)?;
+
+    let mut pixels = vec![false; SCREEN_WIDTH * SCREEN_HEIGHT];
+    let mut sound = false;
+    let mut pending = Vec::new();
+    let mut terminal_size = terminal::size()?;
+    let mut layout: Option<Layout> = None;
+    let mut grid = Grid::default();
+
+    loop {
+        while event::poll(Duration::from_secs(0))? {
+            match event::read()? {
+                Event::Resize(columns, rows) => terminal_size = (columns, rows),
+                Event::Key(key) => {
+                    let chip8_key = keymap.chip8_key(key.code);
+                    match key.kind {
+                        KeyEventKind::Release => {
+                            if let Some(k) = chip8_key {
+                                keys.release(k);
+                            }
+                        }
+                        _ if key.code == KeyCode::Esc
+                            || (key.code == KeyCode::Char('c')
+                                && key.modifiers.contains(KeyModifiers::CONTROL)) =>
+                        {
+                            return Ok(());
+                        }
+                        _ => {
+                            if let Some(k) = chip8_key {
+                                keys.press(k);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let message = keys_message(keys.pressed());
+        if message != sent_keys {
+            stream.write_all(&message)?;
+            sent_keys = message;
+        }
+
+        let mut buffer = [0; 1024];
+        match stream.read(&mut buffer) {
+            Ok(0) => return Err("the server closed the connection".into()),
+            Ok(n) => pending.extend_from_slice(&buffer[..n]),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(e) => return Err(e.into()),
+        }
+        let mut changed = false;
+        while let Some(length) = message_length(&pending) {
+            let message: Vec<u8> = pending.drain(..length).collect();
+            match message[0] {
+                FRAME => {
+                    pixels = message[2..]
+                        .chunks(8)
+                        .flat_map(|row| {
+                            let row = u64::from_be_bytes(row.try_into().unwrap_or_default());
+                            (0..SCREEN_WIDTH).map(move |x| row & (1 << 63 >> x) != 0)
+                        })
+                        .collect();
+                    changed = true;
+                }
+                SOUND => {
+                    sound = message[1] != 0;
+                    changed = true;
+                }
+                _ => return Err("unexpected message from the server".into()),
+            }
+        }
+        let height = pixels.len() / SCREEN_WIDTH;
+        let needed = (renderer.columns(SCREEN_WIDTH), renderer.lines(height) + 1);
+        let new_layout = Layout::new(terminal_size, needed);
+        if !changed && layout == Some(new_layout) {
+            continue;
+        }
+        if layout != Some(new_layout) {
+            layout = Some(new_layout);
+            queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+            grid.invalidate();
+        }
+        match new_layout {
+            Layout::TooSmall { columns, rows } => {
+                let message = format!("Terminal too small (needs {}x{})", columns, rows);
+                queue!(
+                    stdout,
+                    cursor::MoveTo(0, 0),
+                    style::Print(truncate(&message, terminal_size.0))
+                )?;
+                stdout.flush()?;
+            }
+            Layout::Centered { left, top, columns } => {
+                let status = format!(
+                    "Remote: {}{} | Controls: {} | Esc to quit",
+                    address,
+                    if sound { " | Beep" } else { "" },
+                    keymap.layout_hint()
+                );
+                let status = truncate(&status, columns - left);
+                let theme = Theme::default();
+                let display = (&pixels[..], SCREEN_WIDTH);
+                draw_screen(
+                    display,
+                    stdout,
+                    renderer,
+                    theme,
+                    (left, top),
+                    &mut grid,
+                    status,
+                )?;
+            }
+        }
+    }
+}
+
+/// Length of the first complete message in `data`, if there is one yet.
+fn message_length(data: &[u8]) -> Option<usize> {
+    let length = match *data.first()? {
+        FRAME => 2 + *data.get(1)? as usize * 8,
+        SOUND => 2,
+        _ => KEYS_LEN,
+    };
+    (data.len() >= length).then_some(length)
+}
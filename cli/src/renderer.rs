@@ -0,0 +1,202 @@
+use clap::ValueEnum;
+
+/// How display pixels are packed into terminal character cells, or drawn
+/// over them as an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Renderer {
+    /// 1x2 pixels per cell using half blocks
+    #[default]
+    HalfBlock,
+    /// 2x3 pixels per cell using sextant blocks, for small terminals (needs
+    /// a font with Unicode 13's legacy computing symbols)
+    Sextant,
+    /// 2x4 pixels per cell using braille patterns, for small terminals
+    Braille,
+    /// An image through the kitty graphics protocol (kitty, WezTerm,
+    /// Ghostty), falling back to sixel or half blocks if it's unsupported
+    Kitty,
+    /// An image in sixels (xterm -ti vt340, foot, mlterm, WezTerm), falling
+    /// back to kitty or half blocks if it's unsupported
+    Sixel,
+}
+
+// bit of each braille dot, indexed by [row][column] within the cell
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+const BRAILLE_BLANK: u32 = 0x2800;
+// sextants in order from U+1FB00, numbered with bit 0 top left to bit 5
+// bottom right; the blank, full and half blocks are left out, as those
+// already exist
+const SEXTANT_FIRST: u32 = 0x1FB00;
+const SEXTANT_LEFT_HALF: u32 = 0b01_0101;
+const SEXTANT_RIGHT_HALF: u32 = 0b10_1010;
+
+impl Renderer {
+    /// Whether the display is drawn as an image by `graphics`, rather than
+    /// with characters from `cell`.
+    pub fn is_image(self) -> bool {
+        matches!(self, Renderer::Kitty | Renderer::Sixel)
+    }
+
+    /// Display pixels covered by one cell, as (columns, rows). Images cover
+    /// as many cells as half blocks, which keeps pixels about square.
+    pub fn cell_size(self) -> (usize, usize) {
+        match self {
+            Renderer::HalfBlock | Renderer::Kitty | Renderer::Sixel => (1, 2),
+            Renderer::Sextant => (2, 3),
+            Renderer::Braille => (2, 4),
+        }
+    }
+
+    /// Terminal columns needed for a display `width` pixels wide.
+    pub fn columns(self, width: usize) -> u16 {
+        width.div_ceil(self.cell_size().0) as u16
+    }
+
+    /// Terminal lines needed for a display `height` pixels tall.
+    pub fn lines(self, height: usize) -> u16 {
+        height.div_ceil(self.cell_size().1) as u16
+    }
+
+    /// Character for the cell whose top left pixel is (`x`, `y`) in a
+    /// display `width` pixels wide. Pixels past the edges count as off.
+    pub fn cell(self, pixels: &[bool], width: usize, x: usize, y: usize) -> char {
+        let pixel =
+            |x: usize, y: usize| x < width && pixels.get(y * width + x).copied().unwrap_or(false);
+        match self {
+            // the image is drawn over blank cells
+            Renderer::Kitty | Renderer::Sixel => ' ',
+            Renderer::HalfBlock => match (pixel(x, y), pixel(x, y + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            },
+            Renderer::Sextant => {
+                let mut bits = 0;
+                for row in 0..3 {
+                    for column in 0..2 {
+                        if pixel(x + column, y + row) {
+                            bits |= 1 << (row * 2 + column);
+                        }
+                    }
+                }
+                match bits {
+                    0 => ' ',
+                    SEXTANT_LEFT_HALF => '▌',
+                    SEXTANT_RIGHT_HALF => '▐',
+                    0b11_1111 => '█',
+                    _ => {
+                        let skipped =
+                            (bits > SEXTANT_LEFT_HALF) as u32 + (bits > SEXTANT_RIGHT_HALF) as u32;
+                        char::from_u32(SEXTANT_FIRST + bits - 1 - skipped).unwrap_or(' ')
+                    }
+                }
+            }
+            Renderer::Braille => {
+                let mut bits = 0;
+                for (row, dots) in BRAILLE_DOTS.iter().enumerate() {
+                    for (column, dot) in dots.iter().enumerate() {
+                        if pixel(x + column, y + row) {
+                            bits |= dot;
+                        }
+                    }
+                }
+                // a blank braille pattern isn't always drawn as wide as a space
+                if bits == 0 {
+                    ' '
+                } else {
+                    char::from_u32(BRAILLE_BLANK + bits).unwrap_or(' ')
+                }
+            }
+        }
+    }
+}
+
+// unchanged cells between two changed ones that are reprinted rather than
+// skipped, since a cursor move costs more than a few characters
+const MAX_GAP: usize = 3;
+
+/// A run of changed cells to print from (`column`, `line`) of the display.
+pub struct Run {
+    pub column: u16,
+    pub line: u16,
+    pub text: String,
+}
+
+/// The characters last drawn for the display, so each frame only sends the
+/// cells that changed. Over a slow link such as SSH, redrawing every cell
+/// 60 times a second can't keep up.
+#[derive(Debug, Default)]
+pub struct Grid {
+    cells: Vec<char>,
+    columns: usize,
+    // for image renderers, the pixels of the image last drawn
+    pixels: Vec<bool>,
+    status: Option<String>,
+}
+
+impl Grid {
+    /// Forgets what was drawn, so the next frame draws everything, as needed
+    /// after the terminal is cleared or the colours change.
+    pub fn invalidate(&mut self) {
+        self.cells.clear();
+        self.pixels.clear();
+        self.status = None;
+    }
+
+    /// Replaces the drawn cells, `columns` to a line, with `cells`, and
+    /// returns the runs that differ.
+    pub fn update(&mut self, cells: Vec<char>, columns: usize) -> Vec<Run> {
+        let resized = columns != self.columns || cells.len() != self.cells.len();
+        let mut runs = Vec::new();
+        for (line, row) in cells.chunks(columns).enumerate() {
+            let changed =
+                |column: usize| resized || self.cells[line * columns + column] != row[column];
+            let mut column = 0;
+            while column < row.len() {
+                if !changed(column) {
+                    column += 1;
+                    continue;
+                }
+                let start = column;
+                let mut end = column + 1;
+                loop {
+                    let reach = row.len().min(end + MAX_GAP + 1);
+                    match (end..reach).find(|&c| changed(c)) {
+                        Some(next) => end = next + 1,
+                        None => break,
+                    }
+                }
+                runs.push(Run {
+                    column: start as u16,
+                    line: line as u16,
+                    text: row[start..end].iter().collect(),
+                });
+                column = end;
+            }
+        }
+        self.cells = cells;
+        self.columns = columns;
+        runs
+    }
+
+    /// Whether `pixels` differ from the image last drawn, noting them as
+    /// drawn. Images are sent whole, so there are no runs.
+    pub fn update_image(&mut self, pixels: &[bool]) -> bool {
+        if self.pixels == pixels {
+            return false;
+        }
+        self.pixels = pixels.to_vec();
+        true
+    }
+
+    /// Whether `status` differs from the status line last drawn, noting it
+    /// as drawn.
+    pub fn update_status(&mut self, status: &str) -> bool {
+        if self.status.as_deref() == Some(status) {
+            return false;
+        }
+        self.status = Some(status.to_string());
+        true
+    }
+}
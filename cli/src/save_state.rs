@@ -0,0 +1,70 @@
+//! Numbered save state slots on disk: F5 saves to the current slot, F7
+//! loads it and F6 moves to the next one.
+
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use chip_8::{Chip8, Snapshot};
+
+use crate::config;
+
+/// Slots F6 cycles through.
+pub const SLOTS: u8 = 9;
+
+/// Save states of each ROM, kept as `<name>.<slot>.state` in a directory.
+pub struct SaveStates {
+    dir: Option<PathBuf>,
+    slot: u8,
+}
+
+impl SaveStates {
+    /// Slots in `dir`, or in `~/.local/share/chip8/states` if it's `None`.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        SaveStates {
+            dir: dir.or_else(|| config::data_dir().map(|dir| dir.join("states"))),
+            slot: 1,
+        }
+    }
+
+    /// Moves to the next slot, back to 1 after the last, and returns it.
+    pub fn next_slot(&mut self) -> u8 {
+        self.slot = self.slot % SLOTS + 1;
+        self.slot
+    }
+
+    pub fn slot(&self) -> u8 {
+        self.slot
+    }
+
+    /// Writes `chip8`'s state to the current slot of the ROM called `name`.
+    pub fn save(&self, chip8: &Chip8, name: &str) -> Result<(), Box<dyn Error>> {
+        let path = self.path(name)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, chip8.snapshot().to_bytes())
+            .map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Restores `chip8` from the current slot of the ROM called `name`.
+    pub fn load(&self, chip8: &mut Chip8, name: &str) -> Result<(), Box<dyn Error>> {
+        let path = self.path(name)?;
+        let bytes = fs::read(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => format!("slot {} is empty", self.slot),
+            _ => format!("{}: {}", path.display(), e),
+        })?;
+        let snapshot =
+            Snapshot::from_bytes(&bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+        chip8.restore(&snapshot);
+        Ok(())
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or("no directory for save states, set --state-dir")?;
+        Ok(dir.join(format!("{}.{}.state", name, self.slot)))
+    }
+}
@@ -0,0 +1,138 @@
+use clap::ValueEnum;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chip_8::render::Palette;
+use chip_8::{Chip8, SCREEN_WIDTH};
+
+use crate::recording::Recording;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ImageFormat {
+    Png,
+    Pbm,
+}
+
+impl ImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Pbm => "pbm",
+        }
+    }
+}
+
+/// Saves numbered screenshots of the display into a directory.
+pub struct Screenshots {
+    dir: PathBuf,
+    format: ImageFormat,
+    scale: u32,
+    palette: Palette,
+}
+
+impl Screenshots {
+    /// PNGs and GIFs are drawn in `palette`, normally the display's theme.
+    pub fn new(dir: PathBuf, format: ImageFormat, scale: u32, palette: Palette) -> Self {
+        Screenshots {
+            dir,
+            format,
+            scale,
+            palette,
+        }
+    }
+
+    /// Writes the current display to `<name>-NNN.<ext>`, using the first
+    /// number not already taken, and returns the path.
+    pub fn save(&self, chip8: &Chip8, name: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.next_path(name, self.format.extension());
+        let mut out = BufWriter::new(File::create(&path)?);
+        match self.format {
+            ImageFormat::Png => write_png(&mut out, chip8, self.scale, &self.palette)?,
+            ImageFormat::Pbm => write_pbm(&mut out, chip8)?,
+        }
+        out.flush()?;
+        Ok(path)
+    }
+
+    /// Encodes a finished recording as `<name>-NNN.gif` and returns the path.
+    pub fn save_recording(
+        &self,
+        recording: &Recording,
+        name: &str,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.next_path(name, "gif");
+        let mut out = BufWriter::new(File::create(&path)?);
+        recording.write_gif(&mut out, self.scale, &self.palette)?;
+        out.flush()?;
+        Ok(path)
+    }
+
+    fn next_path(&self, name: &str, extension: &str) -> PathBuf {
+        (1..)
+            .map(|n| self.dir.join(format!("{}-{:03}.{}", name, n, extension)))
+            .find(|path| !path.exists())
+            .expect("ran out of file names")
+    }
+}
+
+/// Writes the display as an RGBA PNG in `palette`, with each pixel drawn
+/// `scale` times larger.
+pub fn write_png(
+    out: impl Write,
+    chip8: &Chip8,
+    scale: u32,
+    palette: &Palette,
+) -> Result<(), Box<dyn Error>> {
+    let height = chip8.display_height();
+    let mut rgba = vec![0; SCREEN_WIDTH * height * 4];
+    chip8.render_rgba(&mut rgba, palette);
+
+    let (pixels, _) = rgba.as_chunks::<4>();
+    let scaled = scale_up(pixels, scale);
+
+    let scale = scale as usize;
+    let mut encoder =
+        png::Encoder::new(out, (SCREEN_WIDTH * scale) as u32, (height * scale) as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()?
+        .write_image_data(scaled.as_flattened())?;
+    Ok(())
+}
+
+/// Enlarges a row-major image of the display so each pixel becomes a
+/// `scale` x `scale` block.
+pub fn scale_up<T: Copy>(pixels: &[T], scale: u32) -> Vec<T> {
+    let scale = scale as usize;
+    let width = SCREEN_WIDTH * scale;
+    let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+    for row in pixels.chunks_exact(SCREEN_WIDTH) {
+        let start = scaled.len();
+        for &pixel in row {
+            scaled.extend(std::iter::repeat_n(pixel, scale));
+        }
+        for _ in 1..scale {
+            scaled.extend_from_within(start..start + width);
+        }
+    }
+    scaled
+}
+
+/// Writes the display as a binary PBM, one bit per pixel with lit pixels as 1.
+pub fn write_pbm(mut out: impl Write, chip8: &Chip8) -> std::io::Result<()> {
+    write!(out, "P4\n{} {}\n", SCREEN_WIDTH, chip8.display_height())?;
+    for row in chip8.display_rows() {
+        out.write_all(&row.to_be_bytes())?;
+    }
+    Ok(())
+}
+
+/// Screenshot file name prefix for a ROM path, e.g. `pong` for `roms/pong.ch8`.
+pub fn name_for(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "screenshot".to_string())
+}
@@ -0,0 +1,308 @@
+use std::error::Error;
+use std::path::Path;
+
+#[cfg(not(feature = "scripting"))]
+use chip_8::{Chip8, NUM_KEYS};
+
+#[cfg(feature = "scripting")]
+pub use backend::Script;
+
+/// Compiles the script at `path`.
+#[cfg(feature = "scripting")]
+pub fn load(path: &Path) -> Result<Script, Box<dyn Error>> {
+    Script::load(path)
+}
+
+/// Stand-in used when the CLI is built without the `scripting` feature.
+#[cfg(not(feature = "scripting"))]
+pub enum Script {}
+
+#[cfg(not(feature = "scripting"))]
+impl Script {
+    pub fn frame(&mut self, _chip8: &mut Chip8) -> Result<Vec<String>, String> {
+        match *self {}
+    }
+
+    pub fn keys(&self) -> [bool; NUM_KEYS] {
+        match *self {}
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn load(_path: &Path) -> Result<Script, Box<dyn Error>> {
+    Err("built without the `scripting` feature".into())
+}
+
+#[cfg(feature = "scripting")]
+mod backend {
+    //! Scripts are rhai files that can define two functions:
+    //!
+    //! - `on_start()`, run once before the first frame
+    //! - `on_frame()`, run at the end of every frame
+    //!
+    //! Inside them `this` is an object map kept between calls, and these
+    //! functions work on the emulator:
+    //!
+    //! - `v(x)`, `set_v(x, value)`, `i()`, `set_i(value)`, `pc()`,
+    //!   `set_pc(value)`, `delay_timer()`, `set_delay_timer(value)`,
+    //!   `sound_timer()`, `set_sound_timer(value)`
+    //! - `peek(address)` and `poke(address, value)` for memory
+    //! - `press(key)` and `release(key)` to hold keypad keys from the script
+    //! - `frame()`, the number of frames so far
+    //! - `assert(condition, message)`, which stops the script with `message`
+    //!
+    //! `print` output goes to the status line, or stderr in headless mode.
+    //! A cheat that keeps a lives counter topped up:
+    //!
+    //! ```rhai
+    //! fn on_frame() {
+    //!     poke(0x2F0, 3);
+    //! }
+    //! ```
+
+    use rhai::{AST, CallFnOptions, Dynamic, Engine, EvalAltResult, Scope};
+    use std::cell::RefCell;
+    use std::error::Error;
+    use std::path::Path;
+    use std::rc::Rc;
+
+    use chip_8::{Chip8, NUM_KEYS, NUM_REGISTERS};
+
+    const MEMORY_SIZE: usize = 4096;
+
+    /// What the script sees of the emulator during a call. It's copied in
+    /// before each call and anything the script changed is copied back.
+    struct Machine {
+        v: [u8; NUM_REGISTERS],
+        i: u16,
+        pc: u16,
+        delay_timer: u8,
+        sound_timer: u8,
+        memory: Vec<u8>,
+        frame: i64,
+        keys: [bool; NUM_KEYS],
+        messages: Vec<String>,
+    }
+
+    impl Machine {
+        fn read(&mut self, chip8: &Chip8) {
+            self.v = *chip8.v_registers();
+            self.i = chip8.i_register();
+            self.pc = chip8.pc();
+            self.delay_timer = chip8.delay_timer();
+            self.sound_timer = chip8.sound_timer();
+            for (address, byte) in self.memory.iter_mut().enumerate() {
+                *byte = chip8.read_byte(address as u16).unwrap_or(0);
+            }
+        }
+
+        // only changed values are written, so untouched memory stays as it was
+        fn write(&self, chip8: &mut Chip8) {
+            for (x, &value) in self.v.iter().enumerate() {
+                if chip8.v_registers()[x] != value {
+                    chip8.set_v_register(x, value);
+                }
+            }
+            if chip8.i_register() != self.i {
+                chip8.set_i_register(self.i);
+            }
+            if chip8.pc() != self.pc {
+                chip8.set_pc(self.pc);
+            }
+            if chip8.delay_timer() != self.delay_timer {
+                chip8.set_delay_timer(self.delay_timer);
+            }
+            if chip8.sound_timer() != self.sound_timer {
+                chip8.set_sound_timer(self.sound_timer);
+            }
+            for (address, &value) in self.memory.iter().enumerate() {
+                if chip8.read_byte(address as u16) != Some(value) {
+                    chip8.write_byte(address as u16, value);
+                }
+            }
+        }
+    }
+
+    /// A loaded script and the state it keeps between frames.
+    pub struct Script {
+        engine: Engine,
+        ast: AST,
+        scope: Scope<'static>,
+        this: Dynamic,
+        machine: Rc<RefCell<Machine>>,
+        started: bool,
+    }
+
+    impl Script {
+        pub fn load(path: &Path) -> Result<Script, Box<dyn Error>> {
+            let machine = Rc::new(RefCell::new(Machine {
+                v: [0; NUM_REGISTERS],
+                i: 0,
+                pc: 0,
+                delay_timer: 0,
+                sound_timer: 0,
+                memory: vec![0; MEMORY_SIZE],
+                frame: 0,
+                keys: [false; NUM_KEYS],
+                messages: Vec::new(),
+            }));
+            let engine = engine(&machine);
+            let ast = engine
+                .compile_file(path.into())
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            // top-level statements run once, before the emulator starts
+            let mut scope = Scope::new();
+            engine
+                .run_ast_with_scope(&mut scope, &ast)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            Ok(Script {
+                engine,
+                ast,
+                scope,
+                this: Dynamic::from_map(Default::default()),
+                machine,
+                started: false,
+            })
+        }
+
+        /// Runs the script's hooks for the end of a frame and returns what
+        /// it printed. An error means the script failed and should stop.
+        pub fn frame(&mut self, chip8: &mut Chip8) -> Result<Vec<String>, String> {
+            self.machine.borrow_mut().read(chip8);
+            if !self.started {
+                self.started = true;
+                self.call("on_start")?;
+            }
+            self.call("on_frame")?;
+            let mut machine = self.machine.borrow_mut();
+            machine.write(chip8);
+            machine.frame += 1;
+            Ok(std::mem::take(&mut machine.messages))
+        }
+
+        /// Keys the script is holding down.
+        pub fn keys(&self) -> [bool; NUM_KEYS] {
+            self.machine.borrow().keys
+        }
+
+        fn call(&mut self, name: &str) -> Result<(), String> {
+            let defined = self
+                .ast
+                .iter_functions()
+                .any(|function| function.name == name && function.params.is_empty());
+            if !defined {
+                return Ok(());
+            }
+            let options = CallFnOptions::new()
+                .eval_ast(false)
+                .bind_this_ptr(&mut self.this);
+            self.engine
+                .call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, name, ())
+                .map(|_| ())
+                .map_err(|e| format!("{}: {}", name, e))
+        }
+    }
+
+    type Fallible<T> = Result<T, Box<EvalAltResult>>;
+
+    fn engine(machine: &Rc<RefCell<Machine>>) -> Engine {
+        let mut engine = Engine::new();
+
+        let m = machine.clone();
+        engine.on_print(move |text| m.borrow_mut().messages.push(text.to_string()));
+
+        let m = machine.clone();
+        engine.register_fn("v", move |x: i64| -> Fallible<i64> {
+            Ok(m.borrow().v[register(x)?].into())
+        });
+        let m = machine.clone();
+        engine.register_fn("set_v", move |x: i64, value: i64| -> Fallible<()> {
+            m.borrow_mut().v[register(x)?] = convert(value)?;
+            Ok(())
+        });
+        let m = machine.clone();
+        engine.register_fn("i", move || -> i64 { m.borrow().i.into() });
+        let m = machine.clone();
+        engine.register_fn("set_i", move |value: i64| -> Fallible<()> {
+            m.borrow_mut().i = convert(value)?;
+            Ok(())
+        });
+        let m = machine.clone();
+        engine.register_fn("pc", move || -> i64 { m.borrow().pc.into() });
+        let m = machine.clone();
+        engine.register_fn("set_pc", move |value: i64| -> Fallible<()> {
+            m.borrow_mut().pc = convert(value)?;
+            Ok(())
+        });
+        let m = machine.clone();
+        engine.register_fn("delay_timer", move || -> i64 {
+            m.borrow().delay_timer.into()
+        });
+        let m = machine.clone();
+        engine.register_fn("set_delay_timer", move |value: i64| -> Fallible<()> {
+            m.borrow_mut().delay_timer = convert(value)?;
+            Ok(())
+        });
+        let m = machine.clone();
+        engine.register_fn("sound_timer", move || -> i64 {
+            m.borrow().sound_timer.into()
+        });
+        let m = machine.clone();
+        engine.register_fn("set_sound_timer", move |value: i64| -> Fallible<()> {
+            m.borrow_mut().sound_timer = convert(value)?;
+            Ok(())
+        });
+        let m = machine.clone();
+        engine.register_fn("peek", move |address: i64| -> Fallible<i64> {
+            Ok(m.borrow().memory[memory_address(address)?].into())
+        });
+        let m = machine.clone();
+        engine.register_fn("poke", move |address: i64, value: i64| -> Fallible<()> {
+            m.borrow_mut().memory[memory_address(address)?] = convert(value)?;
+            Ok(())
+        });
+        let m = machine.clone();
+        engine.register_fn("press", move |key: i64| -> Fallible<()> {
+            m.borrow_mut().keys[keypad_key(key)?] = true;
+            Ok(())
+        });
+        let m = machine.clone();
+        engine.register_fn("release", move |key: i64| -> Fallible<()> {
+            m.borrow_mut().keys[keypad_key(key)?] = false;
+            Ok(())
+        });
+        let m = machine.clone();
+        engine.register_fn("frame", move || -> i64 { m.borrow().frame });
+        engine.register_fn("assert", |condition: bool, message: &str| -> Fallible<()> {
+            if condition {
+                Ok(())
+            } else {
+                Err(format!("assertion failed: {}", message).into())
+            }
+        });
+        engine
+    }
+
+    fn convert<T: TryFrom<i64>>(value: i64) -> Fallible<T> {
+        T::try_from(value).map_err(|_| format!("value {} out of range", value).into())
+    }
+
+    fn register(x: i64) -> Fallible<usize> {
+        index(x, NUM_REGISTERS, "register")
+    }
+
+    fn memory_address(address: i64) -> Fallible<usize> {
+        index(address, MEMORY_SIZE, "address")
+    }
+
+    fn keypad_key(key: i64) -> Fallible<usize> {
+        index(key, NUM_KEYS, "key")
+    }
+
+    fn index(value: i64, len: usize, what: &str) -> Fallible<usize> {
+        usize::try_from(value)
+            .ok()
+            .filter(|&index| index < len)
+            .ok_or_else(|| format!("{} {:#X} out of range", what, value).into())
+    }
+}
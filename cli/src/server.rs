@@ -0,0 +1,236 @@
+//! Serves the display and takes keypad input over the network, for running
+//! the emulator on one machine and playing it on another.
+//!
+//! Clients connect over plain TCP or WebSocket on the same port. The
+//! messages are the same either way; over WebSocket each is one binary
+//! message. The server sends:
+//!
+//! - `F`, the display height, then one big-endian `u64` per row with the
+//!   leftmost pixel in the top bit. Sent on connecting and whenever the
+//!   display changes.
+//! - `S` then 1 or 0 when the buzzer starts or stops.
+//!
+//! Clients send `K` and the pressed keys as a little-endian `u16`, bit `n`
+//! for key `n`, whenever they change. TCP clients send their keys as soon
+//! as they connect, which is how they are told apart from WebSocket ones.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tungstenite::{Message, WebSocket};
+
+use chip_8::{Chip8, NUM_KEYS};
+
+pub const FRAME: u8 = b'F';
+pub const SOUND: u8 = b'S';
+pub const KEYS: u8 = b'K';
+pub const KEYS_LEN: usize = 3;
+
+const FRAME_DURATION: Duration = Duration::from_micros(1_000_000 / 60);
+// how long a client thread waits for input before passing on frames
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+// how long a new connection has to show whether it's a WebSocket
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Runs `chip8` in real time without a terminal UI, serving it on `address`
+/// until interrupted.
+pub fn run(chip8: &mut Chip8, address: &str, clock_speed: u64) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    eprintln!("Serving on {}", listener.local_addr()?);
+
+    let (keys_sender, keys_receiver) = mpsc::channel();
+    let mut clients: Vec<Sender<Arc<[u8]>>> = Vec::new();
+    let mut client_keys: HashMap<usize, u16> = HashMap::new();
+    let mut next_id = 0;
+    let mut sound = false;
+    // instructions owed to the next frame, in 60ths
+    let mut budget = 0;
+    let mut next_frame = Instant::now();
+
+    loop {
+        loop {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            };
+            let (frames, frames_receiver) = mpsc::channel();
+            // new clients start with the current display and buzzer
+            let _ = frames.send(frame_message(chip8));
+            let _ = frames.send(Arc::from([SOUND, sound as u8]));
+            clients.push(frames);
+            let id = next_id;
+            next_id += 1;
+            let keys = keys_sender.clone();
+            thread::spawn(move || serve_client(id, stream, frames_receiver, keys));
+        }
+
+        for (id, mask) in keys_receiver.try_iter() {
+            client_keys.insert(id, mask);
+        }
+        // every client plays on the same keypad
+        let mask = client_keys.values().fold(0, |all, mask| all | mask);
+        chip8.set_pressed_keys(std::array::from_fn(|key| mask & (1 << key) != 0));
+
+        budget += clock_speed;
+        let summary = chip8.run_frame((budget / 60) as u32);
+        budget %= 60;
+
+        let mut messages = Vec::new();
+        if !summary.dirty.is_empty() {
+            messages.push(frame_message(chip8));
+        }
+        if summary.sound_playing != sound {
+            sound = summary.sound_playing;
+            messages.push(Arc::from([SOUND, sound as u8]));
+        }
+        // a failed send means the client's thread has finished
+        for message in messages {
+            clients.retain(|client| client.send(message.clone()).is_ok());
+        }
+
+        next_frame += FRAME_DURATION;
+        match next_frame.checked_duration_since(Instant::now()) {
+            Some(wait) => thread::sleep(wait),
+            // running behind, so don't try to catch up
+            None => next_frame = Instant::now(),
+        }
+    }
+}
+
+fn frame_message(chip8: &Chip8) -> Arc<[u8]> {
+    let height = chip8.display_height();
+    let mut message = vec![FRAME, height as u8];
+    for row in &chip8.get_display_packed()[..height] {
+        message.extend(row.to_be_bytes());
+    }
+    message.into()
+}
+
+/// Parses a `K` message into the key mask it carries.
+pub fn parse_keys(message: &[u8]) -> Option<u16> {
+    match message {
+        &[KEYS, low, high] => Some(u16::from_le_bytes([low, high])),
+        _ => None,
+    }
+}
+
+/// The `K` message for the keys in `pressed`.
+pub fn keys_message(pressed: [bool; NUM_KEYS]) -> [u8; KEYS_LEN] {
+    let mask = (0..NUM_KEYS)
+        .filter(|&key| pressed[key])
+        .fold(0u16, |mask, key| mask | 1 << key);
+    let [low, high] = mask.to_le_bytes();
+    [KEYS, low, high]
+}
+
+enum Connection {
+    Tcp { stream: TcpStream, pending: Vec<u8> },
+    WebSocket(Box<WebSocket<TcpStream>>),
+}
+
+impl Connection {
+    fn open(stream: TcpStream) -> io::Result<Connection> {
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let mut start = [0; 4];
+        let peeked = stream.peek(&mut start).unwrap_or(0);
+        let connection = if start[..peeked].starts_with(b"GET") {
+            let socket = tungstenite::accept(stream.try_clone()?).map_err(io::Error::other)?;
+            Connection::WebSocket(Box::new(socket))
+        } else {
+            Connection::Tcp {
+                stream: stream.try_clone()?,
+                pending: Vec::new(),
+            }
+        };
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        Ok(connection)
+    }
+
+    /// The latest key mask the client sent, waiting at most `POLL_INTERVAL`.
+    fn receive_keys(&mut self) -> io::Result<Option<u16>> {
+        match self {
+            Connection::Tcp { stream, pending } => {
+                let mut buffer = [0; 64];
+                match stream.read(&mut buffer) {
+                    Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    Ok(n) => pending.extend_from_slice(&buffer[..n]),
+                    Err(e) if is_timeout(&e) => {}
+                    Err(e) => return Err(e),
+                }
+                let mut mask = None;
+                while pending.len() >= KEYS_LEN {
+                    let message: Vec<u8> = pending.drain(..KEYS_LEN).collect();
+                    mask = Some(parse_keys(&message).ok_or(io::ErrorKind::InvalidData)?);
+                }
+                Ok(mask)
+            }
+            Connection::WebSocket(socket) => match socket.read() {
+                Ok(Message::Binary(message)) => Ok(parse_keys(&message)),
+                Ok(Message::Close(_)) => Err(io::ErrorKind::ConnectionAborted.into()),
+                Ok(_) => Ok(None),
+                Err(tungstenite::Error::Io(e)) if is_timeout(&e) => Ok(None),
+                Err(e) => Err(io::Error::other(e)),
+            },
+        }
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        match self {
+            Connection::Tcp { stream, .. } => stream.write_all(message),
+            Connection::WebSocket(socket) => socket
+                .send(Message::binary(message))
+                .map_err(io::Error::other),
+        }
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+// passes messages between one client and the emulator until either side goes away
+fn serve_client(
+    id: usize,
+    stream: TcpStream,
+    frames: Receiver<Arc<[u8]>>,
+    keys: Sender<(usize, u16)>,
+) {
+    let Ok(mut connection) = Connection::open(stream) else {
+        return;
+    };
+    loop {
+        match connection.receive_keys() {
+            Ok(Some(mask)) => {
+                let _ = keys.send((id, mask));
+            }
+            Ok(None) => {}
+            Err(_) => break,
+        }
+        let sent = loop {
+            match frames.try_recv() {
+                Ok(message) => {
+                    if connection.send(&message).is_err() {
+                        break false;
+                    }
+                }
+                Err(TryRecvError::Empty) => break true,
+                Err(TryRecvError::Disconnected) => break false,
+            }
+        };
+        if !sent {
+            break;
+        }
+    }
+    // a client that leaves lets go of its keys
+    let _ = keys.send((id, 0));
+}
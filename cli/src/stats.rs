@@ -0,0 +1,53 @@
+use std::time::{Duration, Instant};
+
+// how often the measured rates are refreshed
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Achieved frame and instruction rates, measured over one-second windows.
+pub struct Stats {
+    window_start: Instant,
+    frames: u32,
+    instructions: u64,
+    fps: f64,
+    ips: f64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            window_start: Instant::now(),
+            frames: 0,
+            instructions: 0,
+            fps: 0.0,
+            ips: 0.0,
+        }
+    }
+}
+
+impl Stats {
+    pub fn instructions(&mut self, count: u64) {
+        self.instructions += count;
+    }
+
+    /// Counts a drawn frame, refreshing the rates when a window ends.
+    pub fn frame(&mut self) {
+        self.frames += 1;
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= WINDOW {
+            let seconds = elapsed.as_secs_f64();
+            self.fps = f64::from(self.frames) / seconds;
+            self.ips = self.instructions as f64 / seconds;
+            self.frames = 0;
+            self.instructions = 0;
+            self.window_start = Instant::now();
+        }
+    }
+
+    /// Measured rates next to their targets, e.g. `FPS 61.9/62.5 | IPS 699/700`.
+    pub fn summary(&self, target_fps: f64, target_ips: u64) -> String {
+        format!(
+            "FPS {:.1}/{:.1} | IPS {:.0}/{}",
+            self.fps, target_fps, self.ips, target_ips
+        )
+    }
+}
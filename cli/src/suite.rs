@@ -0,0 +1,268 @@
+use std::error::Error;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use chip_8::Chip8;
+
+use crate::headless;
+
+// timer rate the suite runs at, independent of any config file
+const CLOCK_SPEED: u64 = 700;
+
+/// The test ROMs in `roms/tests`, built in so `test` runs without a
+/// directory: (file name, ROM, expected display, groups).
+const BUILTIN: &[(&str, &[u8], &str, &str)] = &[
+    (
+        "1-flow.ch8",
+        include_bytes!("../../roms/tests/1-flow.ch8"),
+        include_str!("../../roms/tests/1-flow.txt"),
+        include_str!("../../roms/tests/1-flow.groups"),
+    ),
+    (
+        "2-arithmetic.ch8",
+        include_bytes!("../../roms/tests/2-arithmetic.ch8"),
+        include_str!("../../roms/tests/2-arithmetic.txt"),
+        include_str!("../../roms/tests/2-arithmetic.groups"),
+    ),
+    (
+        "3-memory.ch8",
+        include_bytes!("../../roms/tests/3-memory.ch8"),
+        include_str!("../../roms/tests/3-memory.txt"),
+        include_str!("../../roms/tests/3-memory.groups"),
+    ),
+    (
+        "4-display-and-input.ch8",
+        include_bytes!("../../roms/tests/4-display-and-input.ch8"),
+        include_str!("../../roms/tests/4-display-and-input.txt"),
+        include_str!("../../roms/tests/4-display-and-input.groups"),
+    ),
+];
+
+/// A test ROM and what its display should look like at the end.
+struct Case {
+    name: String,
+    rom: Vec<u8>,
+    expected: Option<String>,
+    groups: Vec<Group>,
+}
+
+/// Display rows where a test ROM draws the result for some opcodes.
+struct Group {
+    rows: RangeInclusive<usize>,
+    opcodes: String,
+}
+
+/// Runs the test ROMs headlessly and compares each final display with the
+/// expected bitmap (`--headless` output format). Without a directory, the
+/// built-in ROMs from `roms/tests` are run; otherwise every `.ch8` ROM in
+/// `dir`, with the bitmap in `<rom>.txt` next to it. A `<rom>.groups` file
+/// names the opcodes checked on each range of display rows, so results are
+/// reported per opcode group. With `bless`, the expected bitmaps in `dir`
+/// are written from this run instead.
+pub fn run(dir: Option<&Path>, cycles: u64, bless: bool) -> Result<(), Box<dyn Error>> {
+    let cases = match dir {
+        Some(dir) => read_cases(dir)?,
+        None => builtin_cases()?,
+    };
+
+    let mut failed = 0;
+    for case in &cases {
+        let actual = match run_rom(&case.rom, cycles) {
+            Ok(actual) => actual,
+            Err(e) => {
+                failed += 1;
+                println!("FAIL  {} ({})", case.name, e);
+                continue;
+            }
+        };
+
+        if let (true, Some(dir)) = (bless, dir) {
+            fs::write(dir.join(&case.name).with_extension("txt"), &actual)?;
+            println!("BLESS {}", case.name);
+            continue;
+        }
+        let Some(expected) = &case.expected else {
+            let expected_name = Path::new(&case.name).with_extension("txt");
+            println!("SKIP  {} (no {})", case.name, expected_name.display());
+            continue;
+        };
+        let differing = differing_rows(expected, &actual);
+        if differing.is_empty() {
+            println!("PASS  {}", case.name);
+        } else {
+            failed += 1;
+            println!("FAIL  {}", case.name);
+        }
+        report_groups(case, &differing);
+    }
+
+    if failed > 0 {
+        return Err(format!("{} of {} ROMs failed", failed, cases.len()).into());
+    }
+    Ok(())
+}
+
+fn builtin_cases() -> Result<Vec<Case>, Box<dyn Error>> {
+    BUILTIN
+        .iter()
+        .map(|&(name, rom, expected, groups)| {
+            Ok(Case {
+                name: name.to_string(),
+                rom: rom.to_vec(),
+                expected: Some(expected.to_string()),
+                groups: parse_groups(groups).map_err(|e| format!("{}: {}", name, e))?,
+            })
+        })
+        .collect()
+}
+
+fn read_cases(dir: &Path) -> Result<Vec<Case>, Box<dyn Error>> {
+    let mut roms: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ch8"))
+        .collect();
+    roms.sort();
+    if roms.is_empty() {
+        return Err(format!("no .ch8 ROMs in {}", dir.display()).into());
+    }
+
+    let mut cases = Vec::new();
+    for rom in roms {
+        let groups_path = rom.with_extension("groups");
+        let groups = match fs::read_to_string(&groups_path) {
+            Ok(text) => {
+                parse_groups(&text).map_err(|e| format!("{}: {}", groups_path.display(), e))?
+            }
+            Err(_) => Vec::new(),
+        };
+        cases.push(Case {
+            name: rom
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            rom: fs::read(&rom)?,
+            expected: fs::read_to_string(rom.with_extension("txt")).ok(),
+            groups,
+        });
+    }
+    Ok(cases)
+}
+
+/// Parses a groups file: one `FIRST-LAST OPCODES...` line per group, with
+/// the display rows the result is drawn on, e.g. `0-5 3XNN 4XNN`. Lines
+/// starting with `#` are comments.
+fn parse_groups(text: &str) -> Result<Vec<Group>, String> {
+    let mut groups = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = || format!("line {}: expected `FIRST-LAST OPCODES`", index + 1);
+        let (rows, opcodes) = line.split_once(char::is_whitespace).ok_or_else(error)?;
+        let (first, last) = rows.split_once('-').ok_or_else(error)?;
+        let first: usize = first.parse().map_err(|_| error())?;
+        let last: usize = last.parse().map_err(|_| error())?;
+        if first > last {
+            return Err(error());
+        }
+        groups.push(Group {
+            rows: first..=last,
+            opcodes: opcodes.trim().to_string(),
+        });
+    }
+    Ok(groups)
+}
+
+fn run_rom(rom: &[u8], cycles: u64) -> Result<String, Box<dyn Error>> {
+    let mut chip8 = Chip8::new();
+    chip8.init();
+    chip8.seed_rng(0);
+    chip8.load_rom(rom)?;
+    // a ROM that jumps to itself won't draw anything more
+    chip8.set_finish_detection(true);
+    headless::run(&mut chip8, cycles, CLOCK_SPEED, None)?;
+    if let Some(fault) = chip8.fault() {
+        return Err(format!("stopped: {}", fault).into());
+    }
+    Ok(headless::display_text(&chip8))
+}
+
+/// Prints a line per opcode group of `case`, then any differing rows
+/// outside the groups. Without groups, only the differing rows are listed.
+fn report_groups(case: &Case, differing: &[(usize, usize)]) {
+    let overlaps = |rows: &RangeInclusive<usize>, &(first, last): &(usize, usize)| {
+        first <= *rows.end() && *rows.start() <= last
+    };
+    for group in &case.groups {
+        let passed = !differing.iter().any(|run| overlaps(&group.rows, run));
+        println!(
+            "      {}  {}",
+            if passed { "pass" } else { "FAIL" },
+            group.opcodes
+        );
+    }
+    let ungrouped: Vec<String> = differing
+        .iter()
+        .filter(|run| !case.groups.iter().any(|group| overlaps(&group.rows, run)))
+        .map(|&(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{}-{}", first, last)
+            }
+        })
+        .collect();
+    if !ungrouped.is_empty() {
+        println!("      differs in display rows {}", ungrouped.join(", "));
+    }
+}
+
+/// Runs of display rows that differ, as inclusive (first, last) pairs. The
+/// test ROMs print one result per line of text, so a run usually pinpoints
+/// the failing opcode group.
+fn differing_rows(expected: &str, actual: &str) -> Vec<(usize, usize)> {
+    let mut expected_rows = expected.lines();
+    let mut actual_rows = actual.lines();
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for row in 0.. {
+        let (a, b) = (expected_rows.next(), actual_rows.next());
+        if a.is_none() && b.is_none() {
+            break;
+        }
+        if a != b {
+            match runs.last_mut() {
+                Some((_, last)) if *last + 1 == row => *last = row,
+                _ => runs.push((row, row)),
+            }
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_roms_pass() {
+        for case in builtin_cases().unwrap() {
+            let actual = run_rom(&case.rom, 100_000).unwrap();
+            let expected = case.expected.as_deref().unwrap();
+            assert_eq!(differing_rows(expected, &actual), [], "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn test_parse_groups() {
+        let groups = parse_groups("# rows\n0-5 3XNN 4XNN\n\n6-11  1NNN\n").unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].rows, 0..=5);
+        assert_eq!(groups[0].opcodes, "3XNN 4XNN");
+        assert_eq!(groups[1].opcodes, "1NNN");
+        assert!(parse_groups("5-0 1NNN").is_err());
+        assert!(parse_groups("0-5").is_err());
+    }
+}
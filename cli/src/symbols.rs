@@ -0,0 +1,105 @@
+//! Names for addresses, read from a symbol file a ROM's toolchain wrote, so
+//! the debugger can show `CALL draw_paddle` and take `break main_loop`.
+//!
+//! A symbol file has one `ADDRESS NAME` pair per line, the address in hex
+//! with or without `0x`. Blank lines and lines starting with `#` or `;` are
+//! skipped.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use chip_8::{Instruction, decode};
+
+use crate::disasm::disassemble;
+
+/// The symbols of one ROM.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+    addresses: HashMap<String, u16>,
+}
+
+impl Symbols {
+    /// Reads the symbol file at `path`.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// The symbols in `<rom>.sym` next to the ROM at `rom_path`, or none if
+    /// there's no such file.
+    pub fn sidecar(rom_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let path = rom_path.with_extension("sym");
+        if path.is_file() {
+            Self::load(&path)
+        } else {
+            Ok(Symbols::default())
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut symbols = Symbols::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(address), Some(name), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(format!("line {}: expected `ADDRESS NAME`", number + 1));
+            };
+            let digits = address.trim_start_matches("0x").trim_start_matches("0X");
+            let address = u16::from_str_radix(digits, 16)
+                .map_err(|_| format!("line {}: invalid address `{}`", number + 1, address))?;
+            symbols.insert(address, name);
+        }
+        Ok(symbols)
+    }
+
+    fn insert(&mut self, address: u16, name: &str) {
+        // the first name given to an address is the one shown
+        self.names
+            .entry(address)
+            .or_insert_with(|| name.to_string());
+        self.addresses.insert(name.to_string(), address);
+    }
+
+    /// The name of `address`, if it has one.
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    /// The address called `name`.
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    /// `address` in hex, followed by its name in parentheses if it has one.
+    pub fn describe(&self, address: u16) -> String {
+        match self.name(address) {
+            Some(name) => format!("{:#05X} ({})", address, name),
+            None => format!("{:#05X}", address),
+        }
+    }
+
+    /// Like `disassemble`, but with named addresses given by name.
+    pub fn disassemble(&self, opcode: u16) -> String {
+        let text = disassemble(opcode);
+        let target = match decode(opcode) {
+            Some(
+                Instruction::Jump(address)
+                | Instruction::Call(address)
+                | Instruction::LoadI(address)
+                | Instruction::JumpV0(address),
+            ) => address,
+            _ => return text,
+        };
+        match self.name(target) {
+            Some(name) => text.replace(&format!("{:#05X}", target), name),
+            None => text,
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use clap::ValueEnum;
+use crossterm::style::Color;
+use serde::Deserialize;
+
+use chip_8::render::{BLACK, Palette, WHITE, parse_color};
+
+/// Named colour schemes for the terminal display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    /// The terminal's own colours
+    #[default]
+    Default,
+    /// Green on black, like a P1 phosphor monitor
+    GreenPhosphor,
+    /// Amber on black, like a P3 phosphor monitor
+    Amber,
+    /// Black on white
+    Paperwhite,
+}
+
+/// Colours the display is drawn in. `None` leaves the terminal's colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Theme {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    /// Swaps the two colours, for the visual bell.
+    pub reverse: bool,
+}
+
+impl Theme {
+    pub fn named(name: ThemeName) -> Self {
+        let (fg, bg) = match name {
+            ThemeName::Default => return Theme::default(),
+            ThemeName::GreenPhosphor => (rgb(0x33, 0xFF, 0x33), rgb(0x0A, 0x14, 0x0A)),
+            ThemeName::Amber => (rgb(0xFF, 0xB0, 0x00), rgb(0x14, 0x0C, 0x00)),
+            ThemeName::Paperwhite => (rgb(0x10, 0x10, 0x10), rgb(0xF2, 0xF0, 0xE6)),
+        };
+        Theme {
+            fg: Some(fg),
+            bg: Some(bg),
+            reverse: false,
+        }
+    }
+
+    /// Replaces the theme's colours with any given explicitly.
+    pub fn with_overrides(self, fg: Option<Color>, bg: Option<Color>) -> Self {
+        Theme {
+            fg: fg.or(self.fg),
+            bg: bg.or(self.bg),
+            ..self
+        }
+    }
+
+    /// The theme's colours for images: graphics renderers, screenshots and
+    /// recordings. The terminal's own colours can't be read back, so the
+    /// default theme is white on black.
+    pub fn palette(self) -> Palette {
+        let rgba = |color, default| match color {
+            Some(Color::Rgb { r, g, b }) => [r, g, b, 0xFF],
+            _ => default,
+        };
+        let fg = rgba(self.fg, WHITE);
+        let bg = rgba(self.bg, BLACK);
+        if self.reverse {
+            Palette::mono(bg, fg)
+        } else {
+            Palette::mono(fg, bg)
+        }
+    }
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+/// Parses a `#RRGGBB` colour for `--fg` and `--bg`.
+pub fn parse(s: &str) -> Result<Color, String> {
+    parse_color(s)
+        .map(|[r, g, b, _]| rgb(r, g, b))
+        .ok_or_else(|| format!("invalid colour `{}`, expected #RRGGBB", s))
+}
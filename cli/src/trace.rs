@@ -0,0 +1,192 @@
+//! `--trace`: writes a line for each instruction the program runs, with its
+//! address, opcode and mnemonic and the registers and memory it changed.
+
+use clap::ValueEnum;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use chip_8::{Chip8, Instruction, InstructionHook, NUM_REGISTERS};
+
+use crate::symbols::Symbols;
+
+/// Kinds of instructions `--trace-filter` keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TraceFilter {
+    /// Jumps, calls and returns
+    Jumps,
+    /// Writes to memory (FX33 and FX55)
+    Writes,
+    /// Sprite draws and clears
+    Draws,
+}
+
+impl TraceFilter {
+    fn matches(self, instruction: Instruction) -> bool {
+        match self {
+            TraceFilter::Jumps => matches!(
+                instruction,
+                Instruction::Jump(_)
+                    | Instruction::JumpV0(_)
+                    | Instruction::Call(_)
+                    | Instruction::Return
+            ),
+            TraceFilter::Writes => matches!(
+                instruction,
+                Instruction::StoreBcd { .. } | Instruction::StoreRegisters { .. }
+            ),
+            TraceFilter::Draws => matches!(
+                instruction,
+                Instruction::Draw { .. } | Instruction::ClearScreen
+            ),
+        }
+    }
+}
+
+/// The registers an instruction may change, as they were before it ran.
+#[derive(Debug, Clone, Copy, Default)]
+struct Registers {
+    v: [u8; NUM_REGISTERS],
+    i: u16,
+    sp: u16,
+    delay: u8,
+    sound: u8,
+}
+
+impl Registers {
+    fn of(chip8: &Chip8) -> Self {
+        Registers {
+            v: *chip8.v_registers(),
+            i: chip8.i_register(),
+            sp: chip8.sp(),
+            delay: chip8.delay_timer(),
+            sound: chip8.sound_timer(),
+        }
+    }
+}
+
+/// Writes the trace, as an instruction hook on the machine.
+pub struct Tracer {
+    out: BufWriter<Box<dyn Write>>,
+    filters: Vec<TraceFilter>,
+    // instructions still to write, or `None` for no limit
+    remaining: Option<u64>,
+    symbols: Symbols,
+    before: Registers,
+}
+
+impl Tracer {
+    /// Traces to the file at `path`, or to stderr for `-`. Only instructions
+    /// matching one of `filters` are written, or all if there are none, and
+    /// at most `limit` of them.
+    pub fn create(
+        path: &Path,
+        filters: Vec<TraceFilter>,
+        limit: Option<u64>,
+        symbols: Symbols,
+    ) -> Result<Self, Box<dyn Error>> {
+        let out: Box<dyn Write> = if path.as_os_str() == "-" {
+            Box::new(io::stderr())
+        } else {
+            Box::new(File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?)
+        };
+        Ok(Tracer {
+            out: BufWriter::new(out),
+            filters,
+            remaining: limit,
+            symbols,
+            before: Registers::default(),
+        })
+    }
+
+    fn wanted(&self, instruction: Option<Instruction>) -> bool {
+        if self.remaining == Some(0) {
+            return false;
+        }
+        match instruction {
+            _ if self.filters.is_empty() => true,
+            Some(instruction) => self
+                .filters
+                .iter()
+                .any(|filter| filter.matches(instruction)),
+            None => false,
+        }
+    }
+
+    fn line(
+        &self,
+        chip8: &Chip8,
+        pc: u16,
+        opcode: u16,
+        instruction: Option<Instruction>,
+    ) -> String {
+        let mut line = format!(
+            "{:03X}  {:04X}  {:<20}",
+            pc,
+            opcode,
+            self.symbols.disassemble(opcode)
+        );
+        let after = Registers::of(chip8);
+        let before = &self.before;
+        for (x, (old, new)) in before.v.iter().zip(&after.v).enumerate() {
+            if old != new {
+                line.push_str(&format!(" V{:X}={:02X}", x, new));
+            }
+        }
+        if before.i != after.i {
+            line.push_str(&format!(" I={:#05X}", after.i));
+        }
+        if before.sp != after.sp {
+            line.push_str(&format!(" SP={}", after.sp));
+        }
+        if before.delay != after.delay {
+            line.push_str(&format!(" DT={}", after.delay));
+        }
+        if before.sound != after.sound {
+            line.push_str(&format!(" ST={}", after.sound));
+        }
+        // stores go from I as it was before the instruction
+        let written = match instruction {
+            Some(Instruction::StoreBcd { .. }) => 3,
+            Some(Instruction::StoreRegisters { x }) => usize::from(x) + 1,
+            _ => 0,
+        };
+        if written > 0
+            && let Some(bytes) = chip8.read_range(before.i, written)
+        {
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            line.push_str(&format!(" [{:#05X}]={}", before.i, hex.join(" ")));
+        }
+        if let Some(name) = self.symbols.name(pc) {
+            line.push_str(&format!("  <{}>", name));
+        }
+        line.trim_end().to_string()
+    }
+}
+
+impl InstructionHook for Tracer {
+    fn before(&mut self, chip8: &Chip8, _: u16, _: u16, instruction: Option<Instruction>) {
+        if self.wanted(instruction) {
+            self.before = Registers::of(chip8);
+        }
+    }
+
+    fn after(&mut self, chip8: &Chip8, pc: u16, opcode: u16, instruction: Option<Instruction>) {
+        if !self.wanted(instruction) {
+            return;
+        }
+        let line = self.line(chip8, pc, opcode, instruction);
+        // a trace that can't be written stops, rather than the program
+        if writeln!(self.out, "{}", line).is_err() {
+            self.remaining = Some(0);
+            return;
+        }
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining -= 1;
+            if *remaining == 0 {
+                let _ = self.out.flush();
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use clap::ValueEnum;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use chip_8::{Diagnostic, UnknownOpcode};
+
+/// What to do on an opcode that isn't an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OnUnknownOpcode {
+    /// Skip it silently
+    #[default]
+    Ignore,
+    /// Skip it and append its address and opcode to --opcode-log
+    Log,
+    /// Stop in the debugger, showing the code around it
+    Stop,
+}
+
+impl OnUnknownOpcode {
+    pub fn policy(self) -> UnknownOpcode {
+        match self {
+            OnUnknownOpcode::Ignore => UnknownOpcode::Ignore,
+            OnUnknownOpcode::Log => UnknownOpcode::Warn,
+            OnUnknownOpcode::Stop => UnknownOpcode::Fault,
+        }
+    }
+}
+
+/// Appends skipped unknown opcodes to a file, opened on first use.
+pub struct OpcodeLog {
+    path: PathBuf,
+    file: Option<File>,
+    rom_name: String,
+}
+
+impl OpcodeLog {
+    pub fn new(path: PathBuf, rom_name: String) -> Self {
+        OpcodeLog {
+            path,
+            file: None,
+            rom_name,
+        }
+    }
+
+    /// Names the ROM later entries are logged under.
+    pub fn set_rom_name(&mut self, rom_name: String) {
+        self.rom_name = rom_name;
+    }
+
+    pub fn write(&mut self, warnings: &[Diagnostic]) -> io::Result<()> {
+        if warnings.is_empty() {
+            return Ok(());
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            ),
+        };
+        for warning in warnings {
+            writeln!(
+                file,
+                "{}: unknown opcode {:04X} at {:#05X}",
+                self.rom_name, warning.opcode, warning.pc
+            )?;
+        }
+        Ok(())
+    }
+}
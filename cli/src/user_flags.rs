@@ -0,0 +1,57 @@
+//! RPL user flags kept on disk, one file per ROM under
+//! `~/.local/share/chip8/flags`, so SUPER-CHIP high scores last from one
+//! session to the next.
+
+use std::fs;
+use std::path::PathBuf;
+
+use chip_8::Chip8;
+use chip_8::flags::{FlagStorage, NUM_FLAGS};
+
+use crate::config;
+
+/// The flags of one ROM, read from its file and written back on each save.
+pub struct FileFlags {
+    path: PathBuf,
+    flags: [u8; NUM_FLAGS],
+}
+
+impl FileFlags {
+    /// The flags of the ROM called `rom_name`, or `None` if there's no
+    /// data directory to keep them in.
+    pub fn open(rom_name: &str) -> Option<Self> {
+        let path = config::data_dir()?.join("flags").join(rom_name);
+        let mut flags = [0; NUM_FLAGS];
+        // a missing file means nothing was saved yet; SUPER-CHIP saves only 8
+        if let Ok(saved) = fs::read(&path) {
+            let len = saved.len().min(NUM_FLAGS);
+            flags[..len].copy_from_slice(&saved[..len]);
+        }
+        Some(FileFlags { path, flags })
+    }
+}
+
+impl FlagStorage for FileFlags {
+    fn load(&mut self) -> [u8; NUM_FLAGS] {
+        self.flags
+    }
+
+    fn save(&mut self, flags: &[u8; NUM_FLAGS]) {
+        if *flags == self.flags {
+            return;
+        }
+        self.flags = *flags;
+        // if writing fails the flags still last until the emulator quits
+        if let Some(dir) = self.path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(&self.path, self.flags);
+    }
+}
+
+/// Keeps `chip8`'s user flags in the file for `rom_name`.
+pub fn attach(chip8: &mut Chip8, rom_name: &str) {
+    if let Some(flags) = FileFlags::open(rom_name) {
+        chip8.set_flag_storage(flags);
+    }
+}
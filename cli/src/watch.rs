@@ -0,0 +1,52 @@
+//! `--watch`: reloads the ROM when its file changes, for iterating on a
+//! program with an external assembler.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::loader;
+
+// how often the file's modification time is read
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches a ROM file's modification time.
+pub struct RomWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl RomWatcher {
+    /// Watches `path`, taking its current contents as already loaded.
+    pub fn new(path: PathBuf) -> Self {
+        RomWatcher {
+            modified: modified(&path),
+            path,
+            last_check: Instant::now(),
+        }
+    }
+
+    /// The ROM read afresh if the file changed since it was last read, or
+    /// `None` if it didn't. Looks at the file at most every `CHECK_INTERVAL`.
+    pub fn poll(&mut self) -> Option<Result<Vec<u8>, Box<dyn Error>>> {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return None;
+        }
+        self.last_check = Instant::now();
+        // a file being rewritten may be missing for a moment; wait for it
+        let modified = modified(&self.path)?;
+        if self.modified == Some(modified) {
+            return None;
+        }
+        self.modified = Some(modified);
+        Some(loader::read(&self.path))
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
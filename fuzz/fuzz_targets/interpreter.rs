@@ -0,0 +1,55 @@
+//! Runs arbitrary ROMs with arbitrary key presses and checks the interpreter
+//! never panics. Run with `cargo +nightly fuzz run interpreter` from `fuzz/`.
+//!
+//! Input layout: one settings byte, 32 bytes of key states (16 frames of a
+//! 16-bit key mask), then the ROM.
+
+#![no_main]
+
+use chip_8::{Chip8, MAX_ROM_SIZE, MemoryAccess, Quirks};
+use libfuzzer_sys::fuzz_target;
+
+const KEY_FRAMES: usize = 16;
+const FRAMES: usize = 64;
+const CYCLES_PER_FRAME: u32 = 32;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&settings, rest)) = data.split_first() else {
+        return;
+    };
+    let (keys, rom) = rest.split_at(rest.len().min(KEY_FRAMES * 2));
+    let rom = &rom[..rom.len().min(MAX_ROM_SIZE)];
+
+    let mut chip8 = Chip8::new();
+    chip8.init();
+    chip8.seed_rng(u64::from(settings));
+    chip8.set_quirks(Quirks {
+        display_wait: settings & 1 != 0,
+        vf_reset: settings & 4 != 0,
+        wrap_sprites: settings & 8 != 0,
+        jump_vx: settings & 16 != 0,
+        i_overflow: settings & 32 != 0,
+    });
+    chip8.set_memory_access(if settings & 2 != 0 {
+        MemoryAccess::Fault
+    } else {
+        MemoryAccess::Wrap
+    });
+    chip8.load_rom(rom);
+
+    let key_masks: Vec<u16> = keys
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    for frame in 0..FRAMES {
+        if let Some(mask) = key_masks.get(frame % KEY_FRAMES) {
+            chip8.set_pressed_keys(std::array::from_fn(|key| mask & (1 << key) != 0));
+        }
+        let summary = chip8.run_frame(CYCLES_PER_FRAME);
+        assert!(summary.cycles <= CYCLES_PER_FRAME);
+        assert!(chip8.sp() <= 16);
+        if summary.fault.is_some() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,277 @@
+use clap::Parser;
+use std::error::Error;
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use softbuffer::{Context, Surface};
+use winit::application::ApplicationHandler;
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::window::{Window, WindowId};
+
+#[cfg(feature = "audio")]
+use chip_8::audio::{self, Beep};
+use chip_8::render::{self, CycleMode, Palette, PaletteCycle, Rgba};
+use chip_8::{Chip8, NUM_KEYS, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// 60 Hz timer and display rate
+const FRAME_DURATION: Duration = Duration::from_micros(16_667);
+// frames to run at most when catching up after a stall
+const MAX_CATCH_UP_FRAMES: u32 = 5;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// Path to the ROM file to load
+    rom_path: PathBuf,
+
+    /// Clock speed in Hz (instructions per second)
+    #[arg(short, long, default_value_t = 700)]
+    clock_speed: u32,
+
+    /// Window pixels per CHIP-8 pixel
+    #[arg(short, long, default_value_t = 10)]
+    scale: u32,
+
+    /// Foreground colour as #RRGGBB
+    #[arg(long, default_value = "#FFFFFF", value_parser = parse_color)]
+    fg: Rgba,
+
+    /// Background colour as #RRGGBB
+    #[arg(long, default_value = "#000000", value_parser = parse_color)]
+    bg: Rgba,
+
+    /// Octo-style palette instead of --fg and --bg: comma separated
+    /// #RRGGBB colours for the background, plane 1, plane 2 and both planes
+    #[arg(long, value_parser = parse_palette, conflicts_with_all = ["fg", "bg"])]
+    palette: Option<Palette>,
+
+    /// Animate the plane colours: `rotate` or `swap-planes`
+    #[arg(long, value_parser = parse_cycle_mode)]
+    palette_cycle: Option<CycleMode>,
+
+    /// Frames each step of --palette-cycle is shown for
+    #[arg(long, default_value_t = 8, requires = "palette_cycle")]
+    cycle_frames: u32,
+}
+
+fn parse_color(s: &str) -> Result<Rgba, String> {
+    render::parse_color(s).ok_or_else(|| format!("invalid colour `{}`, expected #RRGGBB", s))
+}
+
+fn parse_palette(s: &str) -> Result<Palette, String> {
+    Palette::parse(s)
+        .ok_or_else(|| format!("invalid palette `{}`, expected 2 to 4 #RRGGBB colours", s))
+}
+
+fn parse_cycle_mode(s: &str) -> Result<CycleMode, String> {
+    match s {
+        "rotate" => Ok(CycleMode::Rotate),
+        "swap-planes" => Ok(CycleMode::SwapPlanes),
+        _ => Err(format!(
+            "invalid palette cycle `{}`, expected rotate or swap-planes",
+            s
+        )),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let rom = fs::read(&cli.rom_path)?;
+
+    let mut chip8 = Chip8::new();
+    chip8.init();
+    chip8.load_rom(&rom)?;
+
+    let palette = cli.palette.unwrap_or(Palette::mono(cli.fg, cli.bg));
+    let mut app = App {
+        chip8,
+        keys: [false; NUM_KEYS],
+        cycles_per_frame: (cli.clock_speed / 60).max(1),
+        scale: cli.scale.max(1),
+        palette,
+        palette_cycle: cli
+            .palette_cycle
+            .map(|mode| PaletteCycle::new(palette, mode, cli.cycle_frames)),
+        window: None,
+        surface: None,
+        next_frame: Instant::now(),
+        #[cfg(feature = "audio")]
+        beep: Beep::new(audio::DEFAULT_VOLUME)
+            .map_err(|e| eprintln!("Audio disabled: {}", e))
+            .ok(),
+    };
+
+    let event_loop = EventLoop::new()?;
+    event_loop.run_app(&mut app)?;
+    Ok(())
+}
+
+struct App {
+    chip8: Chip8,
+    keys: [bool; NUM_KEYS],
+    cycles_per_frame: u32,
+    scale: u32,
+    palette: Palette,
+    palette_cycle: Option<PaletteCycle>,
+    window: Option<Rc<Window>>,
+    surface: Option<Surface<Rc<Window>, Rc<Window>>>,
+    next_frame: Instant,
+    #[cfg(feature = "audio")]
+    beep: Option<Beep>,
+}
+
+impl App {
+    fn run_frame(&mut self) {
+        self.chip8.set_pressed_keys(self.keys);
+        self.chip8.run_frame(self.cycles_per_frame);
+        if let Some(cycle) = &mut self.palette_cycle {
+            self.palette = cycle.next_frame();
+        }
+        #[cfg(feature = "audio")]
+        if let Some(beep) = &self.beep {
+            beep.set_playing(self.chip8.is_sound_playing());
+        }
+    }
+
+    fn redraw(&mut self) -> Result<(), Box<dyn Error>> {
+        let (Some(window), Some(surface)) = (&self.window, &mut self.surface) else {
+            return Ok(());
+        };
+        let size = window.inner_size();
+        let (Some(width), Some(height)) =
+            (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+        else {
+            return Ok(());
+        };
+        surface.resize(width, height)?;
+
+        let colors = self
+            .palette
+            .colors()
+            .map(|[r, g, b, _]| u32::from_be_bytes([0, r, g, b]));
+        let pixels = self.chip8.get_display();
+        let display_height = self.chip8.display_height();
+        let mut buffer = surface.buffer_mut()?;
+        let (width, height) = (width.get() as usize, height.get() as usize);
+        // nearest-neighbour scale to whatever size the window currently has
+        for y in 0..height {
+            let src_row = y * display_height / height * SCREEN_WIDTH;
+            for x in 0..width {
+                let pixel = pixels[src_row + x * SCREEN_WIDTH / width];
+                buffer[y * width + x] = colors[pixel as usize];
+            }
+        }
+        buffer.present()?;
+        Ok(())
+    }
+
+    fn set_key(&mut self, key: KeyCode, pressed: bool) {
+        let chip8_key = match key {
+            KeyCode::Digit1 => 0x1,
+            KeyCode::Digit2 => 0x2,
+            KeyCode::Digit3 => 0x3,
+            KeyCode::Digit4 => 0xC,
+            KeyCode::KeyQ => 0x4,
+            KeyCode::KeyW => 0x5,
+            KeyCode::KeyE => 0x6,
+            KeyCode::KeyR => 0xD,
+            KeyCode::KeyA => 0x7,
+            KeyCode::KeyS => 0x8,
+            KeyCode::KeyD => 0x9,
+            KeyCode::KeyF => 0xE,
+            KeyCode::KeyZ => 0xA,
+            KeyCode::KeyX => 0x0,
+            KeyCode::KeyC => 0xB,
+            KeyCode::KeyV => 0xF,
+            _ => return,
+        };
+        self.keys[chip8_key] = pressed;
+    }
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_some() {
+            return;
+        }
+        let attributes = Window::default_attributes()
+            .with_title("CHIP-8")
+            .with_inner_size(LogicalSize::new(
+                SCREEN_WIDTH as u32 * self.scale,
+                SCREEN_HEIGHT as u32 * self.scale,
+            ));
+        let window = match event_loop.create_window(attributes) {
+            Ok(window) => Rc::new(window),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                event_loop.exit();
+                return;
+            }
+        };
+        let surface =
+            Context::new(window.clone()).and_then(|context| Surface::new(&context, window.clone()));
+        match surface {
+            Ok(surface) => self.surface = Some(surface),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                event_loop.exit();
+                return;
+            }
+        }
+        self.window = Some(window);
+        self.next_frame = Instant::now();
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::KeyboardInput { event, .. } => {
+                let PhysicalKey::Code(code) = event.physical_key else {
+                    return;
+                };
+                if code == KeyCode::Escape {
+                    event_loop.exit();
+                    return;
+                }
+                self.set_key(code, event.state == ElementState::Pressed);
+            }
+            WindowEvent::RedrawRequested => {
+                if let Err(e) = self.redraw() {
+                    eprintln!("Error: {}", e);
+                    event_loop.exit();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let now = Instant::now();
+        if now < self.next_frame {
+            event_loop.set_control_flow(ControlFlow::WaitUntil(self.next_frame));
+            return;
+        }
+
+        let mut frames = 0;
+        while self.next_frame <= now && frames < MAX_CATCH_UP_FRAMES {
+            self.run_frame();
+            self.next_frame += FRAME_DURATION;
+            frames += 1;
+        }
+        if self.next_frame <= now {
+            // too far behind (e.g. the process was suspended), drop the missed frames
+            self.next_frame = now + FRAME_DURATION;
+        }
+
+        if let Some(window) = &self.window {
+            window.request_redraw();
+        }
+        event_loop.set_control_flow(ControlFlow::WaitUntil(self.next_frame));
+    }
+}
@@ -0,0 +1,125 @@
+//! JavaScript bindings for the CHIP-8 core.
+//!
+//! Build with `wasm-pack build web --target web --out-dir www/pkg` and serve
+//! `web/www` with any static file server to get the canvas player.
+
+use wasm_bindgen::prelude::*;
+
+use chip_8::render::{self, Palette};
+use chip_8::{Chip8, NUM_KEYS, RGBA_BUFFER_SIZE, SCREEN_WIDTH};
+
+#[wasm_bindgen]
+pub struct Emulator {
+    chip8: Chip8,
+    keys: [bool; NUM_KEYS],
+    palette: Palette,
+    frame: Vec<u8>,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Emulator::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        Emulator {
+            chip8,
+            keys: [false; NUM_KEYS],
+            palette: Palette::default(),
+            frame: vec![0; RGBA_BUFFER_SIZE],
+        }
+    }
+
+    /// Resets the machine and loads a ROM from a `Uint8Array`.
+    /// Returns false if the ROM does not fit in memory.
+    #[wasm_bindgen(js_name = loadRom)]
+    pub fn load_rom(&mut self, rom: &[u8]) -> bool {
+        let mut chip8 = Chip8::new();
+        chip8.init();
+        chip8.set_quirks(self.chip8.quirks());
+        if chip8.load_rom(rom).is_err() {
+            return false;
+        }
+        self.chip8 = chip8;
+        // HIRES ROMs have a taller display
+        self.frame
+            .resize(SCREEN_WIDTH * self.chip8.display_height() * 4, 0);
+        true
+    }
+
+    /// Seeds the random number generator, e.g. from `Math.random()`.
+    #[wasm_bindgen(js_name = seedRng)]
+    pub fn seed_rng(&mut self, seed: f64) {
+        self.chip8.seed_rng(seed.to_bits());
+    }
+
+    /// Executes `count` instructions.
+    pub fn cycle(&mut self, count: u32) {
+        self.chip8.set_pressed_keys(self.keys);
+        for _ in 0..count {
+            self.chip8.cycle();
+        }
+    }
+
+    /// Runs one 60 Hz frame of up to `cycles` instructions and a timer tick,
+    /// returning whether the buzzer is sounding.
+    #[wasm_bindgen(js_name = runFrame)]
+    pub fn run_frame(&mut self, cycles: u32) -> bool {
+        self.chip8.set_pressed_keys(self.keys);
+        self.chip8.run_frame(cycles).sound_playing
+    }
+
+    /// Ticks the 60 Hz timers and returns whether the buzzer is sounding.
+    #[wasm_bindgen(js_name = tickTimers)]
+    pub fn tick_timers(&mut self) -> bool {
+        self.chip8.tick_timers();
+        self.chip8.is_sound_playing()
+    }
+
+    #[wasm_bindgen(js_name = keyDown)]
+    pub fn key_down(&mut self, key: usize) {
+        if let Some(pressed) = self.keys.get_mut(key) {
+            *pressed = true;
+        }
+    }
+
+    #[wasm_bindgen(js_name = keyUp)]
+    pub fn key_up(&mut self, key: usize) {
+        if let Some(pressed) = self.keys.get_mut(key) {
+            *pressed = false;
+        }
+    }
+
+    /// Sets the foreground and background colours from `#RRGGBB` strings.
+    #[wasm_bindgen(js_name = setColors)]
+    pub fn set_colors(&mut self, fg: &str, bg: &str) -> bool {
+        match (render::parse_color(fg), render::parse_color(bg)) {
+            (Some(fg), Some(bg)) => {
+                self.palette = Palette::mono(fg, bg);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Renders the display as RGBA8 pixels, ready for `ImageData`.
+    pub fn framebuffer(&mut self) -> Vec<u8> {
+        self.chip8.render_rgba(&mut self.frame, &self.palette);
+        self.frame.clone()
+    }
+
+    pub fn width() -> usize {
+        SCREEN_WIDTH
+    }
+
+    /// Display height of the loaded ROM, which changes for HIRES ROMs.
+    pub fn height(&self) -> usize {
+        self.chip8.display_height()
+    }
+}
@@ -0,0 +1,74 @@
+import init, { Emulator } from "./pkg/web.js";
+
+// physical key -> CHIP-8 key, same layout as the terminal frontend
+const KEYMAP = {
+  "1": 0x1, "2": 0x2, "3": 0x3, "4": 0xC,
+  "q": 0x4, "w": 0x5, "e": 0x6, "r": 0xD,
+  "a": 0x7, "s": 0x8, "d": 0x9, "f": 0xE,
+  "z": 0xA, "x": 0x0, "c": 0xB, "v": 0xF,
+};
+
+await init();
+
+const emulator = new Emulator();
+emulator.seedRng(Math.random());
+
+const canvas = document.getElementById("screen");
+const ctx = canvas.getContext("2d");
+let image = ctx.createImageData(Emulator.width(), emulator.height());
+const speed = document.getElementById("speed");
+
+let running = false;
+let audio = null;
+let oscillator = null;
+
+function setBeep(on) {
+  if (on && !oscillator) {
+    audio ??= new AudioContext();
+    oscillator = audio.createOscillator();
+    oscillator.type = "square";
+    oscillator.frequency.value = 440;
+    const gain = audio.createGain();
+    gain.gain.value = 0.1;
+    oscillator.connect(gain).connect(audio.destination);
+    oscillator.start();
+  } else if (!on && oscillator) {
+    oscillator.stop();
+    oscillator = null;
+  }
+}
+
+function frame() {
+  if (running) {
+    setBeep(emulator.runFrame(Math.max(1, Math.round(speed.value / 60))));
+    image.data.set(emulator.framebuffer());
+    ctx.putImageData(image, 0, 0);
+  }
+  requestAnimationFrame(frame);
+}
+
+document.getElementById("rom").addEventListener("change", async (event) => {
+  const file = event.target.files[0];
+  if (!file) return;
+  const rom = new Uint8Array(await file.arrayBuffer());
+  running = emulator.loadRom(rom);
+  if (!running) {
+    alert("ROM too large to fit in memory");
+    return;
+  }
+  // HIRES ROMs use a 64x64 display
+  canvas.height = emulator.height();
+  canvas.style.height = `${emulator.height() * 10}px`;
+  image = ctx.createImageData(Emulator.width(), emulator.height());
+});
+
+document.addEventListener("keydown", (event) => {
+  const key = KEYMAP[event.key.toLowerCase()];
+  if (key !== undefined) emulator.keyDown(key);
+});
+document.addEventListener("keyup", (event) => {
+  const key = KEYMAP[event.key.toLowerCase()];
+  if (key !== undefined) emulator.keyUp(key);
+});
+
+requestAnimationFrame(frame);
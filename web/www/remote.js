@@ -0,0 +1,78 @@
+// Display and keypad for an emulator started with `cli --serve`. The
+// protocol is described in cli/src/server.rs.
+
+// physical key -> CHIP-8 key, same layout as the terminal frontend
+const KEYMAP = {
+  "1": 0x1, "2": 0x2, "3": 0x3, "4": 0xC,
+  "q": 0x4, "w": 0x5, "e": 0x6, "r": 0xD,
+  "a": 0x7, "s": 0x8, "d": 0x9, "f": 0xE,
+  "z": 0xA, "x": 0x0, "c": 0xB, "v": 0xF,
+};
+const FRAME = 0x46; // "F"
+const SOUND = 0x53; // "S"
+const KEYS = 0x4B; // "K"
+
+const server = new URLSearchParams(location.search).get("server") ?? `${location.hostname}:8080`;
+const canvas = document.getElementById("screen");
+const ctx = canvas.getContext("2d");
+const status = document.getElementById("status");
+
+let audio = null;
+let oscillator = null;
+let keys = 0;
+
+function setBeep(on) {
+  if (on && !oscillator) {
+    audio ??= new AudioContext();
+    oscillator = audio.createOscillator();
+    oscillator.type = "square";
+    oscillator.frequency.value = 440;
+    const gain = audio.createGain();
+    gain.gain.value = 0.1;
+    oscillator.connect(gain).connect(audio.destination);
+    oscillator.start();
+  } else if (!on && oscillator) {
+    oscillator.stop();
+    oscillator = null;
+  }
+}
+
+// rows are big-endian u64s with the leftmost pixel in the top bit
+function draw(message) {
+  const height = message[1];
+  if (canvas.height !== height) {
+    canvas.height = height;
+    canvas.style.height = `${height * 10}px`;
+  }
+  const image = ctx.createImageData(64, height);
+  for (let y = 0; y < height; y++) {
+    for (let x = 0; x < 64; x++) {
+      const byte = message[2 + y * 8 + (x >> 3)];
+      const lit = (byte >> (7 - (x & 7))) & 1;
+      image.data.fill(lit ? 255 : 0, (y * 64 + x) * 4, (y * 64 + x) * 4 + 3);
+      image.data[(y * 64 + x) * 4 + 3] = 255;
+    }
+  }
+  ctx.putImageData(image, 0, 0);
+}
+
+const socket = new WebSocket(`ws://${server}`);
+socket.binaryType = "arraybuffer";
+socket.onopen = () => { status.textContent = `Connected to ${server}`; };
+socket.onclose = () => { status.textContent = `Disconnected from ${server}`; setBeep(false); };
+socket.onmessage = (event) => {
+  const message = new Uint8Array(event.data);
+  if (message[0] === FRAME) draw(message);
+  else if (message[0] === SOUND) setBeep(message[1] !== 0);
+};
+
+function setKey(event, down) {
+  const key = KEYMAP[event.key.toLowerCase()];
+  if (key === undefined) return;
+  const changed = down ? keys | (1 << key) : keys & ~(1 << key);
+  if (changed === keys || socket.readyState !== WebSocket.OPEN) return;
+  keys = changed;
+  socket.send(new Uint8Array([KEYS, keys & 0xFF, keys >> 8]));
+}
+document.addEventListener("keydown", (event) => setKey(event, true));
+document.addEventListener("keyup", (event) => setKey(event, false));